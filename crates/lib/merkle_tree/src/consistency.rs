@@ -1,13 +1,17 @@
 //! Consistency verification for the Merkle tree.
 
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
 
 use rayon::prelude::*;
 
 use crate::{
     errors::DeserializeError,
     hasher::{HashTree, HasherWithStats},
-    types::{LeafNode, Nibbles, Node, NodeKey, Root},
+    metrics::CONSISTENCY_METRICS,
+    types::{align_key, ChildRef, InternalNode, LeafNode, Nibbles, Node, NodeKey, Root},
     Database, Key, MerkleTree, ValueHash,
 };
 
@@ -43,6 +47,14 @@ pub enum ConsistencyError {
         "leaf at {key} specifies its full key as {full_key}, which doesn't start with the node key"
     )]
     FullKeyMismatch { key: NodeKey, full_key: Key },
+    #[error(
+        "leaf at {key} has full key {full_key}, which does not fit into the tree with depth {depth}"
+    )]
+    KeyOverflow {
+        key: NodeKey,
+        full_key: Key,
+        depth: usize,
+    },
     #[error("leaf with key {full_key} has zero index, while leaf indices must start with 1")]
     ZeroIndex { full_key: Key },
     #[error(
@@ -70,7 +82,12 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
     /// Verifies the internal tree consistency as stored in the database.
     ///
     /// If `validate_indices` flag is set, it will be checked that indices for all tree leaves are
-    /// unique and are sequentially assigned starting from 1.
+    /// unique and are sequentially assigned starting from 1. This flag should not be set for trees
+    /// with [removed entries](crate::TreeInstruction::Remove) since removals leave gaps
+    /// in leaf indices.
+    ///
+    /// For large trees, consider using [`ConsistencyVerifier`], which can be resumed after
+    /// a restart and can collect multiple errors.
     ///
     /// # Errors
     ///
@@ -80,16 +97,7 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
         version: u64,
         validate_indices: bool,
     ) -> Result<(), ConsistencyError> {
-        let manifest = self.db.try_manifest()?;
-        let manifest = manifest.ok_or(ConsistencyError::MissingVersion(version))?;
-        if version >= manifest.version_count {
-            return Err(ConsistencyError::MissingVersion(version));
-        }
-
-        let root = self
-            .db
-            .try_root(version)?
-            .ok_or(ConsistencyError::MissingRoot(version))?;
+        let root = self.load_root_for_verification(version)?;
         let (leaf_count, root_node) = match root {
             Root::Empty => return Ok(()),
             Root::Filled { leaf_count, node } => (leaf_count.get(), node),
@@ -98,23 +106,47 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
         // We want to perform a depth-first walk of the tree in order to not keep
         // much in memory.
         let root_key = Nibbles::EMPTY.with_version(version);
-        let leaf_data = validate_indices.then(|| LeafConsistencyData::new(leaf_count));
-        self.validate_node(&root_node, root_key, leaf_data.as_ref())?;
+        let leaf_data = validate_indices.then(|| LeafConsistencyData::new(leaf_count, true));
+        self.validate_node(&root_node, root_key, leaf_data.as_ref(), None)?;
         if let Some(leaf_data) = leaf_data {
             leaf_data.validate_count()?;
         }
         Ok(())
     }
 
+    fn load_root_for_verification(&self, version: u64) -> Result<Root, ConsistencyError> {
+        let manifest = self.db.try_manifest()?;
+        let manifest = manifest.ok_or(ConsistencyError::MissingVersion(version))?;
+        if version >= manifest.version_count {
+            return Err(ConsistencyError::MissingVersion(version));
+        }
+
+        self.db
+            .try_root(version)?
+            .ok_or(ConsistencyError::MissingRoot(version))
+    }
+
+    /// Validates a node and its descendants. If `errors` are provided, errors in descendants are
+    /// recorded there instead of being propagated until the collector is full.
     fn validate_node(
         &self,
         node: &Node,
         key: NodeKey,
         leaf_data: Option<&LeafConsistencyData>,
+        errors: Option<&ErrorCollector>,
     ) -> Result<ValueHash, ConsistencyError> {
         match node {
             Node::Leaf(leaf) => {
-                let full_key_nibbles = Nibbles::new(&leaf.full_key, key.nibbles.nibble_count());
+                let depth = self.hasher.depth();
+                if leaf.full_key.bit_len() > depth {
+                    return Err(ConsistencyError::KeyOverflow {
+                        key,
+                        full_key: leaf.full_key,
+                        depth,
+                    });
+                }
+                let aligned_key = align_key(&leaf.full_key, depth);
+                let full_key_nibbles = Nibbles::new(&aligned_key, key.nibbles.nibble_count());
                 if full_key_nibbles != key.nibbles {
                     return Err(ConsistencyError::FullKeyMismatch {
                         key,
@@ -122,25 +154,12 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
                     });
                 }
                 if let Some(leaf_data) = leaf_data {
-                    leaf_data.insert_leaf(leaf)?;
+                    leaf_data.insert_leaf(leaf, &key.nibbles)?;
                 }
             }
 
             Node::Internal(node) => {
-                let expected_version = node.child_refs().map(|child_ref| child_ref.version).max();
-                let Some(expected_version) = expected_version else {
-                    return Err(ConsistencyError::EmptyInternalNode { key });
-                };
-                if !key.is_empty() && expected_version != key.version {
-                    return Err(ConsistencyError::KeyVersionMismatch {
-                        key,
-                        expected_version,
-                    });
-                } else if key.is_empty() && expected_version > key.version {
-                    return Err(ConsistencyError::RootVersionMismatch {
-                        max_child_version: expected_version,
-                    });
-                }
+                Self::validate_internal_node_version(node, key)?;
 
                 // `.into_par_iter()` below is the only place where `rayon`-based parallelism
                 // is used in tree verification.
@@ -148,31 +167,10 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
                 children
                     .into_par_iter()
                     .try_for_each(|(nibble, child_ref)| {
-                        let child_key = key
-                            .nibbles
-                            .push(nibble)
-                            .ok_or(ConsistencyError::TerminalInternalNode { key })?;
-                        let child_key = child_key.with_version(child_ref.version);
-                        let child = self
-                            .db
-                            .try_tree_node(&child_key, child_ref.is_leaf)?
-                            .ok_or(ConsistencyError::MissingNode {
-                                key: child_key,
-                                is_leaf: child_ref.is_leaf,
-                            })?;
-
-                        // Recursion here is OK; the tree isn't that deep (~8 nibbles for a tree
-                        // with ~1B entries).
-                        let child_hash = self.validate_node(&child, child_key, leaf_data)?;
-                        if child_hash == child_ref.hash {
-                            Ok(())
-                        } else {
-                            Err(ConsistencyError::HashMismatch {
-                                key,
-                                nibble,
-                                expected: child_ref.hash,
-                                actual: child_hash,
-                            })
+                        let result = self.validate_child(key, nibble, child_ref, leaf_data, errors);
+                        match (result, errors) {
+                            (Err(err), Some(errors)) => errors.push(err),
+                            (result, _) => result,
                         }
                     })?;
             }
@@ -181,50 +179,404 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
         let level = key.nibbles.nibble_count() * 4;
         Ok(node.hash(&mut HasherWithStats::new(&self.hasher), level))
     }
+
+    fn validate_internal_node_version(
+        node: &InternalNode,
+        key: NodeKey,
+    ) -> Result<(), ConsistencyError> {
+        let expected_version = node.child_refs().map(|child_ref| child_ref.version).max();
+        let Some(expected_version) = expected_version else {
+            return Err(ConsistencyError::EmptyInternalNode { key });
+        };
+        if !key.is_empty() && expected_version != key.version {
+            return Err(ConsistencyError::KeyVersionMismatch {
+                key,
+                expected_version,
+            });
+        } else if key.is_empty() && expected_version > key.version {
+            return Err(ConsistencyError::RootVersionMismatch {
+                max_child_version: expected_version,
+            });
+        }
+        Ok(())
+    }
+
+    fn validate_child(
+        &self,
+        parent_key: NodeKey,
+        nibble: u8,
+        child_ref: &ChildRef,
+        leaf_data: Option<&LeafConsistencyData>,
+        errors: Option<&ErrorCollector>,
+    ) -> Result<(), ConsistencyError> {
+        let max_nibble_count = self.hasher.depth() / 4;
+        let child_key = parent_key
+            .nibbles
+            .push(nibble)
+            .filter(|nibbles| nibbles.nibble_count() <= max_nibble_count)
+            .ok_or(ConsistencyError::TerminalInternalNode { key: parent_key })?;
+        let child_key = child_key.with_version(child_ref.version);
+        let child = self
+            .db
+            .try_tree_node(&child_key, child_ref.is_leaf)?
+            .ok_or(ConsistencyError::MissingNode {
+                key: child_key,
+                is_leaf: child_ref.is_leaf,
+            })?;
+
+        // Recursion here is OK; the tree isn't that deep (~8 nibbles for a tree
+        // with ~1B entries).
+        let child_hash = self.validate_node(&child, child_key, leaf_data, errors)?;
+        if child_hash == child_ref.hash {
+            Ok(())
+        } else {
+            Err(ConsistencyError::HashMismatch {
+                key: parent_key,
+                nibble,
+                expected: child_ref.hash,
+                actual: child_hash,
+            })
+        }
+    }
+}
+
+/// Progress of a [`ConsistencyVerifier`] that can be persisted and used to resume verification.
+///
+/// The verified tree version is split into subtrees rooted at the children of the root node;
+/// the cursor records subtrees that were successfully verified, together with their leaf counts.
+/// The cursor also records the root hash of the verified version, so that it is not applied
+/// to a different tree with the same version (e.g., after the tree was reverted and rebuilt).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationCursor {
+    pub(crate) version: u64,
+    pub(crate) root_hash: ValueHash,
+    /// Leaf counts for successfully verified subtrees, indexed by the top-level nibble.
+    pub(crate) verified_subtrees: [Option<u64>; 16],
+}
+
+impl VerificationCursor {
+    fn new(version: u64, root_hash: ValueHash) -> Self {
+        Self {
+            version,
+            root_hash,
+            verified_subtrees: [None; 16],
+        }
+    }
+
+    /// Returns the tree version being verified.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the root hash of the tree version being verified.
+    pub fn root_hash(&self) -> ValueHash {
+        self.root_hash
+    }
+
+    /// Returns the number of successfully verified top-level subtrees.
+    pub fn verified_subtree_count(&self) -> usize {
+        self.verified_subtrees.iter().flatten().count()
+    }
+
+    fn verified_leaf_count(&self) -> u64 {
+        self.verified_subtrees.iter().flatten().sum()
+    }
+}
+
+/// Resumable consistency verifier for a single version of a [`MerkleTree`].
+///
+/// Unlike [`MerkleTree::verify_consistency()`], the verifier splits the tree into subtrees rooted
+/// at the children of the root node and verifies them in parallel. Progress is reported as
+/// a [`VerificationCursor`] each time a subtree is verified; the cursor can be persisted and
+/// later passed to [`Self::resume_from()`] so that verified subtrees are skipped. Additionally,
+/// the verifier can collect multiple errors instead of stopping at the first one.
+///
+/// If leaf indices are validated, duplicate indices are only detected among subtrees verified
+/// in the same [`Self::run()`] call, since the set of encountered indices is not persisted.
+#[derive(Debug)]
+pub struct ConsistencyVerifier<'a, DB, H> {
+    tree: &'a MerkleTree<DB, H>,
+    version: u64,
+    resumed_cursor: Option<VerificationCursor>,
+    validate_indices: bool,
+    max_error_count: usize,
+}
+
+impl<'a, DB: Database, H: HashTree> ConsistencyVerifier<'a, DB, H> {
+    /// Creates a verifier for the specified tree `version`. By default, leaf indices are
+    /// validated and verification stops after the first error.
+    pub fn new(tree: &'a MerkleTree<DB, H>, version: u64) -> Self {
+        Self {
+            tree,
+            version,
+            resumed_cursor: None,
+            validate_indices: true,
+            max_error_count: 1,
+        }
+    }
+
+    /// Sets whether leaf indices should be validated; see [`MerkleTree::verify_consistency()`]
+    /// for details.
+    pub fn set_validate_indices(&mut self, validate_indices: bool) {
+        self.validate_indices = validate_indices;
+    }
+
+    /// Sets the maximum number of errors collected before verification stops.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is zero.
+    pub fn set_max_error_count(&mut self, count: usize) {
+        assert!(count > 0, "Max error count must be positive");
+        self.max_error_count = count;
+    }
+
+    /// Resumes verification from the provided `cursor`. If the root hash recorded in the cursor
+    /// doesn't match the root hash of the verified tree version, the cursor is discarded
+    /// once verification is [run](Self::run()), and verification starts from scratch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cursor refers to a different tree version.
+    pub fn resume_from(&mut self, cursor: VerificationCursor) {
+        assert_eq!(
+            cursor.version, self.version,
+            "Cursor refers to a different tree version"
+        );
+        self.resumed_cursor = Some(cursor);
+    }
+
+    /// Runs verification, calling `on_progress` each time a top-level subtree is successfully
+    /// verified.
+    ///
+    /// # Errors
+    ///
+    /// Returns collected errors (no more than the [configured](Self::set_max_error_count())
+    /// number) if the tree is inconsistent.
+    pub fn run(
+        self,
+        on_progress: impl FnMut(&VerificationCursor) + Send,
+    ) -> Result<(), Vec<ConsistencyError>> {
+        let version = self.version;
+        let root = self
+            .tree
+            .load_root_for_verification(version)
+            .map_err(|err| vec![err])?;
+        let (leaf_count, root_node) = match root {
+            Root::Empty => return Ok(()),
+            Root::Filled { leaf_count, node } => (leaf_count.get(), node),
+        };
+        let Node::Internal(root_node) = root_node else {
+            // The tree consists of a single leaf; there's nothing to split.
+            return self
+                .tree
+                .verify_consistency(version, self.validate_indices)
+                .map_err(|err| vec![err]);
+        };
+        let root_key = Nibbles::EMPTY.with_version(version);
+        MerkleTree::<DB, H>::validate_internal_node_version(&root_node, root_key)
+            .map_err(|err| vec![err])?;
+
+        let root_hash = root_node.hash(&mut HasherWithStats::new(&self.tree.hasher), 0);
+        let cursor = match self.resumed_cursor {
+            Some(cursor) if cursor.root_hash == root_hash => cursor,
+            Some(cursor) => {
+                tracing::warn!(
+                    "Root hash {} in the verification cursor differs from the actual root hash \
+                     {root_hash} of tree version {version}; discarding the cursor",
+                    cursor.root_hash
+                );
+                VerificationCursor::new(version, root_hash)
+            }
+            None => VerificationCursor::new(version, root_hash),
+        };
+
+        let leaf_data = LeafConsistencyData::new(leaf_count, self.validate_indices);
+        let subtrees: Vec<_> = root_node
+            .children()
+            .filter(|&(nibble, _)| cursor.verified_subtrees[usize::from(nibble)].is_none())
+            .collect();
+        let subtree_count = root_node.child_count();
+        CONSISTENCY_METRICS.total_subtrees.set(subtree_count as u64);
+        let verified_subtree_count = cursor.verified_subtree_count();
+        CONSISTENCY_METRICS
+            .verified_subtrees
+            .set(verified_subtree_count as u64);
+        CONSISTENCY_METRICS
+            .verified_leaves
+            .set(cursor.verified_leaf_count());
+        tracing::info!(
+            "Verifying {} / {subtree_count} subtrees for tree version {version}",
+            subtrees.len()
+        );
+
+        let errors = ErrorCollector::new(self.max_error_count);
+        let progress = Mutex::new((cursor, on_progress));
+        subtrees.into_par_iter().for_each(|(nibble, child_ref)| {
+            if errors.is_full() {
+                return;
+            }
+            let latency = CONSISTENCY_METRICS.subtree_latency.start();
+            let subtree_errors = ErrorCollector::new(self.max_error_count);
+            let result = self.tree.validate_child(
+                root_key,
+                nibble,
+                child_ref,
+                Some(&leaf_data),
+                Some(&subtree_errors),
+            );
+            let mut subtree_errors = subtree_errors.into_inner();
+            if let Err(err) = result {
+                subtree_errors.push(err);
+            }
+            let latency = latency.observe();
+
+            if subtree_errors.is_empty() {
+                tracing::debug!("Verified subtree `{nibble:x}` in {latency:?}");
+                let leaf_count = leaf_data.subtree_leaf_count(nibble);
+                let mut guard = progress.lock().unwrap();
+                let (cursor, on_progress) = &mut *guard;
+                cursor.verified_subtrees[usize::from(nibble)] = Some(leaf_count);
+                on_progress(cursor);
+                CONSISTENCY_METRICS
+                    .verified_subtrees
+                    .set(cursor.verified_subtree_count() as u64);
+                CONSISTENCY_METRICS
+                    .verified_leaves
+                    .set(cursor.verified_leaf_count());
+            } else {
+                tracing::warn!(
+                    "Found {} error(s) in subtree `{nibble:x}`",
+                    subtree_errors.len()
+                );
+                for err in subtree_errors {
+                    errors.push(err).ok();
+                }
+            }
+        });
+
+        let mut errors = errors.into_inner();
+        let (cursor, _) = progress.into_inner().unwrap();
+        if errors.is_empty() && self.validate_indices {
+            let actual = cursor.verified_leaf_count();
+            if actual != leaf_count {
+                errors.push(ConsistencyError::LeafCountMismatch {
+                    expected: leaf_count,
+                    actual,
+                });
+            }
+        }
+        CONSISTENCY_METRICS.error_count.set(errors.len() as u64);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Thread-safe collector of consistency errors with a limit on the number of errors.
+#[derive(Debug)]
+struct ErrorCollector {
+    errors: Mutex<Vec<ConsistencyError>>,
+    max_count: usize,
+}
+
+impl ErrorCollector {
+    fn new(max_count: usize) -> Self {
+        Self {
+            errors: Mutex::default(),
+            max_count,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.errors.lock().unwrap().len() >= self.max_count
+    }
+
+    /// Records an error. If the collector is full, returns the error back so that it's propagated
+    /// and verification stops.
+    fn push(&self, err: ConsistencyError) -> Result<(), ConsistencyError> {
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() < self.max_count {
+            errors.push(err);
+            Ok(())
+        } else {
+            Err(err)
+        }
+    }
+
+    fn into_inner(self) -> Vec<ConsistencyError> {
+        self.errors.into_inner().unwrap()
+    }
 }
 
 #[derive(Debug)]
 struct LeafConsistencyData {
     expected_leaf_count: u64,
     actual_leaf_count: AtomicU64,
-    leaf_indices_set: AtomicBitSet,
+    /// Leaf counts split by the top-level nibble of the leaf key.
+    subtree_leaf_counts: [AtomicU64; 16],
+    /// Set of encountered leaf indices; `None` if indices are not validated.
+    leaf_indices_set: Option<AtomicBitSet>,
 }
 
 #[allow(clippy::cast_possible_truncation)] // expected leaf count is quite small
 impl LeafConsistencyData {
-    fn new(expected_leaf_count: u64) -> Self {
+    fn new(expected_leaf_count: u64, validate_indices: bool) -> Self {
         Self {
             expected_leaf_count,
             actual_leaf_count: AtomicU64::new(0),
-            leaf_indices_set: AtomicBitSet::new(expected_leaf_count as usize),
+            subtree_leaf_counts: Default::default(),
+            leaf_indices_set: validate_indices
+                .then(|| AtomicBitSet::new(expected_leaf_count as usize)),
         }
     }
 
-    fn insert_leaf(&self, leaf: &LeafNode) -> Result<(), ConsistencyError> {
+    fn insert_leaf(&self, leaf: &LeafNode, nibbles: &Nibbles) -> Result<(), ConsistencyError> {
+        if let Some(leaf_indices_set) = &self.leaf_indices_set {
+            Self::insert_leaf_index(leaf_indices_set, leaf, self.expected_leaf_count)?;
+        }
+        self.actual_leaf_count.fetch_add(1, Ordering::Relaxed);
+        if nibbles.nibble_count() > 0 {
+            let top_level_nibble = nibbles.bytes()[0] >> 4;
+            self.subtree_leaf_counts[usize::from(top_level_nibble)].fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn insert_leaf_index(
+        leaf_indices_set: &AtomicBitSet,
+        leaf: &LeafNode,
+        expected_leaf_count: u64,
+    ) -> Result<(), ConsistencyError> {
         if leaf.leaf_index == 0 {
             return Err(ConsistencyError::ZeroIndex {
                 full_key: leaf.full_key,
             });
         }
-        if leaf.leaf_index > self.expected_leaf_count {
+        if leaf.leaf_index > expected_leaf_count {
             return Err(ConsistencyError::LeafIndexOverflow {
                 index: leaf.leaf_index,
-                leaf_count: self.expected_leaf_count,
+                leaf_count: expected_leaf_count,
                 full_key: leaf.full_key,
             });
         }
 
         let index = (leaf.leaf_index - 1) as usize;
-        if self.leaf_indices_set.set(index) {
+        if leaf_indices_set.set(index) {
             return Err(ConsistencyError::DuplicateLeafIndex {
                 index: leaf.leaf_index,
                 full_key: leaf.full_key,
             });
         }
-        self.actual_leaf_count.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
+    fn subtree_leaf_count(&self, nibble: u8) -> u64 {
+        self.subtree_leaf_counts[usize::from(nibble)].load(Ordering::Relaxed)
+    }
+
     fn validate_count(mut self) -> Result<(), ConsistencyError> {
         let actual_leaf_count = *self.actual_leaf_count.get_mut();
         if actual_leaf_count == self.expected_leaf_count {
@@ -275,6 +627,7 @@ mod tests {
 
     use super::*;
     use crate::{
+        storage::tests::create_large_tree_entries,
         types::{InternalNode, TreeEntry},
         PatchSet,
     };
@@ -516,4 +869,115 @@ mod tests {
             }
         );
     }
+
+    fn prepare_large_database() -> PatchSet {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(create_large_tree_entries());
+        tree.db
+    }
+
+    #[test]
+    fn resumable_verification_basics() {
+        let tree = MerkleTree::new(prepare_large_database());
+        let mut cursors = vec![];
+        ConsistencyVerifier::new(&tree, 0)
+            .run(|cursor| cursors.push(cursor.clone()))
+            .unwrap();
+
+        assert_eq!(cursors.len(), 16);
+        for (i, cursor) in cursors.iter().enumerate() {
+            assert_eq!(cursor.version(), 0);
+            assert_eq!(cursor.verified_subtree_count(), i + 1);
+        }
+        let last_cursor = cursors.last().unwrap();
+        assert!(last_cursor
+            .verified_subtrees
+            .iter()
+            .all(|&leaf_count| leaf_count == Some(16)));
+
+        // Resume verification from an intermediate cursor.
+        let mut verifier = ConsistencyVerifier::new(&tree, 0);
+        verifier.resume_from(cursors[4].clone());
+        let mut resumed_cursors = vec![];
+        verifier
+            .run(|cursor| resumed_cursors.push(cursor.clone()))
+            .unwrap();
+        assert_eq!(resumed_cursors.len(), 11);
+        assert_eq!(resumed_cursors.last(), Some(last_cursor));
+    }
+
+    #[test]
+    fn resumable_verification_checks_leaf_count() {
+        let tree = MerkleTree::new(prepare_large_database());
+        let mut cursor = VerificationCursor::new(0, tree.root_hash(0).unwrap());
+        cursor.verified_subtrees[3] = Some(20);
+        let mut verifier = ConsistencyVerifier::new(&tree, 0);
+        verifier.resume_from(cursor);
+
+        let errors = verifier.run(|_| ()).unwrap_err();
+        assert_matches!(
+            errors.as_slice(),
+            [ConsistencyError::LeafCountMismatch {
+                expected: 256,
+                actual: 260
+            }]
+        );
+    }
+
+    #[test]
+    fn resumable_verification_discards_cursor_with_different_root_hash() {
+        let tree = MerkleTree::new(prepare_large_database());
+        let mut cursor = VerificationCursor::new(0, ValueHash::repeat_byte(1));
+        cursor.verified_subtrees[3] = Some(20);
+        let mut verifier = ConsistencyVerifier::new(&tree, 0);
+        verifier.resume_from(cursor);
+
+        let mut cursors = vec![];
+        verifier.run(|cursor| cursors.push(cursor.clone())).unwrap();
+        assert_eq!(cursors.len(), 16);
+        let last_cursor = cursors.last().unwrap();
+        assert_eq!(last_cursor.root_hash(), tree.root_hash(0).unwrap());
+        assert!(last_cursor
+            .verified_subtrees
+            .iter()
+            .all(|&leaf_count| leaf_count == Some(16)));
+    }
+
+    #[test]
+    fn resumable_verification_collects_multiple_errors() {
+        let mut db = prepare_large_database();
+        let removed_keys: Vec<_> = db
+            .nodes_mut()
+            .filter_map(|(key, node)| match node {
+                Node::Leaf(leaf) if [1, 100, 200].contains(&leaf.leaf_index) => Some(*key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(removed_keys.len(), 3);
+        for key in &removed_keys {
+            db.remove_node(key);
+        }
+        let tree = MerkleTree::new(db);
+
+        let mut verifier = ConsistencyVerifier::new(&tree, 0);
+        verifier.set_max_error_count(10);
+        let mut cursor = None;
+        let errors = verifier
+            .run(|new_cursor| cursor = Some(new_cursor.clone()))
+            .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        for err in &errors {
+            assert_matches!(
+                err,
+                ConsistencyError::MissingNode { key, is_leaf: true } if removed_keys.contains(key)
+            );
+        }
+        // Subtrees with errors should not be marked as verified.
+        assert_eq!(cursor.unwrap().verified_subtree_count(), 13);
+
+        let mut verifier = ConsistencyVerifier::new(&tree, 0);
+        verifier.set_max_error_count(2);
+        let errors = verifier.run(|_| ()).unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
 }
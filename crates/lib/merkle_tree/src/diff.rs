@@ -0,0 +1,322 @@
+//! Computing differences between tree versions.
+
+use std::{cmp::Ordering, iter};
+
+use crate::{
+    getters::{load_root, EntriesInRange},
+    types::{ChildRef, InternalNode, Nibbles, Node, NodeKey, Root, TreeEntryChange},
+    Database, HashTree, MerkleTree, NoVersionError,
+};
+
+impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
+    /// Returns changes in tree entries between `from_version` and `to_version` of the tree,
+    /// in the ascending key order. Versions may be supplied in any order; e.g., if `from_version`
+    /// is greater than `to_version`, entries inserted after `to_version` will be reported
+    /// as [removed](TreeEntryChange::Removed).
+    ///
+    /// Both tree versions are traversed in parallel. Subtrees referenced by the same
+    /// child ref (i.e., having the same version and hash) in both versions are skipped, so
+    /// the diff for consecutive versions only touches the nodes updated in the newer version.
+    /// Changes are computed lazily as the returned iterator is advanced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the tree versions is missing.
+    pub fn diff(
+        &self,
+        from_version: u64,
+        to_version: u64,
+    ) -> Result<impl Iterator<Item = TreeEntryChange> + '_, NoVersionError> {
+        let old_root = load_root(&self.db, from_version)?;
+        let new_root = load_root(&self.db, to_version)?;
+        Ok(TreeDiff::new(
+            &self.db,
+            old_root,
+            new_root,
+            self.hasher.depth(),
+        ))
+    }
+}
+
+#[derive(Debug)]
+enum PendingNode {
+    Loaded(Node),
+    Ref(NodeKey, bool),
+}
+
+impl PendingNode {
+    fn from_root(root: Root) -> Option<Self> {
+        match root {
+            Root::Empty => None,
+            Root::Filled { node, .. } => Some(Self::Loaded(node)),
+        }
+    }
+}
+
+/// Pair of nodes at the same position in the old and new tree versions.
+type PendingPair = (Nibbles, Option<PendingNode>, Option<PendingNode>);
+
+/// Iterator over changes between two tree versions.
+#[derive(Debug)]
+struct TreeDiff<'a, DB> {
+    db: &'a DB,
+    depth: usize,
+    /// Node pairs pending comparison. The next pair to compare is on top of the stack.
+    pending_pairs: Vec<PendingPair>,
+    /// Merged entries of the last compared pair that were not yet returned.
+    pending_entries: Option<MergedEntries<'a, DB>>,
+}
+
+impl<'a, DB: Database> TreeDiff<'a, DB> {
+    fn new(db: &'a DB, old_root: Root, new_root: Root, depth: usize) -> Self {
+        let old_node = PendingNode::from_root(old_root);
+        let new_node = PendingNode::from_root(new_root);
+        Self {
+            db,
+            depth,
+            pending_pairs: vec![(Nibbles::EMPTY, old_node, new_node)],
+            pending_entries: None,
+        }
+    }
+
+    fn load_node(&self, node: PendingNode) -> Node {
+        match node {
+            PendingNode::Loaded(node) => node,
+            PendingNode::Ref(node_key, is_leaf) => {
+                self.db.tree_node(&node_key, is_leaf).unwrap()
+                // ^ `unwrap()` is safe: all requested nodes are referenced by their parents
+            }
+        }
+    }
+
+    fn push_children(&mut self, nibbles: Nibbles, old: &InternalNode, new: &InternalNode) {
+        let mut child_pairs = vec![];
+        for nibble in 0..InternalNode::CHILD_COUNT {
+            let old_ref = old.child_ref(nibble);
+            let new_ref = new.child_ref(nibble);
+            let is_unchanged = match (old_ref, new_ref) {
+                (None, None) => true,
+                (Some(old_ref), Some(new_ref)) => {
+                    old_ref.version == new_ref.version && old_ref.hash == new_ref.hash
+                }
+                _ => false,
+            };
+            if is_unchanged {
+                continue;
+            }
+
+            let child_nibbles = nibbles.push(nibble).unwrap();
+            // ^ `unwrap()` is safe: internal nodes are never placed at the bottom tree level
+            let to_pending = |child_ref: &ChildRef| {
+                let child_key = child_nibbles.with_version(child_ref.version);
+                PendingNode::Ref(child_key, child_ref.is_leaf)
+            };
+            child_pairs.push((
+                child_nibbles,
+                old_ref.map(to_pending),
+                new_ref.map(to_pending),
+            ));
+        }
+        // Pairs are pushed in the reverse order, so that the pair with the smallest nibble
+        // is compared first.
+        self.pending_pairs.extend(child_pairs.into_iter().rev());
+    }
+
+    /// Compares subtrees at least one of which is a leaf or empty. Such subtrees have no
+    /// common structure, so we just merge their (sorted) entries.
+    fn compare_entries(&mut self, nibbles: Nibbles, old: Option<Node>, new: Option<Node>) {
+        self.pending_entries = Some(MergedEntries {
+            old: EntriesInRange::for_subtree(self.db, nibbles, old, self.depth).peekable(),
+            new: EntriesInRange::for_subtree(self.db, nibbles, new, self.depth).peekable(),
+        });
+    }
+}
+
+/// Changes between entries of old and new subtrees, produced by merging their (sorted) entries.
+#[derive(Debug)]
+struct MergedEntries<'a, DB> {
+    old: iter::Peekable<EntriesInRange<'a, DB>>,
+    new: iter::Peekable<EntriesInRange<'a, DB>>,
+}
+
+impl<DB: Database> Iterator for MergedEntries<'_, DB> {
+    type Item = TreeEntryChange;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ordering = match (self.old.peek(), self.new.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(old), Some(new)) => old.key.cmp(&new.key),
+            };
+            match ordering {
+                Ordering::Less => return self.old.next().map(TreeEntryChange::Removed),
+                Ordering::Greater => return self.new.next().map(TreeEntryChange::Inserted),
+                Ordering::Equal => {
+                    let previous = self.old.next().unwrap();
+                    let current = self.new.next().unwrap();
+                    if previous != current {
+                        return Some(TreeEntryChange::Updated { previous, current });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<DB: Database> Iterator for TreeDiff<'_, DB> {
+    type Item = TreeEntryChange;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entries) = &mut self.pending_entries {
+                if let Some(change) = entries.next() {
+                    return Some(change);
+                }
+                self.pending_entries = None;
+            }
+
+            let (nibbles, old, new) = self.pending_pairs.pop()?;
+            let old = old.map(|node| self.load_node(node));
+            let new = new.map(|node| self.load_node(node));
+            match (old, new) {
+                (Some(Node::Internal(old)), Some(Node::Internal(new))) => {
+                    self.push_children(nibbles, &old, &new);
+                }
+                (old, new) => self.compare_entries(nibbles, old, new),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{Key, PatchSet, TreeEntry, ValueHash};
+
+    fn naive_diff(
+        tree: &MerkleTree<PatchSet>,
+        from_version: u64,
+        to_version: u64,
+    ) -> Vec<TreeEntryChange> {
+        let old_entries: BTreeMap<_, _> = tree
+            .entries_in_range(from_version, ..)
+            .unwrap()
+            .map(|entry| (entry.key, entry))
+            .collect();
+        let mut new_entries: BTreeMap<_, _> = tree
+            .entries_in_range(to_version, ..)
+            .unwrap()
+            .map(|entry| (entry.key, entry))
+            .collect();
+
+        let mut changes = vec![];
+        for (key, previous) in old_entries {
+            match new_entries.remove(&key) {
+                None => changes.push(TreeEntryChange::Removed(previous)),
+                Some(current) if current != previous => {
+                    changes.push(TreeEntryChange::Updated { previous, current });
+                }
+                Some(_) => { /* entry is unchanged */ }
+            }
+        }
+        changes.extend(new_entries.into_values().map(TreeEntryChange::Inserted));
+        changes.sort_unstable_by_key(TreeEntryChange::key);
+        changes
+    }
+
+    fn create_tree() -> MerkleTree<PatchSet> {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+        let mut next_leaf_index = 1;
+        for version in 1..=5_u64 {
+            // Insert new entries and update some of the existing ones.
+            let mut entries: Vec<_> = (0..20_u64)
+                .map(|i| {
+                    let key = Key::from(i * 0x_0123_4567 + version * 0x_89ab);
+                    let entry = TreeEntry::new(key, next_leaf_index, ValueHash::repeat_byte(1));
+                    next_leaf_index += 1;
+                    entry
+                })
+                .collect();
+            let existing_entries: Vec<_> =
+                tree.entries_in_range(version - 1, ..).unwrap().collect();
+            entries.extend(
+                existing_entries
+                    .into_iter()
+                    .step_by(3)
+                    .map(|entry| entry.with_value(ValueHash::repeat_byte(version as u8 + 1))),
+            );
+            tree.extend(entries);
+        }
+        tree
+    }
+
+    #[test]
+    fn diff_for_empty_tree() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+
+        assert_eq!(tree.diff(0, 0).unwrap().count(), 0);
+        assert!(tree.diff(0, 1).is_err());
+        assert!(tree.diff(1, 0).is_err());
+    }
+
+    #[test]
+    fn diff_for_single_entry() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+        let entry = TreeEntry::new(Key::from(42), 1, ValueHash::repeat_byte(1));
+        tree.extend(vec![entry]);
+        let updated_entry = entry.with_value(ValueHash::repeat_byte(2));
+        tree.extend(vec![updated_entry]);
+
+        let diff: Vec<_> = tree.diff(0, 1).unwrap().collect();
+        assert_eq!(diff, [TreeEntryChange::Inserted(entry)]);
+        let diff: Vec<_> = tree.diff(1, 0).unwrap().collect();
+        assert_eq!(diff, [TreeEntryChange::Removed(entry)]);
+        let diff: Vec<_> = tree.diff(1, 2).unwrap().collect();
+        let expected_change = TreeEntryChange::Updated {
+            previous: entry,
+            current: updated_entry,
+        };
+        assert_eq!(diff, [expected_change]);
+        assert_eq!(tree.diff(2, 2).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn diff_with_leaf_replaced_by_internal_node() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        let first_entry = TreeEntry::new(Key::from(1), 1, ValueHash::repeat_byte(1));
+        tree.extend(vec![first_entry]);
+        // Both keys start with the same nibbles, so the leaf is pushed down the tree.
+        let second_entry = TreeEntry::new(Key::from(2), 2, ValueHash::repeat_byte(2));
+        let updated_entry = first_entry.with_value(ValueHash::repeat_byte(3));
+        tree.extend(vec![second_entry, updated_entry]);
+
+        let diff: Vec<_> = tree.diff(0, 1).unwrap().collect();
+        let expected_changes = [
+            TreeEntryChange::Updated {
+                previous: first_entry,
+                current: updated_entry,
+            },
+            TreeEntryChange::Inserted(second_entry),
+        ];
+        assert_eq!(diff, expected_changes);
+    }
+
+    #[test]
+    fn diff_matches_naive_implementation() {
+        let tree = create_tree();
+        for from_version in 0..=5 {
+            for to_version in 0..=5 {
+                let diff: Vec<_> = tree.diff(from_version, to_version).unwrap().collect();
+                let expected_diff = naive_diff(&tree, from_version, to_version);
+                assert_eq!(diff, expected_diff, "from={from_version}, to={to_version}");
+            }
+        }
+    }
+}
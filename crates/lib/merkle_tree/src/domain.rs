@@ -1,5 +1,14 @@
 //! Tying the Merkle tree implementation to the problem domain.
 
+use std::{
+    ops::RangeBounds,
+    panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
 use axon_types::{
     primitives::hasher::blake2::Blake2Hasher,
     proofs::{PrepareBasicCircuitsJob, StorageLogMetadata},
@@ -8,14 +17,17 @@ use axon_types::{
 };
 use axon_utils::b256_to_u256;
 use rayon::{ThreadPool, ThreadPoolBuilder};
+use tokio::sync::Semaphore;
 
 use crate::{
-    storage::{PatchSet, Patched, RocksDBWrapper},
+    metrics::{ReaderMethod, GENERAL_METRICS, READER_METRICS},
+    storage::{PatchSet, Patched, Prefetched, RocksDBWrapper, Storage},
     types::{
-        Key, Root, TreeEntry, TreeEntryWithProof, TreeInstruction, TreeLogEntry, ValueHash,
-        TREE_DEPTH,
+        Key, Root, TreeEntriesPage, TreeEntry, TreeEntryWithProof, TreeInstruction, TreeLogEntry,
+        TreeLogEntryWithProof, TreeMultiProof, ValueHash, TREE_DEPTH,
     },
-    BlockOutput, HashTree, MerkleTree, NoVersionError,
+    BlockOutput, BlockOutputWithProofs, ConsistencyError, ConsistencyVerifier, ExclusionProof,
+    HashTree, MerkleTree, NoVersionError, TreeSnapshot, TruncationError, TruncationReport,
 };
 
 /// Metadata for the current tree state.
@@ -39,26 +51,93 @@ pub struct TreeMetadata {
     pub state_diffs: Vec<StateDiffRecord>,
 }
 
+/// Error regenerating a witness for a past L1 batch using [`AxonTreeReader::witness()`].
+#[derive(Debug, thiserror::Error)]
+pub enum WitnessError {
+    /// Tree version required to regenerate the witness is missing (e.g., was pruned).
+    #[error(transparent)]
+    NoVersion(#[from] NoVersionError),
+    /// Replaying the provided storage logs has led to an unexpected root hash.
+    #[error(
+        "root hash after replaying storage logs ({actual:?}) differs from the root hash \
+         for L1 batch #{l1_batch_number} ({expected:?}); storage logs are probably incorrect"
+    )]
+    RootHashMismatch {
+        /// L1 batch for which the witness was requested.
+        l1_batch_number: L1BatchNumber,
+        /// Root hash stored in the tree.
+        expected: ValueHash,
+        /// Root hash after replaying the storage logs.
+        actual: ValueHash,
+    },
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum TreeMode {
     Lightweight,
     Full,
 }
 
+/// Policy for automatically flushing changes accumulated by an [`AxonTree`] in RAM to RocksDB.
+///
+/// A flush writes all accumulated changes together with the tree manifest in a single RocksDB
+/// write batch, so the persisted tree is consistent even if the process crashes between flushes.
+/// By default, no automatic flushes are performed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AutoFlushPolicy {
+    /// Flush changes once their approximate size in RAM (as returned by
+    /// [`AxonTree::approx_patch_size()`]) reaches this number of bytes.
+    pub max_patch_size: Option<usize>,
+    /// Flush changes once they span this number of L1 batches.
+    pub max_l1_batch_count: Option<usize>,
+}
+
+impl AutoFlushPolicy {
+    fn should_flush(&self, patch_size: usize, l1_batch_count: usize) -> bool {
+        let is_size_exceeded = self.max_patch_size.is_some_and(|max| patch_size >= max);
+        let is_count_exceeded = self
+            .max_l1_batch_count
+            .is_some_and(|max| l1_batch_count >= max);
+        is_size_exceeded || is_count_exceeded
+    }
+}
+
 /// Domain-specific wrapper of the Merkle tree.
 ///
 /// This wrapper will accumulate changes introduced by [`Self::process_l1_batch()`],
 /// [`Self::process_l1_batches()`] and [`Self::revert_logs()`] in RAM without saving them
 /// to RocksDB. The accumulated changes can be saved to RocksDB via [`Self::save()`]
 /// or discarded via [`Self::reset()`].
+///
+/// The tree is generic over the hasher; by default, it uses [`Blake2Hasher`]. The hasher is
+/// recorded in the tree database; opening a database with a different hasher will panic.
 #[derive(Debug)]
-pub struct AxonTree {
-    tree: MerkleTree<Patched<RocksDBWrapper>>,
+pub struct AxonTree<H = Blake2Hasher> {
+    tree: MerkleTree<Patched<RocksDBWrapper>, H>,
     thread_pool: Option<ThreadPool>,
     mode: TreeMode,
+    auto_flush: AutoFlushPolicy,
 }
 
 impl AxonTree {
+    /// Returns metadata based on `storage_logs` generated by the genesis L1 batch. This does not
+    /// create a persistent tree.
+    pub fn process_genesis_batch(storage_logs: &[TreeInstruction<StorageKey>]) -> BlockOutput {
+        Self::process_genesis_batch_with_hasher(storage_logs, Blake2Hasher)
+    }
+
+    /// Creates a tree with the full processing mode.
+    pub fn new(db: RocksDBWrapper) -> Self {
+        Self::with_hasher(db, Blake2Hasher)
+    }
+
+    /// Creates a tree with the lightweight processing mode.
+    pub fn new_lightweight(db: RocksDBWrapper) -> Self {
+        Self::lightweight_with_hasher(db, Blake2Hasher)
+    }
+}
+
+impl<H: HashTree + Clone> AxonTree<H> {
     fn create_thread_pool(thread_count: usize) -> ThreadPool {
         ThreadPoolBuilder::new()
             .thread_name(|idx| format!("new-merkle-tree-{idx}"))
@@ -67,9 +146,17 @@ impl AxonTree {
             .expect("failed initializing `rayon` thread pool")
     }
 
-    /// Returns metadata based on `storage_logs` generated by the genesis L1 batch. This does not
-    /// create a persistent tree.
-    pub fn process_genesis_batch(storage_logs: &[TreeInstruction<StorageKey>]) -> BlockOutput {
+    /// Returns metadata based on `storage_logs` generated by the genesis L1 batch using
+    /// the specified hasher. This does not create a persistent tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `storage_logs` contain [`TreeInstruction::Remove`]s.
+    pub fn process_genesis_batch_with_hasher(
+        storage_logs: &[TreeInstruction<StorageKey>],
+        hasher: H,
+    ) -> BlockOutput {
+        assert_no_removals(storage_logs);
         let kvs = Self::filter_write_instructions(storage_logs);
         tracing::info!(
             "Creating Merkle tree for genesis batch with {instr_count} writes",
@@ -81,7 +168,7 @@ impl AxonTree {
             .map(|instr| instr.map_key(StorageKey::hashed_key_u256))
             .collect();
 
-        let mut in_memory_tree = MerkleTree::new(PatchSet::default());
+        let mut in_memory_tree = MerkleTree::with_hasher(PatchSet::default(), hasher);
         let output = in_memory_tree.extend(kvs);
 
         tracing::info!(
@@ -92,29 +179,38 @@ impl AxonTree {
         output
     }
 
-    /// Creates a tree with the full processing mode.
-    pub fn new(db: RocksDBWrapper) -> Self {
-        Self::new_with_mode(db, TreeMode::Full)
+    /// Creates a tree with the full processing mode and the specified hasher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree in `db` was created with a different hasher.
+    pub fn with_hasher(db: RocksDBWrapper, hasher: H) -> Self {
+        Self::new_with_mode(db, hasher, TreeMode::Full)
     }
 
-    /// Creates a tree with the lightweight processing mode.
-    pub fn new_lightweight(db: RocksDBWrapper) -> Self {
-        Self::new_with_mode(db, TreeMode::Lightweight)
+    /// Creates a tree with the lightweight processing mode and the specified hasher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree in `db` was created with a different hasher.
+    pub fn lightweight_with_hasher(db: RocksDBWrapper, hasher: H) -> Self {
+        Self::new_with_mode(db, hasher, TreeMode::Lightweight)
     }
 
-    fn new_with_mode(db: RocksDBWrapper, mode: TreeMode) -> Self {
+    fn new_with_mode(db: RocksDBWrapper, hasher: H, mode: TreeMode) -> Self {
         Self {
-            tree: MerkleTree::new(Patched::new(db)),
+            tree: MerkleTree::with_hasher(Patched::new(db), hasher),
             thread_pool: None,
             mode,
+            auto_flush: AutoFlushPolicy::default(),
         }
     }
 
     /// Returns a readonly handle to the tree. The handle **does not** see uncommitted changes to
     /// the tree, only ones flushed to RocksDB.
-    pub fn reader(&self) -> AxonTreeReader {
+    pub fn reader(&self) -> AxonTreeReader<H> {
         let db = self.tree.db.inner().clone();
-        AxonTreeReader(MerkleTree::new(db))
+        AxonTreeReader(MerkleTree::with_hasher(db, self.tree.hasher.clone()))
     }
 
     /// Sets the chunk size for multi-get operations. The requested keys will be split
@@ -141,6 +237,18 @@ impl AxonTree {
         self.thread_pool = Some(Self::create_thread_pool(thread_count));
     }
 
+    /// Sets the policy for automatically flushing changes to RocksDB. The policy is checked after
+    /// processing each L1 batch. Flushed changes cannot be discarded via [`Self::reset()`];
+    /// [`Self::revert_logs()`] should be used instead.
+    pub fn set_auto_flush_policy(&mut self, policy: AutoFlushPolicy) {
+        self.auto_flush = policy;
+    }
+
+    /// Returns the approximate size of changes accumulated in RAM, in bytes.
+    pub fn approx_patch_size(&self) -> usize {
+        self.tree.db.approx_patch_size()
+    }
+
     /// Returns the current root hash of this tree.
     pub fn root_hash(&self) -> ValueHash {
         self.tree.latest_root_hash()
@@ -180,17 +288,201 @@ impl AxonTree {
             });
     }
 
+    /// Verifies consistency of the tree after processing the specified L1 batch. Unlike
+    /// [`Self::verify_consistency()`], verification is resumable: its progress is persisted
+    /// in RocksDB after each verified top-level subtree, and is cleared once verification
+    /// succeeds. The persisted progress is discarded if it was recorded for a different root hash
+    /// (e.g., if the tree was reverted and rebuilt in the meantime). The tree version is protected
+    /// from pruning while it is verified.
+    ///
+    /// Only changes flushed to RocksDB are verified. This method is only available for the tree
+    /// (as opposed to [`AxonTreeReader`]s) since it writes to RocksDB.
+    ///
+    /// See [`ConsistencyVerifier`] for more details.
+    ///
+    /// # Errors
+    ///
+    /// Returns up to `max_error_count` errors if the tree is inconsistent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_error_count` is zero.
+    pub fn verify_consistency_resumable(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        max_error_count: usize,
+    ) -> Result<(), Vec<ConsistencyError>> {
+        let version = u64::from(l1_batch_number.0);
+        let db = self.tree.db.inner();
+        let Some(_pin) = db.pin_version(version) else {
+            return Err(vec![ConsistencyError::MissingVersion(version)]);
+        };
+        let reader = self.reader();
+        let mut verifier = ConsistencyVerifier::new(&reader.0, version);
+        verifier.set_max_error_count(max_error_count);
+
+        let cursor = db.verification_cursor().unwrap_or_else(|err| {
+            tracing::warn!("Failed loading verification cursor; starting from scratch: {err}");
+            None
+        });
+        if let Some(cursor) = cursor.filter(|cursor| cursor.version() == version) {
+            tracing::info!(
+                "Resuming verification of tree version {version} with {} verified subtrees",
+                cursor.verified_subtree_count()
+            );
+            verifier.resume_from(cursor);
+        }
+        verifier.run(|cursor| db.save_verification_cursor(Some(cursor)))?;
+        db.save_verification_cursor(None);
+        Ok(())
+    }
+
     /// Processes an iterator of storage logs comprising a single L1 batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `storage_logs` contain [`TreeInstruction::Remove`]s; L1 batches never remove
+    /// tree entries.
     pub fn process_l1_batch(
         &mut self,
         storage_logs: &[TreeInstruction<StorageKey>],
     ) -> TreeMetadata {
-        match self.mode {
+        assert_no_removals(storage_logs);
+        let metadata = match self.mode {
             TreeMode::Full => self.process_l1_batch_full(storage_logs),
             TreeMode::Lightweight => self.process_l1_batch_lightweight(storage_logs),
+        };
+        Self::flush_if_required(&mut self.tree.db, self.auto_flush);
+        metadata
+    }
+
+    /// Processes multiple L1 batches, each comprising storage logs. This is more efficient than
+    /// calling [`Self::process_l1_batch()`] for each batch (e.g., when the tree catches up after
+    /// a downtime): tree nodes required for each batch are prefetched from RocksDB while
+    /// the previous batch is being processed.
+    ///
+    /// Returns metadata for each batch in the order of `l1_batches`; the metadata is identical
+    /// to one produced by processing batches sequentially via [`Self::process_l1_batch()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of `l1_batches` contains [`TreeInstruction::Remove`]s.
+    pub fn process_l1_batches(
+        &mut self,
+        l1_batches: &[Vec<TreeInstruction<StorageKey>>],
+    ) -> Vec<TreeMetadata> {
+        for storage_logs in l1_batches {
+            assert_no_removals(storage_logs);
+        }
+        let mut l1_batch_number = self.next_l1_batch_number();
+        tracing::info!(
+            "Extending Merkle tree with {batch_count} batches starting from #{l1_batch_number} \
+             in {mode:?} mode",
+            batch_count = l1_batches.len(),
+            mode = self.mode
+        );
+
+        match self.mode {
+            TreeMode::Full => {
+                let mut leaf_count = self.tree.latest_root().leaf_count();
+                let mut root_hash = self.tree.latest_root_hash();
+                let batches = l1_batches
+                    .iter()
+                    .map(|instructions| {
+                        let instructions = instructions
+                            .iter()
+                            .map(|instr| instr.map_key(StorageKey::hashed_key_u256));
+                        instructions.collect()
+                    })
+                    .collect();
+                let outputs = self.extend_pipelined(batches, |storage, instructions| {
+                    storage.extend_with_proofs(instructions)
+                });
+
+                let batches_and_outputs = l1_batches.iter().zip(outputs);
+                let metadata = batches_and_outputs.map(|(instructions, output)| {
+                    let metadata = self.full_metadata(
+                        l1_batch_number,
+                        leaf_count,
+                        root_hash,
+                        instructions,
+                        output,
+                    );
+                    leaf_count = metadata.rollup_last_leaf_index - 1;
+                    root_hash = metadata.root_hash;
+                    l1_batch_number += 1;
+                    metadata
+                });
+                metadata.collect()
+            }
+            TreeMode::Lightweight => {
+                let kvs_by_batch: Vec<_> = l1_batches
+                    .iter()
+                    .map(|instructions| Self::filter_write_instructions(instructions))
+                    .collect();
+                let batches = kvs_by_batch
+                    .iter()
+                    .map(|kvs| {
+                        let instructions = kvs.iter().map(|entry| {
+                            TreeInstruction::Write(entry.map_key(StorageKey::hashed_key_u256))
+                        });
+                        instructions.collect()
+                    })
+                    .collect();
+                let outputs = self.extend_pipelined(batches, |storage, instructions| {
+                    storage.extend_with_instructions(instructions)
+                });
+
+                let kvs_and_outputs = kvs_by_batch.into_iter().zip(outputs);
+                let metadata = kvs_and_outputs.map(|(kvs, output)| {
+                    let metadata = Self::lightweight_metadata(l1_batch_number, kvs, output);
+                    l1_batch_number += 1;
+                    metadata
+                });
+                metadata.collect()
+            }
+        }
+    }
+
+    fn extend_pipelined<R: Send>(
+        &mut self,
+        batches: Vec<Vec<TreeInstruction>>,
+        extend: impl Fn(
+                Storage<'_, Prefetched<'_, Patched<RocksDBWrapper>>>,
+                Vec<TreeInstruction>,
+            ) -> (R, PatchSet)
+            + Send,
+    ) -> Vec<R> {
+        let auto_flush = self.auto_flush;
+        let after_apply = move |db: &mut Patched<RocksDBWrapper>| {
+            Self::flush_if_required(db, auto_flush);
+        };
+        if let Some(thread_pool) = &self.thread_pool {
+            thread_pool.install(|| self.tree.extend_pipelined(batches, extend, after_apply))
+        } else {
+            self.tree.extend_pipelined(batches, extend, after_apply)
         }
     }
 
+    fn flush_if_required(db: &mut Patched<RocksDBWrapper>, policy: AutoFlushPolicy) {
+        let patch_size = db.approx_patch_size();
+        let l1_batch_count = db.patched_versions().len();
+        if policy.should_flush(patch_size, l1_batch_count) {
+            tracing::info!("Auto-flushing {l1_batch_count} L1 batches (~{patch_size}B) to RocksDB");
+            Self::flush(db);
+        } else {
+            GENERAL_METRICS.patch_size.set(patch_size as u64);
+        }
+    }
+
+    fn flush(db: &mut Patched<RocksDBWrapper>) {
+        let mut l1_batch_numbers = db.patched_versions();
+        l1_batch_numbers.sort_unstable();
+        tracing::info!("Flushing L1 batches #{l1_batch_numbers:?} to RocksDB");
+        db.flush();
+        GENERAL_METRICS.patch_size.set(0);
+    }
+
     fn process_l1_batch_full(
         &mut self,
         instructions: &[TreeInstruction<StorageKey>],
@@ -214,54 +506,29 @@ impl AxonTree {
         } else {
             self.tree.extend_with_proofs(instructions_with_hashed_keys)
         };
+        self.full_metadata(
+            l1_batch_number,
+            starting_leaf_count,
+            starting_root_hash,
+            instructions,
+            output,
+        )
+    }
 
-        let mut witness = PrepareBasicCircuitsJob::new(starting_leaf_count + 1);
-        witness.reserve(output.logs.len());
-        for (log, instruction) in output.logs.iter().zip(instructions) {
-            let empty_levels_end = TREE_DEPTH - log.merkle_path.len();
-            let empty_subtree_hashes =
-                (0..empty_levels_end).map(|i| Blake2Hasher.empty_subtree_hash(i));
-            let merkle_paths = log.merkle_path.iter().copied();
-            let merkle_paths = empty_subtree_hashes
-                .chain(merkle_paths)
-                .map(|hash| hash.0)
-                .collect();
-
-            let value_written = match instruction {
-                TreeInstruction::Write(entry) => entry.value.0,
-                TreeInstruction::Read(_) => [0_u8; 32],
-            };
-            let log = StorageLogMetadata {
-                root_hash: log.root_hash.0,
-                is_write: !log.base.is_read(),
-                first_write: matches!(log.base, TreeLogEntry::Inserted),
-                merkle_paths,
-                leaf_hashed_key: instruction.key().hashed_key_u256(),
-                leaf_enumeration_index: match instruction {
-                    TreeInstruction::Write(entry) => entry.leaf_index,
-                    TreeInstruction::Read(_) => match log.base {
-                        TreeLogEntry::Read { leaf_index, .. } => leaf_index,
-                        TreeLogEntry::ReadMissingKey => 0,
-                        _ => unreachable!(
-                            "Read instructions always transform to Read / ReadMissingKey log entries"
-                        ),
-                    },
-                },
-                value_written,
-                value_read: match log.base {
-                    TreeLogEntry::Updated { previous_value, .. } => {
-                        if previous_value.0 == value_written {
-                            // A no-op update that must be omitted from the produced `witness`.
-                            continue;
-                        }
-                        previous_value.0
-                    }
-                    TreeLogEntry::Read { value, .. } => value.0,
-                    TreeLogEntry::Inserted | TreeLogEntry::ReadMissingKey => [0_u8; 32],
-                },
-            };
-            witness.push_merkle_path(log);
-        }
+    fn full_metadata(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        starting_leaf_count: u64,
+        starting_root_hash: ValueHash,
+        instructions: &[TreeInstruction<StorageKey>],
+        output: BlockOutputWithProofs,
+    ) -> TreeMetadata {
+        let witness = create_witness(
+            &self.tree.hasher,
+            starting_leaf_count,
+            instructions,
+            &output.logs,
+        );
 
         let root_hash = output.root_hash().unwrap_or(starting_root_hash);
         let logs = output
@@ -272,7 +539,7 @@ impl AxonTree {
             .iter()
             .filter_map(|instruction| match instruction {
                 TreeInstruction::Write(entry) => Some(*entry),
-                TreeInstruction::Read(_) => None,
+                TreeInstruction::Read(_) | TreeInstruction::Remove(_) => None,
             });
         let (initial_writes, repeated_writes, state_diffs) = Self::extract_writes(logs, kvs);
 
@@ -345,6 +612,7 @@ impl AxonTree {
                     // Else we have a no-op update that must be omitted from `repeated_writes`.
                 }
                 TreeLogEntry::Read { .. } | TreeLogEntry::ReadMissingKey => {}
+                TreeLogEntry::Removed { .. } => unreachable!("removals are checked before"),
             }
         }
         state_diffs.sort_unstable_by_key(|rec| (rec.address, rec.key));
@@ -373,6 +641,14 @@ impl AxonTree {
         } else {
             self.tree.extend(kvs_with_derived_key.clone())
         };
+        Self::lightweight_metadata(l1_batch_number, kvs, output)
+    }
+
+    fn lightweight_metadata(
+        l1_batch_number: L1BatchNumber,
+        kvs: Vec<TreeEntry<StorageKey>>,
+        output: BlockOutput,
+    ) -> TreeMetadata {
         let (initial_writes, repeated_writes, state_diffs) =
             Self::extract_writes(output.logs.into_iter(), kvs.into_iter());
 
@@ -403,47 +679,137 @@ impl AxonTree {
             .iter()
             .filter_map(|instruction| match instruction {
                 TreeInstruction::Write(entry) => Some(*entry),
-                TreeInstruction::Read(_) => None,
+                TreeInstruction::Read(_) | TreeInstruction::Remove(_) => None,
             });
         kvs.collect()
     }
 
+    /// Reports what [`Self::revert_logs()`] would do for the specified L1 batch without changing
+    /// the tree. Like the revert itself, the report is based on the state of the tree
+    /// persisted in RocksDB; unsaved changes are ignored.
+    pub fn revert_logs_dry_run(&self, last_l1_batch_to_keep: L1BatchNumber) -> TruncationReport {
+        let retained_version_count = u64::from(last_l1_batch_to_keep.0 + 1);
+        self.reader()
+            .0
+            .truncate_recent_versions_dry_run(retained_version_count)
+    }
+
     /// Reverts the tree to a previous state.
     ///
     /// This method will overwrite all unsaved changes in the tree.
-    pub fn revert_logs(&mut self, last_l1_batch_to_keep: L1BatchNumber) {
-        self.tree.db.reset();
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version for `last_l1_batch_to_keep` was pruned. In this case,
+    /// the tree is not changed.
+    pub fn revert_logs(
+        &mut self,
+        last_l1_batch_to_keep: L1BatchNumber,
+    ) -> Result<(), TruncationError> {
         let retained_version_count = u64::from(last_l1_batch_to_keep.0 + 1);
+        self.reader().0.check_truncation(retained_version_count)?;
+        self.tree.db.reset();
         self.tree.truncate_recent_versions(retained_version_count);
+        Ok(())
     }
 
     /// Saves the accumulated changes in the tree to RocksDB.
     pub fn save(&mut self) {
-        let mut l1_batch_numbers = self.tree.db.patched_versions();
-        l1_batch_numbers.sort_unstable();
-        tracing::info!("Flushing L1 batches #{l1_batch_numbers:?} to RocksDB");
-        self.tree.db.flush();
+        Self::flush(&mut self.tree.db);
     }
 
     /// Resets the tree to the latest database state.
     pub fn reset(&mut self) {
         self.tree.db.reset();
+        GENERAL_METRICS.patch_size.set(0);
     }
 }
 
+fn assert_no_removals(storage_logs: &[TreeInstruction<StorageKey>]) {
+    let has_removals = storage_logs
+        .iter()
+        .any(|instruction| matches!(instruction, TreeInstruction::Remove(_)));
+    assert!(
+        !has_removals,
+        "`TreeInstruction::Remove` is not supported for L1 batches"
+    );
+}
+
+/// Creates a witness for an L1 batch from the logs produced by the tree in the full mode.
+fn create_witness(
+    hasher: &dyn HashTree,
+    starting_leaf_count: u64,
+    instructions: &[TreeInstruction<StorageKey>],
+    logs: &[TreeLogEntryWithProof],
+) -> PrepareBasicCircuitsJob {
+    let mut witness = PrepareBasicCircuitsJob::new(starting_leaf_count + 1);
+    witness.reserve(logs.len());
+    for (log, instruction) in logs.iter().zip(instructions) {
+        let empty_levels_end = TREE_DEPTH - log.merkle_path.len();
+        let empty_subtree_hashes = (0..empty_levels_end).map(|i| hasher.empty_subtree_hash(i));
+        let merkle_paths = log.merkle_path.iter().copied();
+        let merkle_paths = empty_subtree_hashes
+            .chain(merkle_paths)
+            .map(|hash| hash.0)
+            .collect();
+
+        let value_written = match instruction {
+            TreeInstruction::Write(entry) => entry.value.0,
+            TreeInstruction::Read(_) => [0_u8; 32],
+            TreeInstruction::Remove(_) => unreachable!("removals are checked before"),
+        };
+        let log = StorageLogMetadata {
+            root_hash: log.root_hash.0,
+            is_write: !log.base.is_read(),
+            first_write: matches!(log.base, TreeLogEntry::Inserted),
+            merkle_paths,
+            leaf_hashed_key: instruction.key().hashed_key_u256(),
+            leaf_enumeration_index: match instruction {
+                TreeInstruction::Write(entry) => entry.leaf_index,
+                TreeInstruction::Read(_) => match log.base {
+                    TreeLogEntry::Read { leaf_index, .. } => leaf_index,
+                    TreeLogEntry::ReadMissingKey => 0,
+                    _ => unreachable!(
+                        "Read instructions always transform to Read / ReadMissingKey log entries"
+                    ),
+                },
+                TreeInstruction::Remove(_) => unreachable!("removals are checked before"),
+            },
+            value_written,
+            value_read: match log.base {
+                TreeLogEntry::Updated { previous_value, .. } => {
+                    if previous_value.0 == value_written {
+                        // A no-op update that must be omitted from the produced `witness`.
+                        continue;
+                    }
+                    previous_value.0
+                }
+                TreeLogEntry::Read { value, .. } => value.0,
+                TreeLogEntry::Inserted | TreeLogEntry::ReadMissingKey => [0_u8; 32],
+                TreeLogEntry::Removed { .. } => unreachable!("removals are checked before"),
+            },
+        };
+        witness.push_merkle_path(log);
+    }
+    witness
+}
+
 /// Readonly handle to a [`AxonTree`].
 #[derive(Debug)]
-pub struct AxonTreeReader(MerkleTree<RocksDBWrapper>);
+pub struct AxonTreeReader<H = Blake2Hasher>(MerkleTree<RocksDBWrapper, H>);
 
 // While cloning `MerkleTree` is logically unsound, cloning a reader is reasonable since it is
 // readonly.
-impl Clone for AxonTreeReader {
+impl<H: HashTree + Clone> Clone for AxonTreeReader<H> {
     fn clone(&self) -> Self {
-        Self(MerkleTree::new(self.0.db.clone()))
+        Self(MerkleTree::with_hasher(
+            self.0.db.clone(),
+            self.0.hasher.clone(),
+        ))
     }
 }
 
-impl AxonTreeReader {
+impl<H: HashTree> AxonTreeReader<H> {
     /// Returns the current root hash of this tree.
     pub fn root_hash(&self) -> ValueHash {
         self.0.latest_root_hash()
@@ -463,6 +829,73 @@ impl AxonTreeReader {
         self.0.latest_root().leaf_count()
     }
 
+    /// Creates a read-only snapshot of the tree after processing the specified L1 batch.
+    /// Reads from the snapshot are not influenced by concurrent tree reverts, and the pruner
+    /// will not remove the corresponding tree version while the snapshot is alive.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn snapshot(
+        &self,
+        l1_batch_number: L1BatchNumber,
+    ) -> Result<TreeSnapshot<'_, H>, NoVersionError> {
+        let version = u64::from(l1_batch_number.0);
+        self.0.snapshot(version)
+    }
+
+    /// Regenerates the witness for the specified L1 batch from the stored tree versions
+    /// and the original `storage_logs` for the batch. This is useful if the batch was processed
+    /// in the lightweight mode, which doesn't produce witnesses. The tree is not changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version for the L1 batch or the preceding batch is missing
+    /// (e.g., was pruned), or if `storage_logs` don't correspond to the batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `storage_logs` contain [`TreeInstruction::Remove`]s.
+    pub fn witness(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        storage_logs: &[TreeInstruction<StorageKey>],
+    ) -> Result<PrepareBasicCircuitsJob, WitnessError> {
+        assert_no_removals(storage_logs);
+        let version = u64::from(l1_batch_number.0);
+        // Snapshots ensure that the used tree versions are not pruned while we're working.
+        let snapshot = self.0.snapshot(version)?;
+        let prev_snapshot = version
+            .checked_sub(1)
+            .map(|prev_version| self.0.snapshot(prev_version))
+            .transpose()?;
+        let (starting_leaf_count, starting_root_hash) = match &prev_snapshot {
+            Some(prev_snapshot) => (prev_snapshot.leaf_count(), prev_snapshot.root_hash()),
+            None => (0, self.0.hasher.empty_tree_hash()),
+        };
+
+        let instructions = storage_logs
+            .iter()
+            .map(|instr| instr.map_key(StorageKey::hashed_key_u256))
+            .collect();
+        let output = self.0.replay_with_proofs(version, instructions)?;
+        let expected = snapshot.root_hash();
+        let actual = output.root_hash().unwrap_or(starting_root_hash);
+        if actual != expected {
+            return Err(WitnessError::RootHashMismatch {
+                l1_batch_number,
+                expected,
+                actual,
+            });
+        }
+        Ok(create_witness(
+            &self.0.hasher,
+            starting_leaf_count,
+            storage_logs,
+            &output.logs,
+        ))
+    }
+
     /// Reads entries together with Merkle proofs with the specified keys from the tree. The entries
     /// are returned in the same order as requested.
     ///
@@ -477,4 +910,582 @@ impl AxonTreeReader {
         let version = u64::from(l1_batch_number.0);
         self.0.entries_with_proofs(version, keys)
     }
+
+    /// Creates a proof that the specified `key` is missing from the tree after processing
+    /// the specified L1 batch. Returns `Ok(None)` if the key is present in the tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn exclusion_proof(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        key: Key,
+    ) -> Result<Option<ExclusionProof>, NoVersionError> {
+        let version = u64::from(l1_batch_number.0);
+        self.0.exclusion_proof(version, key)
+    }
+
+    /// Reads entries with the specified keys from the tree together with a compact multi-proof
+    /// of their authenticity. Entries in the proof are sorted by key and deduplicated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn entries_with_multi_proof(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        keys: &[Key],
+    ) -> Result<TreeMultiProof, NoVersionError> {
+        let version = u64::from(l1_batch_number.0);
+        self.0.entries_with_multi_proof(version, keys)
+    }
+
+    /// Reads a page of non-empty entries with keys in the specified `range` from the tree
+    /// after processing the specified L1 batch. Entries are returned in the ascending key order,
+    /// optionally with Merkle proofs. See [`MerkleTree::entries_page()`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn entries_page(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        range: impl RangeBounds<Key>,
+        page_size: usize,
+    ) -> Result<TreeEntriesPage, NoVersionError> {
+        let version = u64::from(l1_batch_number.0);
+        self.0.entries_page(version, range, page_size)
+    }
+
+    /// Same as [`Self::entries_page()`], but also provides a Merkle proof for each returned entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub fn entries_page_with_proofs(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        range: impl RangeBounds<Key>,
+        page_size: usize,
+    ) -> Result<TreeEntriesPage<TreeEntryWithProof>, NoVersionError> {
+        let version = u64::from(l1_batch_number.0);
+        self.0.entries_page_with_proofs(version, range, page_size)
+    }
+}
+
+/// Async facade for [`AxonTreeReader`] intended to be used in async contexts (e.g., RPC servers).
+///
+/// All reads are executed on the blocking thread pool of the Tokio runtime. The number of
+/// concurrently executing reads is bounded; excess calls wait until one of the running calls
+/// completes. Calls are cancellation-safe: dropping the returned future before the read starts
+/// executing cancels it. (A read that has already started on the blocking thread pool cannot be
+/// interrupted, but its result is discarded.)
+///
+/// The wrapper is cheaply cloneable; clones share the concurrency limit.
+#[derive(Debug)]
+pub struct AsyncTreeReader<H = Blake2Hasher> {
+    inner: Arc<AxonTreeReader<H>>,
+    permits: Arc<Semaphore>,
+}
+
+impl<H> Clone for AsyncTreeReader<H> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            permits: Arc::clone(&self.permits),
+        }
+    }
+}
+
+impl<H: HashTree> AxonTreeReader<H> {
+    /// Converts this reader into an async facade that executes at most `max_concurrency` reads
+    /// at the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_concurrency` is zero.
+    pub fn into_async(self, max_concurrency: usize) -> AsyncTreeReader<H> {
+        assert!(max_concurrency > 0, "Max concurrency must be positive");
+        AsyncTreeReader {
+            inner: Arc::new(self),
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+        }
+    }
+}
+
+impl<H: HashTree + 'static> AsyncTreeReader<H> {
+    async fn run<T: Send + 'static>(
+        &self,
+        method: ReaderMethod,
+        action: impl FnOnce(&AxonTreeReader<H>) -> T + Send + 'static,
+    ) -> T {
+        let latency = READER_METRICS.latency[&method].start();
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+
+        // If the returned future is dropped, the flag is set by the guard, and the blocking task
+        // will not perform the read if it hasn't started yet.
+        let cancel_guard = CancelOnDrop(Arc::default());
+        let is_cancelled = Arc::clone(&cancel_guard.0);
+        let reader = Arc::clone(&self.inner);
+        let task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            if is_cancelled.load(Ordering::Relaxed) {
+                tracing::debug!("Tree reader call `{method:?}` was cancelled before starting");
+                return None;
+            }
+            Some(action(&reader))
+        });
+
+        let output = match task.await {
+            Ok(Some(output)) => output,
+            Ok(None) => unreachable!("blocking task is cancelled while the caller is alive"),
+            Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+            Err(err) => panic!("Tree reader task was terminated: {err}"),
+        };
+        drop(cancel_guard);
+        latency.observe();
+        output
+    }
+
+    /// Returns the current root hash of the tree.
+    pub async fn root_hash(&self) -> ValueHash {
+        self.run(ReaderMethod::RootHash, |reader| reader.root_hash())
+            .await
+    }
+
+    /// Returns the number of leaves in the tree.
+    pub async fn leaf_count(&self) -> u64 {
+        self.run(ReaderMethod::LeafCount, |reader| reader.leaf_count())
+            .await
+    }
+
+    /// Reads entries together with Merkle proofs with the specified keys from the tree. The entries
+    /// are returned in the same order as requested.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree version corresponding to the L1 batch is missing.
+    pub async fn entries_with_proofs(
+        &self,
+        l1_batch_number: L1BatchNumber,
+        keys: Vec<Key>,
+    ) -> Result<Vec<TreeEntryWithProof>, NoVersionError> {
+        self.run(ReaderMethod::EntriesWithProofs, move |reader| {
+            reader.entries_with_proofs(l1_batch_number, &keys)
+        })
+        .await
+    }
+}
+
+/// Sets the wrapped flag when dropped.
+#[derive(Debug)]
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use assert_matches::assert_matches;
+    use axon_types::{
+        primitives::hasher::{keccak::KeccakHasher, sha256::Sha256Hasher},
+        AccountTreeId, Address,
+    };
+    use axon_utils::b256_from_low_u64_be;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{MerkleTreePruner, VerificationCursor};
+
+    fn create_instructions() -> Vec<TreeInstruction<StorageKey>> {
+        (0..10_u64)
+            .map(|i| {
+                let address = Address::repeat_byte(1);
+                let key = StorageKey::new(AccountTreeId::new(address), b256_from_low_u64_be(i));
+                TreeInstruction::write(key, i + 1, ValueHash::repeat_byte(i as u8))
+            })
+            .collect()
+    }
+
+    fn create_tree(temp_dir: &TempDir) -> AxonTree {
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let mut tree = AxonTree::new(db);
+        tree.process_l1_batch(&create_instructions());
+        tree.save();
+        tree
+    }
+
+    #[test]
+    fn tree_with_custom_hasher() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let mut tree = AxonTree::with_hasher(db.clone(), KeccakHasher);
+        let metadata = tree.process_l1_batch(&create_instructions());
+        tree.save();
+
+        let genesis_output = AxonTree::process_genesis_batch(&create_instructions());
+        assert_ne!(metadata.root_hash, genesis_output.root_hash);
+        let genesis_output =
+            AxonTree::process_genesis_batch_with_hasher(&create_instructions(), KeccakHasher);
+        assert_eq!(metadata.root_hash, genesis_output.root_hash);
+
+        let reader = tree.reader();
+        assert_eq!(reader.root_hash(), metadata.root_hash);
+        let keys = [Key::from(1)];
+        let entries = reader.entries_with_proofs(L1BatchNumber(0), &keys).unwrap();
+        entries[0].verify(&KeccakHasher, metadata.root_hash);
+
+        drop(tree);
+        let tree = AxonTree::with_hasher(db, KeccakHasher);
+        assert_eq!(tree.root_hash(), metadata.root_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Mismatch between the provided tree hasher")]
+    fn opening_tree_with_mismatched_hasher() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let mut tree = AxonTree::with_hasher(db.clone(), Sha256Hasher);
+        tree.process_l1_batch(&create_instructions());
+        tree.save();
+        drop(tree);
+
+        AxonTree::new(db);
+    }
+
+    fn create_batches() -> Vec<Vec<TreeInstruction<StorageKey>>> {
+        let address = Address::repeat_byte(1);
+        let mut leaf_indices = HashMap::new();
+        let batches = (0..5_u64).map(|batch_idx| {
+            let instructions = (batch_idx * 10..batch_idx * 10 + 20).map(|i| {
+                let key = StorageKey::new(AccountTreeId::new(address), b256_from_low_u64_be(i));
+                if i % 7 == 0 {
+                    return TreeInstruction::Read(key);
+                }
+                let next_leaf_index = leaf_indices.len() as u64 + 1;
+                let leaf_index = *leaf_indices.entry(i).or_insert(next_leaf_index);
+                TreeInstruction::write(key, leaf_index, ValueHash::repeat_byte(batch_idx as u8))
+            });
+            instructions.collect()
+        });
+        batches.collect()
+    }
+
+    fn test_processing_multiple_batches(create_tree: fn(RocksDBWrapper) -> AxonTree) {
+        let batches = create_batches();
+        let temp_dir = TempDir::new().unwrap();
+        let mut tree = create_tree(RocksDBWrapper::new(temp_dir.path()));
+        let expected_metadata: Vec<_> = batches
+            .iter()
+            .map(|instructions| tree.process_l1_batch(instructions))
+            .collect();
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut tree = create_tree(RocksDBWrapper::new(temp_dir.path()));
+        tree.use_dedicated_thread_pool(2);
+        // Process the first batch separately so that the tree is non-empty.
+        let mut metadata = vec![tree.process_l1_batch(&batches[0])];
+        metadata.extend(tree.process_l1_batches(&batches[1..]));
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(5));
+
+        assert_eq!(metadata.len(), expected_metadata.len());
+        for (metadata, expected) in metadata.iter().zip(&expected_metadata) {
+            assert_eq!(metadata.root_hash, expected.root_hash);
+            assert_eq!(
+                metadata.rollup_last_leaf_index,
+                expected.rollup_last_leaf_index
+            );
+            let initial_writes = metadata.initial_writes.iter().map(|write| write.index);
+            let expected_initial_writes = expected.initial_writes.iter().map(|write| write.index);
+            assert!(initial_writes.eq(expected_initial_writes));
+            let repeated_writes = metadata.repeated_writes.iter().map(|write| write.index);
+            let expected_repeated_writes = expected.repeated_writes.iter().map(|write| write.index);
+            assert!(repeated_writes.eq(expected_repeated_writes));
+            assert_eq!(metadata.state_diffs.len(), expected.state_diffs.len());
+            assert_eq!(metadata.witness.is_some(), expected.witness.is_some());
+        }
+
+        tree.save();
+        tree.verify_consistency(L1BatchNumber(4));
+        assert!(tree.process_l1_batches(&[]).is_empty());
+    }
+
+    #[test]
+    fn processing_multiple_batches() {
+        test_processing_multiple_batches(AxonTree::new);
+    }
+
+    #[test]
+    fn processing_multiple_batches_in_lightweight_mode() {
+        test_processing_multiple_batches(AxonTree::new_lightweight);
+    }
+
+    #[test]
+    fn regenerating_witness_for_lightweight_tree() {
+        let batches = create_batches();
+        let temp_dir = TempDir::new().unwrap();
+        let mut tree = AxonTree::new(RocksDBWrapper::new(temp_dir.path()));
+        let expected_witnesses: Vec<_> = batches
+            .iter()
+            .map(|instructions| tree.process_l1_batch(instructions).witness.unwrap())
+            .collect();
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut tree = AxonTree::new_lightweight(RocksDBWrapper::new(temp_dir.path()));
+        tree.process_l1_batches(&batches);
+        tree.save();
+        let reader = tree.reader();
+        for (i, (instructions, expected)) in batches.iter().zip(&expected_witnesses).enumerate() {
+            let l1_batch_number = L1BatchNumber(i as u32);
+            let witness = reader.witness(l1_batch_number, instructions).unwrap();
+            assert_eq!(witness, *expected);
+        }
+        // Check that the tree is not changed.
+        assert_eq!(reader.next_l1_batch_number(), L1BatchNumber(5));
+        tree.verify_consistency(L1BatchNumber(4));
+
+        let err = reader.witness(L1BatchNumber(1), &batches[0]).unwrap_err();
+        assert_matches!(
+            err,
+            WitnessError::RootHashMismatch { l1_batch_number, .. }
+                if l1_batch_number == L1BatchNumber(1)
+        );
+        let err = reader.witness(L1BatchNumber(5), &batches[0]).unwrap_err();
+        assert_matches!(err, WitnessError::NoVersion(_));
+    }
+
+    #[test]
+    fn auto_flushing_by_l1_batch_count() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let mut tree = AxonTree::new(db.clone());
+        tree.set_auto_flush_policy(AutoFlushPolicy {
+            max_l1_batch_count: Some(2),
+            ..AutoFlushPolicy::default()
+        });
+        assert_eq!(tree.approx_patch_size(), 0);
+
+        let batches = create_batches();
+        tree.process_l1_batch(&batches[0]);
+        assert!(tree.approx_patch_size() > 0);
+        assert_eq!(tree.reader().next_l1_batch_number(), L1BatchNumber(0));
+        tree.process_l1_batch(&batches[1]);
+        assert_eq!(tree.approx_patch_size(), 0);
+        assert_eq!(tree.reader().next_l1_batch_number(), L1BatchNumber(2));
+
+        tree.process_l1_batches(&batches[2..]);
+        // Batches #2, #3 should be flushed, but #4 should not.
+        assert!(tree.approx_patch_size() > 0);
+        assert_eq!(tree.reader().next_l1_batch_number(), L1BatchNumber(4));
+        let root_hash = tree.root_hash();
+
+        // Emulate a crash; the tree should be restored to the last flushed state.
+        drop(tree);
+        let mut tree = AxonTree::new(db);
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(4));
+        tree.verify_consistency(L1BatchNumber(3));
+        tree.process_l1_batch(&batches[4]);
+        assert_eq!(tree.root_hash(), root_hash);
+    }
+
+    #[test]
+    fn auto_flushing_by_patch_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut tree = AxonTree::new(RocksDBWrapper::new(temp_dir.path()));
+        let batches = create_batches();
+        tree.process_l1_batch(&batches[0]);
+        let patch_size = tree.approx_patch_size();
+        assert!(patch_size > 0);
+        tree.process_l1_batch(&batches[1]);
+        assert!(tree.approx_patch_size() > patch_size);
+
+        tree.set_auto_flush_policy(AutoFlushPolicy {
+            max_patch_size: Some(1),
+            ..AutoFlushPolicy::default()
+        });
+        tree.process_l1_batch(&batches[2]);
+        assert_eq!(tree.approx_patch_size(), 0);
+        assert_eq!(tree.reader().next_l1_batch_number(), L1BatchNumber(3));
+
+        tree.revert_logs(L1BatchNumber(1)).unwrap();
+        assert_eq!(tree.approx_patch_size(), 0);
+        tree.process_l1_batch(&batches[2]);
+        assert_eq!(tree.approx_patch_size(), 0);
+        assert_eq!(tree.reader().next_l1_batch_number(), L1BatchNumber(3));
+    }
+
+    #[test]
+    fn reverting_tree_with_dry_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let mut tree = AxonTree::new(db.clone());
+        let batches = create_batches();
+        let metadata: Vec<_> = batches
+            .iter()
+            .map(|instructions| tree.process_l1_batch(instructions))
+            .collect();
+        tree.save();
+
+        let report = tree.revert_logs_dry_run(L1BatchNumber(2));
+        assert_eq!(report.retained_version_count, 3);
+        assert_eq!(report.root_hash, Some(metadata[2].root_hash));
+        assert_eq!(report.leaf_count + 1, metadata[2].rollup_last_leaf_index);
+        assert!(report.unreachable_node_count > 0);
+        assert!(!report.is_target_version_pruned);
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(5));
+
+        let (mut pruner, _handle) = MerkleTreePruner::new(db, 1);
+        pruner.run_once().unwrap();
+        let report = tree.revert_logs_dry_run(L1BatchNumber(1));
+        assert!(report.is_target_version_pruned);
+        assert_eq!(report.first_retained_version, Some(3));
+        tree.revert_logs(L1BatchNumber(1)).unwrap_err();
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(5));
+
+        tree.revert_logs(L1BatchNumber(3)).unwrap();
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(4));
+        assert_eq!(tree.root_hash(), metadata[3].root_hash);
+        tree.verify_consistency(L1BatchNumber(3));
+    }
+
+    #[test]
+    fn reverting_tree_to_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let mut tree = AxonTree::new(db.clone());
+        let metadata: Vec<_> = create_batches()
+            .iter()
+            .map(|instructions| tree.process_l1_batch(instructions))
+            .collect();
+        tree.save();
+
+        let (mut pruner, _handle) = MerkleTreePruner::new(db, 0);
+        pruner.set_checkpoint_interval(2);
+        pruner.run_once().unwrap();
+
+        let report = tree.revert_logs_dry_run(L1BatchNumber(2));
+        assert_eq!(report.first_retained_version, Some(4));
+        assert!(!report.is_target_version_pruned);
+        assert_eq!(report.root_hash, Some(metadata[2].root_hash));
+        let report = tree.revert_logs_dry_run(L1BatchNumber(1));
+        assert!(report.is_target_version_pruned);
+        tree.revert_logs(L1BatchNumber(1)).unwrap_err();
+
+        tree.revert_logs(L1BatchNumber(2)).unwrap();
+        assert_eq!(tree.next_l1_batch_number(), L1BatchNumber(3));
+        assert_eq!(tree.root_hash(), metadata[2].root_hash);
+        tree.verify_consistency(L1BatchNumber(2));
+    }
+
+    #[test]
+    fn resumable_consistency_verification() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut tree = AxonTree::new(RocksDBWrapper::new(temp_dir.path()));
+        tree.process_l1_batches(&create_batches());
+        tree.save();
+        let db = tree.tree.db.inner().clone();
+        let root_hash = tree.root_hash();
+
+        // Emulate interrupted verification of another tree version; it should be ignored.
+        let mut cursor = VerificationCursor {
+            version: 3,
+            root_hash: tree.tree.root_hash(3).unwrap(),
+            verified_subtrees: [None; 16],
+        };
+        cursor.verified_subtrees[0] = Some(1_000);
+        db.save_verification_cursor(Some(&cursor));
+
+        tree.verify_consistency_resumable(L1BatchNumber(4), 10)
+            .unwrap();
+        assert_eq!(db.verification_cursor().unwrap(), None);
+
+        // Emulate interrupted verification of the same version with a different root hash
+        // (e.g., before the tree was reverted); the cursor should be discarded.
+        db.save_verification_cursor(Some(&VerificationCursor {
+            version: 4,
+            root_hash: ValueHash::repeat_byte(1),
+            verified_subtrees: cursor.verified_subtrees,
+        }));
+        tree.verify_consistency_resumable(L1BatchNumber(4), 10)
+            .unwrap();
+        assert_eq!(db.verification_cursor().unwrap(), None);
+
+        db.save_verification_cursor(Some(&VerificationCursor {
+            version: 4,
+            root_hash,
+            verified_subtrees: cursor.verified_subtrees,
+        }));
+        let errors = tree
+            .verify_consistency_resumable(L1BatchNumber(4), 10)
+            .unwrap_err();
+        assert_matches!(
+            errors.as_slice(),
+            [ConsistencyError::LeafCountMismatch { .. }]
+        );
+        // The cursor should be retained after a failed verification.
+        let cursor = db.verification_cursor().unwrap().unwrap();
+        assert_eq!(cursor.version(), 4);
+        assert_eq!(cursor.root_hash(), root_hash);
+    }
+
+    #[tokio::test]
+    async fn async_reader_basics() {
+        let temp_dir = TempDir::new().unwrap();
+        let tree = create_tree(&temp_dir);
+        let reader = tree.reader();
+        let async_reader = reader.clone().into_async(2);
+
+        assert_eq!(async_reader.root_hash().await, reader.root_hash());
+        assert_eq!(async_reader.leaf_count().await, 10);
+
+        let keys = vec![Key::from(1), Key::MAX];
+        let entries = async_reader
+            .entries_with_proofs(L1BatchNumber(0), keys.clone())
+            .await
+            .unwrap();
+        let expected_entries = reader.entries_with_proofs(L1BatchNumber(0), &keys).unwrap();
+        assert_eq!(entries.len(), expected_entries.len());
+        for (entry, expected) in entries.iter().zip(&expected_entries) {
+            assert_eq!(entry.base, expected.base);
+            assert_eq!(entry.merkle_path, expected.merkle_path);
+        }
+
+        let err = async_reader
+            .entries_with_proofs(L1BatchNumber(1), keys)
+            .await
+            .unwrap_err();
+        assert_eq!(err.missing_version, 1);
+    }
+
+    #[tokio::test]
+    async fn async_reader_bounds_concurrency_and_supports_cancellation() {
+        let temp_dir = TempDir::new().unwrap();
+        let tree = create_tree(&temp_dir);
+        let async_reader = tree.reader().into_async(1);
+
+        // Occupy the only permit so that other calls have to wait.
+        let permit = Arc::clone(&async_reader.permits)
+            .acquire_owned()
+            .await
+            .unwrap();
+        let root_hash = async_reader.root_hash();
+        let timeout_result = tokio::time::timeout(Duration::from_millis(50), root_hash).await;
+        assert!(timeout_result.is_err());
+        // The cancelled call must not hold a permit.
+        assert_eq!(async_reader.permits.available_permits(), 0);
+        drop(permit);
+
+        assert_eq!(async_reader.permits.available_permits(), 1);
+        assert_eq!(async_reader.leaf_count().await, 10);
+        assert_eq!(async_reader.permits.available_permits(), 1);
+    }
 }
@@ -29,6 +29,19 @@ pub enum DeserializeErrorKind {
     /// Unknown tag in the tree manifest.
     #[error("unknown tag `{0}` in tree manifest")]
     UnknownTag(String),
+    /// Unsupported version of the proof wire format.
+    #[error("unsupported wire format version {0}")]
+    UnsupportedFormatVersion(u64),
+    /// Invalid tag byte (e.g., specifying the kind of a tree log entry).
+    #[error("invalid tag byte {0}")]
+    InvalidTag(u8),
+    /// Input has unexpected bytes after the end of the deserialized value.
+    #[error("{0} trailing byte(s) after the end of input")]
+    TrailingBytes(usize),
+    /// Snapshot chunk header is inconsistent with the chunk entries.
+    #[error("snapshot chunk header doesn't match entries: {0}")]
+    InconsistentSnapshotChunk(&'static str),
+
     /// Malformed tag in the tree manifest.
     #[error("malformed tag `{name}` in tree manifest: {err}")]
     MalformedTag {
@@ -73,6 +86,27 @@ pub enum ErrorContext {
     LeafIndex,
     /// Version of a child in an internal node.
     Version,
+
+    /// Version of the proof wire format.
+    FormatVersion,
+    /// Key of a tree entry.
+    EntryKey,
+    /// Value hash of a tree entry.
+    ValueHash,
+    /// Merkle path in a proof.
+    MerklePath,
+    /// Root hash of the tree in a proof.
+    RootHash,
+    /// Number of log entries in a block output.
+    LogCount,
+    /// Log entry with the specified index in a block output.
+    LogEntry(usize),
+    /// Progress cursor of a resumable consistency check.
+    VerificationCursor,
+    /// Number of entries in a snapshot chunk.
+    EntryCount,
+    /// Entry with the specified index in a snapshot chunk.
+    SnapshotEntry(usize),
 }
 
 impl fmt::Display for ErrorContext {
@@ -87,11 +121,21 @@ impl fmt::Display for ErrorContext {
             Self::LeafCount => formatter.write_str("number of leaf nodes"),
             Self::LeafIndex => formatter.write_str("leaf index"),
             Self::Version => formatter.write_str("version of a child"),
+            Self::FormatVersion => formatter.write_str("wire format version"),
+            Self::EntryKey => formatter.write_str("entry key"),
+            Self::ValueHash => formatter.write_str("value hash"),
+            Self::MerklePath => formatter.write_str("Merkle path"),
+            Self::RootHash => formatter.write_str("root hash"),
+            Self::LogCount => formatter.write_str("number of log entries"),
+            Self::LogEntry(idx) => write!(formatter, "log entry #{idx}"),
+            Self::VerificationCursor => formatter.write_str("consistency verification cursor"),
+            Self::EntryCount => formatter.write_str("number of snapshot entries"),
+            Self::SnapshotEntry(idx) => write!(formatter, "snapshot entry #{idx}"),
         }
     }
 }
 
-/// Error deserializing data from RocksDB.
+/// Error deserializing data from RocksDB or a proof in the binary wire format.
 #[derive(Debug)]
 pub struct DeserializeError {
     kind: DeserializeErrorKind,
@@ -1,10 +1,15 @@
 //! Getters for the Merkle tree.
 
+use std::ops::{Bound, RangeBounds};
+
 use crate::{
     hasher::HasherWithStats,
     recovery::MerkleTreeRecovery,
     storage::{LoadAncestorsResult, SortedKeys, WorkingPatchSet},
-    types::{Nibbles, Node, TreeEntry, TreeEntryWithProof},
+    types::{
+        ExclusionProof, Nibbles, Node, NodeKey, Root, TreeEntriesPage, TreeEntry,
+        TreeEntryWithProof, TreeMultiProof, TREE_DEPTH,
+    },
     Database, HashTree, Key, MerkleTree, NoVersionError, PruneDatabase, ValueHash,
 };
 
@@ -21,7 +26,8 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
         version: u64,
         leaf_keys: &[Key],
     ) -> Result<Vec<TreeEntry>, NoVersionError> {
-        load_and_transform_entries(&self.db, version, leaf_keys, extract_entry)
+        let depth = self.hasher.depth();
+        load_and_transform_entries(&self.db, version, depth, leaf_keys, extract_entry)
     }
 
     /// Reads entries together with Merkle proofs with the specified keys from the tree. The entries
@@ -35,43 +41,269 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
         version: u64,
         leaf_keys: &[Key],
     ) -> Result<Vec<TreeEntryWithProof>, NoVersionError> {
+        load_entries_with_proofs(&self.db, &self.hasher, version, leaf_keys)
+    }
+
+    /// Creates a proof that the specified `key` is missing from the tree at the specified
+    /// `version`. Returns `Ok(None)` if the key is present in the tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn exclusion_proof(
+        &self,
+        version: u64,
+        key: Key,
+    ) -> Result<Option<ExclusionProof>, NoVersionError> {
         let mut hasher = HasherWithStats::new(&self.hasher);
-        load_and_transform_entries(
+        let mut proofs = load_and_transform_entries(
             &self.db,
             version,
-            leaf_keys,
-            |patch_set, &leaf_key, longest_prefix| {
-                let (leaf, merkle_path) =
-                    patch_set.create_proof(&mut hasher, leaf_key, longest_prefix, 0);
-                let value = leaf
-                    .as_ref()
-                    .map_or_else(|| ValueHash::ZERO, |leaf| leaf.value_hash);
-                TreeEntry {
-                    key: leaf_key,
-                    value,
-                    leaf_index: leaf.map_or(0, |leaf| leaf.leaf_index),
-                }
-                .with_merkle_path(merkle_path.into_inner())
+            hasher.depth(),
+            &[key],
+            |patch_set, &key, longest_prefix| {
+                let neighbor = match patch_set.get(longest_prefix) {
+                    Some(Node::Leaf(leaf)) if leaf.full_key == key => return None,
+                    Some(Node::Leaf(leaf)) => Some(*leaf),
+                    _ => None,
+                };
+                // If there's a neighbor, we prove its inclusion; otherwise, we prove that
+                // the `key` is mapped to an empty subtree.
+                let proven_key = neighbor.map_or(key, |leaf| leaf.full_key);
+                let (_, merkle_path) =
+                    patch_set.create_proof(&mut hasher, proven_key, longest_prefix, 0);
+                Some(ExclusionProof {
+                    key,
+                    neighbor: neighbor.map(TreeEntry::from),
+                    merkle_path: merkle_path.into_inner(),
+                })
             },
-        )
+        )?;
+        Ok(proofs.pop().unwrap())
+        // ^ `unwrap()` is safe: we've requested a single key
+    }
+
+    /// Reads entries with the specified keys from the tree together with a compact multi-proof
+    /// of their authenticity. Unlike [`Self::entries_with_proofs()`], entries in the returned proof
+    /// are sorted by key, and duplicate keys are removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    pub fn entries_with_multi_proof(
+        &self,
+        version: u64,
+        leaf_keys: &[Key],
+    ) -> Result<TreeMultiProof, NoVersionError> {
+        let mut leaf_keys = leaf_keys.to_vec();
+        leaf_keys.sort_unstable();
+        leaf_keys.dedup();
+        let proofs = self.entries_with_proofs(version, &leaf_keys)?;
+        Ok(TreeMultiProof::new(&proofs, self.hasher.depth()))
+    }
+
+    /// Iterates over all non-empty entries with keys in the specified `range` at the specified
+    /// tree `version`. Entries are yielded in the ascending key order.
+    ///
+    /// The iterator is lazy: tree nodes are loaded from the database as the iteration progresses,
+    /// and subtrees not intersecting with the `range` are not loaded at all.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    pub fn entries_in_range(
+        &self,
+        version: u64,
+        range: impl RangeBounds<Key>,
+    ) -> Result<impl Iterator<Item = TreeEntry> + '_, NoVersionError> {
+        let root = load_root(&self.db, version)?;
+        Ok(EntriesInRange::new(
+            &self.db,
+            root,
+            range,
+            self.hasher.depth(),
+        ))
+    }
+
+    /// Reads a page of non-empty entries with keys in the specified `range` at the specified tree
+    /// `version`. The page contains at most `page_size` entries in the ascending key order.
+    /// If there are more entries in the `range`, the returned page contains a cursor
+    /// ([`TreeEntriesPage::next_key`]) that can be used as the start of the range
+    /// to load the next page.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    pub fn entries_page(
+        &self,
+        version: u64,
+        range: impl RangeBounds<Key>,
+        page_size: usize,
+    ) -> Result<TreeEntriesPage, NoVersionError> {
+        let mut entries: Vec<_> = self
+            .entries_in_range(version, range)?
+            .take(page_size.saturating_add(1))
+            .collect();
+        let next_key = if entries.len() > page_size {
+            entries.pop().map(|entry| entry.key)
+        } else {
+            None
+        };
+        Ok(TreeEntriesPage { entries, next_key })
+    }
+
+    /// Same as [`Self::entries_page()`], but also provides a Merkle proof for each returned entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    pub fn entries_page_with_proofs(
+        &self,
+        version: u64,
+        range: impl RangeBounds<Key>,
+        page_size: usize,
+    ) -> Result<TreeEntriesPage<TreeEntryWithProof>, NoVersionError> {
+        let page = self.entries_page(version, range, page_size)?;
+        let keys: Vec<_> = page.entries.iter().map(|entry| entry.key).collect();
+        Ok(TreeEntriesPage {
+            entries: self.entries_with_proofs(version, &keys)?,
+            next_key: page.next_key,
+        })
+    }
+}
+
+pub(crate) fn load_root(db: &impl Database, version: u64) -> Result<Root, NoVersionError> {
+    db.root(version).ok_or_else(|| {
+        let manifest = db.manifest().unwrap_or_default();
+        NoVersionError {
+            missing_version: version,
+            version_count: manifest.version_count,
+        }
+    })
+}
+
+/// Iterator over tree entries in a key range, traversing the tree depth-first.
+#[derive(Debug)]
+pub(crate) struct EntriesInRange<'a, DB> {
+    db: &'a DB,
+    range: (Bound<Key>, Bound<Key>),
+    /// Depth of the tree; used to convert node nibbles to key ranges.
+    depth: usize,
+    /// Nodes pending traversal. The next node to visit is on top of the stack.
+    pending_nodes: Vec<PendingNode>,
+}
+
+#[derive(Debug)]
+enum PendingNode {
+    Loaded(Nibbles, Node),
+    Ref(NodeKey, bool),
+}
+
+impl<'a, DB: Database> EntriesInRange<'a, DB> {
+    fn new(db: &'a DB, root: Root, range: impl RangeBounds<Key>, depth: usize) -> Self {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        let pending_nodes = match root {
+            Root::Empty => vec![],
+            Root::Filled { node, .. } => vec![PendingNode::Loaded(Nibbles::EMPTY, node)],
+        };
+        Self {
+            db,
+            range,
+            depth,
+            pending_nodes,
+        }
+    }
+
+    /// Creates an iterator over all entries in the subtree rooted at `node` with the specified
+    /// `nibbles`.
+    pub(crate) fn for_subtree(
+        db: &'a DB,
+        nibbles: Nibbles,
+        node: Option<Node>,
+        depth: usize,
+    ) -> Self {
+        Self {
+            db,
+            range: (Bound::Unbounded, Bound::Unbounded),
+            depth,
+            pending_nodes: node
+                .map(|node| PendingNode::Loaded(nibbles, node))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Checks whether the subtree with the specified `nibbles` may contain keys in the range.
+    fn intersects_range(range: &(Bound<Key>, Bound<Key>), nibbles: &Nibbles, depth: usize) -> bool {
+        // Nibbles are aligned to the most significant key bit; see `align_key()`.
+        let alignment = TREE_DEPTH - depth;
+        let min_key = Key::from_be_bytes(*nibbles.bytes()) >> alignment;
+        let max_key = min_key | (Key::MAX >> (4 * nibbles.nibble_count() + alignment));
+        let is_after_start = match range.0 {
+            Bound::Included(start) => max_key >= start,
+            Bound::Excluded(start) => max_key > start,
+            Bound::Unbounded => true,
+        };
+        let is_before_end = match range.1 {
+            Bound::Included(end) => min_key <= end,
+            Bound::Excluded(end) => min_key < end,
+            Bound::Unbounded => true,
+        };
+        is_after_start && is_before_end
+    }
+}
+
+impl<DB: Database> Iterator for EntriesInRange<'_, DB> {
+    type Item = TreeEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(pending_node) = self.pending_nodes.pop() {
+            let (nibbles, node) = match pending_node {
+                PendingNode::Loaded(nibbles, node) => (nibbles, node),
+                PendingNode::Ref(node_key, is_leaf) => {
+                    let node = self.db.tree_node(&node_key, is_leaf).unwrap();
+                    // ^ `unwrap()` is safe: all requested nodes are referenced by their parents
+                    (node_key.nibbles, node)
+                }
+            };
+
+            match node {
+                Node::Leaf(leaf) => {
+                    if self.range.contains(&leaf.full_key) {
+                        return Some(leaf.into());
+                    }
+                }
+                Node::Internal(node) => {
+                    let range = &self.range;
+                    let children = node.children().filter_map(|(nibble, child_ref)| {
+                        let child_nibbles = nibbles.push(nibble)?;
+                        Self::intersects_range(range, &child_nibbles, self.depth).then(|| {
+                            let child_key = child_nibbles.with_version(child_ref.version);
+                            PendingNode::Ref(child_key, child_ref.is_leaf)
+                        })
+                    });
+                    // Children are pushed in the reverse order, so that the child with
+                    // the smallest nibble is visited first.
+                    let children: Vec<_> = children.collect();
+                    self.pending_nodes.extend(children.into_iter().rev());
+                }
+            }
+        }
+        None
     }
 }
 
 fn load_and_transform_entries<T>(
     db: &impl Database,
     version: u64,
+    depth: usize,
     leaf_keys: &[Key],
     mut transform: impl FnMut(&mut WorkingPatchSet, &Key, &Nibbles) -> T,
 ) -> Result<Vec<T>, NoVersionError> {
-    let root = db.root(version).ok_or_else(|| {
-        let manifest = db.manifest().unwrap_or_default();
-        NoVersionError {
-            missing_version: version,
-            version_count: manifest.version_count,
-        }
-    })?;
+    let root = load_root(db, version)?;
     let sorted_keys = SortedKeys::new(leaf_keys.iter().copied());
-    let mut patch_set = WorkingPatchSet::new(version, root);
+    let mut patch_set = WorkingPatchSet::new(version, root, depth);
     let LoadAncestorsResult {
         longest_prefixes, ..
     } = patch_set.load_ancestors(&sorted_keys, db);
@@ -83,6 +315,34 @@ fn load_and_transform_entries<T>(
         .collect())
 }
 
+fn load_entries_with_proofs(
+    db: &impl Database,
+    hasher: &dyn HashTree,
+    version: u64,
+    leaf_keys: &[Key],
+) -> Result<Vec<TreeEntryWithProof>, NoVersionError> {
+    let mut hasher = HasherWithStats::new(hasher);
+    load_and_transform_entries(
+        db,
+        version,
+        hasher.depth(),
+        leaf_keys,
+        |patch_set, &leaf_key, longest_prefix| {
+            let (leaf, merkle_path) =
+                patch_set.create_proof(&mut hasher, leaf_key, longest_prefix, 0);
+            let value = leaf
+                .as_ref()
+                .map_or_else(|| ValueHash::ZERO, |leaf| leaf.value_hash);
+            TreeEntry {
+                key: leaf_key,
+                value,
+                leaf_index: leaf.map_or(0, |leaf| leaf.leaf_index),
+            }
+            .with_merkle_path(merkle_path.into_inner())
+        },
+    )
+}
+
 fn extract_entry(
     patch_set: &mut WorkingPatchSet,
     leaf_key: &Key,
@@ -101,12 +361,24 @@ impl<DB: PruneDatabase, H: HashTree> MerkleTreeRecovery<DB, H> {
     /// returned entry will be [empty](TreeEntry::is_empty()).
     #[allow(clippy::missing_panics_doc)]
     pub fn entries(&self, leaf_keys: &[Key]) -> Vec<TreeEntry> {
-        load_and_transform_entries(&self.db, self.recovered_version(), leaf_keys, extract_entry)
+        let version = self.recovered_version();
+        let depth = self.hasher.depth();
+        load_and_transform_entries(&self.db, version, depth, leaf_keys, extract_entry)
             .unwrap_or_else(|_| {
                 // If there's no recovered version, the recovered tree is empty yet.
                 leaf_keys.iter().map(|key| TreeEntry::empty(*key)).collect()
             })
     }
+
+    /// Reads the last recovered entry together with its Merkle path. Since recovery proceeds
+    /// in the increasing key order, only hashes to the left of the entry in the path are final;
+    /// this is sufficient to start a [`TreeRangeDigest`](crate::TreeRangeDigest) from the entry.
+    pub(crate) fn last_entry_with_proof(&self) -> Option<TreeEntryWithProof> {
+        let key = self.last_processed_key()?;
+        let version = self.recovered_version();
+        let entries = load_entries_with_proofs(&self.db, &self.hasher, version, &[key]).ok()?;
+        entries.into_iter().next()
+    }
 }
 
 #[cfg(test)]
@@ -149,4 +421,230 @@ mod tests {
         assert!(entries[1].base.is_empty());
         entries[1].verify(&tree.hasher, output.root_hash);
     }
+
+    #[test]
+    fn multi_proof_in_empty_tree() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+        let missing_keys = [Key::from(123), Key::from(456)];
+
+        let proof = tree.entries_with_multi_proof(0, &missing_keys).unwrap();
+        assert_eq!(proof.entries.len(), 2);
+        assert!(proof.entries.iter().all(TreeEntry::is_empty));
+        assert!(proof.hashes.is_empty());
+        proof.verify(&tree.hasher, tree.hasher.empty_tree_hash());
+    }
+
+    #[test]
+    fn multi_proof_deduplicates_hashes() {
+        let (tree, entries) = create_tree_with_entries(100);
+        let root_hash = tree.root_hash(0).unwrap();
+        let mut keys: Vec<_> = entries.iter().map(|entry| entry.key).collect();
+        keys.push(Key::from(123)); // missing key
+        keys.push(keys[0]); // duplicate key
+        keys.reverse();
+
+        let proof = tree.entries_with_multi_proof(0, &keys).unwrap();
+        assert_eq!(proof.entries.len(), keys.len() - 1);
+        assert!(proof.entries.windows(2).all(|w| w[0].key < w[1].key));
+        proof.verify(&tree.hasher, root_hash);
+
+        let proofs = tree.entries_with_proofs(0, &keys).unwrap();
+        let hash_count: usize = proofs.iter().map(|proof| proof.merkle_path.len()).sum();
+        assert!(
+            proof.hashes.len() * 3 < hash_count,
+            "{} vs {hash_count}",
+            proof.hashes.len()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Root hash mismatch")]
+    fn multi_proof_with_tampered_entry() {
+        let (tree, entries) = create_tree_with_entries(20);
+        let root_hash = tree.root_hash(0).unwrap();
+        let keys: Vec<_> = entries.iter().map(|entry| entry.key).collect();
+
+        let mut proof = tree.entries_with_multi_proof(0, &keys[..5]).unwrap();
+        proof.entries[2].value = ValueHash::repeat_byte(0xff);
+        proof.verify(&tree.hasher, root_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Redundant hashes in the proof")]
+    fn multi_proof_with_redundant_hash() {
+        let (tree, entries) = create_tree_with_entries(20);
+        let root_hash = tree.root_hash(0).unwrap();
+
+        let mut proof = tree.entries_with_multi_proof(0, &[entries[3].key]).unwrap();
+        proof.hashes.push(ValueHash::ZERO);
+        proof.verify(&tree.hasher, root_hash);
+    }
+
+    #[test]
+    fn exclusion_proofs_in_small_trees() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+        let missing_key = Key::from(123);
+        let proof = tree.exclusion_proof(0, missing_key).unwrap().unwrap();
+        assert_eq!(proof.neighbor, None);
+        assert!(proof.merkle_path.is_empty());
+        proof.verify(&tree.hasher, tree.hasher.empty_tree_hash());
+
+        let key = Key::from(987_654);
+        let entry = TreeEntry::new(key, 1, ValueHash::repeat_byte(1));
+        let output = tree.extend(vec![entry]);
+        assert!(tree.exclusion_proof(1, key).unwrap().is_none());
+        let proof = tree.exclusion_proof(1, missing_key).unwrap().unwrap();
+        assert_eq!(proof.neighbor, Some(entry));
+        assert!(proof.merkle_path.is_empty());
+        proof.verify(&tree.hasher, output.root_hash);
+    }
+
+    #[test]
+    fn exclusion_proofs_with_neighbors_and_empty_subtrees() {
+        let (tree, entries) = create_tree_with_entries(100);
+        let root_hash = tree.root_hash(0).unwrap();
+
+        for entry in &entries {
+            assert!(tree.exclusion_proof(0, entry.key).unwrap().is_none());
+
+            // This key shares a long prefix with `entry`, so its path ends at `entry`.
+            let missing_key = entry.key ^ Key::from(1);
+            let proof = tree.exclusion_proof(0, missing_key).unwrap().unwrap();
+            assert_eq!(proof.neighbor, Some(*entry));
+            proof.verify(&tree.hasher, root_hash);
+        }
+
+        // This key lands in an empty subtree: there are no keys starting with the `0x00` byte.
+        let missing_key = Key::from(123);
+        let proof = tree.exclusion_proof(0, missing_key).unwrap().unwrap();
+        assert_eq!(proof.neighbor, None);
+        proof.verify(&tree.hasher, root_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Neighbor leaf is placed too low in the tree")]
+    fn exclusion_proof_with_far_neighbor() {
+        let (tree, entries) = create_tree_with_entries(100);
+        let root_hash = tree.root_hash(0).unwrap();
+
+        let mut proof = tree
+            .exclusion_proof(0, entries[0].key ^ Key::from(1))
+            .unwrap()
+            .unwrap();
+        // The neighbor cannot prove exclusion of a key from another subtree.
+        proof.key = entries[50].key;
+        proof.verify(&tree.hasher, root_hash);
+    }
+
+    fn create_tree_with_entries(entry_count: u64) -> (MerkleTree<PatchSet>, Vec<TreeEntry>) {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        // Keys are spread evenly across the key space; they are sorted by construction.
+        let key_step = Key::MAX / Key::from(entry_count + 1);
+        let entries: Vec<_> = (1..=entry_count)
+            .map(|i| TreeEntry::new(Key::from(i) * key_step, i, ValueHash::repeat_byte(i as u8)))
+            .collect();
+        tree.extend(entries.clone());
+        (tree, entries)
+    }
+
+    #[test]
+    fn entries_in_range_for_empty_tree() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+
+        assert_eq!(tree.entries_in_range(0, ..).unwrap().count(), 0);
+        assert!(tree.entries_in_range(1, ..).is_err());
+        let page = tree.entries_page(0, .., 10).unwrap();
+        assert!(page.entries.is_empty());
+        assert_eq!(page.next_key, None);
+    }
+
+    #[test]
+    fn entries_in_range_for_single_node_tree() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        let key = Key::from(987_654);
+        tree.extend(vec![TreeEntry::new(key, 1, ValueHash::repeat_byte(1))]);
+
+        let entries: Vec<_> = tree.entries_in_range(0, ..).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, key);
+        assert_eq!(tree.entries_in_range(0, key..).unwrap().count(), 1);
+        assert_eq!(tree.entries_in_range(0, ..key).unwrap().count(), 0);
+        assert_eq!(tree.entries_in_range(0, ..=key).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn entries_in_range_are_sorted_and_bounded() {
+        let (tree, entries) = create_tree_with_entries(100);
+
+        let all_entries: Vec<_> = tree.entries_in_range(0, ..).unwrap().collect();
+        assert_eq!(all_entries, entries);
+
+        let (start, end) = (entries[10].key, entries[50].key);
+        let range_entries: Vec<_> = tree.entries_in_range(0, start..end).unwrap().collect();
+        assert_eq!(range_entries, entries[10..50]);
+
+        let range_entries: Vec<_> = tree
+            .entries_in_range(0, (start + Key::from(1))..=end)
+            .unwrap()
+            .collect();
+        assert_eq!(range_entries, entries[11..=50]);
+    }
+
+    #[test]
+    fn entries_in_range_at_past_version() {
+        let (mut tree, entries) = create_tree_with_entries(50);
+        let new_key = entries[0].key + Key::from(1);
+        let new_entry = TreeEntry::new(new_key, 51, ValueHash::repeat_byte(0xff));
+        let updated_entry = entries[1].with_value(ValueHash::repeat_byte(0xfe));
+        tree.extend(vec![new_entry, updated_entry]);
+
+        let old_entries: Vec<_> = tree.entries_in_range(0, ..).unwrap().collect();
+        assert_eq!(old_entries, entries);
+
+        let new_entries: Vec<_> = tree.entries_in_range(1, ..).unwrap().collect();
+        assert_eq!(new_entries.len(), entries.len() + 1);
+        assert_eq!(new_entries[1], new_entry);
+        assert_eq!(new_entries[2], updated_entry);
+    }
+
+    #[test]
+    fn paginating_entries() {
+        let (tree, entries) = create_tree_with_entries(100);
+
+        let mut start = Key::ZERO;
+        let mut loaded_entries = vec![];
+        loop {
+            let page = tree.entries_page(0, start.., 7).unwrap();
+            assert!(page.entries.len() <= 7);
+            loaded_entries.extend(page.entries);
+            match page.next_key {
+                Some(next_key) => start = next_key,
+                None => break,
+            }
+        }
+        assert_eq!(loaded_entries, entries);
+
+        let page = tree.entries_page(0, .., 100).unwrap();
+        assert_eq!(page.entries.len(), 100);
+        assert_eq!(page.next_key, None);
+    }
+
+    #[test]
+    fn paginating_entries_with_proofs() {
+        let (tree, entries) = create_tree_with_entries(30);
+        let root_hash = tree.root_hash(0).unwrap();
+
+        let page = tree
+            .entries_page_with_proofs(0, entries[5].key.., 10)
+            .unwrap();
+        assert_eq!(page.entries.len(), 10);
+        assert_eq!(page.next_key, Some(entries[15].key));
+        for (entry, expected) in page.entries.iter().zip(&entries[5..15]) {
+            assert_eq!(entry.base, *expected);
+            entry.verify(&tree.hasher, root_hash);
+        }
+    }
 }
@@ -7,7 +7,9 @@ use once_cell::sync::Lazy;
 mod nodes;
 mod proofs;
 
-use axon_types::primitives::hasher::{blake2::Blake2Hasher, Hasher};
+use axon_types::primitives::hasher::{
+    blake2::Blake2Hasher, keccak::KeccakHasher, sha256::Sha256Hasher, Hasher,
+};
 
 pub(crate) use self::nodes::{InternalNodeCache, MerklePath};
 pub use self::proofs::TreeRangeDigest;
@@ -31,10 +33,17 @@ pub trait HashTree: Send + Sync {
     /// are encouraged to cache the returned values.
     fn empty_subtree_hash(&self, depth: usize) -> ValueHash;
 
+    /// Returns the depth of the tree (i.e., the number of bits in tree keys). The default
+    /// implementation returns 256, which corresponds to full-size keys. Smaller depths can be
+    /// configured using [`WithDepth`].
+    fn depth(&self) -> usize {
+        TREE_DEPTH
+    }
+
     /// Returns the hash of the empty tree. The default implementation uses
-    /// [`Self::empty_subtree_hash()`].
+    /// [`Self::empty_subtree_hash()`] and [`Self::depth()`].
     fn empty_tree_hash(&self) -> ValueHash {
-        self.empty_subtree_hash(TREE_DEPTH)
+        self.empty_subtree_hash(self.depth())
     }
 }
 
@@ -54,20 +63,73 @@ impl<H: HashTree + ?Sized> HashTree for &H {
     fn empty_subtree_hash(&self, depth: usize) -> ValueHash {
         (**self).empty_subtree_hash(depth)
     }
+
+    fn depth(&self) -> usize {
+        (**self).depth()
+    }
+}
+
+/// [`HashTree`] wrapper changing the tree depth. This allows using a tree with keys shorter
+/// than 256 bits (e.g., 64-bit or 160-bit keys), which results in shorter Merkle proofs.
+///
+/// In a tree with depth `d`, keys must be less than `2^d`; the most significant key bit
+/// corresponds to the root level of the tree. The depth is persisted in the tree tags, so a tree
+/// must be always opened with the depth it was created with.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WithDepth<H> {
+    inner: H,
+    depth: usize,
+}
+
+impl<H: HashTree> WithDepth<H> {
+    /// Wraps the provided hasher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `depth` is zero, is not divisible by 8 or exceeds 256.
+    pub fn new(inner: H, depth: usize) -> Self {
+        assert!(
+            depth > 0 && depth % 8 == 0 && depth <= TREE_DEPTH,
+            "Invalid tree depth {depth}; must be a positive multiple of 8 not exceeding {TREE_DEPTH}"
+        );
+        Self { inner, depth }
+    }
+}
+
+impl<H: HashTree> HashTree for WithDepth<H> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn hash_leaf(&self, value_hash: &ValueHash, leaf_index: u64) -> ValueHash {
+        self.inner.hash_leaf(value_hash, leaf_index)
+    }
+
+    fn hash_branch(&self, lhs: &ValueHash, rhs: &ValueHash) -> ValueHash {
+        self.inner.hash_branch(lhs, rhs)
+    }
+
+    fn empty_subtree_hash(&self, depth: usize) -> ValueHash {
+        self.inner.empty_subtree_hash(depth)
+    }
+
+    fn depth(&self) -> usize {
+        self.depth
+    }
 }
 
 impl dyn HashTree + '_ {
-    /// Extends the provided `path` to length `TREE_DEPTH`.
+    /// Extends the provided `path` to length [`HashTree::depth()`].
     fn extend_merkle_path<'a>(
         &'a self,
         path: &'a [ValueHash],
     ) -> impl Iterator<Item = ValueHash> + 'a {
-        let empty_hash_count = TREE_DEPTH - path.len();
+        let empty_hash_count = self.depth() - path.len();
         let empty_hashes = (0..empty_hash_count).map(|depth| self.empty_subtree_hash(depth));
         empty_hashes.chain(path.iter().copied())
     }
 
-    fn fold_merkle_path(&self, path: &[ValueHash], entry: TreeEntry) -> ValueHash {
+    pub(crate) fn fold_merkle_path(&self, path: &[ValueHash], entry: TreeEntry) -> ValueHash {
         let mut hash = self.hash_leaf(&entry.value, entry.leaf_index);
         let full_path = self.extend_merkle_path(path);
         for (depth, adjacent_hash) in full_path.enumerate() {
@@ -114,34 +176,49 @@ impl HashTree for () {
     }
 }
 
-impl HashTree for Blake2Hasher {
-    fn name(&self) -> &'static str {
-        "blake2s256"
-    }
+/// Implements [`HashTree`] for a [`Hasher`] producing 32-byte hashes. Leaves are hashed
+/// as `hash(u64::to_be_bytes(leaf_index) ++ value_hash)`; the empty leaf hash
+/// is `hash([0_u8; 40])`.
+macro_rules! impl_hash_tree {
+    ($hasher:ty, $name:tt) => {
+        impl HashTree for $hasher {
+            fn name(&self) -> &'static str {
+                $name
+            }
 
-    fn hash_leaf(&self, value_hash: &ValueHash, leaf_index: u64) -> ValueHash {
-        let mut bytes = [0_u8; 40];
-        bytes[..8].copy_from_slice(&leaf_index.to_be_bytes());
-        bytes[8..].copy_from_slice(value_hash.as_ref());
-        self.hash_bytes(&bytes)
-    }
+            fn hash_leaf(&self, value_hash: &ValueHash, leaf_index: u64) -> ValueHash {
+                let mut bytes = [0_u8; 40];
+                bytes[..8].copy_from_slice(&leaf_index.to_be_bytes());
+                bytes[8..].copy_from_slice(value_hash.as_ref());
+                self.hash_bytes(&bytes)
+            }
 
-    /// Compresses the hashes of 2 children in a branch node.
-    fn hash_branch(&self, lhs: &ValueHash, rhs: &ValueHash) -> ValueHash {
-        self.compress(lhs, rhs)
-    }
+            /// Compresses the hashes of 2 children in a branch node.
+            fn hash_branch(&self, lhs: &ValueHash, rhs: &ValueHash) -> ValueHash {
+                self.compress(lhs, rhs)
+            }
 
-    /// Returns the hash of an empty subtree with the given depth.
-    fn empty_subtree_hash(&self, depth: usize) -> ValueHash {
-        static EMPTY_TREE_HASHES: Lazy<Vec<ValueHash>> = Lazy::new(compute_empty_tree_hashes);
-        EMPTY_TREE_HASHES[depth]
-    }
+            /// Returns the hash of an empty subtree with the given depth.
+            fn empty_subtree_hash(&self, depth: usize) -> ValueHash {
+                static EMPTY_TREE_HASHES: Lazy<Vec<ValueHash>> =
+                    Lazy::new(|| compute_empty_tree_hashes(&<$hasher>::default()));
+                EMPTY_TREE_HASHES[depth]
+            }
+        }
+    };
 }
 
-fn compute_empty_tree_hashes() -> Vec<ValueHash> {
-    let empty_leaf_hash = Blake2Hasher.hash_bytes(&[0_u8; 40]);
+impl_hash_tree!(Blake2Hasher, "blake2s256");
+impl_hash_tree!(KeccakHasher, "keccak256");
+impl_hash_tree!(Sha256Hasher, "sha256");
+
+fn compute_empty_tree_hashes<H>(hasher: &H) -> Vec<ValueHash>
+where
+    H: Hasher<Hash = ValueHash> + HashTree,
+{
+    let empty_leaf_hash = hasher.hash_bytes(&[0_u8; 40]);
     iter::successors(Some(empty_leaf_hash), |hash| {
-        Some(Blake2Hasher.hash_branch(hash, hash))
+        Some(hasher.hash_branch(hash, hash))
     })
     .take(TREE_DEPTH + 1)
     .collect()
@@ -221,14 +298,20 @@ impl HasherWithStats<'_> {
     pub fn empty_subtree_hash(&self, depth: usize) -> ValueHash {
         self.inner.empty_subtree_hash(depth)
     }
+
+    pub fn depth(&self) -> usize {
+        self.inner.depth()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use axon_types::{AccountTreeId, Address, StorageKey, B256};
 
     use super::*;
-    use crate::types::LeafNode;
+    use crate::types::{Key, LeafNode};
 
     #[test]
     fn empty_tree_hash_is_as_expected() {
@@ -241,6 +324,47 @@ mod tests {
         assert_eq!(hasher.empty_tree_hash(), EXPECTED_HASH);
     }
 
+    #[test]
+    fn empty_hashes_are_consistent_for_all_hashers() {
+        let hashers: [&dyn HashTree; 3] = [&Blake2Hasher, &KeccakHasher, &Sha256Hasher];
+        for hasher in hashers {
+            let empty_leaf_hash = hasher.hash_leaf(&ValueHash::ZERO, 0);
+            assert_eq!(hasher.empty_subtree_hash(0), empty_leaf_hash);
+            let empty_branch_hash = hasher.hash_branch(&empty_leaf_hash, &empty_leaf_hash);
+            assert_eq!(hasher.empty_subtree_hash(1), empty_branch_hash);
+        }
+
+        let empty_tree_hashes: HashSet<_> = hashers
+            .iter()
+            .map(|hasher| hasher.empty_tree_hash())
+            .collect();
+        assert_eq!(empty_tree_hashes.len(), hashers.len());
+        let names: HashSet<_> = hashers.iter().map(|hasher| hasher.name()).collect();
+        assert_eq!(names.len(), hashers.len());
+    }
+
+    #[test]
+    fn hasher_with_custom_depth() {
+        let hasher = WithDepth::new(Blake2Hasher, 64);
+        assert_eq!(hasher.name(), "blake2s256");
+        assert_eq!(hasher.depth(), 64);
+        assert_eq!(
+            hasher.empty_tree_hash(),
+            Blake2Hasher.empty_subtree_hash(64)
+        );
+
+        let leaf = LeafNode::new(TreeEntry::new(Key::from(5), 1, B256::repeat_byte(1)));
+        let leaf_hash = leaf.hash(&mut HasherWithStats::new(&hasher), 0);
+        let hasher: &dyn HashTree = &hasher;
+        assert_eq!(hasher.fold_merkle_path(&[], leaf.into()), leaf_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid tree depth 60")]
+    fn invalid_custom_depth() {
+        WithDepth::new(Blake2Hasher, 60);
+    }
+
     #[test]
     fn leaf_is_hashed_as_expected() {
         // Reference value taken from the previous implementation.
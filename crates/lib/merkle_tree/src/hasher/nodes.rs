@@ -4,12 +4,12 @@ use std::slice;
 
 use crate::{
     hasher::HasherWithStats,
-    types::{ChildRef, InternalNode, LeafNode, Node, ValueHash, TREE_DEPTH},
+    types::{ChildRef, InternalNode, LeafNode, Node, ValueHash},
 };
 
 impl LeafNode {
     pub(crate) fn hash(&self, hasher: &mut HasherWithStats<'_>, level: usize) -> ValueHash {
-        let hashing_iterations = TREE_DEPTH - level;
+        let hashing_iterations = hasher.depth() - level;
         let mut hash = hasher.hash_leaf(&self.value_hash, self.leaf_index);
         for depth in 0..hashing_iterations {
             let empty_tree_hash = hasher.empty_subtree_hash(depth);
@@ -41,7 +41,7 @@ impl MerklePath {
         if let Some(hash) = maybe_hash {
             self.hashes.push(hash);
         } else if !self.hashes.is_empty() {
-            let depth = TREE_DEPTH - self.current_level;
+            let depth = hasher.depth() - self.current_level;
             let empty_subtree_hash = hasher.empty_subtree_hash(depth);
             self.hashes.push(empty_subtree_hash);
         }
@@ -107,7 +107,7 @@ impl InternalNodeCache {
         for (level_in_tree, next_level_hashes) in (1..=4).rev().zip(levels) {
             let overall_level = level + level_in_tree;
             // Depth of a potential empty subtree rooted at the current level.
-            let subtree_depth = TREE_DEPTH - overall_level;
+            let subtree_depth = hasher.depth() - overall_level;
 
             let left_idx = idx - idx % 2;
             let right_idx = left_idx + 1;
@@ -151,7 +151,7 @@ impl InternalNode {
         for level_in_tree in (1..=4).rev() {
             let overall_level = level + level_in_tree;
             // Depth of a potential empty subtree rooted at the current level.
-            let subtree_depth = TREE_DEPTH - overall_level;
+            let subtree_depth = hasher.depth() - overall_level;
 
             for i in 0..next_level_len {
                 level_hashes[i] = hasher.hash_optional_branch(
@@ -166,7 +166,7 @@ impl InternalNode {
                 cache.set_level(level_in_tree - 1, &level_hashes);
             }
         }
-        level_hashes[0].unwrap_or_else(|| hasher.empty_subtree_hash(TREE_DEPTH - level))
+        level_hashes[0].unwrap_or_else(|| hasher.empty_subtree_hash(hasher.depth() - level))
     }
 
     pub(crate) fn hash(&self, hasher: &mut HasherWithStats<'_>, level: usize) -> ValueHash {
@@ -216,6 +216,17 @@ impl InternalNodeUpdater<'_, '_> {
     pub fn update_child_hash(&mut self, child_hash: ValueHash) -> ValueHash {
         let child_ref = self.node.child_ref_mut(self.nibble).unwrap();
         child_ref.hash = child_hash;
+        self.update_node_hash()
+    }
+
+    /// Removes the child reference for the affected nibble (if it's present) and returns
+    /// the updated hash of the node.
+    pub fn remove_child_ref(&mut self) -> ValueHash {
+        self.node.remove_child_ref(self.nibble);
+        self.update_node_hash()
+    }
+
+    fn update_node_hash(&mut self) -> ValueHash {
         let child_hashes = self.node.child_hashes();
 
         if let Some(cache) = self.node.cache_mut() {
@@ -264,6 +275,7 @@ mod tests {
     };
 
     use super::*;
+    use crate::types::TREE_DEPTH;
 
     fn test_internal_node_hashing(child_indexes: &[u8]) {
         println!("Testing indices: {child_indexes:?}");
@@ -5,8 +5,8 @@ use std::mem;
 use crate::{
     hasher::{HashTree, HasherWithStats},
     types::{
-        BlockOutputWithProofs, Key, LeafNode, TreeEntry, TreeEntryWithProof, TreeInstruction,
-        TreeLogEntry, ValueHash, TREE_DEPTH,
+        BlockOutputWithProofs, ExclusionProof, Key, LeafNode, TreeEntry, TreeEntryWithProof,
+        TreeInstruction, TreeLogEntry, TreeMultiProof, ValueHash, TREE_DEPTH,
     },
     utils,
 };
@@ -28,12 +28,22 @@ impl BlockOutputWithProofs {
 
         let mut root_hash = old_root_hash;
         for (op, &instruction) in self.logs.iter().zip(instructions) {
-            assert!(op.merkle_path.len() <= TREE_DEPTH);
-            if matches!(instruction, TreeInstruction::Read(_)) {
-                assert_eq!(op.root_hash, root_hash);
-                assert!(op.base.is_read());
-            } else {
-                assert!(!op.base.is_read());
+            assert!(op.merkle_path.len() <= hasher.depth());
+            match instruction {
+                TreeInstruction::Read(_) => {
+                    assert_eq!(op.root_hash, root_hash);
+                    assert!(op.base.is_read());
+                }
+                TreeInstruction::Write(_) => assert!(!op.base.is_read()),
+                TreeInstruction::Remove(_) => {
+                    // Removing a missing key is a no-op.
+                    if op.base.is_read() {
+                        assert_eq!(op.base, TreeLogEntry::ReadMissingKey);
+                        assert_eq!(op.root_hash, root_hash);
+                    } else {
+                        assert!(matches!(op.base, TreeLogEntry::Removed { .. }));
+                    }
+                }
             }
 
             let prev_entry = match op.base {
@@ -44,14 +54,23 @@ impl BlockOutputWithProofs {
                     leaf_index,
                     previous_value: value,
                 }
-                | TreeLogEntry::Read { leaf_index, value } => {
-                    TreeEntry::new(instruction.key(), leaf_index, value)
-                }
+                | TreeLogEntry::Read { leaf_index, value }
+                | TreeLogEntry::Removed {
+                    leaf_index,
+                    previous_value: value,
+                } => TreeEntry::new(instruction.key(), leaf_index, value),
             };
 
             let prev_hash = hasher.fold_merkle_path(&op.merkle_path, prev_entry);
             assert_eq!(prev_hash, root_hash);
-            if let TreeInstruction::Write(new_entry) = instruction {
+            let new_entry = match (instruction, op.base) {
+                (TreeInstruction::Write(new_entry), _) => Some(new_entry),
+                (TreeInstruction::Remove(key), TreeLogEntry::Removed { .. }) => {
+                    Some(TreeEntry::empty(key))
+                }
+                _ => None,
+            };
+            if let Some(new_entry) = new_entry {
                 let next_hash = hasher.fold_merkle_path(&op.merkle_path, new_entry);
                 assert_eq!(next_hash, op.root_hash);
             }
@@ -78,6 +97,206 @@ impl TreeEntryWithProof {
     }
 }
 
+impl ExclusionProof {
+    /// Verifies this proof.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the proof doesn't verify.
+    pub fn verify(&self, hasher: &dyn HashTree, trusted_root_hash: ValueHash) {
+        let depth = hasher.depth();
+        assert!(
+            self.merkle_path.len() <= depth,
+            "Merkle path length is too large"
+        );
+        let proven_entry = if let Some(neighbor) = self.neighbor {
+            assert_ne!(neighbor.key, self.key, "Neighbor has the excluded key");
+            assert!(neighbor.leaf_index != 0, "Neighbor leaf is empty");
+
+            // The depth at which the paths to `neighbor` and `key` diverge. The neighbor leaf
+            // must be placed above this depth, so that the `key` subtree is empty.
+            // Note that the depth is counted from the leaf level, so it doesn't depend
+            // on the tree depth.
+            let diverging_depth =
+                TREE_DEPTH - 1 - utils::find_diverging_bit(self.key, neighbor.key);
+            assert!(
+                diverging_depth < depth - self.merkle_path.len(),
+                "Neighbor leaf is placed too low in the tree"
+            );
+            neighbor
+        } else {
+            TreeEntry::empty(self.key)
+        };
+
+        let root_hash = hasher.fold_merkle_path(&self.merkle_path, proven_entry);
+        assert_eq!(root_hash, trusted_root_hash, "Root hash mismatch");
+    }
+}
+
+/// Node on a certain level of the tree used when building or verifying a [`TreeMultiProof`].
+#[derive(Debug, Clone, Copy)]
+struct MultiProofNode<T> {
+    /// Index of the node on its level (i.e., the key of any leaf in the node subtree shifted right
+    /// by the node depth).
+    position: Key,
+    /// Depth, starting from which the node siblings may be non-empty.
+    path_start: usize,
+    data: T,
+}
+
+impl<T> MultiProofNode<T> {
+    fn is_left_sibling_of(&self, other: &Self) -> bool {
+        !self.position.bit(0) && self.position ^ Key::from(1) == other.position
+    }
+
+    fn into_parent(self, data: T) -> Self {
+        Self {
+            position: self.position >> 1,
+            data,
+            ..self
+        }
+    }
+}
+
+impl TreeMultiProof {
+    /// Compacts proofs for the provided entries into a multi-proof for a tree with the specified
+    /// `tree_depth`. Entries must be sorted by key and must not contain duplicate keys.
+    pub(crate) fn new(proofs: &[TreeEntryWithProof], tree_depth: usize) -> Self {
+        debug_assert!(
+            proofs
+                .windows(2)
+                .all(|window| window[0].base.key < window[1].base.key),
+            "Proven entries must be sorted by key and have unique keys"
+        );
+
+        let mut nodes: Vec<_> = proofs
+            .iter()
+            .enumerate()
+            .map(|(i, proof)| MultiProofNode {
+                position: proof.base.key,
+                path_start: tree_depth - proof.merkle_path.len(),
+                data: i,
+            })
+            .collect();
+        let mut hashes = vec![];
+        for depth in 0..tree_depth {
+            let mut parent_nodes = Vec::with_capacity(nodes.len());
+            let mut nodes_iter = nodes.into_iter().peekable();
+            while let Some(node) = nodes_iter.next() {
+                if let Some(sibling) = nodes_iter.next_if(|next| node.is_left_sibling_of(next)) {
+                    let path_start = node.path_start.max(sibling.path_start);
+                    parent_nodes.push(MultiProofNode {
+                        path_start,
+                        ..node.into_parent(node.data)
+                    });
+                } else {
+                    if depth >= node.path_start {
+                        let merkle_path = &proofs[node.data].merkle_path;
+                        hashes.push(merkle_path[depth + merkle_path.len() - tree_depth]);
+                    }
+                    parent_nodes.push(node.into_parent(node.data));
+                }
+            }
+            nodes = parent_nodes;
+        }
+
+        Self {
+            entries: proofs.iter().map(|proof| proof.base).collect(),
+            path_lengths: proofs
+                .iter()
+                .map(|proof| proof.merkle_path.len() as u16)
+                .collect(),
+            hashes,
+        }
+    }
+
+    /// Verifies this proof.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the proof doesn't verify.
+    pub fn verify(&self, hasher: &dyn HashTree, trusted_root_hash: ValueHash) {
+        assert_eq!(
+            self.entries.len(),
+            self.path_lengths.len(),
+            "Mismatch between the number of entries and path lengths"
+        );
+        assert!(
+            self.entries
+                .windows(2)
+                .all(|window| window[0].key < window[1].key),
+            "Entries must be sorted by key and have unique keys"
+        );
+        if self.entries.is_empty() {
+            assert!(
+                self.hashes.is_empty(),
+                "Unexpected hashes in an empty proof"
+            );
+            return;
+        }
+
+        let tree_depth = hasher.depth();
+        let mut hasher = HasherWithStats::new(hasher);
+        let nodes = self.entries.iter().zip(&self.path_lengths);
+        let mut nodes: Vec<_> = nodes
+            .map(|(entry, &path_length)| {
+                assert!(
+                    usize::from(path_length) <= tree_depth,
+                    "Merkle path length is too large"
+                );
+                if entry.leaf_index == 0 {
+                    assert!(
+                        entry.value.is_zero(),
+                        "Invalid missing value specification: leaf index is zero, \
+                         but value is non-default"
+                    );
+                }
+                MultiProofNode {
+                    position: entry.key,
+                    path_start: tree_depth - usize::from(path_length),
+                    data: hasher.hash_leaf(&entry.value, entry.leaf_index),
+                }
+            })
+            .collect();
+
+        let mut hashes = self.hashes.iter();
+        for depth in 0..tree_depth {
+            let mut parent_nodes = Vec::with_capacity(nodes.len());
+            let mut nodes_iter = nodes.into_iter().peekable();
+            while let Some(node) = nodes_iter.next() {
+                let parent_node = if let Some(sibling) =
+                    nodes_iter.next_if(|next| node.is_left_sibling_of(next))
+                {
+                    let hash = hasher.hash_branch(&node.data, &sibling.data);
+                    let path_start = node.path_start.max(sibling.path_start);
+                    MultiProofNode {
+                        path_start,
+                        ..node.into_parent(hash)
+                    }
+                } else {
+                    let sibling_hash = if depth < node.path_start {
+                        hasher.empty_subtree_hash(depth)
+                    } else {
+                        *hashes.next().expect("Not enough hashes in the proof")
+                    };
+                    let hash = if node.position.bit(0) {
+                        hasher.hash_branch(&sibling_hash, &node.data)
+                    } else {
+                        hasher.hash_branch(&node.data, &sibling_hash)
+                    };
+                    node.into_parent(hash)
+                };
+                parent_nodes.push(parent_node);
+            }
+            nodes = parent_nodes;
+        }
+
+        assert!(hashes.next().is_none(), "Redundant hashes in the proof");
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].data, trusted_root_hash, "Root hash mismatch");
+    }
+}
+
 /// Range digest in a Merkle tree allowing to compute its root hash based on the provided entries.
 ///
 /// - The entries must be ordered by key. I.e., the first entry must have the numerically smallest
@@ -93,7 +312,8 @@ impl TreeEntryWithProof {
 /// # Implementation details
 ///
 /// A streaming approach is used. `TreeRange` occupies `O(1)` RAM w.r.t. the number of entries.
-/// `TreeRange` consists of `TREE_DEPTH = 256` hashes and a constant amount of other data.
+/// `TreeRange` consists of `depth` hashes (256 for full-depth trees) and a constant amount of
+/// other data.
 // We keep a *left contour* of hashes, i.e., known hashes to the left of the last processed key.
 // Initially, the left contour is a filtered Merkle path for the start entry; we only take into
 // account left hashes in the path (ones for which the corresponding start key bit is 1), and
@@ -104,9 +324,9 @@ impl TreeEntryWithProof {
 //
 // ```text
 // ...
-// diverging_level:          /                         \
+// diverging_depth:          /                         \
 // ...                       |  (only empty subtrees)  |
-// TREE_DEPTH:           current_leaf              next_leaf
+// 0:                    current_leaf              next_leaf
 // ```
 //
 // We update the left contour by collapsing the last processed entry up to the diverging bit.
@@ -122,14 +342,25 @@ impl TreeEntryWithProof {
 #[derive(Debug)]
 pub struct TreeRangeDigest<'a> {
     hasher: HasherWithStats<'a>,
+    depth: usize,
     current_leaf: LeafNode,
-    left_contour: Box<[ValueHash; TREE_DEPTH]>,
+    left_contour: Vec<ValueHash>,
 }
 
 impl<'a> TreeRangeDigest<'a> {
-    /// Starts a new Merkle tree range.
-    #[allow(clippy::missing_panics_doc)] // false positive
+    /// Starts a new Merkle tree range. The tree depth is taken from [`HashTree::depth()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start_key` doesn't fit into the tree (i.e., is greater or equal to `2^depth`),
+    /// or if the Merkle path of `start_entry` is longer than the tree depth.
     pub fn new(hasher: &'a dyn HashTree, start_key: Key, start_entry: &TreeEntryWithProof) -> Self {
+        let depth = hasher.depth();
+        Self::check_key(&start_key, depth);
+        assert!(
+            start_entry.merkle_path.len() <= depth,
+            "Merkle path length is too large"
+        );
         let full_path = hasher.extend_merkle_path(&start_entry.merkle_path);
         let left_contour = full_path.enumerate().map(|(depth, adjacent_hash)| {
             if start_key.bit(depth) {
@@ -138,35 +369,45 @@ impl<'a> TreeRangeDigest<'a> {
                 hasher.empty_subtree_hash(depth)
             }
         });
-        let left_contour: Vec<_> = left_contour.collect();
         Self {
             hasher: HasherWithStats::new(hasher),
+            depth,
             current_leaf: LeafNode::new(start_entry.base),
-            left_contour: left_contour.try_into().unwrap(),
-            // ^ `unwrap()` is safe by construction; `left_contour` will always have necessary
-            // length
+            left_contour: left_contour.collect(),
         }
     }
 
+    fn check_key(key: &Key, depth: usize) {
+        assert!(
+            key.bit_len() <= depth,
+            "Key {key:0>64x} does not fit into the tree with depth {depth}"
+        );
+    }
+
     /// Updates this digest with a new entry.
     ///
     /// # Panics
     ///
-    /// Panics if the provided `key` is not greater than the previous key provided to this digest.
+    /// Panics if the provided `key` is not greater than the previous key provided to this digest,
+    /// or doesn't fit into the tree.
     pub fn update(&mut self, entry: TreeEntry) {
         assert!(
             entry.key > self.current_leaf.full_key,
             "Keys provided to a digest must be monotonically increasing"
         );
+        Self::check_key(&entry.key, self.depth);
 
-        let diverging_level = utils::find_diverging_bit(self.current_leaf.full_key, entry.key) + 1;
+        // The depth (counted from the leaf level) at which the paths to the current and new keys
+        // diverge. Since both keys fit into the tree, it is less than the tree depth.
+        let diverging_depth =
+            TREE_DEPTH - 1 - utils::find_diverging_bit(self.current_leaf.full_key, entry.key);
 
-        // Hash the current leaf up to the `diverging_level`, taking current `left_contour` into
+        // Hash the current leaf up to the `diverging_depth`, taking current `left_contour` into
         // account.
         let mut hash = self
             .hasher
             .hash_leaf(&self.current_leaf.value_hash, self.current_leaf.leaf_index);
-        for depth in 0..(TREE_DEPTH - diverging_level) {
+        for depth in 0..diverging_depth {
             let empty_subtree_hash = self.hasher.empty_subtree_hash(depth);
             // Replace the left contour value with the default one.
             let left_hash = mem::replace(&mut self.left_contour[depth], empty_subtree_hash);
@@ -180,7 +421,7 @@ impl<'a> TreeRangeDigest<'a> {
             };
         }
         // Record the computed hash.
-        self.left_contour[TREE_DEPTH - diverging_level] = hash;
+        self.left_contour[diverging_depth] = hash;
         self.current_leaf = LeafNode::new(entry);
     }
 
@@ -189,9 +430,13 @@ impl<'a> TreeRangeDigest<'a> {
     /// # Panics
     ///
     /// Panics if the provided `final_key` is not greater than the previous key provided to this
-    /// digest.
+    /// digest or doesn't fit into the tree, or if its Merkle path is longer than the tree depth.
     pub fn finalize(mut self, final_entry: &TreeEntryWithProof) -> ValueHash {
         self.update(final_entry.base);
+        assert!(
+            final_entry.merkle_path.len() <= self.depth,
+            "Merkle path length is too large"
+        );
 
         let full_path = self
             .hasher
@@ -17,11 +17,24 @@
 //! implementations:
 //!
 //! - [`Blake2Hasher`] is the main implementation based on Blake2s-256
+//! - `KeccakHasher` and `Sha256Hasher` from `axon_types::primitives::hasher` are alternative
+//!   implementations based on Keccak-256 and SHA-256, respectively
 //! - `()` provides a no-op implementation useful for benchmarking.
 //!
+//! The hasher used by a tree is recorded in the tree database; a tree cannot be opened
+//! with a hasher different from the one it was created with.
+//!
+//! By default, tree keys are 256-bit. A tree with shorter keys (e.g., 64-bit or 160-bit ones)
+//! can be created by wrapping the hasher in [`WithDepth`]. Like the hasher, the tree depth
+//! is recorded in the database and is checked when the tree is opened.
+//!
+//! Tree proofs (e.g., [`TreeEntryWithProof`] and [`BlockOutputWithProofs`]) can be exchanged
+//! using a stable, versioned [wire format](wire).
+//!
 //! # Tree hashing specification
 //!
-//! A tree is hashed as if it was a full binary Merkle tree with `2^256` leaves:
+//! A tree is hashed as if it was a full binary Merkle tree with `2^256` leaves (or `2^depth`
+//! leaves for trees with a custom depth):
 //!
 //! - Hash of a vacant leaf is `hash([0_u8; 40])`, where `hash` is the hash function used
 //!   (Blake2s-256).
@@ -38,6 +51,7 @@
 //! [Jellyfish Merkle tree]: https://developers.diem.com/papers/jellyfish-merkle-tree/2021-01-14.pdf
 
 mod consistency;
+mod diff;
 pub mod domain;
 mod errors;
 mod getters;
@@ -45,9 +59,13 @@ mod hasher;
 mod metrics;
 mod pruning;
 pub mod recovery;
+mod repair;
+mod snapshot;
 mod storage;
+mod truncation;
 mod types;
 mod utils;
+pub mod wire;
 
 /// Unstable types that should not be used unless you know what you're doing (e.g., implementing
 /// `Database` trait for a custom type). There are no guarantees whatsoever that APIs / structure of
@@ -60,22 +78,34 @@ pub mod unstable {
     };
 }
 
+use std::{mem, panic, thread};
+
 use axon_types::primitives::hasher::blake2::Blake2Hasher;
 
 pub use crate::{
-    errors::NoVersionError,
-    hasher::{HashTree, TreeRangeDigest},
+    consistency::{ConsistencyError, ConsistencyVerifier, VerificationCursor},
+    errors::{DeserializeError, NoVersionError},
+    hasher::{HashTree, TreeRangeDigest, WithDepth},
     pruning::{MerkleTreePruner, MerkleTreePrunerHandle},
+    repair::{RepairAction, RepairReport},
+    snapshot::TreeSnapshot,
     storage::{
         Database, MerkleTreeColumnFamily, PatchSet, Patched, PruneDatabase, PrunePatchSet,
         RocksDBWrapper,
     },
+    truncation::{TruncationError, TruncationReport},
     types::{
-        BlockOutput, BlockOutputWithProofs, Key, TreeEntry, TreeEntryWithProof, TreeInstruction,
-        TreeLogEntry, TreeLogEntryWithProof, ValueHash,
+        BlockOutput, BlockOutputWithProofs, ExclusionProof, Key, TreeEntriesPage, TreeEntry,
+        TreeEntryChange, TreeEntryWithProof, TreeInstruction, TreeLogEntry, TreeLogEntryWithProof,
+        TreeMultiProof, ValueHash,
     },
 };
-use crate::{hasher::HasherWithStats, storage::Storage, types::Root};
+use crate::{
+    getters::load_root,
+    hasher::HasherWithStats,
+    storage::{Prefetched, PrefetchedNodes, Storage},
+    types::Root,
+};
 
 /// Binary Merkle tree implemented using AR16MT from Diem [Jellyfish Merkle tree] white paper.
 ///
@@ -191,6 +221,8 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
     ///
     /// The current implementation does not actually remove node data for the removed versions
     /// since it's likely to be reused in the future (especially upper-level internal nodes).
+    /// No checks are performed as to whether the retained versions were pruned; see
+    /// [`Self::try_truncate_recent_versions()`] for a checked alternative.
     pub fn truncate_recent_versions(&mut self, retained_version_count: u64) {
         let mut manifest = self.db.manifest().unwrap_or_default();
         if manifest.version_count > retained_version_count {
@@ -213,6 +245,21 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
         output
     }
 
+    /// Extends this tree by creating its new version from the provided instructions. Unlike
+    /// [`Self::extend()`], instructions may include reads and [removals](TreeInstruction::Remove).
+    /// Reads do not change the tree, but are recorded in the returned logs.
+    ///
+    /// # Return value
+    ///
+    /// Returns information about the update such as the final tree hash.
+    pub fn extend_with_instructions(&mut self, instructions: Vec<TreeInstruction>) -> BlockOutput {
+        let next_version = self.db.manifest().unwrap_or_default().version_count;
+        let storage = Storage::new(&self.db, &self.hasher, next_version, true);
+        let (output, patch) = storage.extend_with_instructions(instructions);
+        self.db.apply_patch(patch);
+        output
+    }
+
     /// Extends this tree by creating its new version, computing an authenticity Merkle proof
     /// for each provided instruction.
     ///
@@ -230,6 +277,83 @@ impl<DB: Database, H: HashTree> MerkleTree<DB, H> {
         self.db.apply_patch(patch);
         output
     }
+
+    /// Recomputes the update of the tree from `version - 1` to `version` based on the provided
+    /// `instructions`, with an authenticity Merkle proof for each instruction. This allows
+    /// obtaining proofs for versions created with [`Self::extend()`]. The tree is not changed;
+    /// in particular, it's not checked that `instructions` correspond to the stored `version`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `version` or the preceding version is missing from the tree
+    /// (e.g., was pruned).
+    pub fn replay_with_proofs(
+        &self,
+        version: u64,
+        instructions: Vec<TreeInstruction>,
+    ) -> Result<BlockOutputWithProofs, NoVersionError> {
+        load_root(&self.db, version)?;
+        if let Some(prev_version) = version.checked_sub(1) {
+            load_root(&self.db, prev_version)?;
+        }
+        let storage = Storage::new(&self.db, &self.hasher, version, true);
+        let (output, _) = storage.extend_with_proofs(instructions);
+        Ok(output)
+    }
+
+    /// Extends this tree by creating a new version for each of the provided `batches`; each batch
+    /// is processed by the `extend` closure. While a batch is being processed, nodes required
+    /// for the next batch are prefetched from the database on a separate thread.
+    ///
+    /// The outputs are the same as if the batches were processed sequentially. Nodes for the next
+    /// batch are loaded from the tree version preceding the currently processed batch; since node
+    /// keys are versioned, prefetched nodes remain valid after the current batch is applied. Nodes
+    /// changed by the current batch are held in memory and are looked up in `self.db` as usual.
+    ///
+    /// `after_apply` is called after each batch is applied to the database.
+    pub(crate) fn extend_pipelined<R>(
+        &mut self,
+        batches: Vec<Vec<TreeInstruction>>,
+        mut extend: impl FnMut(Storage<'_, Prefetched<'_, DB>>, Vec<TreeInstruction>) -> (R, PatchSet),
+        mut after_apply: impl FnMut(&mut DB),
+    ) -> Vec<R> {
+        let depth = self.hasher.depth();
+        let mut prefetched = PrefetchedNodes::default();
+        let mut outputs = Vec::with_capacity(batches.len());
+        let mut batches = batches.into_iter().peekable();
+        while let Some(instructions) = batches.next() {
+            let version = self.db.manifest().unwrap_or_default().version_count;
+            let next_keys: Option<Vec<_>> = batches
+                .peek()
+                .map(|next_batch| next_batch.iter().map(TreeInstruction::key).collect());
+            let prefetch_args = next_keys.zip(version.checked_sub(1));
+
+            let tree_db = &self.db;
+            let db = Prefetched::new(tree_db, mem::take(&mut prefetched));
+            let (output, patch) = thread::scope(|scope| {
+                let prefetch_task = prefetch_args.map(|(keys, prev_version)| {
+                    scope.spawn(move || {
+                        PrefetchedNodes::load(tree_db, prev_version, depth, keys.into_iter())
+                    })
+                });
+                let storage = Storage::new(&db, &self.hasher, version, true);
+                let output = extend(storage, instructions);
+                if let Some(task) = prefetch_task {
+                    prefetched = task.join().unwrap_or_else(|err| panic::resume_unwind(err));
+                    tracing::debug!(
+                        "Prefetched {} nodes for tree version {}",
+                        prefetched.len(),
+                        version + 1
+                    );
+                }
+                output
+            });
+            self.db.apply_patch(patch);
+            after_apply(&mut self.db);
+            outputs.push(output);
+        }
+        outputs
+    }
 }
 
 #[cfg(test)]
@@ -278,4 +402,38 @@ mod tests {
 
         MerkleTree::new(db);
     }
+
+    #[test]
+    fn custom_tree_depth_is_persisted() {
+        let hasher = WithDepth::new(Blake2Hasher, 64);
+        let mut tree = MerkleTree::with_hasher(PatchSet::default(), hasher);
+        tree.extend(vec![TreeEntry::new(
+            Key::from(1),
+            1,
+            ValueHash::repeat_byte(1),
+        )]);
+
+        let tags = tree.db.manifest().unwrap().tags.unwrap();
+        assert_eq!(tags.depth, 64);
+        assert_eq!(tags.hasher, "blake2s256");
+        MerkleTree::with_hasher(tree.db, WithDepth::new(Blake2Hasher, 64));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected tree depth: expected 64, got 256")]
+    fn custom_tree_depth_mismatch() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(vec![]);
+
+        MerkleTree::with_hasher(tree.db, WithDepth::new(Blake2Hasher, 64));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit into the tree with depth 64")]
+    fn inserting_key_not_fitting_into_custom_depth() {
+        let hasher = WithDepth::new(Blake2Hasher, 64);
+        let mut tree = MerkleTree::with_hasher(PatchSet::default(), hasher);
+        let key = Key::from(1) << 64;
+        tree.extend(vec![TreeEntry::new(key, 1, ValueHash::repeat_byte(1))]);
+    }
 }
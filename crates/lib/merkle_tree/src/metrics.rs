@@ -17,6 +17,9 @@ use crate::types::Nibbles;
 pub(crate) struct GeneralMetrics {
     /// Current number of leaves in the tree.
     pub leaf_count: Gauge<u64>,
+    /// Approximate size of tree changes held in RAM by `AxonTree`.
+    #[metrics(unit = Unit::Bytes)]
+    pub patch_size: Gauge<u64>,
 }
 
 #[vetric::register]
@@ -80,6 +83,13 @@ struct TreeUpdateMetrics {
     /// Number of existing leaves updated while processing a single block.
     #[metrics(buckets = NODE_COUNT_BUCKETS)]
     updated_leaves: Histogram<u64>,
+    /// Number of existing leaves removed while processing a single block.
+    #[metrics(buckets = NODE_COUNT_BUCKETS)]
+    removed_leaves: Histogram<u64>,
+    /// Number of internal nodes removed when collapsing the tree after leaf removals
+    /// while processing a single block.
+    #[metrics(buckets = NODE_COUNT_BUCKETS)]
+    removed_internal_nodes: Histogram<u64>,
     /// Average level of leaves moved or created while processing a single block.
     #[metrics(buckets = LEAF_LEVEL_BUCKETS)]
     avg_leaf_level: Histogram<f64>,
@@ -115,6 +125,8 @@ pub(crate) struct TreeUpdaterStats {
     pub new_internal_nodes: u64,
     pub moved_leaves: u64,
     pub updated_leaves: u64,
+    pub removed_leaves: u64,
+    pub removed_internal_nodes: u64,
     pub leaf_level_sum: u64,
     pub max_leaf_level: u64,
     pub key_reads: u64,
@@ -131,6 +143,8 @@ impl fmt::Debug for TreeUpdaterStats {
             .field("new_internal_nodes", &self.new_internal_nodes)
             .field("moved_leaves", &self.moved_leaves)
             .field("updated_leaves", &self.updated_leaves)
+            .field("removed_leaves", &self.removed_leaves)
+            .field("removed_internal_nodes", &self.removed_internal_nodes)
             .field("avg_leaf_level", &self.avg_leaf_level())
             .field("max_leaf_level", &self.max_leaf_level)
             .field("key_reads", &self.key_reads)
@@ -164,6 +178,10 @@ impl TreeUpdaterStats {
         metrics.new_internal_nodes.observe(self.new_internal_nodes);
         metrics.moved_leaves.observe(self.moved_leaves);
         metrics.updated_leaves.observe(self.updated_leaves);
+        metrics.removed_leaves.observe(self.removed_leaves);
+        metrics
+            .removed_internal_nodes
+            .observe(self.removed_internal_nodes);
         metrics.avg_leaf_level.observe(self.avg_leaf_level());
         metrics.max_leaf_level.observe(self.max_leaf_level);
 
@@ -184,6 +202,8 @@ impl ops::AddAssign for TreeUpdaterStats {
         self.new_internal_nodes += rhs.new_internal_nodes;
         self.moved_leaves += rhs.moved_leaves;
         self.updated_leaves += rhs.updated_leaves;
+        self.removed_leaves += rhs.removed_leaves;
+        self.removed_internal_nodes += rhs.removed_internal_nodes;
         self.leaf_level_sum += rhs.leaf_level_sum;
         self.max_leaf_level = self.max_leaf_level.max(rhs.max_leaf_level);
 
@@ -340,6 +360,10 @@ struct PruningMetrics {
     /// Number of pruned node keys on a specific pruning iteration.
     #[metrics(buckets = NODE_COUNT_BUCKETS)]
     key_count: Histogram<usize>,
+    /// Number of stale node keys on a specific pruning iteration that were retained because
+    /// they are required by archival checkpoint versions.
+    #[metrics(buckets = NODE_COUNT_BUCKETS)]
+    retained_key_count: Histogram<usize>,
     /// Lower and upper boundaries on the new stale key versions deleted
     /// during a pruning iteration. The lower boundary is inclusive, the upper one is exclusive.
     deleted_stale_key_versions: Family<Bound, Gauge<u64>>,
@@ -352,6 +376,7 @@ static PRUNING_METRICS: Global<PruningMetrics> = Global::new();
 pub struct PruningStats {
     pub target_retained_version: u64,
     pub pruned_key_count: usize,
+    pub retained_key_count: usize,
     pub deleted_stale_key_versions: ops::Range<u64>,
 }
 
@@ -361,6 +386,9 @@ impl PruningStats {
             .target_retained_version
             .set(self.target_retained_version);
         PRUNING_METRICS.key_count.observe(self.pruned_key_count);
+        PRUNING_METRICS
+            .retained_key_count
+            .observe(self.retained_key_count);
         PRUNING_METRICS.deleted_stale_key_versions[&Bound::Start]
             .set(self.deleted_stale_key_versions.start);
         PRUNING_METRICS.deleted_stale_key_versions[&Bound::End]
@@ -381,3 +409,50 @@ pub(crate) struct PruningTimings {
 
 #[vetric::register]
 pub(crate) static PRUNING_TIMINGS: Global<PruningTimings> = Global::new();
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    EncodeLabelValue,
+    EncodeLabelSet
+)]
+#[metrics(label = "method", rename_all = "snake_case")]
+pub(crate) enum ReaderMethod {
+    RootHash,
+    LeafCount,
+    EntriesWithProofs,
+}
+
+#[derive(Debug, Metrics)]
+#[metrics(prefix = "merkle_tree_reader")]
+pub(crate) struct ReaderMetrics {
+    /// Latency of async tree reader calls, including waiting for a concurrency permit.
+    #[metrics(buckets = Buckets::LATENCIES)]
+    pub latency: Family<ReaderMethod, Histogram<Duration>>,
+}
+
+#[vetric::register]
+pub(crate) static READER_METRICS: Global<ReaderMetrics> = Global::new();
+
+#[derive(Debug, Metrics)]
+#[metrics(prefix = "merkle_tree_consistency")]
+pub(crate) struct ConsistencyMetrics {
+    /// Number of top-level subtrees in the tree version being verified.
+    pub total_subtrees: Gauge<u64>,
+    /// Number of top-level subtrees successfully verified so far.
+    pub verified_subtrees: Gauge<u64>,
+    /// Number of leaves in the successfully verified subtrees.
+    pub verified_leaves: Gauge<u64>,
+    /// Number of errors found during the latest verification.
+    pub error_count: Gauge<u64>,
+    /// Latency of verifying a single top-level subtree.
+    #[metrics(buckets = Buckets::LATENCIES)]
+    pub subtree_latency: Histogram<Duration>,
+}
+
+#[vetric::register]
+pub(crate) static CONSISTENCY_METRICS: Global<ConsistencyMetrics> = Global::new();
@@ -1,22 +1,66 @@
 //! Tree pruning logic.
 
-use std::{fmt, sync::mpsc, time::Duration};
+use std::{
+    collections::BTreeMap,
+    fmt, panic,
+    sync::{mpsc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use tokio::sync::watch;
 
 use crate::{
     metrics::{PruningStats, PRUNING_TIMINGS},
+    snapshot::{PinnedVersions, VersionPin},
     storage::{PruneDatabase, PrunePatchSet},
 };
 
-/// Handle for a [`MerkleTreePruner`] allowing to abort its operation.
+/// Maximum pause for a pruner if database writes are stalled.
+const STALLED_WRITES_PAUSE: Duration = Duration::from_secs(1);
+
+/// Handle for a [`MerkleTreePruner`] allowing to abort its operation and to pin tree versions.
 ///
 /// The pruner is aborted once the handle is dropped.
 #[must_use = "Pruner is aborted once handle is dropped"]
 #[derive(Debug)]
 pub struct MerkleTreePrunerHandle {
     aborted_sender: mpsc::Sender<()>,
+    pinned_versions: PinnedVersions,
+    pins: Mutex<BTreeMap<u64, VersionPin>>,
 }
 
 impl MerkleTreePrunerHandle {
+    /// Pins the specified tree `version` (e.g., the one corresponding to the last finalized
+    /// L1 batch), so that it's retained by the pruner until it's [unpinned](Self::unpin_version()).
+    /// Like with [`TreeSnapshot`](crate::TreeSnapshot)s, the pruner does not advance past
+    /// the minimum pinned version.
+    ///
+    /// Returns `false` if the version cannot be pinned because the pruner has already selected it
+    /// for pruning (the version may be partially or fully pruned in this case). Pinning
+    /// an already pinned version is a no-op.
+    #[must_use = "version is not pinned if it is being pruned"]
+    pub fn pin_version(&self, version: u64) -> bool {
+        let mut pins = self.pins.lock().unwrap();
+        if pins.contains_key(&version) {
+            return true;
+        }
+        let Some(pin) = self.pinned_versions.pin(version) else {
+            return false;
+        };
+        pins.insert(version, pin);
+        true
+    }
+
+    /// Unpins the specified tree `version`. If the version is not pinned, this is a no-op.
+    pub fn unpin_version(&self, version: u64) {
+        self.pins.lock().unwrap().remove(&version);
+    }
+
+    /// Returns tree versions currently pinned using this handle.
+    pub fn pinned_versions(&self) -> Vec<u64> {
+        self.pins.lock().unwrap().keys().copied().collect()
+    }
+
     /// Aborts the pruner that this handle is attached to. If the pruner has already terminated
     /// (e.g., due to a panic), this is a no-op.
     pub fn abort(self) {
@@ -26,7 +70,8 @@ impl MerkleTreePrunerHandle {
 
 /// Component responsible for Merkle tree pruning, i.e. removing nodes not referenced by new
 /// versions of the tree. A pruner should be instantiated using a [`Clone`] of the tree database,
-/// possibly configured and then [`run()`](Self::run()) on its own thread.
+/// possibly configured and then [`run()`](Self::run()) on its own thread, or driven by
+/// [`run_async()`](Self::run_async()) in the async context.
 /// [`MerkleTreePrunerHandle`] provides a way to gracefully shut down the pruner.
 ///
 /// # Implementation details
@@ -35,14 +80,30 @@ impl MerkleTreePrunerHandle {
 /// stale keys are recorded in a separate column family. A pruner takes stale keys that were
 /// produced by a certain range of tree versions, and removes the corresponding nodes from the tree
 /// (in RocksDB, this uses simple pointwise `delete_cf()` operations). The range of versions
-/// depends on pruning policies; for now, it's "remove versions older than `latest_version - N`",
-/// where `N` is a configurable number set when the pruner [is created](Self::new()).
+/// depends on pruning policies:
+///
+/// - Versions older than `latest_version - N` are removed, where `N` is a configurable number set
+///   when the pruner [is created](Self::new()).
+/// - If a [retention period](Self::set_retention_period()) is set, versions created within
+///   this period are retained.
+/// - Versions pinned by [`TreeSnapshot`]s or via [`MerkleTreePrunerHandle::pin_version()`]
+///   are never pruned while pinned. Conversely, once the pruner has selected versions to prune,
+///   snapshots cannot be created for them.
+/// - If a [checkpoint interval](Self::set_checkpoint_interval()) `K` is set, every `K`th version
+///   is retained as an archival checkpoint. Unlike with other policies, the pruner advances
+///   past checkpoints, skipping stale keys that are still required by a checkpoint.
+///
+/// [`TreeSnapshot`]: crate::TreeSnapshot
 pub struct MerkleTreePruner<DB> {
     db: DB,
     past_versions_to_keep: u64,
+    retention_period: Option<Duration>,
+    checkpoint_interval: Option<u64>,
     target_pruned_key_count: usize,
+    max_pruned_keys_per_second: Option<usize>,
     poll_interval: Duration,
     aborted_receiver: mpsc::Receiver<()>,
+    pinned_versions: PinnedVersions,
 }
 
 impl<DB> fmt::Debug for MerkleTreePruner<DB> {
@@ -50,7 +111,13 @@ impl<DB> fmt::Debug for MerkleTreePruner<DB> {
         formatter
             .debug_struct("MerkleTreePruner")
             .field("past_versions_to_keep", &self.past_versions_to_keep)
+            .field("retention_period", &self.retention_period)
+            .field("checkpoint_interval", &self.checkpoint_interval)
             .field("target_pruned_key_count", &self.target_pruned_key_count)
+            .field(
+                "max_pruned_keys_per_second",
+                &self.max_pruned_keys_per_second,
+            )
             .field("poll_interval", &self.poll_interval)
             .finish_non_exhaustive()
     }
@@ -66,13 +133,22 @@ impl<DB: PruneDatabase> MerkleTreePruner<DB> {
     /// is dropped.*
     pub fn new(db: DB, past_versions_to_keep: u64) -> (Self, MerkleTreePrunerHandle) {
         let (aborted_sender, aborted_receiver) = mpsc::channel();
-        let handle = MerkleTreePrunerHandle { aborted_sender };
+        let pinned_versions = PinnedVersions::default();
+        let handle = MerkleTreePrunerHandle {
+            aborted_sender,
+            pinned_versions: pinned_versions.clone(),
+            pins: Mutex::default(),
+        };
         let this = Self {
             db,
             past_versions_to_keep,
+            retention_period: None,
+            checkpoint_interval: None,
             target_pruned_key_count: 500_000,
+            max_pruned_keys_per_second: None,
             poll_interval: Duration::from_secs(60),
             aborted_receiver,
+            pinned_versions,
         };
         (this, handle)
     }
@@ -86,6 +162,21 @@ impl<DB: PruneDatabase> MerkleTreePruner<DB> {
         self.target_pruned_key_count = count;
     }
 
+    /// Sets the maximum average number of node keys pruned per second. If set, the pruner sleeps
+    /// between iterations so that the rate is not exceeded, and the number of keys pruned
+    /// on a single iteration is capped by this value. This allows limiting the IO budget
+    /// consumed by pruning.
+    ///
+    /// By default, the rate is not limited.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_keys` is zero.
+    pub fn set_max_pruned_keys_per_second(&mut self, max_keys: usize) {
+        assert!(max_keys > 0, "Max pruned keys per second must be positive");
+        self.max_pruned_keys_per_second = Some(max_keys);
+    }
+
     /// Sets the sleep duration when the pruner cannot progress. This time should be enough
     /// for the tree to produce enough stale keys.
     ///
@@ -94,10 +185,133 @@ impl<DB: PruneDatabase> MerkleTreePruner<DB> {
         self.poll_interval = poll_interval;
     }
 
+    /// Sets the wall-clock period during which created tree versions are retained.
+    ///
+    /// Version creation times are persisted in the tree database, so the retention period
+    /// is enforced across pruner restarts. Versions without a recorded creation time
+    /// (e.g., created by older code or recovered from a snapshot) are considered to be created
+    /// at the same time as the next version with a recorded time. If no retained version has
+    /// a recorded creation time (e.g., if the database doesn't support
+    /// [recording creation times](PruneDatabase::version_created_at())), the retention period
+    /// is not applied.
+    ///
+    /// By default, the retention period is not set.
+    pub fn set_retention_period(&mut self, period: Duration) {
+        self.retention_period = Some(period);
+    }
+
+    /// Sets the interval `K` for archival checkpoints, so that every `K`th tree version
+    /// (i.e., versions 0, `K`, `2K`, ...) is retained indefinitely.
+    ///
+    /// Nodes retained for checkpoints are not tracked by the pruner afterwards; they will not be
+    /// removed even if the interval is changed or reset later.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn set_checkpoint_interval(&mut self, interval: u64) {
+        assert!(interval > 0, "Checkpoint interval must be positive");
+        self.checkpoint_interval = Some(interval);
+    }
+
     fn target_retained_version(&self) -> Option<u64> {
         let manifest = self.db.manifest()?;
         let latest_version = manifest.version_count.checked_sub(1)?;
-        latest_version.checked_sub(self.past_versions_to_keep)
+        let mut target_version = latest_version.checked_sub(self.past_versions_to_keep)?;
+
+        if let Some(retention_period) = self.retention_period {
+            let expired_version =
+                self.expired_version(target_version, latest_version, retention_period);
+            let Some(expired_version) = expired_version else {
+                tracing::debug!(
+                    "All tree versions are within the retention period {retention_period:?}"
+                );
+                return None;
+            };
+            if expired_version < target_version {
+                tracing::info!(
+                    "Retaining version {expired_version} per retention period {retention_period:?} \
+                     instead of target version {target_version}"
+                );
+                target_version = expired_version;
+            }
+        }
+
+        // Reserving versions for pruning prevents pinning them via the pruner handle
+        // or snapshots afterwards, so both pin registries are locked while reserving.
+        let reserved_version =
+            self.pinned_versions
+                .reserve_for_pruning(target_version, |version| {
+                    if version < target_version {
+                        tracing::info!(
+                            "Retaining version {version} pinned via pruner handle \
+                     instead of target version {target_version}"
+                        );
+                    }
+                    let reserved_version = self.db.reserve_for_pruning(version);
+                    if reserved_version < version {
+                        tracing::info!(
+                            "Retaining version {reserved_version} pinned by a tree snapshot \
+                     instead of version {version}"
+                        );
+                    }
+                    reserved_version
+                });
+        Some(reserved_version)
+    }
+
+    /// Returns the latest version not exceeding `max_version` created before the retention
+    /// period, or `None` if there is no such version. If none of the versions up to
+    /// `latest_version` has a recorded creation time, returns `max_version`.
+    fn expired_version(
+        &self,
+        max_version: u64,
+        latest_version: u64,
+        retention_period: Duration,
+    ) -> Option<u64> {
+        let now = SystemTime::now();
+        // Versions preceding the minimum stale key version are already pruned, except for
+        // the latest of them, which is retained.
+        let first_version = self
+            .db
+            .min_stale_key_version()
+            .map_or(max_version, |version| version.saturating_sub(1));
+
+        let mut expired_version = None;
+        for version in first_version..=latest_version {
+            let Some(created_at) = self.db.version_created_at(version) else {
+                // The version is considered to be created together with the next stamped version.
+                continue;
+            };
+            let is_expired = version <= max_version
+                && now
+                    .duration_since(created_at)
+                    .is_ok_and(|elapsed| elapsed >= retention_period);
+            if !is_expired {
+                return expired_version;
+            }
+            expired_version = Some(version);
+        }
+
+        if expired_version.is_none() {
+            tracing::debug!(
+                "No version creation times are recorded; retention period is not applied"
+            );
+        }
+        Some(expired_version.unwrap_or(max_version))
+    }
+
+    /// Checks whether a node created at `node_version` and replaced at `stale_version` is required
+    /// by an archival checkpoint, i.e., whether there is a checkpoint version
+    /// in `node_version..stale_version`.
+    fn is_required_by_checkpoint(&self, node_version: u64, stale_version: u64) -> bool {
+        let Some(interval) = self.checkpoint_interval else {
+            return false;
+        };
+        let Some(last_required_version) = stale_version.checked_sub(1) else {
+            return false;
+        };
+        last_required_version / interval * interval >= node_version
     }
 
     #[doc(hidden)] // Used in integration tests; logically private
@@ -108,31 +322,46 @@ impl<DB: PruneDatabase> MerkleTreePruner<DB> {
         let stale_key_new_versions = min_stale_key_version..=target_retained_version;
         tracing::info!("Collecting stale keys with new versions in {stale_key_new_versions:?}");
 
+        let target_pruned_key_count = self
+            .max_pruned_keys_per_second
+            .map_or(self.target_pruned_key_count, |max_keys| {
+                self.target_pruned_key_count.min(max_keys)
+            });
         let load_stale_keys_latency = PRUNING_TIMINGS.load_stale_keys.start();
         let mut pruned_keys = vec![];
+        let mut loaded_key_count = 0;
         let mut max_stale_key_version = min_stale_key_version;
         for version in stale_key_new_versions {
             max_stale_key_version = version;
-            pruned_keys.extend_from_slice(&self.db.stale_keys(version));
-            if pruned_keys.len() >= self.target_pruned_key_count {
+            let stale_keys = self.db.stale_keys(version);
+            loaded_key_count += stale_keys.len();
+            pruned_keys.extend(
+                stale_keys
+                    .into_iter()
+                    .filter(|key| !self.is_required_by_checkpoint(key.version, version)),
+            );
+            if loaded_key_count >= target_pruned_key_count {
                 break;
             }
         }
         load_stale_keys_latency.observe();
 
-        if pruned_keys.is_empty() {
+        if loaded_key_count == 0 {
             tracing::info!("No stale keys to remove; skipping");
             return None;
         }
         let deleted_stale_key_versions = min_stale_key_version..(max_stale_key_version + 1);
+        let retained_key_count = loaded_key_count - pruned_keys.len();
         tracing::info!(
-            "Collected {} stale keys with new versions in {deleted_stale_key_versions:?}",
+            "Collected {} stale keys with new versions in {deleted_stale_key_versions:?}; \
+             retained {retained_key_count} keys required by archival checkpoints",
             pruned_keys.len()
         );
 
         let stats = PruningStats {
             target_retained_version,
             pruned_key_count: pruned_keys.len(),
+            retained_key_count,
             deleted_stale_key_versions: deleted_stale_key_versions.clone(),
         };
         let patch = PrunePatchSet::new(pruned_keys, deleted_stale_key_versions);
@@ -142,23 +371,54 @@ impl<DB: PruneDatabase> MerkleTreePruner<DB> {
         Some(stats)
     }
 
+    /// Performs a single pruning iteration and returns the delay before the next iteration.
+    fn run_iteration(&mut self) -> Duration {
+        if self.db.has_stalled_writes() {
+            let pause = self.poll_interval.min(STALLED_WRITES_PAUSE);
+            tracing::info!("Database writes are stalled; pausing pruning for {pause:?}");
+            return pause;
+        }
+
+        let started_at = Instant::now();
+        let Some(stats) = self.run_once() else {
+            tracing::debug!("No pruning required per specified policies; waiting");
+            return self.poll_interval;
+        };
+        let has_more_work = stats.has_more_work();
+        let pruned_key_count = stats.pruned_key_count;
+        stats.report();
+
+        let rate_limit_delay = self
+            .max_pruned_keys_per_second
+            .map_or(Duration::ZERO, |max_keys| {
+                let budgeted_time =
+                    Duration::from_secs_f64(pruned_key_count as f64 / max_keys as f64);
+                budgeted_time.saturating_sub(started_at.elapsed())
+            });
+        if has_more_work {
+            rate_limit_delay
+        } else {
+            self.poll_interval.max(rate_limit_delay)
+        }
+    }
+
+    /// Checks whether the pruner was aborted via its handle.
+    fn is_aborted(&self) -> bool {
+        match self.aborted_receiver.try_recv() {
+            Ok(()) => true, // Abort was requested
+            Err(mpsc::TryRecvError::Disconnected) => {
+                tracing::warn!("Pruner handle is dropped without calling `abort()`; exiting");
+                true
+            }
+            Err(mpsc::TryRecvError::Empty) => false,
+        }
+    }
+
     /// Runs this pruner indefinitely until it is aborted by dropping its handle.
     pub fn run(mut self) {
         tracing::info!("Started Merkle tree pruner {self:?}");
         loop {
-            let timeout = if let Some(stats) = self.run_once() {
-                let has_more_work = stats.has_more_work();
-                stats.report();
-                if has_more_work {
-                    Duration::ZERO
-                } else {
-                    self.poll_interval
-                }
-            } else {
-                tracing::debug!("No pruning required per specified policies; waiting");
-                self.poll_interval
-            };
-
+            let timeout = self.run_iteration();
             match self.aborted_receiver.recv_timeout(timeout) {
                 Ok(()) => break, // Abort was requested
                 Err(mpsc::RecvTimeoutError::Disconnected) => {
@@ -173,6 +433,45 @@ impl<DB: PruneDatabase> MerkleTreePruner<DB> {
     }
 }
 
+impl<DB: PruneDatabase + Send + 'static> MerkleTreePruner<DB> {
+    /// Runs this pruner in the async context until a stop signal is sent via `stop_receiver`
+    /// (matching the convention used by other long-running components), or until the pruner
+    /// is aborted via its handle. Pruning iterations are executed on the blocking thread pool.
+    ///
+    /// # Panics
+    ///
+    /// Propagates panics occurring during pruning.
+    pub async fn run_async(mut self, mut stop_receiver: watch::Receiver<bool>) {
+        tracing::info!("Started async Merkle tree pruner {self:?}");
+        loop {
+            if *stop_receiver.borrow() {
+                tracing::info!("Stop signal received, shutting down Merkle tree pruner");
+                break;
+            }
+            if self.is_aborted() {
+                break;
+            }
+
+            let task = tokio::task::spawn_blocking(move || {
+                let timeout = self.run_iteration();
+                (self, timeout)
+            });
+            let (pruner, timeout) = match task.await {
+                Ok(output) => output,
+                Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+                Err(err) => panic!("Pruning task was terminated: {err}"),
+            };
+            self = pruner;
+
+            let stop_signal = tokio::time::timeout(timeout, stop_receiver.changed()).await;
+            if let Ok(Err(_)) = stop_signal {
+                tracing::warn!("Stop signal sender for Merkle tree pruner was dropped; exiting");
+                break;
+            }
+        }
+    }
+}
+
 impl PruningStats {
     fn has_more_work(&self) -> bool {
         self.target_retained_version + 1 > self.deleted_stale_key_versions.end
@@ -184,20 +483,26 @@ impl PruningStats {
 mod tests {
     use std::{collections::HashSet, thread, time::Instant};
 
+    use tempfile::TempDir;
+
     use super::*;
     use crate::{
         types::{Node, NodeKey},
-        Database, Key, MerkleTree, PatchSet, TreeEntry, ValueHash,
+        Database, Key, MerkleTree, PatchSet, RocksDBWrapper, TreeEntry, ValueHash,
     };
 
     fn create_db() -> PatchSet {
         let mut db = PatchSet::default();
+        populate_db(&mut db);
+        db
+    }
+
+    fn populate_db(db: &mut impl Database) {
         for i in 0..5_u64 {
             let key = Key::from(i);
             let value = ValueHash::left_padding_from(&i.to_be_bytes());
-            MerkleTree::new(&mut db).extend(vec![TreeEntry::new(key, i + 1, value)]);
+            MerkleTree::new(&mut *db).extend(vec![TreeEntry::new(key, i + 1, value)]);
         }
-        db
     }
 
     #[test]
@@ -233,6 +538,175 @@ mod tests {
         }
     }
 
+    #[test]
+    fn pruner_with_pinned_versions() {
+        let mut db = create_db();
+        let (mut pruner, handle) = MerkleTreePruner::new(&mut db, 0);
+        assert!(handle.pin_version(2));
+        assert!(handle.pin_version(3));
+        assert_eq!(handle.pinned_versions(), [2, 3]);
+
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 2);
+        assert_eq!(stats.deleted_stale_key_versions, 1..3);
+
+        handle.unpin_version(2);
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 3);
+        assert_eq!(stats.deleted_stale_key_versions, 3..4);
+
+        handle.unpin_version(3);
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 4);
+        assert!(pruner.run_once().is_none());
+    }
+
+    #[test]
+    fn pruner_with_retention_period() {
+        let mut db = create_db();
+        let (mut pruner, _handle) = MerkleTreePruner::new(&mut db, 0);
+        pruner.set_retention_period(Duration::from_secs(3_600));
+        assert!(pruner.run_once().is_none());
+        drop(pruner);
+
+        let (mut pruner, _handle) = MerkleTreePruner::new(&mut db, 0);
+        pruner.set_retention_period(Duration::ZERO);
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 4);
+        assert_eq!(stats.deleted_stale_key_versions, 1..5);
+    }
+
+    #[test]
+    fn retention_period_uses_version_creation_times() {
+        let mut db = create_db();
+        let created_at = SystemTime::now() - Duration::from_secs(7_200);
+        for version in 0..=2 {
+            db.set_created_at(version, Some(created_at));
+        }
+
+        for _ in 0..2 {
+            // Creation times are persisted, so a restarted pruner should make the same decision.
+            let (mut pruner, _handle) = MerkleTreePruner::new(&mut db, 0);
+            pruner.set_retention_period(Duration::from_secs(3_600));
+            assert_eq!(pruner.target_retained_version(), Some(2));
+        }
+
+        // Versions without a creation time are retained together with the next version.
+        db.set_created_at(2, None);
+        let (mut pruner, _handle) = MerkleTreePruner::new(&mut db, 0);
+        pruner.set_retention_period(Duration::from_secs(3_600));
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 1);
+        assert_eq!(stats.deleted_stale_key_versions, 1..2);
+    }
+
+    #[test]
+    fn retention_period_is_not_applied_without_creation_times() {
+        let mut db = create_db();
+        for version in 0..=4 {
+            db.set_created_at(version, None);
+        }
+
+        let (mut pruner, _handle) = MerkleTreePruner::new(&mut db, 0);
+        pruner.set_retention_period(Duration::from_secs(3_600));
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 4);
+        assert_eq!(stats.deleted_stale_key_versions, 1..5);
+    }
+
+    #[test]
+    fn handle_cannot_pin_versions_being_pruned() {
+        let mut db = create_db();
+        let (mut pruner, handle) = MerkleTreePruner::new(&mut db, 0);
+        assert!(handle.pin_version(3));
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 3);
+
+        assert!(!handle.pin_version(2));
+        assert!(handle.pin_version(3));
+        assert!(handle.pin_version(4));
+        assert_eq!(handle.pinned_versions(), [3, 4]);
+    }
+
+    #[test]
+    fn pruner_with_checkpoints() {
+        let mut db = PatchSet::default();
+        let kvs = generate_key_value_pairs(0..100);
+        for chunk in kvs.chunks(10) {
+            MerkleTree::new(&mut db).extend(chunk.to_vec());
+        }
+        // Overwrite some of the existing keys so that nodes in the checkpoints are replaced.
+        let new_kvs = generate_key_value_pairs((0..100).step_by(3));
+        let new_kvs = new_kvs.into_iter().map(|entry| TreeEntry {
+            value: ValueHash::repeat_byte(1),
+            ..entry
+        });
+        MerkleTree::new(&mut db).extend(new_kvs.collect());
+
+        let (mut pruner, _handle) = MerkleTreePruner::new(&mut db, 0);
+        pruner.set_checkpoint_interval(4);
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 10);
+        assert_eq!(stats.deleted_stale_key_versions, 1..11);
+        assert!(stats.pruned_key_count > 0);
+        assert!(stats.retained_key_count > 0);
+        drop(pruner);
+
+        assert_eq!(db.min_stale_key_version(), None);
+        let tree = MerkleTree::new(&mut db);
+        for version in 0..=10 {
+            if version % 4 == 0 || version == 10 {
+                tree.verify_consistency(version, true).unwrap();
+            } else {
+                assert!(tree.root(version).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn rate_limited_pruner() {
+        let mut db = create_db();
+        let (mut pruner, _handle) = MerkleTreePruner::new(&mut db, 0);
+        pruner.set_max_pruned_keys_per_second(1);
+
+        let delay = pruner.run_iteration();
+        // At least 1 key should be pruned, so the pruner should wait for ~1s.
+        assert!(delay > Duration::from_millis(500), "{delay:?}");
+        drop(pruner);
+        // The pruner should've processed stale keys only for a single version.
+        assert_eq!(db.min_stale_key_version(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn async_pruner_basics() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut db = RocksDBWrapper::new(temp_dir.path());
+        populate_db(&mut db);
+
+        let (mut pruner, _handle) = MerkleTreePruner::new(db.clone(), 0);
+        pruner.set_poll_interval(Duration::from_secs(30));
+        let (stop_sender, stop_receiver) = watch::channel(false);
+        let pruner_task = tokio::spawn(pruner.run_async(stop_receiver));
+
+        let started_at = Instant::now();
+        while db.min_stale_key_version().is_some() {
+            assert!(
+                started_at.elapsed() < Duration::from_secs(10),
+                "Pruner didn't prune the tree in time"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        for version in 0..4 {
+            assert!(db.root(version).is_none());
+        }
+
+        stop_sender.send_replace(true);
+        tokio::time::timeout(Duration::from_secs(10), pruner_task)
+            .await
+            .expect("Pruner was not stopped in time")
+            .unwrap();
+    }
+
     #[test]
     fn pruner_is_aborted_immediately_when_requested() {
         let (mut pruner, pruner_handle) = MerkleTreePruner::new(PatchSet::default(), 0);
@@ -0,0 +1,365 @@
+//! Exporting tree snapshots as size-bounded chunks.
+
+use std::{
+    fs, io, mem,
+    path::{Path, PathBuf},
+};
+
+use super::RecoveryChunk;
+use crate::{
+    getters::load_root,
+    hasher::{HashTree, HasherWithStats},
+    storage::Database,
+    types::{Key, Nibbles, Node, Root, TreeEntry, ValueHash, HASH_SIZE, KEY_SIZE, TREE_DEPTH},
+    MerkleTree, NoVersionError,
+};
+
+/// Maximum byte size of a LEB128-encoded `u64` value.
+const MAX_LEB128_SIZE: usize = 10;
+/// Maximum byte size of an encoded snapshot entry.
+const MAX_ENTRY_SIZE: usize = KEY_SIZE + HASH_SIZE + MAX_LEB128_SIZE;
+/// Maximum byte size of an encoded chunk header: the format version, tree version, root hash,
+/// entry count, boundary keys and boundary Merkle paths.
+const MAX_HEADER_SIZE: usize =
+    3 * MAX_LEB128_SIZE + HASH_SIZE + 2 * KEY_SIZE + 2 * (MAX_LEB128_SIZE + TREE_DEPTH * HASH_SIZE);
+/// Minimum allowed chunk size. A chunk of this size fits a single entry.
+const MIN_CHUNK_SIZE: usize = MAX_HEADER_SIZE + MAX_ENTRY_SIZE;
+/// Default maximum chunk size.
+const DEFAULT_MAX_CHUNK_SIZE: usize = 16 << 20; // 16 MiB
+
+/// Header of a [`SnapshotChunk`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotChunkHeader {
+    /// Exported tree version.
+    pub version: u64,
+    /// Root hash of the exported tree version.
+    pub root_hash: ValueHash,
+    /// Number of entries in the chunk.
+    pub entry_count: u64,
+    /// Key of the first entry in the chunk.
+    pub first_key: Key,
+    /// Key of the last entry in the chunk.
+    pub last_key: Key,
+    /// Merkle path for the first entry in the chunk.
+    pub first_entry_path: Vec<ValueHash>,
+    /// Merkle path for the last entry in the chunk.
+    pub last_entry_path: Vec<ValueHash>,
+}
+
+/// Chunk of a tree snapshot produced by [`SnapshotExporter`]. Chunks can be encoded in
+/// the binary wire format and fed to [`MerkleTreeRecovery`](super::MerkleTreeRecovery)
+/// after converting them to [`RecoveryChunk`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotChunk {
+    /// Chunk header.
+    pub header: SnapshotChunkHeader,
+    /// Entries in the chunk ordered by increasing key.
+    pub entries: Vec<TreeEntry>,
+}
+
+impl From<SnapshotChunk> for RecoveryChunk {
+    fn from(chunk: SnapshotChunk) -> Self {
+        Self {
+            entries: chunk.entries,
+            first_entry_path: chunk.header.first_entry_path,
+            last_entry_path: chunk.header.last_entry_path,
+        }
+    }
+}
+
+/// Exporter of a tree version as a snapshot consisting of [`SnapshotChunk`]s. Leaves are
+/// exported in the increasing key order; each chunk occupies at most the
+/// [configured](Self::set_max_chunk_size()) number of bytes when encoded.
+#[derive(Debug)]
+pub struct SnapshotExporter<'a, DB, H> {
+    tree: &'a MerkleTree<DB, H>,
+    version: u64,
+    root: Root,
+    root_hash: ValueHash,
+    max_chunk_size: usize,
+}
+
+impl<'a, DB: Database, H: HashTree> SnapshotExporter<'a, DB, H> {
+    /// Creates an exporter for the specified tree `version`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version doesn't exist.
+    pub fn new(tree: &'a MerkleTree<DB, H>, version: u64) -> Result<Self, NoVersionError> {
+        let root = load_root(&tree.db, version)?;
+        let root_hash = match &root {
+            Root::Empty => tree.hasher.empty_tree_hash(),
+            Root::Filled { node, .. } => node.hash(&mut HasherWithStats::new(&tree.hasher), 0),
+        };
+        Ok(Self {
+            tree,
+            version,
+            root,
+            root_hash,
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the maximum byte size of an encoded chunk. The chunk size is estimated conservatively,
+    /// assuming maximum-length Merkle paths for boundary entries.
+    ///
+    /// The default value is 16 MiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is too small to fit a chunk with a single entry (~16 KiB).
+    pub fn set_max_chunk_size(&mut self, size: usize) {
+        assert!(
+            size >= MIN_CHUNK_SIZE,
+            "Max chunk size must be at least {MIN_CHUNK_SIZE} bytes"
+        );
+        self.max_chunk_size = size;
+    }
+
+    /// Exports the tree version, supplying chunks to `sink` in the increasing key order.
+    /// Returns the number of exported chunks; an empty tree is exported as zero chunks.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors returned by `sink`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree version is inconsistent (e.g., some of its nodes are missing).
+    pub fn export(
+        &self,
+        mut sink: impl FnMut(SnapshotChunk) -> io::Result<()>,
+    ) -> io::Result<usize> {
+        let Root::Filled { node, .. } = &self.root else {
+            return Ok(0);
+        };
+        let max_entry_count = (self.max_chunk_size - MAX_HEADER_SIZE) / MAX_ENTRY_SIZE;
+
+        let mut chunk_count = 0;
+        let mut entries = Vec::with_capacity(max_entry_count.min(1 << 16));
+        let mut on_leaf = |entry: TreeEntry| -> io::Result<()> {
+            entries.push(entry);
+            if entries.len() == max_entry_count {
+                sink(self.create_chunk(mem::take(&mut entries)))?;
+                chunk_count += 1;
+            }
+            Ok(())
+        };
+        self.visit_leaves(Nibbles::EMPTY, node, &mut on_leaf)?;
+
+        if !entries.is_empty() {
+            sink(self.create_chunk(entries))?;
+            chunk_count += 1;
+        }
+        tracing::info!(
+            "Exported tree version {} as {chunk_count} chunk(s)",
+            self.version
+        );
+        Ok(chunk_count)
+    }
+
+    /// Exports the tree version to files in the specified directory, which must exist.
+    /// Returns paths to the created files ordered by chunk index.
+    ///
+    /// # Errors
+    ///
+    /// Propagates I/O errors.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same situations as [`Self::export()`].
+    pub fn export_to_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        self.export(|chunk| {
+            let path = dir.join(format!(
+                "snapshot_{}_chunk_{:06}.bin",
+                self.version,
+                paths.len()
+            ));
+            fs::write(&path, chunk.to_bytes())?;
+            paths.push(path);
+            Ok(())
+        })?;
+        Ok(paths)
+    }
+
+    /// Visits leaves in the subtree rooted at `node` in the increasing key order.
+    fn visit_leaves(
+        &self,
+        nibbles: Nibbles,
+        node: &Node,
+        on_leaf: &mut dyn FnMut(TreeEntry) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let node = match node {
+            Node::Leaf(leaf) => return on_leaf(TreeEntry::from(*leaf)),
+            Node::Internal(node) => node,
+        };
+
+        let child_keys: Vec<_> = node
+            .children()
+            .map(|(nibble, child_ref)| {
+                let child_nibbles = nibbles.push(nibble).unwrap();
+                // ^ `unwrap()` is safe: internal nodes cannot be at the terminal level
+                (
+                    child_nibbles.with_version(child_ref.version),
+                    child_ref.is_leaf,
+                )
+            })
+            .collect();
+        let children = self.tree.db.tree_nodes(&child_keys);
+        for ((child_key, _), child) in child_keys.iter().zip(children) {
+            let child = child.unwrap_or_else(|| panic!("Node at {child_key} is missing"));
+            self.visit_leaves(child_key.nibbles, &child, on_leaf)?;
+        }
+        Ok(())
+    }
+
+    fn create_chunk(&self, entries: Vec<TreeEntry>) -> SnapshotChunk {
+        let first_key = entries[0].key;
+        let last_key = entries[entries.len() - 1].key;
+        let proofs = self
+            .tree
+            .entries_with_proofs(self.version, &[first_key, last_key])
+            .unwrap();
+        // ^ `unwrap()` is safe: the version was checked to exist on exporter creation
+        let [first_entry, last_entry]: [_; 2] = proofs.try_into().unwrap();
+
+        SnapshotChunk {
+            header: SnapshotChunkHeader {
+                version: self.version,
+                root_hash: self.root_hash,
+                entry_count: entries.len() as u64,
+                first_key,
+                last_key,
+                first_entry_path: first_entry.merkle_path,
+                last_entry_path: last_entry.merkle_path,
+            },
+            entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use futures::StreamExt;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{recovery::MerkleTreeRecovery, PatchSet};
+
+    fn create_tree() -> MerkleTree<PatchSet> {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        for version in 0..3_u64 {
+            let entries = (0..50_u64).map(|i| {
+                let index = version * 50 + i;
+                let key = Key::from(index).wrapping_mul(Key::from(0x_dead_beef_u64) << 200);
+                TreeEntry::new(key, index + 1, ValueHash::repeat_byte(version as u8 + 1))
+            });
+            tree.extend(entries.collect());
+        }
+        tree
+    }
+
+    #[test]
+    fn exporting_snapshot_in_chunks() {
+        let tree = create_tree();
+        let mut exporter = SnapshotExporter::new(&tree, 1).unwrap();
+        exporter.set_max_chunk_size(MIN_CHUNK_SIZE + 10 * MAX_ENTRY_SIZE);
+
+        let mut chunks = vec![];
+        let chunk_count = exporter
+            .export(|chunk| {
+                chunks.push(chunk);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(chunk_count, 10); // 100 entries, 11 entries per chunk
+        assert_eq!(chunks.len(), chunk_count);
+
+        let root_hash = tree.root_hash(1).unwrap();
+        let mut all_entries = vec![];
+        for chunk in &chunks {
+            let header = &chunk.header;
+            assert_eq!(header.version, 1);
+            assert_eq!(header.root_hash, root_hash);
+            assert_eq!(header.entry_count, chunk.entries.len() as u64);
+            assert_eq!(header.first_key, chunk.entries[0].key);
+            assert_eq!(header.last_key, chunk.entries.last().unwrap().key);
+            assert!(chunk.to_bytes().len() <= exporter.max_chunk_size);
+
+            let restored_chunk = SnapshotChunk::from_bytes(&chunk.to_bytes()).unwrap();
+            assert_eq!(restored_chunk, *chunk);
+            all_entries.extend_from_slice(&chunk.entries);
+        }
+        assert_eq!(all_entries.len(), 100);
+        assert!(all_entries
+            .windows(2)
+            .all(|window| window[0].key < window[1].key));
+
+        let empty_tree = MerkleTree::new(PatchSet::default());
+        let exporter = SnapshotExporter::new(&empty_tree, 0);
+        assert_matches!(exporter, Err(NoVersionError { .. }));
+    }
+
+    #[test]
+    fn malformed_chunks_are_rejected() {
+        let tree = create_tree();
+        let exporter = SnapshotExporter::new(&tree, 0).unwrap();
+        let mut chunks = vec![];
+        exporter
+            .export(|chunk| {
+                chunks.push(chunk);
+                Ok(())
+            })
+            .unwrap();
+        let [chunk] = chunks.try_into().unwrap();
+
+        let mut bytes = chunk.to_bytes();
+        bytes.push(0);
+        let err = SnapshotChunk::from_bytes(&bytes).unwrap_err().to_string();
+        assert!(err.contains("trailing byte"), "{err}");
+
+        let mut malformed_chunk = chunk;
+        malformed_chunk.header.first_key = Key::MAX;
+        let err = SnapshotChunk::from_bytes(&malformed_chunk.to_bytes())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("first key"), "{err}");
+
+        // Truncate the chunk in the middle of the LEB128-encoded entry count.
+        let mut bytes = vec![1, 0]; // format version, tree version
+        bytes.extend_from_slice(&[0; 32]); // root hash
+        bytes.push(0x80);
+        let err = SnapshotChunk::from_bytes(&bytes).unwrap_err().to_string();
+        assert!(err.contains("number of snapshot entries"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn exported_snapshot_can_be_recovered() {
+        let tree = create_tree();
+        let temp_dir = TempDir::new().unwrap();
+        let mut exporter = SnapshotExporter::new(&tree, 2).unwrap();
+        exporter.set_max_chunk_size(MIN_CHUNK_SIZE + 20 * MAX_ENTRY_SIZE);
+        let paths = exporter.export_to_dir(temp_dir.path()).unwrap();
+        assert_eq!(paths.len(), 8); // 150 entries, 21 entries per chunk
+
+        let chunks = futures::stream::iter(paths).then(|path| async move {
+            let bytes = fs::read(path)?;
+            let chunk = SnapshotChunk::from_bytes(&bytes)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            Ok::<_, io::Error>(RecoveryChunk::from(chunk))
+        });
+        let root_hash = tree.root_hash(2).unwrap();
+        let recovery = MerkleTreeRecovery::new(PatchSet::default(), 2);
+        let (recovery, stats) = recovery
+            .extend_from_stream(chunks, root_hash, 3)
+            .await
+            .unwrap();
+        assert_eq!(stats.applied_entry_count, 150);
+
+        let recovered_tree = MerkleTree::new(recovery.finalize());
+        assert_eq!(recovered_tree.latest_root_hash(), root_hash);
+        recovered_tree.verify_consistency(2, true).unwrap();
+    }
+}
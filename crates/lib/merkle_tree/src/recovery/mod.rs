@@ -28,7 +28,10 @@
 //!
 //! `RecoveryEntry` chunks are not validated during recovery. They can be authenticated using
 //! [`TreeRangeDigest`](crate::TreeRangeDigest)s provided that the tree root hash is authenticated
-//! using external means.
+//! using external means. [`MerkleTreeRecovery::extend_from_stream()`] performs such
+//! authentication for [`RecoveryChunk`]s supplied by an async stream.
+//!
+//! Snapshots can be produced from an existing tree using [`SnapshotExporter`].
 //!
 //! # Implementation details
 //!
@@ -41,6 +44,13 @@ use std::time::Instant;
 
 use axon_types::primitives::hasher::blake2::Blake2Hasher;
 
+mod export;
+mod streaming;
+
+pub use self::{
+    export::{SnapshotChunk, SnapshotChunkHeader, SnapshotExporter},
+    streaming::{RecoveryChunk, StreamingRecoveryError, StreamingRecoveryStats},
+};
 use crate::{
     hasher::{HashTree, HasherWithStats},
     storage::{PatchSet, PruneDatabase, PrunePatchSet, Storage},
@@ -51,7 +61,7 @@ use crate::{
 #[derive(Debug)]
 pub struct MerkleTreeRecovery<DB, H = Blake2Hasher> {
     pub(crate) db: DB,
-    hasher: H,
+    pub(crate) hasher: H,
     recovered_version: u64,
 }
 
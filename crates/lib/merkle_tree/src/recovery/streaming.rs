@@ -0,0 +1,698 @@
+//! Streaming recovery from an external source of snapshot chunks.
+
+use std::{error::Error as StdError, panic, pin::pin};
+
+use futures::{Stream, StreamExt};
+use tokio::task::JoinError;
+
+use super::MerkleTreeRecovery;
+use crate::{
+    hasher::{HashTree, TreeRangeDigest},
+    storage::PruneDatabase,
+    types::{Key, TreeEntry, TreeEntryWithProof, ValueHash},
+};
+
+/// Chunk of a tree snapshot covering a contiguous range of keys. Chunks are supplied to
+/// [`MerkleTreeRecovery::extend_from_stream()`].
+///
+/// The range of keys covered by the chunk is defined by its first and last entries.
+/// Merkle proofs for these entries allow checking that the chunk contains *all* tree entries
+/// in this range using a [`TreeRangeDigest`].
+#[derive(Debug, Clone)]
+pub struct RecoveryChunk {
+    /// Entries in the chunk ordered by increasing key.
+    pub entries: Vec<TreeEntry>,
+    /// Merkle path for the first entry in the chunk.
+    pub first_entry_path: Vec<ValueHash>,
+    /// Merkle path for the last entry in the chunk. Ignored if the chunk has a single entry.
+    pub last_entry_path: Vec<ValueHash>,
+}
+
+impl RecoveryChunk {
+    fn first_entry_with_proof(&self) -> TreeEntryWithProof {
+        TreeEntryWithProof {
+            base: self.entries[0],
+            merkle_path: self.first_entry_path.clone(),
+        }
+    }
+
+    fn last_entry_with_proof(&self) -> TreeEntryWithProof {
+        let merkle_path = if self.entries.len() == 1 {
+            &self.first_entry_path
+        } else {
+            &self.last_entry_path
+        };
+        TreeEntryWithProof {
+            base: self.entries[self.entries.len() - 1],
+            merkle_path: merkle_path.clone(),
+        }
+    }
+
+    fn verify(
+        &self,
+        hasher: &dyn HashTree,
+        chunk_index: usize,
+        expected_root_hash: ValueHash,
+    ) -> Result<(), StreamingRecoveryError> {
+        let Some(first_entry) = self.entries.first() else {
+            return Err(StreamingRecoveryError::EmptyChunk { chunk_index });
+        };
+        let is_sorted = self
+            .entries
+            .windows(2)
+            .all(|window| window[0].key < window[1].key);
+        if !is_sorted {
+            return Err(StreamingRecoveryError::UnsortedChunk { chunk_index });
+        }
+        // Since entries are sorted, it's sufficient to check the last key.
+        let last_key = self.entries[self.entries.len() - 1].key;
+        let depth = hasher.depth();
+        if last_key.bit_len() > depth {
+            return Err(StreamingRecoveryError::KeyOutOfRange {
+                chunk_index,
+                key: last_key,
+                depth,
+            });
+        }
+
+        let root_hash = if self.entries.len() == 1 {
+            hasher.fold_merkle_path(&self.first_entry_path, *first_entry)
+        } else {
+            let first_entry = self.first_entry_with_proof();
+            let mut digest = TreeRangeDigest::new(hasher, first_entry.base.key, &first_entry);
+            for &entry in &self.entries[1..self.entries.len() - 1] {
+                digest.update(entry);
+            }
+            digest.finalize(&self.last_entry_with_proof())
+        };
+
+        if root_hash == expected_root_hash {
+            Ok(())
+        } else {
+            Err(StreamingRecoveryError::RootHashMismatch {
+                chunk_index,
+                expected: expected_root_hash,
+                actual: root_hash,
+            })
+        }
+    }
+
+    /// Checks that the tree has no entries between `prev_entry` (the last entry of the preceding
+    /// chunk) and the first entry of this chunk. Only hashes to the left of `prev_entry` in its
+    /// Merkle path are used, so the path may be taken from a partially recovered tree.
+    fn verify_gap(
+        &self,
+        hasher: &dyn HashTree,
+        chunk_index: usize,
+        prev_entry: &TreeEntryWithProof,
+        expected_root_hash: ValueHash,
+    ) -> Result<(), StreamingRecoveryError> {
+        let digest = TreeRangeDigest::new(hasher, prev_entry.base.key, prev_entry);
+        let root_hash = digest.finalize(&self.first_entry_with_proof());
+        if root_hash == expected_root_hash {
+            Ok(())
+        } else {
+            Err(StreamingRecoveryError::MissingEntries {
+                chunk_index,
+                prev_key: prev_entry.base.key,
+                start_key: self.entries[0].key,
+            })
+        }
+    }
+}
+
+/// Error that can occur during [streaming recovery](MerkleTreeRecovery::extend_from_stream()).
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum StreamingRecoveryError {
+    /// Error reading a chunk from the source.
+    #[error("failed reading chunk #{chunk_index}: {source}")]
+    Source {
+        /// Zero-based index of the chunk in the stream.
+        chunk_index: usize,
+        /// Error returned by the source.
+        #[source]
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// Chunk has no entries.
+    #[error("chunk #{chunk_index} is empty")]
+    EmptyChunk {
+        /// Zero-based index of the chunk in the stream.
+        chunk_index: usize,
+    },
+    /// Chunk entries are not sorted by increasing key.
+    #[error("entries in chunk #{chunk_index} are not sorted by increasing key")]
+    UnsortedChunk {
+        /// Zero-based index of the chunk in the stream.
+        chunk_index: usize,
+    },
+    /// Chunk contains a key that doesn't fit into the tree (i.e., is greater or equal
+    /// to `2^depth`).
+    #[error(
+        "chunk #{chunk_index} contains key {key:0>64x} not fitting into the tree \
+         with depth {depth}"
+    )]
+    KeyOutOfRange {
+        /// Zero-based index of the chunk in the stream.
+        chunk_index: usize,
+        /// Key not fitting into the tree.
+        key: Key,
+        /// Tree depth.
+        depth: usize,
+    },
+    /// Chunk overlaps with a preceding chunk in the stream.
+    #[error(
+        "chunk #{chunk_index} starting with key {start_key:0>64x} overlaps with a preceding chunk \
+         ending with key {prev_key:0>64x}"
+    )]
+    OverlappingChunk {
+        /// Zero-based index of the chunk in the stream.
+        chunk_index: usize,
+        /// First key in the chunk.
+        start_key: Key,
+        /// Last key in the preceding chunk.
+        prev_key: Key,
+    },
+    /// Root hash computed for the chunk using its boundary proofs doesn't match the expected one.
+    #[error(
+        "root hash computed for chunk #{chunk_index} ({actual:?}) differs from the expected one \
+         ({expected:?})"
+    )]
+    RootHashMismatch {
+        /// Zero-based index of the chunk in the stream.
+        chunk_index: usize,
+        /// Expected root hash of the recovered tree.
+        expected: ValueHash,
+        /// Root hash computed for the chunk.
+        actual: ValueHash,
+    },
+    /// Tree has entries between the preceding chunk (or the last entry recovered previously)
+    /// and the chunk, i.e., the stream misses chunks.
+    #[error(
+        "tree has entries between key {prev_key:0>64x} and chunk #{chunk_index} starting with key \
+         {start_key:0>64x} that are missing from the stream"
+    )]
+    MissingEntries {
+        /// Zero-based index of the chunk in the stream.
+        chunk_index: usize,
+        /// Last key in the preceding chunk, or the last key recovered previously.
+        prev_key: Key,
+        /// First key in the chunk.
+        start_key: Key,
+    },
+    /// Root hash of the tree after applying all chunks from the stream doesn't match the expected
+    /// one, i.e., the stream misses chunks at its start or end.
+    #[error(
+        "root hash of the recovered tree ({actual:?}) differs from the expected one \
+         ({expected:?}); the stream is probably missing chunks at its start or end"
+    )]
+    RecoveredRootHashMismatch {
+        /// Expected root hash of the recovered tree.
+        expected: ValueHash,
+        /// Root hash of the recovered tree.
+        actual: ValueHash,
+    },
+}
+
+/// Statistics for [streaming recovery](MerkleTreeRecovery::extend_from_stream()).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamingRecoveryStats {
+    /// Number of chunks applied to the tree.
+    pub applied_chunk_count: usize,
+    /// Number of chunks skipped because they were recovered previously.
+    pub skipped_chunk_count: usize,
+    /// Number of entries applied to the tree.
+    pub applied_entry_count: usize,
+}
+
+impl<DB, H> MerkleTreeRecovery<DB, H>
+where
+    DB: PruneDatabase + Send + 'static,
+    H: HashTree + Clone + 'static,
+{
+    /// Extends the tree with chunks from an async stream, e.g., read from files on the local disk.
+    ///
+    /// Chunks must cover non-overlapping key ranges and be ordered by increasing key. Each chunk
+    /// is checked against `expected_root_hash` using its boundary proofs; up to
+    /// `verification_concurrency` chunks are read and checked concurrently on the blocking thread
+    /// pool. Verified chunks are applied in batches: all chunks verified by the time the previous
+    /// batch is applied are combined, so that hashing of their entries is parallelized.
+    /// Batches consist of consecutive chunks and are applied in the stream order, so the progress
+    /// is persisted after each batch: if recovery is interrupted, it can be resumed by supplying
+    /// the same stream. Chunks (or their parts) with keys not exceeding
+    /// [`Self::last_processed_key()`] are skipped.
+    ///
+    /// Before a chunk is applied, the gap between it and the preceding chunk (or the last entry
+    /// recovered previously) is checked to contain no tree entries. After the stream ends,
+    /// the root hash of the recovered tree is compared to `expected_root_hash`.
+    ///
+    /// Trees with a [custom depth](crate::WithDepth) are supported; the depth is taken
+    /// from the tree hasher.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading a chunk fails, a chunk is malformed or doesn't match
+    /// `expected_root_hash`, or the stream misses chunks. Chunks preceding the erroneous one
+    /// are persisted, so recovery can be resumed by creating a new recovery instance for the same
+    /// database. If the root hash mismatch is detected after the stream ends, the recovered
+    /// tree must be discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `verification_concurrency` is zero. Propagates panics occurring when processing
+    /// chunks.
+    pub async fn extend_from_stream<S, E>(
+        self,
+        chunks: S,
+        expected_root_hash: ValueHash,
+        verification_concurrency: usize,
+    ) -> Result<(Self, StreamingRecoveryStats), StreamingRecoveryError>
+    where
+        S: Stream<Item = Result<RecoveryChunk, E>>,
+        E: StdError + Send + Sync + 'static,
+    {
+        assert!(
+            verification_concurrency > 0,
+            "Verification concurrency must be positive"
+        );
+
+        let task = tokio::task::spawn_blocking(move || {
+            let last_entry = self.last_entry_with_proof();
+            (self, last_entry)
+        });
+        let (mut this, recovered_entry) = unwrap_join_result(task.await);
+        let last_processed_key = recovered_entry.as_ref().map(|entry| entry.base.key);
+        if let Some(key) = last_processed_key {
+            tracing::info!("Resuming recovery after key {key:0>64x}");
+        }
+        let hasher = this.hasher.clone();
+        let checked_chunks = chunks.enumerate().map(|(chunk_index, chunk)| {
+            let hasher = hasher.clone();
+            async move {
+                let chunk = chunk.map_err(|err| StreamingRecoveryError::Source {
+                    chunk_index,
+                    source: Box::new(err),
+                })?;
+                let is_recovered = chunk
+                    .entries
+                    .last()
+                    .is_some_and(|entry| last_processed_key.is_some_and(|key| entry.key <= key));
+                if is_recovered {
+                    return Ok((chunk_index, None));
+                }
+
+                let task = tokio::task::spawn_blocking(move || {
+                    let verification = chunk.verify(&hasher, chunk_index, expected_root_hash);
+                    verification.map(|()| chunk)
+                });
+                let chunk = unwrap_join_result(task.await)?;
+                Ok::<_, StreamingRecoveryError>((chunk_index, Some(chunk)))
+            }
+        });
+        let mut checked_chunks = pin!(checked_chunks
+            .buffered(verification_concurrency)
+            .ready_chunks(verification_concurrency));
+
+        let mut stats = StreamingRecoveryStats::default();
+        let mut prev_key = None;
+        // Last entry preceding the next chunk, used to check that there are no gaps between chunks.
+        let mut prev_entry = recovered_entry;
+        while let Some(checked_chunks_batch) = checked_chunks.next().await {
+            // All chunks verified by this time are applied together. They cover non-overlapping
+            // key ranges, so their entries are hashed in parallel when the batch is applied.
+            let mut batch_entries = vec![];
+            let mut batch_chunk_indices = vec![];
+            let mut batch_error = None;
+            for checked_chunk in checked_chunks_batch {
+                let (chunk_index, chunk) = match checked_chunk {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        batch_error = Some(err);
+                        break;
+                    }
+                };
+                let Some(mut chunk) = chunk else {
+                    tracing::debug!("Skipping chunk #{chunk_index} recovered previously");
+                    stats.skipped_chunk_count += 1;
+                    continue;
+                };
+
+                let start_key = chunk.entries[0].key;
+                // ^ Indexing is safe: the chunk was checked to be non-empty.
+                if let Some(prev_key) = prev_key.filter(|&key| start_key <= key) {
+                    batch_error = Some(StreamingRecoveryError::OverlappingChunk {
+                        chunk_index,
+                        start_key,
+                        prev_key,
+                    });
+                    break;
+                }
+                prev_key = chunk.entries.last().map(|entry| entry.key);
+
+                // If the chunk starts before the last recovered entry, there's no gap to check;
+                // the chunk itself is checked to cover its key range completely.
+                if let Some(prev_entry) = prev_entry.filter(|entry| entry.base.key < start_key) {
+                    let gap_check =
+                        chunk.verify_gap(&hasher, chunk_index, &prev_entry, expected_root_hash);
+                    if let Err(err) = gap_check {
+                        batch_error = Some(err);
+                        break;
+                    }
+                }
+                prev_entry = Some(chunk.last_entry_with_proof());
+
+                if let Some(key) = last_processed_key {
+                    let recovered_entry_count =
+                        chunk.entries.partition_point(|entry| entry.key <= key);
+                    chunk.entries.drain(..recovered_entry_count);
+                }
+                batch_chunk_indices.push(chunk_index);
+                batch_entries.extend(chunk.entries);
+            }
+
+            // Chunks preceding an erroneous one are applied so that the progress isn't lost.
+            if !batch_chunk_indices.is_empty() {
+                let entry_count = batch_entries.len();
+                stats.applied_chunk_count += batch_chunk_indices.len();
+                stats.applied_entry_count += entry_count;
+
+                let task = tokio::task::spawn_blocking(move || {
+                    this.extend_random(batch_entries);
+                    this
+                });
+                this = unwrap_join_result(task.await);
+                tracing::info!(
+                    "Applied chunks {batch_chunk_indices:?} with {entry_count} entries in total"
+                );
+            }
+            if let Some(err) = batch_error {
+                return Err(err);
+            }
+        }
+
+        let task = tokio::task::spawn_blocking(move || {
+            let root_hash = this.root_hash();
+            (this, root_hash)
+        });
+        let (this, root_hash) = unwrap_join_result(task.await);
+        if root_hash != expected_root_hash {
+            return Err(StreamingRecoveryError::RecoveredRootHashMismatch {
+                expected: expected_root_hash,
+                actual: root_hash,
+            });
+        }
+        Ok((this, stats))
+    }
+}
+
+fn unwrap_join_result<T>(result: Result<T, JoinError>) -> T {
+    match result {
+        Ok(output) => output,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        Err(err) => panic!("Recovery task was terminated: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use assert_matches::assert_matches;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{storage::PatchSet, Blake2Hasher, MerkleTree, RocksDBWrapper, WithDepth};
+
+    fn create_source_tree() -> (MerkleTree<PatchSet>, Vec<TreeEntry>) {
+        let entries = (0..100_u64).map(|i| {
+            let key = (Key::from(i) << 240) | Key::from(i * 3);
+            TreeEntry::new(key, i + 1, ValueHash::repeat_byte(i as u8))
+        });
+        let entries: Vec<_> = entries.collect();
+        let mut tree = MerkleTree::new(PatchSet::default());
+        tree.extend(entries.clone());
+        (tree, entries)
+    }
+
+    fn create_chunks<H: HashTree>(
+        tree: &MerkleTree<PatchSet, H>,
+        entries: &[TreeEntry],
+        chunk_size: usize,
+    ) -> Vec<RecoveryChunk> {
+        let chunks = entries.chunks(chunk_size).map(|entries| {
+            let boundary_keys = [entries[0].key, entries[entries.len() - 1].key];
+            let [first_entry, last_entry] = tree
+                .entries_with_proofs(0, &boundary_keys)
+                .unwrap()
+                .try_into()
+                .unwrap();
+            RecoveryChunk {
+                entries: entries.to_vec(),
+                first_entry_path: first_entry.merkle_path,
+                last_entry_path: last_entry.merkle_path,
+            }
+        });
+        chunks.collect()
+    }
+
+    fn chunk_stream(
+        chunks: Vec<RecoveryChunk>,
+    ) -> impl Stream<Item = Result<RecoveryChunk, io::Error>> {
+        futures::stream::iter(chunks.into_iter().map(Ok))
+    }
+
+    #[tokio::test]
+    async fn recovering_tree_from_stream() {
+        let (tree, entries) = create_source_tree();
+        let root_hash = tree.latest_root_hash();
+        let chunks = create_chunks(&tree, &entries, 7);
+
+        let recovery = MerkleTreeRecovery::new(PatchSet::default(), 0);
+        let (recovery, stats) = recovery
+            .extend_from_stream(chunk_stream(chunks), root_hash, 4)
+            .await
+            .unwrap();
+        assert_eq!(stats.applied_chunk_count, 15);
+        assert_eq!(stats.skipped_chunk_count, 0);
+        assert_eq!(stats.applied_entry_count, entries.len());
+        assert_eq!(recovery.root_hash(), root_hash);
+
+        let tree = MerkleTree::new(recovery.finalize());
+        tree.verify_consistency(0, true).unwrap();
+    }
+
+    #[tokio::test]
+    async fn recovering_tree_with_custom_depth_from_stream() {
+        let hasher = WithDepth::new(Blake2Hasher, 64);
+        let entries = (0..100_u64).map(|i| {
+            let key = (Key::from(i) << 56) | Key::from(i * 3);
+            TreeEntry::new(key, i + 1, ValueHash::repeat_byte(i as u8))
+        });
+        let entries: Vec<_> = entries.collect();
+        let mut tree = MerkleTree::with_hasher(PatchSet::default(), hasher.clone());
+        tree.extend(entries.clone());
+        let root_hash = tree.latest_root_hash();
+        let chunks = create_chunks(&tree, &entries, 7);
+
+        let recovery = MerkleTreeRecovery::with_hasher(PatchSet::default(), 0, hasher.clone());
+        let (recovery, stats) = recovery
+            .extend_from_stream(chunk_stream(chunks.clone()), root_hash, 4)
+            .await
+            .unwrap();
+        assert_eq!(stats.applied_entry_count, entries.len());
+        assert_eq!(recovery.root_hash(), root_hash);
+
+        let mut truncated_chunks = chunks.clone();
+        truncated_chunks.remove(3);
+        let recovery = MerkleTreeRecovery::with_hasher(PatchSet::default(), 0, hasher.clone());
+        let err = recovery
+            .extend_from_stream(chunk_stream(truncated_chunks), root_hash, 4)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            StreamingRecoveryError::MissingEntries { chunk_index: 3, .. }
+        );
+
+        let mut invalid_chunks = chunks;
+        invalid_chunks[1].entries[6].key = Key::from(1) << 64;
+        let recovery = MerkleTreeRecovery::with_hasher(PatchSet::default(), 0, hasher);
+        let err = recovery
+            .extend_from_stream(chunk_stream(invalid_chunks), root_hash, 4)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            StreamingRecoveryError::KeyOutOfRange {
+                chunk_index: 1,
+                depth: 64,
+                ..
+            }
+        );
+    }
+
+    /// Returns a stream that fails after yielding the specified chunks.
+    fn interrupted_chunk_stream(
+        chunks: Vec<RecoveryChunk>,
+    ) -> impl Stream<Item = Result<RecoveryChunk, io::Error>> {
+        let error = io::Error::other("source failure");
+        chunk_stream(chunks).chain(futures::stream::once(async move { Err(error) }))
+    }
+
+    async fn recover_partially(
+        db: RocksDBWrapper,
+        chunks: Vec<RecoveryChunk>,
+        root_hash: ValueHash,
+    ) -> MerkleTreeRecovery<RocksDBWrapper> {
+        let chunk_count = chunks.len();
+        let recovery = MerkleTreeRecovery::new(db.clone(), 0);
+        let err = recovery
+            .extend_from_stream(interrupted_chunk_stream(chunks), root_hash, 2)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            StreamingRecoveryError::Source { chunk_index, .. } if chunk_index == chunk_count
+        );
+        MerkleTreeRecovery::new(db, 0)
+    }
+
+    #[tokio::test]
+    async fn resuming_recovery_from_stream() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let (tree, entries) = create_source_tree();
+        let root_hash = tree.latest_root_hash();
+        let chunks = create_chunks(&tree, &entries, 10);
+
+        let recovery = recover_partially(db, chunks[..3].to_vec(), root_hash).await;
+        assert_eq!(recovery.last_processed_key(), Some(entries[29].key));
+
+        // Emulate a restart with the source using different chunking.
+        let chunks = create_chunks(&tree, &entries, 20);
+        let (recovery, stats) = recovery
+            .extend_from_stream(chunk_stream(chunks), root_hash, 2)
+            .await
+            .unwrap();
+        assert_eq!(stats.skipped_chunk_count, 1);
+        assert_eq!(stats.applied_chunk_count, 4);
+        assert_eq!(stats.applied_entry_count, entries.len() - 30);
+        assert_eq!(recovery.root_hash(), root_hash);
+    }
+
+    #[tokio::test]
+    async fn resuming_recovery_from_stream_at_chunk_boundary() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let (tree, entries) = create_source_tree();
+        let root_hash = tree.latest_root_hash();
+        let chunks = create_chunks(&tree, &entries, 10);
+
+        let recovery = recover_partially(db.clone(), chunks[..3].to_vec(), root_hash).await;
+        // The gap between the last recovered entry and the next chunk should be checked
+        // using the Merkle path from the partially recovered tree.
+        let mut truncated_chunks = chunks.clone();
+        truncated_chunks.remove(3);
+        let err = recovery
+            .extend_from_stream(chunk_stream(truncated_chunks), root_hash, 2)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            StreamingRecoveryError::MissingEntries { chunk_index: 3, prev_key, start_key }
+                if prev_key == entries[29].key && start_key == entries[40].key
+        );
+
+        let recovery = MerkleTreeRecovery::new(db, 0);
+        let (recovery, stats) = recovery
+            .extend_from_stream(chunk_stream(chunks), root_hash, 2)
+            .await
+            .unwrap();
+        assert_eq!(stats.skipped_chunk_count, 3);
+        assert_eq!(stats.applied_chunk_count, 7);
+        assert_eq!(recovery.root_hash(), root_hash);
+    }
+
+    #[tokio::test]
+    async fn missing_chunks_are_detected() {
+        let (tree, entries) = create_source_tree();
+        let root_hash = tree.latest_root_hash();
+        let chunks = create_chunks(&tree, &entries, 10);
+
+        let mut truncated_chunks = chunks.clone();
+        truncated_chunks.remove(4);
+        let recovery = MerkleTreeRecovery::new(PatchSet::default(), 0);
+        let err = recovery
+            .extend_from_stream(chunk_stream(truncated_chunks), root_hash, 2)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            StreamingRecoveryError::MissingEntries { chunk_index: 4, prev_key, start_key }
+                if prev_key == entries[39].key && start_key == entries[50].key
+        );
+
+        for missing_chunk_idx in [0, chunks.len() - 1] {
+            let mut truncated_chunks = chunks.clone();
+            truncated_chunks.remove(missing_chunk_idx);
+            let recovery = MerkleTreeRecovery::new(PatchSet::default(), 0);
+            let err = recovery
+                .extend_from_stream(chunk_stream(truncated_chunks), root_hash, 2)
+                .await
+                .unwrap_err();
+            assert_matches!(
+                err,
+                StreamingRecoveryError::RecoveredRootHashMismatch { expected, .. }
+                    if expected == root_hash
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_chunks_are_rejected() {
+        let (tree, entries) = create_source_tree();
+        let root_hash = tree.latest_root_hash();
+        let mut chunks = create_chunks(&tree, &entries, 10);
+
+        chunks[2].entries[3].value = ValueHash::ZERO;
+        let recovery = MerkleTreeRecovery::new(PatchSet::default(), 0);
+        let err = recovery
+            .extend_from_stream(chunk_stream(chunks.clone()), root_hash, 2)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            StreamingRecoveryError::RootHashMismatch { chunk_index: 2, .. }
+        );
+
+        // Skipped entries should be detected as well.
+        chunks[2].entries.remove(3);
+        let recovery = MerkleTreeRecovery::new(PatchSet::default(), 0);
+        let err = recovery
+            .extend_from_stream(chunk_stream(chunks), root_hash, 2)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            StreamingRecoveryError::RootHashMismatch { chunk_index: 2, .. }
+        );
+    }
+
+    #[tokio::test]
+    async fn overlapping_chunks_are_rejected() {
+        let (tree, entries) = create_source_tree();
+        let root_hash = tree.latest_root_hash();
+        let mut chunks = create_chunks(&tree, &entries, 10);
+        chunks.insert(1, chunks[0].clone());
+
+        let recovery = MerkleTreeRecovery::new(PatchSet::default(), 0);
+        let err = recovery
+            .extend_from_stream(chunk_stream(chunks), root_hash, 2)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            StreamingRecoveryError::OverlappingChunk { chunk_index: 1, .. }
+        );
+    }
+}
@@ -0,0 +1,434 @@
+//! Repairing inconsistencies in the Merkle tree stored in RocksDB.
+
+use std::ops;
+
+use crate::{
+    getters::load_root,
+    hasher::HasherWithStats,
+    storage::RocksDBWrapper,
+    types::{
+        align_key, ChildRef, InternalNode, LeafNode, Nibbles, Node, NodeKey, Root, TREE_DEPTH,
+    },
+    Database, HashTree, Key, MerkleTree, NoVersionError, TreeEntry, ValueHash,
+};
+
+/// Change made to the tree by [`MerkleTree::repair()`] (or planned to be made in the dry-run mode).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairAction {
+    /// Child reference in an internal node was updated to match the actual child.
+    RehashedChild {
+        /// Key of the updated internal node.
+        key: NodeKey,
+        /// Nibble of the updated child reference.
+        nibble: u8,
+        /// Hash previously recorded in the child reference.
+        old_hash: ValueHash,
+        /// Actual hash of the child.
+        new_hash: ValueHash,
+    },
+    /// Subtree rooted at a missing or unreadable node was rebuilt from the supplied entries.
+    RebuiltSubtree {
+        /// Key of the subtree root.
+        key: NodeKey,
+        /// Number of entries in the rebuilt subtree.
+        entry_count: usize,
+    },
+}
+
+/// Outcome of [`MerkleTree::repair()`].
+#[derive(Debug, Clone)]
+pub struct RepairReport {
+    /// Repaired tree version.
+    pub version: u64,
+    /// Changes made to the tree, or planned to be made in the dry-run mode.
+    pub actions: Vec<RepairAction>,
+    /// Keys of missing or unreadable nodes that could not be rebuilt because the supplied source
+    /// has no entries for them.
+    pub unrepaired_nodes: Vec<NodeKey>,
+    /// Root hash of the tree version after repair.
+    pub root_hash: ValueHash,
+    /// Whether the tree was left intact because repair was run in the dry-run mode.
+    pub is_dry_run: bool,
+}
+
+impl<H: HashTree> MerkleTree<RocksDBWrapper, H> {
+    /// Repairs inconsistencies in the specified tree `version`, such as the ones detected by
+    /// [`Self::verify_consistency()`]:
+    ///
+    /// - If the hash of a child differs from the one recorded in its parent internal node,
+    ///   the parent is rehashed and rewritten. Changes propagate up to the tree root.
+    /// - If a node is missing or cannot be deserialized, the subtree rooted at it is rebuilt from
+    ///   entries returned by `entries_source`. The source is queried with the range of keys
+    ///   covered by the subtree and must return all tree entries in this range for `version`.
+    ///
+    /// Rewritten nodes retain their keys, so that other tree versions referencing these nodes
+    /// are repaired as well. Each change is logged. If `dry_run` is set, changes are only
+    /// reported and are not written to the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing.
+    pub fn repair(
+        &mut self,
+        version: u64,
+        entries_source: impl FnMut(ops::RangeInclusive<Key>) -> Vec<TreeEntry>,
+        dry_run: bool,
+    ) -> Result<RepairReport, NoVersionError> {
+        let root = load_root(&self.db, version)?;
+        tracing::info!("Repairing tree version {version} (dry run: {dry_run})");
+
+        let mut repairer = Repairer {
+            db: &self.db,
+            hasher: &self.hasher,
+            entries_source,
+            actions: vec![],
+            unrepaired_nodes: vec![],
+            changed_nodes: vec![],
+        };
+        let (root, root_hash, is_root_changed) = match root {
+            Root::Empty => (Root::Empty, self.hasher.empty_tree_hash(), false),
+            Root::Filled {
+                leaf_count,
+                node: Node::Internal(mut node),
+            } => {
+                let root_key = Nibbles::EMPTY.with_version(version);
+                let is_changed = repairer.repair_children(root_key, &mut node);
+                let root_hash = node.hash(&mut HasherWithStats::new(&self.hasher), 0);
+                (
+                    Root::new(leaf_count.get(), node.into()),
+                    root_hash,
+                    is_changed,
+                )
+            }
+            Root::Filled {
+                leaf_count,
+                node: Node::Leaf(leaf),
+            } => {
+                let root_hash = leaf.hash(&mut HasherWithStats::new(&self.hasher), 0);
+                (Root::new(leaf_count.get(), leaf.into()), root_hash, false)
+            }
+        };
+        let Repairer {
+            actions,
+            unrepaired_nodes,
+            changed_nodes,
+            ..
+        } = repairer;
+
+        if !dry_run && (is_root_changed || !changed_nodes.is_empty()) {
+            let root = is_root_changed.then_some((version, &root));
+            self.db.overwrite_nodes(root, &changed_nodes);
+            tracing::info!(
+                "Rewritten {} node(s) for tree version {version}",
+                changed_nodes.len() + usize::from(is_root_changed)
+            );
+        }
+        if !unrepaired_nodes.is_empty() {
+            tracing::warn!(
+                "{} node(s) in tree version {version} could not be repaired: {unrepaired_nodes:?}",
+                unrepaired_nodes.len()
+            );
+        }
+
+        Ok(RepairReport {
+            version,
+            actions,
+            unrepaired_nodes,
+            root_hash,
+            is_dry_run: dry_run,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Repairer<'a, F> {
+    db: &'a RocksDBWrapper,
+    hasher: &'a dyn HashTree,
+    entries_source: F,
+    actions: Vec<RepairAction>,
+    unrepaired_nodes: Vec<NodeKey>,
+    changed_nodes: Vec<(NodeKey, Node)>,
+}
+
+impl<F> Repairer<'_, F>
+where
+    F: FnMut(ops::RangeInclusive<Key>) -> Vec<TreeEntry>,
+{
+    /// Repairs a non-root node and returns its hash.
+    fn repair_node(&mut self, key: NodeKey, node: Node) -> ValueHash {
+        let level = key.nibbles.nibble_count() * 4;
+        match node {
+            Node::Leaf(leaf) => leaf.hash(&mut HasherWithStats::new(self.hasher), level),
+            Node::Internal(mut node) => {
+                let is_changed = self.repair_children(key, &mut node);
+                let hash = node.hash(&mut HasherWithStats::new(self.hasher), level);
+                if is_changed {
+                    self.changed_nodes.push((key, node.into()));
+                }
+                hash
+            }
+        }
+    }
+
+    /// Repairs children of an internal node. Returns `true` if any child reference was changed.
+    fn repair_children(&mut self, key: NodeKey, node: &mut InternalNode) -> bool {
+        let max_nibble_count = self.hasher.depth() / 4;
+        let children: Vec<_> = node
+            .children()
+            .map(|(nibble, child_ref)| (nibble, *child_ref))
+            .collect();
+
+        let mut is_changed = false;
+        for (nibble, child_ref) in children {
+            let Some(child_nibbles) = key
+                .nibbles
+                .push(nibble)
+                .filter(|nibbles| nibbles.nibble_count() <= max_nibble_count)
+            else {
+                tracing::warn!("Internal node at {key} is at the terminal tree level; skipping");
+                continue;
+            };
+            let child_key = child_nibbles.with_version(child_ref.version);
+
+            let actual_child_ref = match self.db.try_tree_node(&child_key, child_ref.is_leaf) {
+                Ok(Some(child)) => ChildRef {
+                    hash: self.repair_node(child_key, child),
+                    ..child_ref
+                },
+                Ok(None) => {
+                    tracing::warn!("Node at {child_key} is missing");
+                    let Some(child_ref) = self.rebuild_subtree(child_key) else {
+                        continue;
+                    };
+                    child_ref
+                }
+                Err(err) => {
+                    tracing::warn!("Node at {child_key} cannot be deserialized: {err}");
+                    let Some(child_ref) = self.rebuild_subtree(child_key) else {
+                        continue;
+                    };
+                    child_ref
+                }
+            };
+
+            if actual_child_ref.hash != child_ref.hash
+                || actual_child_ref.is_leaf != child_ref.is_leaf
+            {
+                tracing::info!(
+                    "Updating child ref `{nibble:x}` of internal node at {key}: {:?} -> {:?}",
+                    child_ref.hash,
+                    actual_child_ref.hash
+                );
+                self.actions.push(RepairAction::RehashedChild {
+                    key,
+                    nibble,
+                    old_hash: child_ref.hash,
+                    new_hash: actual_child_ref.hash,
+                });
+                node.insert_child_ref(nibble, actual_child_ref);
+                is_changed = true;
+            }
+        }
+        is_changed
+    }
+
+    /// Rebuilds the subtree rooted at `key` from the entries source. All nodes in the rebuilt
+    /// subtree have the same version as the subtree root; this keeps the tree consistent
+    /// since node versions are only used for addressing.
+    fn rebuild_subtree(&mut self, key: NodeKey) -> Option<ChildRef> {
+        let depth = self.hasher.depth();
+        // Nibbles are aligned to the most significant key bit; see `align_key()`.
+        let alignment = TREE_DEPTH - depth;
+        let min_key = Key::from_be_bytes(*key.nibbles.bytes()) >> alignment;
+        let max_key = min_key | (Key::MAX >> (4 * key.nibbles.nibble_count() + alignment));
+
+        let mut entries = (self.entries_source)(min_key..=max_key);
+        entries.retain(|entry| (min_key..=max_key).contains(&entry.key) && !entry.is_empty());
+        entries.sort_unstable_by_key(|entry| entry.key);
+        entries.dedup_by_key(|entry| entry.key);
+        if entries.is_empty() {
+            tracing::warn!("No entries supplied to rebuild subtree at {key}; skipping");
+            self.unrepaired_nodes.push(key);
+            return None;
+        }
+
+        let node_count = self.changed_nodes.len();
+        let child_ref = self.build_subtree(key.nibbles, key.version, &entries);
+        tracing::info!(
+            "Rebuilt subtree at {key} from {} entries ({} nodes)",
+            entries.len(),
+            self.changed_nodes.len() - node_count
+        );
+        self.actions.push(RepairAction::RebuiltSubtree {
+            key,
+            entry_count: entries.len(),
+        });
+        Some(child_ref)
+    }
+
+    /// Builds a subtree from `entries` sorted by key.
+    fn build_subtree(&mut self, nibbles: Nibbles, version: u64, entries: &[TreeEntry]) -> ChildRef {
+        let level = nibbles.nibble_count() * 4;
+        if let [entry] = entries {
+            let leaf = LeafNode::new(*entry);
+            let hash = leaf.hash(&mut HasherWithStats::new(self.hasher), level);
+            self.changed_nodes
+                .push((nibbles.with_version(version), leaf.into()));
+            return ChildRef {
+                hash,
+                version,
+                is_leaf: true,
+            };
+        }
+
+        let depth = self.hasher.depth();
+        let nibble_index = nibbles.nibble_count();
+        let child_nibble =
+            |entry: &TreeEntry| Nibbles::nibble(&align_key(&entry.key, depth), nibble_index);
+        let mut node = InternalNode::default();
+        let mut remaining_entries = entries;
+        while let Some(first_entry) = remaining_entries.first() {
+            let nibble = child_nibble(first_entry);
+            let group_len = remaining_entries
+                .iter()
+                .position(|entry| child_nibble(entry) != nibble)
+                .unwrap_or(remaining_entries.len());
+            let (group, tail) = remaining_entries.split_at(group_len);
+            let child_nibbles = nibbles.push(nibble).unwrap();
+            // ^ `unwrap()` is safe: entries have distinct keys, so a group at the terminal level
+            // consists of a single entry and is handled above.
+            let child_ref = self.build_subtree(child_nibbles, version, group);
+            node.insert_child_ref(nibble, child_ref);
+            remaining_entries = tail;
+        }
+
+        let hash = node.hash(&mut HasherWithStats::new(self.hasher), level);
+        self.changed_nodes
+            .push((nibbles.with_version(version), node.into()));
+        ChildRef {
+            hash,
+            version,
+            is_leaf: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{storage::tests::create_large_tree_entries, MerkleTreeColumnFamily};
+
+    fn create_tree(temp_dir: &TempDir) -> (MerkleTree<RocksDBWrapper>, Vec<TreeEntry>) {
+        let mut tree = MerkleTree::new(RocksDBWrapper::new(temp_dir.path()));
+        let entries = create_large_tree_entries();
+        tree.extend(entries.clone());
+        (tree, entries)
+    }
+
+    fn entries_source(
+        entries: &[TreeEntry],
+    ) -> impl FnMut(ops::RangeInclusive<Key>) -> Vec<TreeEntry> + '_ {
+        move |range| {
+            let entries = entries.iter().filter(|entry| range.contains(&entry.key));
+            entries.copied().collect()
+        }
+    }
+
+    fn remove_node(tree: &MerkleTree<RocksDBWrapper>, key: NodeKey) {
+        let db = tree.db.clone().into_inner();
+        let mut batch = db.new_write_batch();
+        batch.delete_cf(MerkleTreeColumnFamily::Tree, &key.to_db_key());
+        db.write(batch).unwrap();
+    }
+
+    #[test]
+    fn repairing_hash_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut tree, entries) = create_tree(&temp_dir);
+        let expected_root_hash = tree.latest_root_hash();
+
+        let mut root = tree.root(0).unwrap();
+        let Root::Filled {
+            node: Node::Internal(node),
+            ..
+        } = &mut root
+        else {
+            unreachable!();
+        };
+        node.child_ref_mut(3).unwrap().hash = ValueHash::ZERO;
+        tree.db.overwrite_nodes(Some((0, &root)), &[]);
+        assert_ne!(tree.latest_root_hash(), expected_root_hash);
+        tree.verify_consistency(0, true).unwrap_err();
+
+        let report = tree.repair(0, entries_source(&entries), true).unwrap();
+        assert!(report.is_dry_run);
+        assert_eq!(report.root_hash, expected_root_hash);
+        assert_matches!(
+            report.actions.as_slice(),
+            [RepairAction::RehashedChild {
+                key,
+                nibble: 3,
+                old_hash,
+                ..
+            }] if *key == NodeKey::empty(0) && *old_hash == ValueHash::ZERO
+        );
+        // The dry run should not change the tree.
+        tree.verify_consistency(0, true).unwrap_err();
+
+        let report = tree.repair(0, entries_source(&entries), false).unwrap();
+        assert_eq!(report.actions.len(), 1);
+        assert!(report.unrepaired_nodes.is_empty());
+        tree.verify_consistency(0, true).unwrap();
+        assert_eq!(tree.latest_root_hash(), expected_root_hash);
+
+        // Repairing a consistent tree should be a no-op.
+        let report = tree.repair(0, entries_source(&entries), false).unwrap();
+        assert!(report.actions.is_empty());
+    }
+
+    #[test]
+    fn rebuilding_lost_subtrees() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut tree, entries) = create_tree(&temp_dir);
+        let expected_root_hash = tree.latest_root_hash();
+
+        let lost_leaf_key = Nibbles::new(&entries[17].key, 2).with_version(0);
+        remove_node(&tree, lost_leaf_key);
+        let lost_internal_key = Nibbles::new(&entries[200].key, 1).with_version(0);
+        remove_node(&tree, lost_internal_key);
+        tree.verify_consistency(0, true).unwrap_err();
+
+        let report = tree.repair(0, entries_source(&entries), false).unwrap();
+        assert!(report.unrepaired_nodes.is_empty());
+        let rebuilt_keys: Vec<_> = report
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                RepairAction::RebuiltSubtree { key, entry_count } => Some((*key, *entry_count)),
+                RepairAction::RehashedChild { .. } => None,
+            })
+            .collect();
+        assert_eq!(rebuilt_keys.len(), 2);
+        assert!(rebuilt_keys.contains(&(lost_leaf_key, 1)));
+        assert!(rebuilt_keys.contains(&(lost_internal_key, 16)));
+
+        tree.verify_consistency(0, true).unwrap();
+        assert_eq!(tree.latest_root_hash(), expected_root_hash);
+    }
+
+    #[test]
+    fn lost_subtree_without_entries_is_not_repaired() {
+        let temp_dir = TempDir::new().unwrap();
+        let (mut tree, entries) = create_tree(&temp_dir);
+        let lost_leaf_key = Nibbles::new(&entries[17].key, 2).with_version(0);
+        remove_node(&tree, lost_leaf_key);
+
+        let report = tree.repair(0, |_| vec![], false).unwrap();
+        assert!(report.actions.is_empty());
+        assert_eq!(report.unrepaired_nodes, [lost_leaf_key]);
+        tree.verify_consistency(0, true).unwrap_err();
+    }
+}
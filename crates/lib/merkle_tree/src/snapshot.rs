@@ -0,0 +1,328 @@
+//! Read-only tree snapshots pinned to a specific tree version.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::RangeBounds,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+};
+
+use axon_types::primitives::hasher::blake2::Blake2Hasher;
+use once_cell::sync::Lazy;
+
+use crate::{
+    getters::load_root,
+    storage::{RocksDBSnapshotWrapper, RocksDBWrapper},
+    types::{ExclusionProof, Key, TreeEntry, TreeEntryWithProof, TreeMultiProof, ValueHash},
+    Database, HashTree, MerkleTree, NoVersionError,
+};
+
+#[derive(Debug, Default)]
+struct PinnedVersionsInner {
+    pin_counts: BTreeMap<u64, usize>,
+    /// Versions less than this one cannot be pinned since they may be removed by an in-flight
+    /// or a completed pruning iteration.
+    min_pinnable_version: u64,
+}
+
+/// Registry of pinned tree versions (e.g., by [`TreeSnapshot`]s). Cloning the registry produces
+/// a handle to the same registry.
+///
+/// Pinning a version and reserving versions for pruning are performed under the same lock,
+/// so a version cannot be pinned after the pruner has decided to remove it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PinnedVersions(Arc<Mutex<PinnedVersionsInner>>);
+
+impl PinnedVersions {
+    /// Returns the registry shared by all [`RocksDBWrapper`]s for the RocksDB instance
+    /// at the specified path.
+    pub(crate) fn for_db_path(path: &Path) -> Self {
+        static REGISTRIES: Lazy<Mutex<HashMap<PathBuf, Weak<Mutex<PinnedVersionsInner>>>>> =
+            Lazy::new(Mutex::default);
+
+        let mut registries = REGISTRIES.lock().unwrap();
+        if let Some(registry) = registries.get(path).and_then(Weak::upgrade) {
+            return Self(registry);
+        }
+        registries.retain(|_, registry| registry.strong_count() > 0);
+        let registry = Self::default();
+        registries.insert(path.to_owned(), Arc::downgrade(&registry.0));
+        registry
+    }
+
+    /// Pins the specified `version`. Returns `None` if the version may be pruned already.
+    pub(crate) fn pin(&self, version: u64) -> Option<VersionPin> {
+        let mut inner = self.0.lock().unwrap();
+        if version < inner.min_pinnable_version {
+            return None;
+        }
+        *inner.pin_counts.entry(version).or_default() += 1;
+        Some(VersionPin {
+            versions: self.clone(),
+            version,
+        })
+    }
+
+    /// Adjusts `target_retained_version` so that all pinned versions are retained,
+    /// and prevents pinning versions preceding the returned version afterwards.
+    /// `adjust` is called on the adjusted version while the registry is locked and can
+    /// decrease it further (e.g., taking into account other registries).
+    pub(crate) fn reserve_for_pruning(
+        &self,
+        target_retained_version: u64,
+        adjust: impl FnOnce(u64) -> u64,
+    ) -> u64 {
+        let mut inner = self.0.lock().unwrap();
+        let min_pinned_version = inner.pin_counts.keys().next().copied();
+        let target_version =
+            min_pinned_version.map_or(target_retained_version, |v| v.min(target_retained_version));
+        let target_version = adjust(target_version);
+        inner.min_pinnable_version = inner.min_pinnable_version.max(target_version);
+        target_version
+    }
+}
+
+/// Guard pinning a tree version in [`PinnedVersions`]. The version is unpinned on drop.
+#[derive(Debug)]
+pub(crate) struct VersionPin {
+    versions: PinnedVersions,
+    version: u64,
+}
+
+impl Drop for VersionPin {
+    fn drop(&mut self) {
+        let mut inner = self.versions.0.lock().unwrap();
+        let count = inner.pin_counts.get_mut(&self.version).unwrap();
+        // ^ `unwrap()` is safe: the version is pinned while the guard is alive
+        *count -= 1;
+        if *count == 0 {
+            inner.pin_counts.remove(&self.version);
+        }
+    }
+}
+
+/// Read-only handle to a specific version of a [`MerkleTree`] backed by RocksDB.
+///
+/// Reads are performed from a RocksDB snapshot, so they are not influenced by concurrent writes
+/// to the tree (e.g., [`MerkleTree::truncate_recent_versions()`]). Additionally, the snapshot
+/// version is pinned while the handle is alive, so that [`MerkleTreePruner`]s working
+/// with the same RocksDB instance do not prune it. A snapshot cannot be created for a version
+/// that a pruner has already started pruning.
+///
+/// [`MerkleTreePruner`]: crate::MerkleTreePruner
+pub struct TreeSnapshot<'a, H = Blake2Hasher> {
+    tree: MerkleTree<RocksDBSnapshotWrapper<'a>, &'a H>,
+    version: u64,
+    _pin: VersionPin,
+}
+
+impl<H> fmt::Debug for TreeSnapshot<'_, H> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("TreeSnapshot")
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<H: HashTree> MerkleTree<RocksDBWrapper, H> {
+    /// Creates a read-only snapshot of the tree at the specified `version`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree `version` is missing, or if it is being pruned.
+    pub fn snapshot(&self, version: u64) -> Result<TreeSnapshot<'_, H>, NoVersionError> {
+        let Some((db, pin)) = self.db.snapshot(version) else {
+            return Err(NoVersionError {
+                missing_version: version,
+                version_count: self
+                    .db
+                    .manifest()
+                    .map_or(0, |manifest| manifest.version_count),
+            });
+        };
+        // Check that the version is present in the snapshot. If it's there, it cannot be removed
+        // from the snapshot afterwards.
+        load_root(&db, version)?;
+        Ok(TreeSnapshot {
+            tree: MerkleTree {
+                db,
+                hasher: &self.hasher,
+            },
+            version,
+            _pin: pin,
+        })
+    }
+}
+
+// Methods below unwrap `NoVersionError`s. This is safe since the snapshot version is checked
+// when the snapshot is created, and it cannot be removed from the underlying RocksDB snapshot.
+#[allow(clippy::missing_panics_doc)]
+impl<H: HashTree> TreeSnapshot<'_, H> {
+    /// Returns the tree version this snapshot is pinned to.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns the root hash of the tree.
+    pub fn root_hash(&self) -> ValueHash {
+        self.tree.root_hash(self.version).unwrap()
+    }
+
+    /// Returns the number of leaves in the tree.
+    pub fn leaf_count(&self) -> u64 {
+        self.tree.db.root(self.version).unwrap().leaf_count()
+    }
+
+    /// Reads entries with the specified keys from the tree. See [`MerkleTree::entries()`]
+    /// for details.
+    pub fn entries(&self, leaf_keys: &[Key]) -> Vec<TreeEntry> {
+        self.tree.entries(self.version, leaf_keys).unwrap()
+    }
+
+    /// Reads entries together with Merkle proofs with the specified keys from the tree.
+    /// See [`MerkleTree::entries_with_proofs()`] for details.
+    pub fn entries_with_proofs(&self, leaf_keys: &[Key]) -> Vec<TreeEntryWithProof> {
+        self.tree
+            .entries_with_proofs(self.version, leaf_keys)
+            .unwrap()
+    }
+
+    /// Reads entries with the specified keys from the tree together with a compact multi-proof.
+    /// See [`MerkleTree::entries_with_multi_proof()`] for details.
+    pub fn entries_with_multi_proof(&self, leaf_keys: &[Key]) -> TreeMultiProof {
+        self.tree
+            .entries_with_multi_proof(self.version, leaf_keys)
+            .unwrap()
+    }
+
+    /// Creates a proof that the specified `key` is missing from the tree. Returns `None`
+    /// if the key is present in the tree.
+    pub fn exclusion_proof(&self, key: Key) -> Option<ExclusionProof> {
+        self.tree.exclusion_proof(self.version, key).unwrap()
+    }
+
+    /// Iterates over non-empty entries with keys in the specified `range` in the ascending
+    /// key order.
+    pub fn entries_in_range(
+        &self,
+        range: impl RangeBounds<Key>,
+    ) -> impl Iterator<Item = TreeEntry> + '_ {
+        self.tree.entries_in_range(self.version, range).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{MerkleTreePruner, PruneDatabase};
+
+    fn create_tree(db: &RocksDBWrapper, version_count: u64) -> MerkleTree<RocksDBWrapper> {
+        let mut tree = MerkleTree::new(db.clone());
+        for i in 0..version_count {
+            let value = ValueHash::repeat_byte(i as u8);
+            // Update the same entry in each version, so that the pruner has work to do.
+            let updated_entry = TreeEntry::new(Key::from(0), 1, value);
+            let new_entry = TreeEntry::new(Key::from(i + 1), i + 2, value);
+            tree.extend(vec![updated_entry, new_entry]);
+        }
+        tree
+    }
+
+    #[test]
+    fn snapshot_is_not_affected_by_truncation() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let tree = create_tree(&db, 3);
+        let mut writer = MerkleTree::new(db);
+
+        let snapshot = tree.snapshot(2).unwrap();
+        assert_eq!(snapshot.version(), 2);
+        let expected_root_hash = tree.root_hash(2).unwrap();
+        assert_eq!(snapshot.root_hash(), expected_root_hash);
+        assert_eq!(snapshot.leaf_count(), 4);
+
+        // Overwrite the snapshot version with other data.
+        writer.truncate_recent_versions(2);
+        let new_entry = TreeEntry::new(Key::from(100), 4, ValueHash::repeat_byte(0xff));
+        writer.extend(vec![new_entry]);
+        assert_ne!(writer.root_hash(2).unwrap(), expected_root_hash);
+
+        assert_eq!(snapshot.root_hash(), expected_root_hash);
+        let keys = [Key::from(0), Key::from(3), Key::from(100)];
+        let entries = snapshot.entries(&keys);
+        assert_eq!(entries[0].value, ValueHash::repeat_byte(2));
+        assert_eq!(entries[1].value, ValueHash::repeat_byte(2));
+        assert!(entries[2].is_empty());
+        assert_eq!(snapshot.entries_in_range(..).count(), 4);
+        for entry in snapshot.entries_with_proofs(&keys) {
+            entry.verify(&Blake2Hasher, expected_root_hash);
+        }
+        drop(snapshot);
+
+        assert!(tree.snapshot(3).is_err());
+    }
+
+    #[test]
+    fn pruner_respects_pinned_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let tree = create_tree(&db, 5);
+        let (mut pruner, _handle) = MerkleTreePruner::new(db.clone(), 0);
+
+        let snapshot = tree.snapshot(1).unwrap();
+        let other_snapshot = tree.snapshot(3).unwrap();
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 1);
+        assert_eq!(stats.deleted_stale_key_versions, 1..2);
+        tree.verify_consistency(1, true).unwrap();
+        drop(snapshot);
+
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 3);
+        tree.verify_consistency(3, true).unwrap();
+        assert!(tree.snapshot(2).is_err());
+        drop(other_snapshot);
+
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 4);
+        assert!(tree.snapshot(3).is_err());
+        tree.verify_consistency(4, true).unwrap();
+    }
+
+    #[test]
+    fn snapshots_cannot_be_created_for_versions_being_pruned() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let tree = create_tree(&db, 5);
+
+        // Emulate the pruner selecting versions to prune.
+        assert_eq!(db.reserve_for_pruning(3), 3);
+        assert!(tree.root(2).is_some()); // The version is not pruned yet...
+        assert!(tree.snapshot(2).is_err()); // ...but it cannot be pinned
+        let snapshot = tree.snapshot(3).unwrap();
+
+        assert_eq!(db.reserve_for_pruning(4), 3);
+        drop(snapshot);
+        assert_eq!(db.reserve_for_pruning(4), 4);
+        assert!(tree.snapshot(3).is_err());
+    }
+
+    #[test]
+    fn pinned_versions_are_shared_among_wrappers() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDBWrapper::new(temp_dir.path());
+        let tree = create_tree(&db, 5);
+        let pruner_db = RocksDBWrapper::from(db.clone().into_inner());
+        let (mut pruner, _handle) = MerkleTreePruner::new(pruner_db, 0);
+
+        let snapshot = tree.snapshot(2).unwrap();
+        let stats = pruner.run_once().unwrap();
+        assert_eq!(stats.target_retained_version, 2);
+        assert_eq!(snapshot.root_hash(), tree.root_hash(2).unwrap());
+        tree.verify_consistency(2, true).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 //! `Database` trait and its implementations.
 
-use std::ops;
+use std::{ops, time::SystemTime};
 
 use crate::{
     errors::DeserializeError,
@@ -187,12 +187,18 @@ impl Database for PatchSet {
 pub struct Patched<DB> {
     inner: DB,
     patch: Option<PatchSet>,
+    /// Approximate size of `patch` in RAM, in bytes.
+    patch_size: usize,
 }
 
 impl<DB: Database> Patched<DB> {
     /// Wraps the provided database.
     pub fn new(inner: DB) -> Self {
-        Self { inner, patch: None }
+        Self {
+            inner,
+            patch: None,
+            patch_size: 0,
+        }
     }
 
     pub(crate) fn patched_versions(&self) -> Vec<u64> {
@@ -201,6 +207,11 @@ impl<DB: Database> Patched<DB> {
         })
     }
 
+    /// Returns the approximate size of changes held in RAM, in bytes.
+    pub fn approx_patch_size(&self) -> usize {
+        self.patch_size
+    }
+
     /// Returns the value from the patch and a flag whether this value is final (i.e., a DB lookup
     /// is not required).
     fn lookup_patch(&self, key: &NodeKey, is_leaf: bool) -> (Option<Node>, bool) {
@@ -236,11 +247,13 @@ impl<DB: Database> Patched<DB> {
         if let Some(patch) = self.patch.take() {
             self.inner.apply_patch(patch);
         }
+        self.patch_size = 0;
     }
 
     /// Forgets about changes held in RAM.
     pub fn reset(&mut self) {
         self.patch = None;
+        self.patch_size = 0;
     }
 
     /// Returns the wrapped database.
@@ -329,8 +342,20 @@ impl<DB: Database> Database for Patched<DB> {
 
     fn apply_patch(&mut self, patch: PatchSet) {
         if let Some(existing_patch) = &mut self.patch {
+            let truncates_versions =
+                patch.manifest.version_count < existing_patch.manifest.version_count;
+            let patch_size = patch.approx_size();
+            // Nodes merged into the updated version may overwrite existing ones.
+            let overwritten_size = existing_patch.approx_overwritten_size(&patch);
             existing_patch.apply_patch(patch);
+            self.patch_size = if truncates_versions {
+                // Some sub-patches were removed; we need to recompute the size from scratch.
+                existing_patch.approx_size()
+            } else {
+                self.patch_size + patch_size - overwritten_size
+            };
         } else {
+            self.patch_size = patch.approx_size();
             self.patch = Some(patch);
         }
     }
@@ -360,6 +385,15 @@ impl PrunePatchSet {
 
 /// Functionality to prune past versions of the Merkle tree.
 pub trait PruneDatabase: Database {
+    /// Prepares pruning versions preceding `target_retained_version`. Returns the adjusted target
+    /// version, which must not exceed the minimum version pinned by a [`TreeSnapshot`].
+    /// After this call, versions preceding the returned version must not be pinned.
+    ///
+    /// [`TreeSnapshot`]: crate::TreeSnapshot
+    fn reserve_for_pruning(&self, target_retained_version: u64) -> u64 {
+        target_retained_version
+    }
+
     /// Returns the minimum new version for stale keys present in this database, or `None`
     /// if there are no stale keys.
     fn min_stale_key_version(&self) -> Option<u64>;
@@ -367,11 +401,31 @@ pub trait PruneDatabase: Database {
     /// Returns a list of node keys obsoleted in the specified `version` of the tree.
     fn stale_keys(&self, version: u64) -> Vec<NodeKey>;
 
+    /// Returns the time the specified tree `version` was created, or `None` if it is not known
+    /// (e.g., if the version was created by an older version of the code, or was recovered
+    /// from a snapshot). Creation times may be removed for pruned versions.
+    ///
+    /// The default implementation returns `None`, i.e., the pruning retention period
+    /// is not applied.
+    fn version_created_at(&self, _version: u64) -> Option<SystemTime> {
+        None
+    }
+
     /// Atomically prunes the tree and updates information about the minimum retained version.
     fn prune(&mut self, patch: PrunePatchSet);
+
+    /// Checks whether writes to the underlying storage are currently stalled. The pruner pauses
+    /// while this is the case so that it doesn't compete with the tree for write throughput.
+    fn has_stalled_writes(&self) -> bool {
+        false
+    }
 }
 
 impl<T: PruneDatabase + ?Sized> PruneDatabase for &mut T {
+    fn reserve_for_pruning(&self, target_retained_version: u64) -> u64 {
+        (**self).reserve_for_pruning(target_retained_version)
+    }
+
     fn min_stale_key_version(&self) -> Option<u64> {
         (**self).min_stale_key_version()
     }
@@ -380,9 +434,17 @@ impl<T: PruneDatabase + ?Sized> PruneDatabase for &mut T {
         (**self).stale_keys(version)
     }
 
+    fn version_created_at(&self, version: u64) -> Option<SystemTime> {
+        (**self).version_created_at(version)
+    }
+
     fn prune(&mut self, patch: PrunePatchSet) {
         (**self).prune(patch);
     }
+
+    fn has_stalled_writes(&self) -> bool {
+        (**self).has_stalled_writes()
+    }
 }
 
 impl PruneDatabase for PatchSet {
@@ -400,6 +462,10 @@ impl PruneDatabase for PatchSet {
             .unwrap_or_default()
     }
 
+    fn version_created_at(&self, version: u64) -> Option<SystemTime> {
+        self.patches_by_version.get(&version)?.created_at
+    }
+
     fn prune(&mut self, patch: PrunePatchSet) {
         for key in &patch.pruned_node_keys {
             let Some(patch) = self.patches_by_version.get_mut(&key.version) else {
@@ -628,4 +694,25 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn patch_size_with_merged_update_patches() {
+        let manifest = Manifest::new(10, &());
+        let mut patched = Patched::new(PatchSet::default());
+        for nibble_counts in [&[1, 2] as &[_], &[2, 3]] {
+            let root = Root::new(2, Node::Internal(InternalNode::default()));
+            let patch = PatchSet::new(
+                manifest.clone(),
+                9,
+                root,
+                generate_nodes(9, nibble_counts),
+                vec![],
+                Operation::Update,
+            );
+            patched.apply_patch(patch);
+
+            let expected_size = patched.patch.as_ref().unwrap().approx_size();
+            assert_eq!(patched.approx_patch_size(), expected_size);
+        }
+    }
 }
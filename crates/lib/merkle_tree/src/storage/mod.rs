@@ -2,24 +2,29 @@
 
 mod database;
 mod patch;
+mod prefetch;
 mod proofs;
 mod rocksdb;
 mod serialization;
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
 
-pub(crate) use self::patch::{LoadAncestorsResult, WorkingPatchSet};
 pub use self::{
     database::{Database, NodeKeys, Patched, PruneDatabase, PrunePatchSet},
     patch::PatchSet,
     rocksdb::{MerkleTreeColumnFamily, RocksDBWrapper},
 };
+pub(crate) use self::{
+    patch::{LoadAncestorsResult, WorkingPatchSet},
+    prefetch::{Prefetched, PrefetchedNodes},
+    rocksdb::RocksDBSnapshotWrapper,
+};
 use crate::{
     hasher::HashTree,
     metrics::{TreeUpdaterStats, BLOCK_TIMINGS, GENERAL_METRICS},
     types::{
-        BlockOutput, ChildRef, InternalNode, Key, LeafNode, Manifest, Nibbles, Node, Root,
-        TreeEntry, TreeLogEntry, TreeTags, ValueHash,
+        align_key, BlockOutput, ChildRef, InternalNode, Key, LeafNode, Manifest, Nibbles, Node,
+        NodeKey, Root, TreeEntry, TreeInstruction, TreeLogEntry, TreeTags, ValueHash,
     },
 };
 
@@ -39,10 +44,10 @@ struct TreeUpdater {
 }
 
 impl TreeUpdater {
-    fn new(version: u64, root: Root) -> Self {
+    fn new(version: u64, root: Root, depth: usize) -> Self {
         Self {
             metrics: TreeUpdaterStats::default(),
-            patch_set: WorkingPatchSet::new(version, root),
+            patch_set: WorkingPatchSet::new(version, root, depth),
         }
     }
 
@@ -134,6 +139,7 @@ impl TreeUpdater {
     ) -> (TreeLogEntry, NewLeafData) {
         let version = self.patch_set.root_version();
         let key = entry.key;
+        let depth = self.patch_set.depth();
 
         let traverse_outcome = self.patch_set.traverse(key, parent_nibbles);
         let (log, leaf_data) = match traverse_outcome {
@@ -148,10 +154,12 @@ impl TreeUpdater {
             TraverseOutcome::LeafMismatch(nibbles, leaf) => {
                 self.update_moved_leaf_ref(&nibbles);
 
+                let aligned_key = align_key(&key, depth);
+                let aligned_leaf_key = align_key(&leaf.full_key, depth);
                 let mut nibble_idx = nibbles.nibble_count();
                 loop {
-                    let moved_leaf_nibble = Nibbles::nibble(&leaf.full_key, nibble_idx);
-                    let new_leaf_nibble = Nibbles::nibble(&key, nibble_idx);
+                    let moved_leaf_nibble = Nibbles::nibble(&aligned_leaf_key, nibble_idx);
+                    let new_leaf_nibble = Nibbles::nibble(&aligned_key, nibble_idx);
                     let mut node = InternalNode::default();
                     if moved_leaf_nibble == new_leaf_nibble {
                         // Insert a path of internal nodes with a single child.
@@ -162,7 +170,7 @@ impl TreeUpdater {
                         node.insert_child_ref(new_leaf_nibble, ChildRef::leaf(version));
                         node.insert_child_ref(moved_leaf_nibble, ChildRef::leaf(version));
                     }
-                    let node_nibbles = Nibbles::new(&key, nibble_idx);
+                    let node_nibbles = Nibbles::new(&aligned_key, nibble_idx);
                     self.insert_node(node_nibbles, node, true);
                     if moved_leaf_nibble != new_leaf_nibble {
                         break;
@@ -171,9 +179,9 @@ impl TreeUpdater {
                 }
 
                 let new_leaf = LeafNode::new(entry);
-                let new_leaf_nibbles = Nibbles::new(&key, nibble_idx + 1);
+                let new_leaf_nibbles = Nibbles::new(&aligned_key, nibble_idx + 1);
                 let leaf_data = NewLeafData::new(new_leaf_nibbles, new_leaf);
-                let moved_leaf_nibbles = Nibbles::new(&leaf.full_key, nibble_idx + 1);
+                let moved_leaf_nibbles = Nibbles::new(&aligned_leaf_key, nibble_idx + 1);
                 let leaf_data = leaf_data.with_adjacent_leaf(moved_leaf_nibbles, leaf);
                 (TreeLogEntry::Inserted, leaf_data)
             }
@@ -219,6 +227,172 @@ impl TreeUpdater {
         (log, leaf_data)
     }
 
+    /// Reads the value at the specified `key` without changing the tree. This is used
+    /// for [`TreeInstruction::Read`]s in the lightweight operation mode; in the full mode,
+    /// reads are handled by [`Self::prove()`].
+    fn read(&mut self, key: Key, parent_nibbles: &Nibbles) -> TreeLogEntry {
+        match self.patch_set.traverse(key, parent_nibbles) {
+            TraverseOutcome::LeafMatch(_, leaf) => {
+                self.metrics.key_reads += 1;
+                TreeLogEntry::read(leaf.leaf_index, leaf.value_hash)
+            }
+            TraverseOutcome::LeafMismatch(..) | TraverseOutcome::MissingChild(_) => {
+                self.metrics.missing_key_reads += 1;
+                TreeLogEntry::ReadMissingKey
+            }
+        }
+    }
+
+    /// Removes the entry with the specified `key` from the tree. If the key is missing,
+    /// the tree is not changed, and [`TreeLogEntry::ReadMissingKey`] is returned.
+    ///
+    /// After the leaf is removed, its ancestors are collapsed similarly to the Jellyfish Merkle
+    /// tree: an internal node left without children is removed, and an internal node left
+    /// with a single leaf child is replaced with this leaf. Thus, the tree has the same shape
+    /// as if `key` was never inserted. Nodes with less than `min_nibble_count` nibbles are never
+    /// removed or replaced; the caller is responsible for collapsing them if necessary.
+    ///
+    /// As with [`Self::insert()`], node hashes are not updated. Returns the log entry together
+    /// with the nibbles of the topmost node changed by the removal (this node may be removed
+    /// as well).
+    fn remove<DB: Database + ?Sized>(
+        &mut self,
+        db: &DB,
+        key: Key,
+        parent_nibbles: &Nibbles,
+        min_nibble_count: usize,
+    ) -> (TreeLogEntry, Option<Nibbles>) {
+        let version = self.patch_set.root_version();
+        let TraverseOutcome::LeafMatch(leaf_nibbles, leaf) =
+            self.patch_set.traverse(key, parent_nibbles)
+        else {
+            self.metrics.missing_key_reads += 1;
+            return (TreeLogEntry::ReadMissingKey, None);
+        };
+        let log = TreeLogEntry::removed(leaf.leaf_index, leaf.value_hash);
+        self.metrics.removed_leaves += 1;
+
+        if leaf_nibbles.nibble_count() == 0 {
+            // The root is a leaf; the tree becomes empty.
+            self.patch_set.take_root();
+            return (log, Some(Nibbles::EMPTY));
+        }
+        self.patch_set.remove(&leaf_nibbles);
+
+        // Nibbles of the node removed from the tree, and an optional leaf that should
+        // take its place.
+        let mut nibbles = leaf_nibbles;
+        let mut moved_leaf: Option<LeafNode> = None;
+        while let Some((parent_nibbles, last_nibble)) = nibbles.split_last() {
+            let Some(Node::Internal(parent)) = self.get_mut(&parent_nibbles) else {
+                unreachable!("Node parent must be an internal node");
+            };
+
+            let can_collapse = parent_nibbles.nibble_count() >= min_nibble_count;
+            let collapsed_leaf = match (parent.child_count(), moved_leaf) {
+                // The parent node has the removed node as the only child.
+                (1, moved_leaf) if can_collapse => Some(moved_leaf),
+                // The parent node has the only child besides the removed node; if this child
+                // is a leaf, it replaces the parent node.
+                (2, None) if can_collapse => {
+                    let (sibling_nibble, sibling_ref) = parent
+                        .children()
+                        .find(|(nibble, _)| *nibble != last_nibble)
+                        .map(|(nibble, child_ref)| (nibble, *child_ref))
+                        .unwrap();
+                    // ^ `unwrap()` is safe: the parent has 2 children
+                    if sibling_ref.is_leaf {
+                        let sibling_nibbles = parent_nibbles.push(sibling_nibble).unwrap();
+                        // ^ `unwrap()` is safe: `sibling_nibbles` has the same length as `nibbles`
+                        let sibling_key = sibling_nibbles.with_version(sibling_ref.version);
+                        Some(Some(self.take_leaf(db, sibling_key)))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some(collapsed_leaf) = collapsed_leaf {
+                // The parent node is collapsed; continue to its parent.
+                moved_leaf = collapsed_leaf;
+                self.metrics.removed_internal_nodes += 1;
+                if parent_nibbles.nibble_count() == 0 {
+                    if let Some(leaf) = moved_leaf {
+                        self.insert_node(Nibbles::EMPTY, leaf, false);
+                    } else {
+                        self.patch_set.take_root();
+                    }
+                    return (log, Some(Nibbles::EMPTY));
+                }
+                self.patch_set.remove(&parent_nibbles);
+                nibbles = parent_nibbles;
+                continue;
+            }
+
+            // The parent node is retained; update its child ref.
+            let Some(Node::Internal(parent)) = self.get_mut(&parent_nibbles) else {
+                unreachable!("Node parent must be an internal node");
+            };
+            if let Some(leaf) = moved_leaf {
+                parent.insert_child_ref(last_nibble, ChildRef::leaf(version));
+                self.insert_node(nibbles, leaf, false);
+            } else {
+                parent.remove_child_ref(last_nibble);
+            }
+            break;
+        }
+
+        // Traverse nodes up to the root level and update `ChildRef.version`.
+        let mut cursor = nibbles
+            .split_last()
+            .map_or(Nibbles::EMPTY, |(parent, _)| parent);
+        while let Some((parent_nibbles, last_nibble)) = cursor.split_last() {
+            let child_ref = self
+                .patch_set
+                .child_ref_mut(&parent_nibbles, last_nibble)
+                .unwrap();
+            child_ref.version = child_ref.version.max(version);
+            cursor = parent_nibbles;
+        }
+
+        (log, Some(nibbles))
+    }
+
+    /// Removes a leaf with the specified key from the tree, loading it from the database
+    /// if necessary.
+    fn take_leaf<DB: Database + ?Sized>(&mut self, db: &DB, key: NodeKey) -> LeafNode {
+        let node = if let Some(node) = self.patch_set.remove(&key.nibbles) {
+            node
+        } else {
+            self.metrics.db_reads += 1;
+            let node = db.tree_node(&key, true).unwrap();
+            // ^ `unwrap()` is safe: the node is referenced by its parent
+            self.patch_set.mark_removed(key);
+            node
+        };
+        let Node::Leaf(leaf) = node else {
+            unreachable!("Node at {key} must be a leaf");
+        };
+        leaf
+    }
+
+    /// Replaces the internal root node with its only child if this child is a leaf.
+    fn collapse_root<DB: Database + ?Sized>(&mut self, db: &DB) {
+        let Some(Node::Internal(root)) = self.patch_set.get(&Nibbles::EMPTY) else {
+            return;
+        };
+        if root.child_count() != 1 {
+            return;
+        }
+        let (nibble, child_ref) = root.last_child_ref();
+        if child_ref.is_leaf {
+            let child_key = Nibbles::single(nibble).with_version(child_ref.version);
+            let leaf = self.take_leaf(db, child_key);
+            self.insert_node(Nibbles::EMPTY, leaf, false);
+        }
+    }
+
     fn update_moved_leaf_ref(&mut self, leaf_nibbles: &Nibbles) {
         if let Some((parent_nibbles, last_nibble)) = leaf_nibbles.split_last() {
             let child_ref = self
@@ -276,25 +450,42 @@ impl<'a, DB: Database + ?Sized> Storage<'a, DB> {
             } else {
                 Operation::Update
             },
-            updater: TreeUpdater::new(version, root),
+            updater: TreeUpdater::new(version, root, hasher.depth()),
         }
     }
 
     /// Extends the Merkle tree in the lightweight operation mode, without intermediate hash
     /// computations.
-    pub fn extend(mut self, entries: Vec<TreeEntry>) -> (BlockOutput, PatchSet) {
+    pub fn extend(self, entries: Vec<TreeEntry>) -> (BlockOutput, PatchSet) {
+        let instructions = entries.into_iter().map(TreeInstruction::Write).collect();
+        self.extend_with_instructions(instructions)
+    }
+
+    /// Same as [`Self::extend()`], but with arbitrary instructions (including reads and removals).
+    pub fn extend_with_instructions(
+        mut self,
+        instructions: Vec<TreeInstruction>,
+    ) -> (BlockOutput, PatchSet) {
         let load_nodes_latency = BLOCK_TIMINGS.load_nodes.start();
-        let sorted_keys = SortedKeys::new(entries.iter().map(|entry| entry.key));
+        let sorted_keys = SortedKeys::new(instructions.iter().map(TreeInstruction::key));
         let parent_nibbles = self.updater.load_ancestors(&sorted_keys, self.db);
         let load_nodes_latency = load_nodes_latency.observe();
         tracing::debug!("Load stage took {load_nodes_latency:?}");
 
         let extend_patch_latency = BLOCK_TIMINGS.extend_patch.start();
-        let mut logs = Vec::with_capacity(entries.len());
-        for (entry, parent_nibbles) in entries.into_iter().zip(parent_nibbles) {
-            let (log, _) = self.updater.insert(entry, &parent_nibbles);
-            if matches!(log, TreeLogEntry::Inserted) {
-                self.leaf_count += 1;
+        let mut logs = Vec::with_capacity(instructions.len());
+        for (instruction, parent_nibbles) in instructions.into_iter().zip(parent_nibbles) {
+            let log = match instruction {
+                TreeInstruction::Write(entry) => self.updater.insert(entry, &parent_nibbles).0,
+                TreeInstruction::Read(key) => self.updater.read(key, &parent_nibbles),
+                TreeInstruction::Remove(key) => {
+                    self.updater.remove(self.db, key, &parent_nibbles, 0).0
+                }
+            };
+            match log {
+                TreeLogEntry::Inserted => self.leaf_count += 1,
+                TreeLogEntry::Removed { .. } => self.leaf_count -= 1,
+                _ => { /* the leaf count doesn't change */ }
             }
             logs.push(log);
         }
@@ -332,7 +523,8 @@ impl<'a, DB: Database + ?Sized> Storage<'a, DB> {
             }
             prev_key = Some(entry.key);
 
-            let key_nibbles = Nibbles::new(&entry.key, prev_nibbles.nibble_count());
+            let aligned_key = align_key(&entry.key, self.hasher.depth());
+            let key_nibbles = Nibbles::new(&aligned_key, prev_nibbles.nibble_count());
             let parent_nibbles = prev_nibbles.common_prefix(&key_nibbles);
             let (_, new_leaf) = self.updater.insert(entry, &parent_nibbles);
             prev_nibbles = new_leaf.nibbles;
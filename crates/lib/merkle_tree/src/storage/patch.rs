@@ -2,8 +2,8 @@
 
 use std::{
     collections::{hash_map::Entry, HashMap},
-    iter,
-    time::Instant,
+    iter, mem,
+    time::{Instant, SystemTime},
 };
 
 use rayon::prelude::*;
@@ -13,8 +13,8 @@ use crate::{
     metrics::HashingStats,
     storage::{proofs::SUBTREE_COUNT, Operation, SortedKeys, TraverseOutcome},
     types::{
-        ChildRef, InternalNode, Key, LeafNode, Manifest, Nibbles, NibblesBytes, Node, NodeKey,
-        Root, ValueHash, KEY_SIZE,
+        align_key, ChildRef, InternalNode, Key, LeafNode, Manifest, Nibbles, NibblesBytes, Node,
+        NodeKey, Root, ValueHash,
     },
     utils, Database,
 };
@@ -28,12 +28,25 @@ pub(super) struct PartialPatchSet {
     //   - `HashMap`s indexed by version
     //   - Full upper levels (i.e., `Vec<Option<Node>>`)
     pub nodes: HashMap<NodeKey, Node>,
+    /// Creation time of the version; only set for patches creating a new version.
+    pub created_at: Option<SystemTime>,
 }
 
 impl PartialPatchSet {
     pub fn merge(&mut self, other: Self) {
         self.root = other.root;
         self.nodes.extend(other.nodes);
+        self.created_at = other.created_at.or(self.created_at);
+    }
+
+    fn approx_size(&self) -> usize {
+        let root_size = self.root.as_ref().map_or(0, Root::approx_size);
+        let node_sizes = self.nodes.values().map(Self::approx_node_size);
+        root_size + node_sizes.sum::<usize>()
+    }
+
+    fn approx_node_size(node: &Node) -> usize {
+        mem::size_of::<NodeKey>() + node.approx_size()
     }
 }
 
@@ -87,13 +100,14 @@ impl PatchSet {
 
         nodes.shrink_to_fit(); // We never insert into `nodes` later
         stale_keys.shrink_to_fit();
+        let (updated_version, created_at) = match &operation {
+            Operation::Insert => (None, Some(SystemTime::now())),
+            Operation::Update => (Some(version), None),
+        };
         let partial_patch = PartialPatchSet {
             root: Some(root),
             nodes,
-        };
-        let updated_version = match &operation {
-            Operation::Insert => None,
-            Operation::Update => Some(version),
+            created_at,
         };
 
         Self {
@@ -124,6 +138,43 @@ impl PatchSet {
         });
         copied_hashes.sum()
     }
+
+    /// Estimates the size of this patch in RAM, in bytes. The estimate does not account
+    /// for the overhead of hash maps.
+    pub(super) fn approx_size(&self) -> usize {
+        let sub_patch_sizes = self
+            .patches_by_version
+            .values()
+            .map(PartialPatchSet::approx_size);
+        let stale_key_count: usize = self.stale_keys_by_version.values().map(Vec::len).sum();
+        sub_patch_sizes.sum::<usize>() + stale_key_count * mem::size_of::<NodeKey>()
+    }
+
+    /// Estimates the size of data in this patch that will be overwritten if `other` is applied
+    /// to it, in bytes. Only the sub-patch for the updated version can be overwritten; its root
+    /// and the nodes present in `other` are replaced when merging patches.
+    pub(super) fn approx_overwritten_size(&self, other: &Self) -> usize {
+        let Some(updated_version) = self.updated_version else {
+            return 0;
+        };
+        if other.updated_version != Some(updated_version) {
+            return 0;
+        }
+        let (Some(patch), Some(other_patch)) = (
+            self.patches_by_version.get(&updated_version),
+            other.patches_by_version.get(&updated_version),
+        ) else {
+            return 0;
+        };
+
+        let root_size = patch.root.as_ref().map_or(0, Root::approx_size);
+        let overwritten_nodes = other_patch
+            .nodes
+            .keys()
+            .filter_map(|key| patch.nodes.get(key));
+        let node_sizes = overwritten_nodes.map(PartialPatchSet::approx_node_size);
+        root_size + node_sizes.sum::<usize>()
+    }
 }
 
 #[cfg(test)] // extensions to test tree consistency
@@ -137,6 +188,12 @@ impl PatchSet {
         patch.root.as_mut()
     }
 
+    #[cfg(test)]
+    pub(crate) fn set_created_at(&mut self, version: u64, created_at: Option<SystemTime>) {
+        let patch = self.patches_by_version.get_mut(&version).unwrap();
+        patch.created_at = created_at;
+    }
+
     pub(crate) fn remove_root(&mut self, version: u64) {
         let patch = self.patches_by_version.get_mut(&version).unwrap();
         patch.root = None;
@@ -185,14 +242,19 @@ pub(crate) struct LoadAncestorsResult {
 #[derive(Debug)]
 pub(crate) struct WorkingPatchSet {
     root_version: u64,
+    /// Depth of the tree; used to extract nibbles from keys.
+    depth: usize,
     // Group changes by `nibble_count` (which is linearly tied to the tree depth:
     // `depth == nibble_count * 4`) so that we can compute hashes for all changed nodes
     // in a single traversal in `Self::finalize()`.
     changes_by_nibble_count: Vec<HashMap<NibblesBytes, WorkingNode>>,
+    /// Keys of nodes from previous tree versions removed from the tree (rather than replaced
+    /// with a new node at the same position).
+    removed_keys: Vec<NodeKey>,
 }
 
 impl WorkingPatchSet {
-    pub fn new(root_version: u64, root: Root) -> Self {
+    pub fn new(root_version: u64, root: Root, depth: usize) -> Self {
         let changes_by_nibble_count = match root {
             Root::Filled { node, .. } => {
                 let root_node = WorkingNode::new(node, root_version.checked_sub(1));
@@ -203,7 +265,9 @@ impl WorkingPatchSet {
         };
         Self {
             root_version,
+            depth,
             changes_by_nibble_count,
+            removed_keys: Vec::new(),
         }
     }
 
@@ -211,6 +275,10 @@ impl WorkingPatchSet {
         self.root_version
     }
 
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
     pub fn get(&self, nibbles: &Nibbles) -> Option<&Node> {
         let node = self
             .changes_by_nibble_count
@@ -238,6 +306,25 @@ impl WorkingPatchSet {
         }
     }
 
+    /// Removes a node from this patch set. If the node is present in the previous tree versions,
+    /// its key is marked as stale. The root node is never marked as stale by this method;
+    /// it is replaced for each new version anyway.
+    pub fn remove(&mut self, key: &Nibbles) -> Option<Node> {
+        let level = self.changes_by_nibble_count.get_mut(key.nibble_count())?;
+        let node = level.remove(key.bytes())?;
+        if let Some(prev_version) = node.prev_version {
+            if key.nibble_count() > 0 {
+                self.removed_keys.push(key.with_version(prev_version));
+            }
+        }
+        Some(node.inner)
+    }
+
+    /// Marks a node loaded from the database as removed from the tree.
+    pub fn mark_removed(&mut self, key: NodeKey) {
+        self.removed_keys.push(key);
+    }
+
     /// Marks the retrieved node as changed.
     pub fn get_mut(&mut self, key: &Nibbles) -> Option<&mut Node> {
         let level = self.changes_by_nibble_count.get_mut(key.nibble_count())?;
@@ -271,6 +358,18 @@ impl WorkingPatchSet {
         self.changes_by_nibble_count.push(level);
     }
 
+    /// Returns all non-root nodes loaded from the database, keyed by their database keys.
+    pub fn into_loaded_nodes(self) -> HashMap<NodeKey, Node> {
+        let levels = self.changes_by_nibble_count.into_iter().enumerate().skip(1);
+        let nodes = levels.flat_map(|(nibble_count, level)| {
+            level.into_iter().filter_map(move |(nibbles, node)| {
+                let nibbles = Nibbles::from_parts(nibbles, nibble_count);
+                Some((nibbles.with_version(node.prev_version?), node.inner))
+            })
+        });
+        nodes.collect()
+    }
+
     /// Ensures that the root node in the patch set, if it exists, is an internal node. Returns
     /// a copy of the root node.
     pub fn ensure_internal_root_node(&mut self) -> InternalNode {
@@ -278,11 +377,12 @@ impl WorkingPatchSet {
             Some(Node::Internal(node)) => node.clone(),
             Some(Node::Leaf(leaf)) => {
                 let leaf = *leaf;
-                let first_nibble = Nibbles::nibble(&leaf.full_key, 0);
+                let aligned_key = align_key(&leaf.full_key, self.depth);
+                let first_nibble = Nibbles::nibble(&aligned_key, 0);
                 let mut internal_node = InternalNode::default();
                 internal_node.insert_child_ref(first_nibble, ChildRef::leaf(self.root_version));
                 self.insert(Nibbles::EMPTY, internal_node.clone().into());
-                self.insert(Nibbles::new(&leaf.full_key, 1), leaf.into());
+                self.insert(Nibbles::new(&aligned_key, 1), leaf.into());
                 internal_node
             }
             None => {
@@ -297,8 +397,11 @@ impl WorkingPatchSet {
     pub fn split(self) -> [Self; SUBTREE_COUNT] {
         let mut parts = [(); SUBTREE_COUNT].map(|()| Self {
             root_version: self.root_version,
+            depth: self.depth,
             changes_by_nibble_count: vec![HashMap::new(); self.changes_by_nibble_count.len()],
+            removed_keys: Vec::new(),
         });
+        parts[0].removed_keys = self.removed_keys;
 
         let levels = self.changes_by_nibble_count.into_iter().enumerate();
         for (nibble_count, level) in levels {
@@ -320,6 +423,8 @@ impl WorkingPatchSet {
 
     pub fn merge(&mut self, other: Self) {
         debug_assert_eq!(self.root_version, other.root_version);
+        debug_assert_eq!(self.depth, other.depth);
+        self.removed_keys.extend(other.removed_keys);
 
         let other_len = other.changes_by_nibble_count.len();
         if self.changes_by_nibble_count.len() < other_len {
@@ -390,13 +495,18 @@ impl WorkingPatchSet {
         I: IntoIterator<Item = (Nibbles, Option<ValueHash>, WorkingNode)>,
     {
         let mut changes_by_nibble_count = self.changes_by_nibble_count;
+        let mut stale_keys = self.removed_keys;
         let len = changes_by_nibble_count.iter().map(HashMap::len).sum();
         if len == 0 {
             // The tree is empty and there is no root present.
-            return (None, PatchSet::for_empty_root(manifest, self.root_version));
+            let mut patch = PatchSet::for_empty_root(manifest, self.root_version);
+            if let Some(patch_stale_keys) = patch.stale_keys_by_version.get_mut(&self.root_version)
+            {
+                patch_stale_keys.extend(stale_keys);
+            }
+            return (None, patch);
         }
         let mut patched_nodes = HashMap::with_capacity(len);
-        let mut stale_keys = vec![];
 
         // Compute hashes for the changed nodes with decreasing nibble count (i.e., topologically
         // sorted) and store the computed hash in the parent nodes.
@@ -506,6 +616,7 @@ impl WorkingPatchSet {
         let mut prev_nibbles = None;
         // Cumulative number of db reads.
         let mut db_reads = 0;
+        let max_nibble_count = self.depth / 4;
         for nibble_count in 1.. {
             // Extract `nibble_count` nibbles from each key for which we haven't found the parent
             // yet. Note that nibbles in `requested_keys` are sorted.
@@ -513,14 +624,15 @@ impl WorkingPatchSet {
                 if longest_prefixes[*idx].is_some() {
                     return None;
                 }
-                if nibble_count > 2 * KEY_SIZE {
+                let key = align_key(key, self.depth);
+                if nibble_count > max_nibble_count {
                     // We have traversed to the final tree level. There's nothing to load;
                     // we just need to record the longest prefix as the full key.
-                    longest_prefixes[*idx] = Some(Nibbles::new(key, 2 * KEY_SIZE));
+                    longest_prefixes[*idx] = Some(Nibbles::new(&key, max_nibble_count));
                     return None;
                 }
 
-                let nibbles = Nibbles::new(key, nibble_count);
+                let nibbles = Nibbles::new(&key, nibble_count);
                 let (this_parent_nibbles, last_nibble) = nibbles.split_last().unwrap();
                 // ^ `unwrap()` is safe by construction; `nibble_count` is positive
                 let this_ref = self.child_ref(&this_parent_nibbles, last_nibble);
@@ -569,8 +681,20 @@ impl WorkingPatchSet {
     }
 
     pub(super) fn traverse(&self, key: Key, parent_nibbles: &Nibbles) -> TraverseOutcome {
-        for nibble_idx in parent_nibbles.nibble_count().. {
-            let nibbles = Nibbles::new(&key, nibble_idx);
+        // If the tree was collapsed by removing keys after `parent_nibbles` were computed,
+        // the node at `parent_nibbles` may be missing. In this case, we start from its closest
+        // existing ancestor.
+        let mut start_nibbles = *parent_nibbles;
+        while self.get(&start_nibbles).is_none() {
+            let Some((ancestor_nibbles, _)) = start_nibbles.split_last() else {
+                break;
+            };
+            start_nibbles = ancestor_nibbles;
+        }
+
+        let aligned_key = align_key(&key, self.depth);
+        for nibble_idx in start_nibbles.nibble_count().. {
+            let nibbles = Nibbles::new(&aligned_key, nibble_idx);
             match self.get(&nibbles) {
                 Some(Node::Internal(_)) => { /* continue descent */ }
                 Some(Node::Leaf(leaf)) if leaf.full_key == key => {
@@ -633,7 +757,10 @@ impl WorkingPatchSet {
                 // Find the level at which `leaf.full_key` and `key` diverge.
                 // Note the addition of 1; e.g., if the keys differ at 0th bit, they
                 // differ at level 1 of the tree.
-                let diverging_level = utils::find_diverging_bit(key, leaf.full_key) + 1;
+                let diverging_level = utils::find_diverging_bit(
+                    align_key(&key, self.depth),
+                    align_key(&leaf.full_key, self.depth),
+                ) + 1;
                 let nibble_count = nibbles.nibble_count();
                 debug_assert!(diverging_level > 4 * nibble_count);
                 let mut path = MerklePath::new(diverging_level);
@@ -683,7 +810,7 @@ mod tests {
     use super::*;
     use crate::{
         storage::Storage,
-        types::{Key, LeafNode, TreeEntry},
+        types::{Key, LeafNode, TreeEntry, TREE_DEPTH},
     };
 
     fn patch_len(patch: &WorkingPatchSet) -> usize {
@@ -692,7 +819,7 @@ mod tests {
 
     #[test]
     fn splitting_patch_set() {
-        let mut patch = WorkingPatchSet::new(0, Root::Empty);
+        let mut patch = WorkingPatchSet::new(0, Root::Empty, TREE_DEPTH);
         let node = patch.ensure_internal_root_node();
         assert_eq!(node.child_count(), 0);
 
@@ -738,7 +865,7 @@ mod tests {
     #[test]
     fn loading_greatest_key() {
         // Test empty DB.
-        let mut patch = WorkingPatchSet::new(0, Root::Empty);
+        let mut patch = WorkingPatchSet::new(0, Root::Empty, TREE_DEPTH);
         let load_result = patch.load_greatest_key(&PatchSet::default());
         assert!(load_result.is_none());
 
@@ -749,7 +876,7 @@ mod tests {
             Storage::new(&db, &(), 0, true).extend(vec![TreeEntry::new(key, 1, ValueHash::ZERO)]);
         db.apply_patch(patch);
 
-        let mut patch = WorkingPatchSet::new(1, db.root(0).unwrap());
+        let mut patch = WorkingPatchSet::new(1, db.root(0).unwrap(), TREE_DEPTH);
         let (greatest_leaf, load_result) = patch.load_greatest_key(&db).unwrap();
         assert_eq!(greatest_leaf.full_key, key);
         assert_eq!(load_result.longest_prefixes.len(), 1);
@@ -765,7 +892,7 @@ mod tests {
         )]);
         db.apply_patch(patch);
 
-        let mut patch = WorkingPatchSet::new(2, db.root(1).unwrap());
+        let mut patch = WorkingPatchSet::new(2, db.root(1).unwrap(), TREE_DEPTH);
         let (greatest_leaf, load_result) = patch.load_greatest_key(&db).unwrap();
         assert_eq!(greatest_leaf.full_key, other_key);
         assert_eq!(load_result.longest_prefixes.len(), 1);
@@ -780,7 +907,7 @@ mod tests {
         )]);
         db.apply_patch(patch);
 
-        let mut patch = WorkingPatchSet::new(3, db.root(2).unwrap());
+        let mut patch = WorkingPatchSet::new(3, db.root(2).unwrap(), TREE_DEPTH);
         let (greatest_leaf, load_result) = patch.load_greatest_key(&db).unwrap();
         assert_eq!(greatest_leaf.full_key, greater_key);
         assert_eq!(load_result.longest_prefixes.len(), 1);
@@ -0,0 +1,144 @@
+//! Prefetching tree nodes for the upcoming tree updates.
+
+use std::collections::HashMap;
+
+use super::{Database, NodeKeys, PatchSet, SortedKeys, WorkingPatchSet};
+use crate::{
+    errors::DeserializeError,
+    types::{Key, Manifest, Node, NodeKey, Root},
+};
+
+/// Nodes loaded from the database in advance of a tree update.
+///
+/// Since node keys include node versions, a node read from the database never changes
+/// (unless it's pruned, which can only happen for stale nodes). Hence, prefetched nodes
+/// remain valid for all future tree versions.
+#[derive(Debug, Default)]
+pub(crate) struct PrefetchedNodes(HashMap<NodeKey, Node>);
+
+impl PrefetchedNodes {
+    /// Loads ancestors for the specified `keys` in the tree at `version` (i.e., all nodes
+    /// on the paths from the root to the keys).
+    pub fn load<DB: Database + ?Sized>(
+        db: &DB,
+        version: u64,
+        depth: usize,
+        keys: impl Iterator<Item = Key>,
+    ) -> Self {
+        let Some(root) = db.root(version) else {
+            return Self::default();
+        };
+        // The root version is irrelevant since we only load nodes from `db`.
+        let mut patch_set = WorkingPatchSet::new(version + 1, root, depth);
+        patch_set.load_ancestors(&SortedKeys::new(keys), db);
+        Self(patch_set.into_loaded_nodes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Wrapper for a [`Database`] that looks up nodes in [`PrefetchedNodes`] before querying
+/// the wrapped database. The wrapper is read-only.
+#[derive(Debug)]
+pub(crate) struct Prefetched<'a, DB: ?Sized> {
+    inner: &'a DB,
+    nodes: PrefetchedNodes,
+}
+
+impl<'a, DB: Database + ?Sized> Prefetched<'a, DB> {
+    pub fn new(inner: &'a DB, nodes: PrefetchedNodes) -> Self {
+        Self { inner, nodes }
+    }
+}
+
+impl<DB: Database + ?Sized> Database for Prefetched<'_, DB> {
+    fn try_manifest(&self) -> Result<Option<Manifest>, DeserializeError> {
+        self.inner.try_manifest()
+    }
+
+    fn try_root(&self, version: u64) -> Result<Option<Root>, DeserializeError> {
+        self.inner.try_root(version)
+    }
+
+    fn try_tree_node(
+        &self,
+        key: &NodeKey,
+        is_leaf: bool,
+    ) -> Result<Option<Node>, DeserializeError> {
+        if let Some(node) = self.nodes.0.get(key) {
+            return Ok(Some(node.clone()));
+        }
+        self.inner.try_tree_node(key, is_leaf)
+    }
+
+    fn tree_nodes(&self, keys: &NodeKeys) -> Vec<Option<Node>> {
+        let db_keys: Vec<_> = keys
+            .iter()
+            .filter(|(key, _)| !self.nodes.0.contains_key(key))
+            .copied()
+            .collect();
+        if db_keys.len() == keys.len() {
+            return self.inner.tree_nodes(keys);
+        }
+
+        let mut db_values = self.inner.tree_nodes(&db_keys).into_iter();
+        let values = keys.iter().map(|(key, _)| {
+            if let Some(node) = self.nodes.0.get(key) {
+                Some(node.clone())
+            } else {
+                db_values.next().unwrap()
+            }
+        });
+        values.collect()
+    }
+
+    fn apply_patch(&mut self, _patch: PatchSet) {
+        unreachable!("prefetched databases are read-only");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axon_types::B256;
+
+    use super::*;
+    use crate::{
+        storage::{tests::FIRST_KEY, Storage},
+        types::{TreeEntry, TreeInstruction},
+    };
+
+    #[test]
+    fn prefetched_nodes_are_used_for_tree_updates() {
+        let mut db = PatchSet::default();
+        let entries = (0..100_u64)
+            .map(|i| TreeEntry::new(Key::from(i) << 200, i + 1, B256::repeat_byte(i as u8)));
+        let (_, patch) = Storage::new(&db, &(), 0, true).extend(entries.collect());
+        db.apply_patch(patch);
+
+        let keys = [FIRST_KEY, Key::from(10) << 200, Key::from(50) << 200];
+        let prefetched = PrefetchedNodes::load(&db, 0, 256, keys.iter().copied());
+        assert!(!prefetched.0.is_empty());
+        // All nodes should be loaded from the database.
+        assert!(prefetched.0.iter().all(|(key, node)| {
+            db.tree_node(key, matches!(node, Node::Leaf(_))).as_ref() == Some(node)
+        }));
+
+        let instructions: Vec<_> = keys
+            .iter()
+            .map(|&key| TreeInstruction::Write(TreeEntry::new(key, 1, B256::repeat_byte(0xff))))
+            .collect();
+        let prefetched_db = Prefetched::new(&db, prefetched);
+        let (output, patch) = Storage::new(&prefetched_db, &(), 1, true)
+            .extend_with_instructions(instructions.clone());
+        let (expected_output, expected_patch) =
+            Storage::new(&db, &(), 1, true).extend_with_instructions(instructions);
+        assert_eq!(output, expected_output);
+        assert_eq!(patch.root(1), expected_patch.root(1));
+        assert_eq!(
+            patch.patches_by_version[&1].nodes,
+            expected_patch.patches_by_version[&1].nodes
+        );
+    }
+}
@@ -23,13 +23,16 @@
 //! - Produce partial Merkle proofs for levels 4.. (rather than full proofs for levels 0..) when
 //!   working in groups. The root hash for each of the proofs will actually be the *subtree* root
 //!   hash, and Merkle proofs would have at most 252 `ValueHash`es.
-//! - Recombine the proofs in the original `instructions` order. For each write instruction, update
-//!   the corresponding child reference hash in the root node to equal the (subtree) root hash from
-//!   the proof, and recompute the root hash of the root node. Then, extend the Merkle proof with
+//! - Recombine the proofs in the original `instructions` order. For each write or remove
+//!   instruction, update the corresponding child reference hash in the root node to equal
+//!   the (subtree) root hash from the proof (or remove the reference if the subtree became empty),
+//!   and recompute the root hash of the root node. Then, extend the Merkle proof with
 //!   upper 4 `ValueHash`es based on the root node.
 //!
 //! This approach only works if the root is an [`InternalNode`]. Fortunately, we can always
 //! transform the root to an `InternalNode` and then transform it back if necessary.
+//! For the same reason, removals never collapse the root node during parallel processing;
+//! the root is collapsed (if necessary) after all logs are finalized.
 //!
 //! ## Merging patch sets
 //!
@@ -45,8 +48,8 @@ use crate::{
     metrics::{HashingStats, TreeUpdaterStats, BLOCK_TIMINGS, GENERAL_METRICS},
     storage::{Database, NewLeafData, PatchSet, SortedKeys, Storage, TreeUpdater},
     types::{
-        BlockOutputWithProofs, InternalNode, Key, Nibbles, Node, TreeInstruction, TreeLogEntry,
-        TreeLogEntryWithProof, ValueHash,
+        align_key, BlockOutputWithProofs, InternalNode, Key, Nibbles, Node, TreeInstruction,
+        TreeLogEntry, TreeLogEntryWithProof, ValueHash,
     },
     utils::merge_by_index,
 };
@@ -57,8 +60,9 @@ pub(super) const SUBTREE_COUNT: usize = 16;
 const SUBTREE_ROOT_LEVEL: usize = 4;
 
 impl TreeUpdater {
-    fn extend_precomputed(
+    fn extend_precomputed<DB: Database + ?Sized>(
         &mut self,
+        db: &DB,
         hasher: &mut HasherWithStats<'_>,
         first_nibble: u8,
         instructions: Vec<InstructionWithPrecomputes>,
@@ -96,6 +100,26 @@ impl TreeUpdater {
                         root_hash,
                     }
                 }
+                TreeInstruction::Remove(key) => {
+                    // The Merkle path for the removed key is the same before and after removal,
+                    // so we create it before modifying the tree.
+                    let (_, merkle_path) = self.patch_set.create_proof(
+                        hasher,
+                        key,
+                        &parent_nibbles,
+                        SUBTREE_ROOT_LEVEL / 4,
+                    );
+                    let (log, changed_nibbles) =
+                        self.remove(db, key, &parent_nibbles, SUBTREE_ROOT_LEVEL / 4);
+                    if let Some(nibbles) = changed_nibbles {
+                        root_hash = self.update_hashes_after_removal(hasher, nibbles);
+                    }
+                    TreeLogEntryWithProof {
+                        base: log,
+                        merkle_path,
+                        root_hash,
+                    }
+                }
             };
             logs.push((index, log));
         }
@@ -149,6 +173,46 @@ impl TreeUpdater {
         (node_hash, merkle_path)
     }
 
+    /// Updates hashes for the node at `nibbles` changed by a removal and all its ancestors
+    /// up to the subtree root. The node may be missing (i.e., removed from the tree) or be a leaf
+    /// moved up the tree. Returns the new root hash of the subtree.
+    fn update_hashes_after_removal(
+        &mut self,
+        hasher: &mut HasherWithStats<'_>,
+        mut nibbles: Nibbles,
+    ) -> ValueHash {
+        debug_assert!(nibbles.nibble_count() * 4 >= SUBTREE_ROOT_LEVEL);
+        let level = nibbles.nibble_count() * 4;
+        let mut node_hash = self
+            .patch_set
+            .get(&nibbles)
+            .map(|node| node.hash(hasher, level));
+
+        while let Some((parent_nibbles, last_nibble)) = nibbles.split_last() {
+            if parent_nibbles.nibble_count() == 0 {
+                break;
+            }
+
+            let parent = self.patch_set.get_mut(&parent_nibbles);
+            let Some(Node::Internal(parent)) = parent else {
+                unreachable!()
+            };
+            let parent_level = parent_nibbles.nibble_count() * 4;
+            let mut updater = parent.updater(hasher, parent_level, last_nibble);
+            node_hash = Some(if let Some(node_hash) = node_hash {
+                updater.update_child_hash(node_hash)
+            } else {
+                updater.remove_child_ref()
+            });
+            nibbles = parent_nibbles;
+        }
+
+        node_hash.unwrap_or_else(|| {
+            // The entire subtree was removed.
+            hasher.empty_subtree_hash(hasher.depth() - SUBTREE_ROOT_LEVEL)
+        })
+    }
+
     /// Proves the existence or absence of a key in the tree.
     pub(super) fn prove(
         &mut self,
@@ -194,6 +258,7 @@ impl TreeUpdater {
     ) -> Vec<TreeLogEntryWithProof> {
         let version = self.patch_set.root_version();
         let mut root_hash = root.hash(hasher, 0);
+        let empty_subtree_hash = hasher.empty_subtree_hash(hasher.depth() - SUBTREE_ROOT_LEVEL);
 
         // Check the kind of each of subtrees. This is used later to ensure the correct
         // `ChildRef.is_leaf` values in the root node.
@@ -208,8 +273,13 @@ impl TreeUpdater {
             let nibble = u8::try_from(subtree_idx).unwrap();
             let mut updater = root.updater(hasher, 0, nibble);
             if !log.base.is_read() {
-                updater.ensure_child_ref(version, is_leaf_by_subtree[subtree_idx]);
-                root_hash = updater.update_child_hash(log.root_hash);
+                root_hash = if log.root_hash == empty_subtree_hash {
+                    // The subtree was emptied by removals.
+                    updater.remove_child_ref()
+                } else {
+                    updater.ensure_child_ref(version, is_leaf_by_subtree[subtree_idx]);
+                    updater.update_child_hash(log.root_hash)
+                };
             }
             updater.extend_merkle_path(&mut log.merkle_path);
 
@@ -243,7 +313,8 @@ impl<'a, DB: Database + ?Sized> Storage<'a, DB> {
         let parent_nibbles = self.updater.load_ancestors(&sorted_keys, self.db);
         load_nodes_latency.observe();
 
-        let instruction_parts = InstructionWithPrecomputes::split(instructions, parent_nibbles);
+        let instruction_parts =
+            InstructionWithPrecomputes::split(instructions, parent_nibbles, self.hasher.depth());
         let initial_root = self.updater.patch_set.ensure_internal_root_node();
         let initial_metrics = self.updater.metrics;
         let storage_parts = self.updater.split();
@@ -260,7 +331,8 @@ impl<'a, DB: Database + ?Sized> Storage<'a, DB> {
                 || self.hasher.with_stats(&hashing_stats),
                 |hasher, (i, (mut storage, instructions))| {
                     let first_nibble = u8::try_from(i).unwrap();
-                    let logs = storage.extend_precomputed(hasher, first_nibble, instructions);
+                    let logs =
+                        storage.extend_precomputed(self.db, hasher, first_nibble, instructions);
                     (storage, logs)
                 },
             )
@@ -292,12 +364,14 @@ impl<'a, DB: Database + ?Sized> Storage<'a, DB> {
         logs: Vec<(usize, TreeLogEntryWithProof<MerklePath>)>,
     ) -> (BlockOutputWithProofs, PatchSet) {
         self.leaf_count += self.updater.metrics.new_leaves;
+        self.leaf_count -= self.updater.metrics.removed_leaves;
         tracing::debug!(
             "Finished updating tree; total leaf count: {}, stats: {:?}",
             self.leaf_count,
             self.updater.metrics
         );
         let logs = self.updater.finalize_logs(hasher, root, logs);
+        self.updater.collapse_root(self.db);
         self.updater.metrics.report();
 
         let patch = self
@@ -330,6 +404,7 @@ impl InstructionWithPrecomputes {
     fn split(
         instructions: Vec<TreeInstruction>,
         parent_nibbles: Vec<Nibbles>,
+        depth: usize,
     ) -> [Vec<Self>; SUBTREE_COUNT] {
         const EMPTY_VEC: Vec<InstructionWithPrecomputes> = Vec::new();
         // ^ Need to extract this to a constant to be usable as an array initializer.
@@ -337,7 +412,7 @@ impl InstructionWithPrecomputes {
         let mut parts = [EMPTY_VEC; SUBTREE_COUNT];
         let it = instructions.into_iter().zip(parent_nibbles);
         for (index, (instruction, parent_nibbles)) in it.enumerate() {
-            let first_nibble = Nibbles::nibble(&instruction.key(), 0);
+            let first_nibble = Nibbles::nibble(&align_key(&instruction.key(), depth), 0);
             let part = &mut parts[first_nibble as usize];
             part.push(Self {
                 index,
@@ -1,13 +1,22 @@
 //! RocksDB implementation of [`Database`].
 
-use std::path::Path;
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use axon_storage::{db::NamedColumnFamily, rocksdb::DBPinnableSlice, RocksDB};
+use axon_storage::{
+    db::NamedColumnFamily,
+    rocksdb::{self, DBPinnableSlice},
+    AccessMode, ReadOnly, ReadWrite, RocksDB, RocksDBSnapshot, Secondary,
+};
 use rayon::prelude::*;
 
 use crate::{
+    consistency::VerificationCursor,
     errors::{DeserializeError, ErrorContext},
     metrics::ApplyPatchStats,
+    snapshot::{PinnedVersions, VersionPin},
     storage::{
         database::{PruneDatabase, PrunePatchSet},
         Database, NodeKeys, PatchSet,
@@ -22,6 +31,8 @@ pub enum MerkleTreeColumnFamily {
     /// `NodeKey` -> `Node` mapping.
     Tree,
     /// Column family containing stale node keys that are eventually removed by the pruning logic.
+    /// Also contains creation times of tree versions keyed by the 8-byte version; these keys
+    /// cannot collide with stale keys, which are longer.
     StaleKeys,
 }
 
@@ -51,24 +62,34 @@ impl NamedColumnFamily for MerkleTreeColumnFamily {
 /// results. The intended usage of cloning is to have no more than one component of each kind
 /// modifying RocksDB (i.e., no more than one `MerkleTree` and no more than one `MerkleTreePruner`).
 ///
+/// # Access modes
+///
+/// By default, the wrapped RocksDB instance is opened in the [`ReadWrite`] mode. Wrappers
+/// for instances opened in the [`ReadOnly`] or [`Secondary`] modes can be used to read the tree
+/// while another process owns it (e.g., by an offline inspector). Such wrappers implement
+/// [`Database`], but panic if a tree built on top of them is modified.
+///
 /// [`MerkleTree`]: crate::MerkleTree
 /// [`MerkleTreePruner`]: crate::MerkleTreePruner
 #[derive(Debug, Clone)]
-pub struct RocksDBWrapper {
-    db: RocksDB<MerkleTreeColumnFamily>,
+pub struct RocksDBWrapper<M = ReadWrite> {
+    db: RocksDB<MerkleTreeColumnFamily, M>,
     multi_get_chunk_size: usize,
+    /// Versions pinned by [`TreeSnapshot`]s. Shared among all wrappers for the same RocksDB
+    /// instance, so that the pruner can respect snapshots created by the tree.
+    ///
+    /// [`TreeSnapshot`]: crate::TreeSnapshot
+    pinned_versions: PinnedVersions,
 }
 
-impl RocksDBWrapper {
+impl<M: AccessMode> RocksDBWrapper<M> {
     /// Key to store the tree [`Manifest`].
     // This key must not overlap with keys for nodes; easy to see that it's true,
     // since the minimum node key is [0, 0, 0, 0, 0, 0, 0, 0].
     const MANIFEST_KEY: &'static [u8] = &[0];
-
-    /// Creates a new wrapper, initializing RocksDB at the specified directory.
-    pub fn new(path: &Path) -> Self {
-        Self::from(RocksDB::new(path))
-    }
+    /// Key to store the [`VerificationCursor`] of a resumable consistency check. Similarly to
+    /// the manifest key, it doesn't overlap with node keys.
+    const VERIFICATION_CURSOR_KEY: &'static [u8] = &[1];
 
     /// Sets the chunk size for multi-get operations. The requested keys will be split
     /// into chunks of this size and requested in parallel using `rayon`. Setting chunk size
@@ -87,66 +108,131 @@ impl RocksDBWrapper {
         self.multi_get_chunk_size = chunk_size;
     }
 
-    fn raw_node(&self, key: &[u8]) -> Option<Vec<u8>> {
+    /// Returns the wrapped RocksDB instance.
+    pub fn into_inner(self) -> RocksDB<MerkleTreeColumnFamily, M> {
         self.db
-            .get_cf(MerkleTreeColumnFamily::Tree, key)
-            .expect("Failed reading from RocksDB")
     }
 
-    fn raw_nodes(&self, keys: &NodeKeys) -> Vec<Option<DBPinnableSlice<'_>>> {
-        // `par_chunks()` below uses `rayon` to speed up multi-get I/O;
-        // see `Self::set_multi_get_chunk_size()` docs for an explanation why this makes sense.
-        keys.par_chunks(self.multi_get_chunk_size)
-            .map(|chunk| {
-                let keys = chunk.iter().map(|(key, _)| key.to_db_key());
-                let results = self.db.multi_get_cf(MerkleTreeColumnFamily::Tree, keys);
-                results
-                    .into_iter()
-                    .map(|result| result.expect("Failed reading from RocksDB"))
-            })
-            .flatten_iter()
-            .collect()
+    /// Returns the persisted progress of a resumable consistency check, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the persisted cursor cannot be deserialized.
+    pub fn verification_cursor(&self) -> Result<Option<VerificationCursor>, DeserializeError> {
+        let Some(raw_cursor) = self.raw_node(Self::VERIFICATION_CURSOR_KEY) else {
+            return Ok(None);
+        };
+        VerificationCursor::deserialize(&raw_cursor)
+            .map(Some)
+            .map_err(|err| err.with_context(ErrorContext::VerificationCursor))
     }
+}
 
-    fn deserialize_node(
-        raw_node: &[u8],
-        key: &NodeKey,
-        is_leaf: bool,
-    ) -> Result<Node, DeserializeError> {
-        // If we didn't succeed with the patch set, or the key version is old,
-        // access the underlying storage.
-        let node = if is_leaf {
-            LeafNode::deserialize(raw_node).map(Node::Leaf)
-        } else {
-            InternalNode::deserialize(raw_node).map(Node::Internal)
-        };
-        node.map_err(|err| {
-            err.with_context(if is_leaf {
-                ErrorContext::Leaf(*key)
-            } else {
-                ErrorContext::InternalNode(*key)
-            })
-        })
+impl RocksDBWrapper {
+    /// Creates a new wrapper, initializing RocksDB at the specified directory.
+    pub fn new(path: &Path) -> Self {
+        Self::from(RocksDB::new(path))
     }
 
-    /// Returns the wrapped RocksDB instance.
-    pub fn into_inner(self) -> RocksDB<MerkleTreeColumnFamily> {
+    /// Persists the progress of a resumable consistency check. If `cursor` is `None`,
+    /// removes the persisted progress.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn save_verification_cursor(&self, cursor: Option<&VerificationCursor>) {
+        let tree_cf = MerkleTreeColumnFamily::Tree;
+        let mut write_batch = self.db.new_write_batch();
+        if let Some(cursor) = cursor {
+            let mut buffer = vec![];
+            cursor.serialize(&mut buffer);
+            write_batch.put_cf(tree_cf, Self::VERIFICATION_CURSOR_KEY, &buffer);
+        } else {
+            write_batch.delete_cf(tree_cf, Self::VERIFICATION_CURSOR_KEY);
+        }
         self.db
+            .write(write_batch)
+            .expect("Failed writing a batch to RocksDB");
     }
-}
 
-impl From<RocksDB<MerkleTreeColumnFamily>> for RocksDBWrapper {
-    fn from(db: RocksDB<MerkleTreeColumnFamily>) -> Self {
-        Self {
-            db,
-            multi_get_chunk_size: usize::MAX,
+    /// Overwrites the specified nodes and, optionally, the root for a certain version without
+    /// changing the manifest. This is used to repair tree inconsistencies.
+    pub(crate) fn overwrite_nodes(
+        &mut self,
+        root: Option<(u64, &Root)>,
+        nodes: &[(NodeKey, Node)],
+    ) {
+        let tree_cf = MerkleTreeColumnFamily::Tree;
+        let mut write_batch = self.db.new_write_batch();
+        let mut node_bytes = Vec::with_capacity(128);
+        if let Some((version, root)) = root {
+            root.serialize(&mut node_bytes);
+            write_batch.put_cf(tree_cf, &NodeKey::empty(version).to_db_key(), &node_bytes);
         }
+        for (node_key, node) in nodes {
+            node_bytes.clear();
+            node.serialize(&mut node_bytes);
+            write_batch.put_cf(tree_cf, &node_key.to_db_key(), &node_bytes);
+        }
+        self.db
+            .write(write_batch)
+            .expect("Failed writing a batch to RocksDB");
+    }
+
+    /// Checks whether the provided key in [`MerkleTreeColumnFamily::StaleKeys`] stores a version
+    /// creation time rather than a stale key.
+    fn is_created_at_key(key_bytes: &[u8]) -> bool {
+        key_bytes.len() == 8
+    }
+
+    /// Pins the specified `version`, so that it's not removed by the pruner while the returned
+    /// guard is alive. Returns `None` if the version is being pruned or was pruned.
+    pub(crate) fn pin_version(&self, version: u64) -> Option<VersionPin> {
+        self.pinned_versions.pin(version)
+    }
+
+    /// Creates a point-in-time snapshot of the database with the specified `version` pinned.
+    /// The version is pinned before the RocksDB snapshot is taken, so that the pruner cannot
+    /// remove the version in between. Returns `None` if the version is being pruned or was pruned.
+    pub(crate) fn snapshot(
+        &self,
+        version: u64,
+    ) -> Option<(RocksDBSnapshotWrapper<'_>, VersionPin)> {
+        let pin = self.pinned_versions.pin(version)?;
+        let snapshot = RocksDBSnapshotWrapper {
+            snapshot: self.db.snapshot(),
+            multi_get_chunk_size: self.multi_get_chunk_size,
+        };
+        Some((snapshot, pin))
     }
 }
 
-impl Database for RocksDBWrapper {
-    fn try_manifest(&self) -> Result<Option<Manifest>, DeserializeError> {
-        let Some(raw_manifest) = self.raw_node(Self::MANIFEST_KEY) else {
+fn deserialize_node(
+    raw_node: &[u8],
+    key: &NodeKey,
+    is_leaf: bool,
+) -> Result<Node, DeserializeError> {
+    // If we didn't succeed with the patch set, or the key version is old,
+    // access the underlying storage.
+    let node = if is_leaf {
+        LeafNode::deserialize(raw_node).map(Node::Leaf)
+    } else {
+        InternalNode::deserialize(raw_node).map(Node::Internal)
+    };
+    node.map_err(|err| {
+        err.with_context(if is_leaf {
+            ErrorContext::Leaf(*key)
+        } else {
+            ErrorContext::InternalNode(*key)
+        })
+    })
+}
+
+/// Raw node access shared by [`RocksDBWrapper`] and its snapshots.
+trait ReadRawNodes: Send + Sync {
+    fn raw_node(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+    fn raw_nodes(&self, keys: &NodeKeys) -> Vec<Option<DBPinnableSlice<'_>>>;
+
+    fn read_manifest(&self) -> Result<Option<Manifest>, DeserializeError> {
+        let Some(raw_manifest) = self.raw_node(<RocksDBWrapper>::MANIFEST_KEY) else {
             return Ok(None);
         };
         Manifest::deserialize(&raw_manifest)
@@ -154,7 +240,7 @@ impl Database for RocksDBWrapper {
             .map_err(|err| err.with_context(ErrorContext::Manifest))
     }
 
-    fn try_root(&self, version: u64) -> Result<Option<Root>, DeserializeError> {
+    fn read_root(&self, version: u64) -> Result<Option<Root>, DeserializeError> {
         let Some(raw_root) = self.raw_node(&NodeKey::empty(version).to_db_key()) else {
             return Ok(None);
         };
@@ -163,7 +249,7 @@ impl Database for RocksDBWrapper {
             .map_err(|err| err.with_context(ErrorContext::Root(version)))
     }
 
-    fn try_tree_node(
+    fn read_tree_node(
         &self,
         key: &NodeKey,
         is_leaf: bool,
@@ -171,21 +257,77 @@ impl Database for RocksDBWrapper {
         let Some(raw_node) = self.raw_node(&key.to_db_key()) else {
             return Ok(None);
         };
-        Self::deserialize_node(&raw_node, key, is_leaf).map(Some)
+        deserialize_node(&raw_node, key, is_leaf).map(Some)
     }
 
-    fn tree_nodes(&self, keys: &NodeKeys) -> Vec<Option<Node>> {
+    fn read_tree_nodes(&self, keys: &NodeKeys) -> Vec<Option<Node>> {
         let raw_nodes = self.raw_nodes(keys).into_iter().zip(keys);
 
         let nodes = raw_nodes.map(|(maybe_node, (key, is_leaf))| {
             maybe_node
-                .map(|raw_node| Self::deserialize_node(&raw_node, key, *is_leaf))
+                .map(|raw_node| deserialize_node(&raw_node, key, *is_leaf))
                 .transpose()
         });
         nodes
             .collect::<Result<_, _>>()
             .unwrap_or_else(|err| panic!("{err}"))
     }
+}
+
+impl<M: AccessMode> ReadRawNodes for RocksDBWrapper<M> {
+    fn raw_node(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.db
+            .get_cf(MerkleTreeColumnFamily::Tree, key)
+            .expect("Failed reading from RocksDB")
+    }
+
+    fn raw_nodes(&self, keys: &NodeKeys) -> Vec<Option<DBPinnableSlice<'_>>> {
+        // `par_chunks()` below uses `rayon` to speed up multi-get I/O;
+        // see `Self::set_multi_get_chunk_size()` docs for an explanation why this makes sense.
+        keys.par_chunks(self.multi_get_chunk_size)
+            .map(|chunk| {
+                let keys = chunk.iter().map(|(key, _)| key.to_db_key());
+                let results = self.db.multi_get_cf(MerkleTreeColumnFamily::Tree, keys);
+                results
+                    .into_iter()
+                    .map(|result| result.expect("Failed reading from RocksDB"))
+            })
+            .flatten_iter()
+            .collect()
+    }
+}
+
+impl<M: AccessMode> From<RocksDB<MerkleTreeColumnFamily, M>> for RocksDBWrapper<M> {
+    fn from(db: RocksDB<MerkleTreeColumnFamily, M>) -> Self {
+        let pinned_versions = PinnedVersions::for_db_path(db.path());
+        Self {
+            db,
+            multi_get_chunk_size: usize::MAX,
+            pinned_versions,
+        }
+    }
+}
+
+impl Database for RocksDBWrapper {
+    fn try_manifest(&self) -> Result<Option<Manifest>, DeserializeError> {
+        self.read_manifest()
+    }
+
+    fn try_root(&self, version: u64) -> Result<Option<Root>, DeserializeError> {
+        self.read_root(version)
+    }
+
+    fn try_tree_node(
+        &self,
+        key: &NodeKey,
+        is_leaf: bool,
+    ) -> Result<Option<Node>, DeserializeError> {
+        self.read_tree_node(key, is_leaf)
+    }
+
+    fn tree_nodes(&self, keys: &NodeKeys) -> Vec<Option<Node>> {
+        self.read_tree_nodes(keys)
+    }
 
     fn apply_patch(&mut self, patch: PatchSet) {
         let tree_cf = MerkleTreeColumnFamily::Tree;
@@ -198,7 +340,11 @@ impl Database for RocksDBWrapper {
         patch.manifest.serialize(&mut node_bytes);
         write_batch.put_cf(tree_cf, Self::MANIFEST_KEY, &node_bytes);
 
+        let mut created_at_by_version = vec![];
         for (version, sub_patch) in patch.patches_by_version {
+            if let Some(created_at) = sub_patch.created_at {
+                created_at_by_version.push((version, created_at));
+            }
             let is_update = patch.updated_version == Some(version);
             let root_key = NodeKey::empty(version);
             if !is_update {
@@ -224,6 +370,16 @@ impl Database for RocksDBWrapper {
         }
 
         let stale_keys_cf = MerkleTreeColumnFamily::StaleKeys;
+        for (version, created_at) in created_at_by_version {
+            let created_at = created_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |timestamp| timestamp.as_millis() as u64);
+            write_batch.put_cf(
+                stale_keys_cf,
+                &version.to_be_bytes(),
+                &created_at.to_be_bytes(),
+            );
+        }
         let all_stale_keys = patch
             .stale_keys_by_version
             .into_iter()
@@ -242,11 +398,69 @@ impl Database for RocksDBWrapper {
     }
 }
 
+/// Implements [`Database`] for [`RocksDBWrapper`]s with the specified non-writable access modes.
+macro_rules! impl_read_only_database {
+    ($($mode:ty),+) => {
+        $(
+        impl Database for RocksDBWrapper<$mode> {
+            fn try_manifest(&self) -> Result<Option<Manifest>, DeserializeError> {
+                self.read_manifest()
+            }
+
+            fn try_root(&self, version: u64) -> Result<Option<Root>, DeserializeError> {
+                self.read_root(version)
+            }
+
+            fn try_tree_node(
+                &self,
+                key: &NodeKey,
+                is_leaf: bool,
+            ) -> Result<Option<Node>, DeserializeError> {
+                self.read_tree_node(key, is_leaf)
+            }
+
+            fn tree_nodes(&self, keys: &NodeKeys) -> Vec<Option<Node>> {
+                self.read_tree_nodes(keys)
+            }
+
+            fn apply_patch(&mut self, _patch: PatchSet) {
+                panic!(
+                    "Cannot modify RocksDB opened in {} mode",
+                    <$mode as AccessMode>::NAME
+                );
+            }
+        }
+        )+
+    };
+}
+
+impl_read_only_database!(ReadOnly, Secondary);
+
+impl RocksDBWrapper<Secondary> {
+    /// Catches up with the changes made by the primary RocksDB instance. Since the wrapper
+    /// is cloneable, this can be called on a clone of the wrapper owned by a tree.
+    ///
+    /// # Errors
+    ///
+    /// Propagates RocksDB errors.
+    pub fn try_catch_up_with_primary(&self) -> Result<(), rocksdb::Error> {
+        self.db.try_catch_up_with_primary()
+    }
+}
+
 impl PruneDatabase for RocksDBWrapper {
+    fn reserve_for_pruning(&self, target_retained_version: u64) -> u64 {
+        self.pinned_versions
+            .reserve_for_pruning(target_retained_version, |version| version)
+    }
+
     fn min_stale_key_version(&self) -> Option<u64> {
         let stale_keys_cf = MerkleTreeColumnFamily::StaleKeys;
-        let kv_bytes = self.db.prefix_iterator_cf(stale_keys_cf, &[]).next()?;
-        let version_prefix: [u8; 8] = kv_bytes.0[..8].try_into().unwrap();
+        let (key_bytes, _) = self
+            .db
+            .prefix_iterator_cf(stale_keys_cf, &[])
+            .find(|(key_bytes, _)| !Self::is_created_at_key(key_bytes))?;
+        let version_prefix: [u8; 8] = key_bytes[..8].try_into().unwrap();
         Some(u64::from_be_bytes(version_prefix))
     }
 
@@ -256,6 +470,7 @@ impl PruneDatabase for RocksDBWrapper {
         let keys = self
             .db
             .prefix_iterator_cf(stale_keys_cf, &version_prefix)
+            .filter(|(key_bytes, _)| !Self::is_created_at_key(key_bytes))
             .map(|entry| {
                 let key_bytes = entry.0;
                 debug_assert_eq!(&key_bytes[..8], version_prefix);
@@ -264,6 +479,18 @@ impl PruneDatabase for RocksDBWrapper {
         keys.collect()
     }
 
+    fn version_created_at(&self, version: u64) -> Option<SystemTime> {
+        let stale_keys_cf = MerkleTreeColumnFamily::StaleKeys;
+        let raw_timestamp = self
+            .db
+            .get_cf(stale_keys_cf, &version.to_be_bytes())
+            .expect("Failed reading from RocksDB")?;
+        let timestamp: [u8; 8] = raw_timestamp.as_slice().try_into().unwrap_or_else(|_| {
+            panic!("Malformed creation time for tree version {version}: {raw_timestamp:?}")
+        });
+        Some(UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(timestamp)))
+    }
+
     fn prune(&mut self, patch: PrunePatchSet) {
         let mut write_batch = self.db.new_write_batch();
 
@@ -281,12 +508,73 @@ impl PruneDatabase for RocksDBWrapper {
             .write(write_batch)
             .expect("Failed writing a batch to RocksDB");
     }
+
+    fn has_stalled_writes(&self) -> bool {
+        self.db.has_stalled_writes()
+    }
+}
+
+/// Read-only [`Database`] implementation for a point-in-time snapshot of [`RocksDBWrapper`].
+#[derive(Debug)]
+pub(crate) struct RocksDBSnapshotWrapper<'a> {
+    snapshot: RocksDBSnapshot<'a, MerkleTreeColumnFamily>,
+    multi_get_chunk_size: usize,
+}
+
+impl ReadRawNodes for RocksDBSnapshotWrapper<'_> {
+    fn raw_node(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.snapshot
+            .get_cf(MerkleTreeColumnFamily::Tree, key)
+            .expect("Failed reading from RocksDB")
+    }
+
+    fn raw_nodes(&self, keys: &NodeKeys) -> Vec<Option<DBPinnableSlice<'_>>> {
+        keys.par_chunks(self.multi_get_chunk_size)
+            .map(|chunk| {
+                let keys = chunk.iter().map(|(key, _)| key.to_db_key());
+                let results = self
+                    .snapshot
+                    .multi_get_cf(MerkleTreeColumnFamily::Tree, keys);
+                results
+                    .into_iter()
+                    .map(|result| result.expect("Failed reading from RocksDB"))
+            })
+            .flatten_iter()
+            .collect()
+    }
+}
+
+impl Database for RocksDBSnapshotWrapper<'_> {
+    fn try_manifest(&self) -> Result<Option<Manifest>, DeserializeError> {
+        self.read_manifest()
+    }
+
+    fn try_root(&self, version: u64) -> Result<Option<Root>, DeserializeError> {
+        self.read_root(version)
+    }
+
+    fn try_tree_node(
+        &self,
+        key: &NodeKey,
+        is_leaf: bool,
+    ) -> Result<Option<Node>, DeserializeError> {
+        self.read_tree_node(key, is_leaf)
+    }
+
+    fn tree_nodes(&self, keys: &NodeKeys) -> Vec<Option<Node>> {
+        self.read_tree_nodes(keys)
+    }
+
+    fn apply_patch(&mut self, _patch: PatchSet) {
+        unreachable!("RocksDB snapshots are read-only");
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::{HashMap, HashSet};
 
+    use axon_storage::RocksDBOptions;
     use tempfile::TempDir;
 
     use super::*;
@@ -336,6 +624,69 @@ mod tests {
         assert_contains_exactly_keys(&db, &expected_keys);
     }
 
+    #[test]
+    fn reading_tree_via_read_only_and_secondary_instances() {
+        let dir = TempDir::new().expect("failed creating temporary dir for RocksDB");
+        let mut db = RocksDBWrapper::new(dir.path());
+        let root = Root::new(2, Node::Internal(InternalNode::default()));
+        db.apply_patch(create_patch(0, root, generate_nodes(0, &[1, 2])));
+
+        let options = RocksDBOptions::default();
+        let read_only_db = RocksDBWrapper::from(RocksDB::open_read_only(dir.path(), options));
+        assert_eq!(read_only_db.manifest().unwrap().version_count, 1);
+        assert!(read_only_db.root(0).is_some());
+
+        let secondary_dir = TempDir::new().expect("failed creating temporary dir for RocksDB");
+        let secondary_db = RocksDB::open_secondary(dir.path(), secondary_dir.path(), options);
+        let secondary_db = RocksDBWrapper::from(secondary_db);
+        assert_eq!(secondary_db.manifest().unwrap().version_count, 1);
+
+        let root = Root::new(3, Node::Internal(InternalNode::default()));
+        db.apply_patch(create_patch(1, root, generate_nodes(1, &[3])));
+        assert!(read_only_db.root(1).is_none());
+        secondary_db.try_catch_up_with_primary().unwrap();
+        assert_eq!(secondary_db.manifest().unwrap().version_count, 2);
+        assert!(secondary_db.root(1).is_some());
+    }
+
+    #[test]
+    fn version_creation_times_are_persisted() {
+        let dir = TempDir::new().expect("failed creating temporary dir for RocksDB");
+        let mut db = RocksDBWrapper::new(dir.path());
+        let started_at = SystemTime::now() - Duration::from_millis(1);
+        let root = Root::new(2, Node::Internal(InternalNode::default()));
+        db.apply_patch(create_patch(0, root, generate_nodes(0, &[1, 2])));
+        let mut patch = create_patch(1, Root::Empty, HashMap::new());
+        patch
+            .stale_keys_by_version
+            .insert(1, vec![NodeKey::empty(0)]);
+        db.apply_patch(patch);
+        drop(db);
+
+        let db = RocksDBWrapper::new(dir.path());
+        for version in 0..2 {
+            let created_at = db.version_created_at(version).unwrap();
+            assert!(created_at >= started_at, "{created_at:?}");
+        }
+        assert_eq!(db.version_created_at(2), None);
+        // Creation times must not be confused with stale keys.
+        assert_eq!(db.min_stale_key_version(), Some(1));
+        assert!(db.stale_keys(0).is_empty());
+        assert_eq!(db.stale_keys(1), [NodeKey::empty(0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot modify RocksDB opened in read-only mode")]
+    fn read_only_instance_cannot_be_modified() {
+        let dir = TempDir::new().expect("failed creating temporary dir for RocksDB");
+        drop(RocksDBWrapper::new(dir.path()));
+
+        let options = RocksDBOptions::default();
+        let mut db = RocksDBWrapper::from(RocksDB::open_read_only(dir.path(), options));
+        let root = Root::new(2, Node::Internal(InternalNode::default()));
+        db.apply_patch(create_patch(0, root, generate_nodes(0, &[1, 2])));
+    }
+
     fn assert_contains_exactly_keys(db: &RocksDBWrapper, expected_keys: &HashSet<NodeKey>) {
         let cf = MerkleTreeColumnFamily::Tree;
         let actual_keys: HashSet<_> = db
@@ -3,6 +3,7 @@
 use std::str;
 
 use crate::{
+    consistency::VerificationCursor,
     errors::{DeserializeError, DeserializeErrorKind, ErrorContext},
     types::{
         ChildRef, InternalNode, Key, LeafNode, Manifest, Node, Root, TreeTags, ValueHash,
@@ -195,6 +196,55 @@ impl Node {
     }
 }
 
+impl VerificationCursor {
+    /// Cursor is serialized as a LEB128-encoded version, followed by the root hash of the version,
+    /// a 16-bit little-endian bitmap of verified subtrees and LEB128-encoded leaf counts
+    /// for each verified subtree.
+    pub(super) fn deserialize(mut bytes: &[u8]) -> Result<Self, DeserializeError> {
+        let version = leb128::read::unsigned(&mut bytes)
+            .map_err(|err| DeserializeErrorKind::Leb128(err).with_context(ErrorContext::Version))?;
+        if bytes.len() < HASH_SIZE + 2 {
+            return Err(DeserializeErrorKind::UnexpectedEof.into());
+        }
+        let root_hash = ValueHash::from_slice(&bytes[..HASH_SIZE]);
+        let (bitmap, mut bytes) = bytes[HASH_SIZE..].split_at(2);
+        let bitmap = u16::from_le_bytes([bitmap[0], bitmap[1]]);
+
+        let mut verified_subtrees = [None; 16];
+        for (i, leaf_count) in verified_subtrees.iter_mut().enumerate() {
+            if bitmap & (1 << i) != 0 {
+                let count = leb128::read::unsigned(&mut bytes).map_err(|err| {
+                    DeserializeErrorKind::Leb128(err).with_context(ErrorContext::LeafCount)
+                })?;
+                *leaf_count = Some(count);
+            }
+        }
+        if !bytes.is_empty() {
+            return Err(DeserializeErrorKind::TrailingBytes(bytes.len()).into());
+        }
+        Ok(Self {
+            version,
+            root_hash,
+            verified_subtrees,
+        })
+    }
+
+    pub(super) fn serialize(&self, buffer: &mut Vec<u8>) {
+        leb128::write::unsigned(buffer, self.version).unwrap();
+        buffer.extend_from_slice(self.root_hash.as_ref());
+        let mut bitmap = 0_u16;
+        for (i, leaf_count) in self.verified_subtrees.iter().enumerate() {
+            if leaf_count.is_some() {
+                bitmap |= 1 << i;
+            }
+        }
+        buffer.extend_from_slice(&bitmap.to_le_bytes());
+        for &leaf_count in self.verified_subtrees.iter().flatten() {
+            leb128::write::unsigned(buffer, leaf_count).unwrap();
+        }
+    }
+}
+
 impl TreeTags {
     /// Tags are serialized as a length-prefixed list of `(&str, &str)` tuples, where each
     /// `&str` is length-prefixed as well. All lengths are encoded using LEB128.
@@ -321,6 +371,34 @@ mod tests {
         assert_eq!(manifest_copy, manifest);
     }
 
+    #[test]
+    fn serializing_verification_cursor() {
+        let mut verified_subtrees = [None; 16];
+        verified_subtrees[0] = Some(5);
+        verified_subtrees[9] = Some(200);
+        let cursor = VerificationCursor {
+            version: 3,
+            root_hash: ValueHash::repeat_byte(0x42),
+            verified_subtrees,
+        };
+        let mut buffer = vec![];
+        cursor.serialize(&mut buffer);
+        assert_eq!(buffer[0], 3); // version
+        assert_eq!(buffer[1..=HASH_SIZE], [0x42; HASH_SIZE]);
+        assert_eq!(
+            buffer[(HASH_SIZE + 1)..],
+            [0b_0000_0001, 0b_0000_0010, 5, 200, 1]
+        );
+        // ^ LE bitmap, LEB128-encoded leaf counts
+
+        let cursor_copy = VerificationCursor::deserialize(&buffer).unwrap();
+        assert_eq!(cursor_copy, cursor);
+
+        buffer.push(0);
+        let err = VerificationCursor::deserialize(&buffer).unwrap_err();
+        assert!(err.to_string().contains("trailing byte"), "{err}");
+    }
+
     #[test]
     fn serializing_manifest_with_recovery_flag() {
         let mut manifest = Manifest::new(42, &());
@@ -12,7 +12,8 @@ use test_casing::test_casing;
 use super::*;
 use crate::{
     hasher::{HasherWithStats, MerklePath},
-    types::{NodeKey, TreeInstruction, KEY_SIZE},
+    types::{NodeKey, TreeInstruction, KEY_SIZE, TREE_DEPTH},
+    MerkleTree, WithDepth,
 };
 
 pub(super) const FIRST_KEY: Key = U256::from_limbs([0, 0, 0, 0x_dead_beef_0000_0000]);
@@ -46,10 +47,20 @@ pub(super) fn create_patch(
     )
 }
 
+/// Creates 256 entries for a tree large enough to have all top-level subtrees filled. Keys are
+/// uniformly distributed among 16 top-level subtrees.
+pub(crate) fn create_large_tree_entries() -> Vec<TreeEntry> {
+    let entries = (0..256_u64).map(|i| {
+        let key = Key::from(i) << 248;
+        TreeEntry::new(key, i + 1, B256::repeat_byte(i as u8))
+    });
+    entries.collect()
+}
+
 #[test]
 fn inserting_entries_in_empty_database() {
     let db = PatchSet::default();
-    let mut updater = TreeUpdater::new(0, Root::Empty);
+    let mut updater = TreeUpdater::new(0, Root::Empty, TREE_DEPTH);
     assert_eq!(updater.patch_set.root_version(), 0);
     assert!(updater.patch_set.get(&Nibbles::EMPTY).is_none());
 
@@ -158,7 +169,7 @@ fn assert_storage_with_3_keys(updater: &TreeUpdater) {
 
 #[test]
 fn changing_child_ref_type() {
-    let mut updater = TreeUpdater::new(0, Root::Empty);
+    let mut updater = TreeUpdater::new(0, Root::Empty, TREE_DEPTH);
     updater.insert(
         TreeEntry::new(FIRST_KEY, 1, B256::new([1; 32])),
         &Nibbles::EMPTY,
@@ -200,7 +211,7 @@ fn inserting_node_in_non_empty_database() {
     let (_, patch) = storage.extend(kvs);
     db.apply_patch(patch);
 
-    let mut updater = TreeUpdater::new(1, db.root(0).unwrap());
+    let mut updater = TreeUpdater::new(1, db.root(0).unwrap(), TREE_DEPTH);
     let sorted_keys = SortedKeys::new([THIRD_KEY, E_KEY, SECOND_KEY].into_iter());
     let parent_nibbles = updater.load_ancestors(&sorted_keys, &db);
     assert_eq!(updater.metrics.db_reads, 10);
@@ -251,7 +262,7 @@ fn inserting_node_in_non_empty_database_with_moved_key() {
     let (_, patch) = storage.extend(kvs);
     db.apply_patch(patch);
 
-    let mut updater = TreeUpdater::new(1, db.root(0).unwrap());
+    let mut updater = TreeUpdater::new(1, db.root(0).unwrap(), TREE_DEPTH);
     let sorted_keys = SortedKeys::new([SECOND_KEY].into_iter());
     let parent_nibbles = updater.load_ancestors(&sorted_keys, &db);
     assert_eq!(
@@ -278,7 +289,7 @@ fn inserting_node_in_non_empty_database_with_moved_key() {
 
 #[test]
 fn proving_keys_existence_and_absence() {
-    let mut updater = TreeUpdater::new(0, Root::Empty);
+    let mut updater = TreeUpdater::new(0, Root::Empty, TREE_DEPTH);
     updater.patch_set.ensure_internal_root_node(); // Necessary for proofs to work.
     updater.insert(
         TreeEntry::new(FIRST_KEY, 1, B256::new([1; 32])),
@@ -826,3 +837,191 @@ fn recovery_pruning_equivalence(
         test_recovery_pruning_equivalence(kind, chunk_size, recovery_chunk_size, hasher);
     }
 }
+
+/// Computes the root hash of a tree with the specified `depth` as a full binary Merkle tree.
+fn naive_root_hash(hasher: &dyn HashTree, entries: &[TreeEntry], depth: usize) -> ValueHash {
+    if entries.is_empty() {
+        return hasher.empty_subtree_hash(depth);
+    }
+    if depth == 0 {
+        assert_eq!(entries.len(), 1);
+        return hasher.hash_leaf(&entries[0].value, entries[0].leaf_index);
+    }
+
+    let (left, right): (Vec<_>, Vec<_>) = entries
+        .iter()
+        .copied()
+        .partition(|entry| !entry.key.bit(depth - 1));
+    let left_hash = naive_root_hash(hasher, &left, depth - 1);
+    let right_hash = naive_root_hash(hasher, &right, depth - 1);
+    hasher.hash_branch(&left_hash, &right_hash)
+}
+
+#[test_casing(3, [64, 160, 256])]
+fn tree_with_custom_depth(depth: usize) {
+    const RNG_SEED: u64 = 321;
+
+    let hasher = WithDepth::new(Blake2Hasher, depth);
+    let mut rng = StdRng::seed_from_u64(RNG_SEED);
+    let key_mask = U256::MAX >> (TREE_DEPTH - depth);
+    let mut entries: Vec<_> = (0..100_u64)
+        .map(|i| {
+            let key = U256::from_limbs([rng.gen(), rng.gen(), rng.gen(), rng.gen()]) & key_mask;
+            TreeEntry::new(key, i + 1, ValueHash::repeat_byte(i as u8))
+        })
+        .collect();
+
+    let mut tree = MerkleTree::with_hasher(PatchSet::default(), &hasher);
+    for chunk in entries.chunks(30) {
+        tree.extend(chunk.to_vec());
+    }
+    let root_hash = naive_root_hash(&hasher, &entries, depth);
+    assert_eq!(tree.latest_root_hash(), root_hash);
+    tree.verify_consistency(3, true).unwrap();
+
+    let missing_key = Key::from(1);
+    let mut keys: Vec<_> = entries.iter().map(|entry| entry.key).collect();
+    keys.push(missing_key);
+    for proof in tree.entries_with_proofs(3, &keys).unwrap() {
+        assert!(proof.merkle_path.len() <= depth);
+        proof.verify(&hasher, root_hash);
+    }
+    let multi_proof = tree.entries_with_multi_proof(3, &keys).unwrap();
+    multi_proof.verify(&hasher, root_hash);
+    let exclusion_proof = tree.exclusion_proof(3, missing_key).unwrap().unwrap();
+    exclusion_proof.verify(&hasher, root_hash);
+
+    keys.pop(); // remove `missing_key`
+    keys.sort_unstable();
+    let range_entries: Vec<_> = tree
+        .entries_in_range(3, keys[10]..keys[20])
+        .unwrap()
+        .map(|entry| entry.key)
+        .collect();
+    assert_eq!(range_entries, keys[10..20]);
+
+    let mut instructions: Vec<_> = entries
+        .iter_mut()
+        .take(10)
+        .map(|entry| {
+            *entry = entry.with_value(ValueHash::repeat_byte(0xff));
+            TreeInstruction::Write(*entry)
+        })
+        .collect();
+    instructions.push(TreeInstruction::Read(missing_key));
+    let output = tree.extend_with_proofs(instructions.clone());
+    output.verify_proofs(&hasher, root_hash, &instructions);
+    let new_root_hash = naive_root_hash(&hasher, &entries, depth);
+    assert_eq!(output.root_hash(), Some(new_root_hash));
+    assert_eq!(tree.latest_root_hash(), new_root_hash);
+}
+
+#[test]
+fn removing_key_collapses_tree() {
+    let mut database = PatchSet::default();
+    let storage = Storage::new(&database, &(), 0, true);
+    let entries = vec![
+        TreeEntry::new(FIRST_KEY, 1, B256::repeat_byte(1)),
+        TreeEntry::new(SECOND_KEY, 2, B256::repeat_byte(2)),
+    ];
+    let (_, patch) = storage.extend(entries);
+    database.apply_patch(patch);
+
+    let storage = Storage::new(&database, &(), 1, true);
+    let instructions = vec![TreeInstruction::Remove(SECOND_KEY)];
+    let (output, patch) = storage.extend_with_instructions(instructions);
+    assert_eq!(output.leaf_count, 1);
+    assert_matches!(
+        output.logs[0],
+        TreeLogEntry::Removed { leaf_index: 2, previous_value }
+            if previous_value == B256::repeat_byte(2)
+    );
+
+    // The remaining leaf should become the root node.
+    let root = patch.root(1).unwrap();
+    let Root::Filled {
+        leaf_count,
+        node: Node::Leaf(leaf),
+    } = root
+    else {
+        panic!("Unexpected root: {root:?}");
+    };
+    assert_eq!(u64::from(leaf_count), 1);
+    assert_eq!(leaf.full_key, FIRST_KEY);
+    assert_eq!(patch.patches_by_version[&1].nodes.len(), 0);
+
+    // All nodes from the previous version should be marked as stale.
+    let stale_keys: HashSet<_> = patch.stale_keys_by_version[&1].iter().copied().collect();
+    let expected_stale_keys: HashSet<_> = database.patches_by_version[&0]
+        .nodes
+        .keys()
+        .copied()
+        .chain([Nibbles::EMPTY.with_version(0)])
+        .collect();
+    assert_eq!(stale_keys, expected_stale_keys);
+    database.apply_patch(patch);
+
+    let storage = Storage::new(&database, &(), 2, true);
+    let instructions = vec![
+        TreeInstruction::Remove(SECOND_KEY),
+        TreeInstruction::Remove(FIRST_KEY),
+    ];
+    let (output, patch) = storage.extend_with_instructions(instructions);
+    assert_eq!(output.leaf_count, 0);
+    assert_eq!(output.logs[0], TreeLogEntry::ReadMissingKey);
+    assert_matches!(output.logs[1], TreeLogEntry::Removed { leaf_index: 1, .. });
+    assert_matches!(patch.root(2), Some(Root::Empty));
+}
+
+#[test_casing(2, [false, true])]
+fn removing_entries(with_proofs: bool) {
+    const RNG_SEED: u64 = 42;
+
+    let mut rng = StdRng::seed_from_u64(RNG_SEED);
+    let mut entries: Vec<_> = (0..100_u64)
+        .map(|i| {
+            let key = U256::from_limbs([rng.gen(), rng.gen(), rng.gen(), rng.gen()]);
+            TreeEntry::new(key, i + 1, ValueHash::repeat_byte(i as u8))
+        })
+        .collect();
+    let mut tree = MerkleTree::new(PatchSet::default());
+    tree.extend(entries.clone());
+
+    entries.shuffle(&mut rng);
+    let mut version = 0;
+    while !entries.is_empty() {
+        let removed_count = entries.len().min(30);
+        let removed_entries: Vec<_> = entries.drain(..removed_count).collect();
+        let missing_key = Key::from(version + 1);
+        let instructions: Vec<_> = removed_entries
+            .iter()
+            .map(|entry| TreeInstruction::Remove(entry.key))
+            .chain([TreeInstruction::Remove(missing_key)])
+            .collect();
+
+        let old_root_hash = tree.latest_root_hash();
+        let (logs, root_hash, leaf_count) = if with_proofs {
+            let output = tree.extend_with_proofs(instructions.clone());
+            output.verify_proofs(&Blake2Hasher, old_root_hash, &instructions);
+            let logs: Vec<_> = output.logs.iter().map(|log| log.base).collect();
+            (logs, output.root_hash().unwrap(), output.leaf_count)
+        } else {
+            let output = tree.extend_with_instructions(instructions);
+            (output.logs, output.root_hash, output.leaf_count)
+        };
+        version += 1;
+
+        for (log, entry) in logs.iter().zip(&removed_entries) {
+            assert_eq!(*log, TreeLogEntry::removed(entry.leaf_index, entry.value));
+        }
+        assert_eq!(*logs.last().unwrap(), TreeLogEntry::ReadMissingKey);
+        assert_eq!(leaf_count, entries.len() as u64);
+
+        let expected_root_hash = naive_root_hash(&Blake2Hasher, &entries, TREE_DEPTH);
+        assert_eq!(root_hash, expected_root_hash);
+        assert_eq!(tree.latest_root_hash(), expected_root_hash);
+        tree.verify_consistency(version, false).unwrap();
+    }
+
+    assert_matches!(tree.root(version), Some(Root::Empty));
+}
@@ -0,0 +1,313 @@
+//! Truncating recent versions of the Merkle tree, with a preliminary dry run.
+
+use crate::{
+    types::{Nibbles, Node, Root},
+    HashTree, MerkleTree, PruneDatabase, ValueHash,
+};
+
+/// Outcome of truncating recent tree versions as predicted by
+/// [`MerkleTree::truncate_recent_versions_dry_run()`]. The tree is not changed by the dry run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruncationReport {
+    /// Number of tree versions that would be retained after truncation.
+    pub retained_version_count: u64,
+    /// Root hash of the latest retained version. `None` if this version is pruned.
+    pub root_hash: Option<ValueHash>,
+    /// Number of leaves in the latest retained version.
+    pub leaf_count: u64,
+    /// Number of nodes (including roots) created by truncated versions. These nodes would become
+    /// unreachable after truncation; they are not removed from the database.
+    pub unreachable_node_count: usize,
+    /// First tree version not affected by pruning, or `None` if the tree is empty. Archival
+    /// checkpoints preceding this version are retained as well.
+    pub first_retained_version: Option<u64>,
+    /// Whether the latest retained version was pruned. If set, truncation will be refused by
+    /// [`MerkleTree::try_truncate_recent_versions()`].
+    pub is_target_version_pruned: bool,
+}
+
+/// Error truncating recent tree versions.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "cannot truncate tree to {retained_version_count} versions: the latest retained version \
+     would be pruned (first retained version is {first_retained_version})"
+)]
+pub struct TruncationError {
+    retained_version_count: u64,
+    first_retained_version: u64,
+}
+
+impl<DB: PruneDatabase, H: HashTree> MerkleTree<DB, H> {
+    /// Returns the first tree version that is not affected by pruning, or `None` if the tree
+    /// has no versions. Versions preceding the returned one may be partially or fully removed
+    /// by a [`MerkleTreePruner`], except for [archival checkpoints], which are retained intact.
+    ///
+    /// [`MerkleTreePruner`]: crate::MerkleTreePruner
+    /// [archival checkpoints]: crate::MerkleTreePruner::set_checkpoint_interval()
+    pub fn first_retained_version(&self) -> Option<u64> {
+        let latest_version = self.latest_version()?;
+        // Stale keys produced by each version `v` reference nodes required by version `v - 1`.
+        // Since the pruner removes stale keys together with the referenced nodes, versions
+        // starting from `min_stale_key_version - 1` are intact.
+        let first_retained_version = self
+            .db
+            .min_stale_key_version()
+            .map_or(latest_version, |version| version.saturating_sub(1));
+        Some(first_retained_version.min(latest_version))
+    }
+
+    /// Predicts the outcome of [truncating](Self::truncate_recent_versions()) the tree so that
+    /// it has `retained_version_count` versions, without changing the tree.
+    pub fn truncate_recent_versions_dry_run(
+        &self,
+        retained_version_count: u64,
+    ) -> TruncationReport {
+        let version_count = self.db.manifest().unwrap_or_default().version_count;
+        let retained_version_count = retained_version_count.min(version_count);
+        let first_retained_version = self.first_retained_version();
+        let target_version = retained_version_count.checked_sub(1);
+        let is_target_version_pruned = match (target_version, first_retained_version) {
+            (Some(target), Some(first_retained)) => self.is_pruned(target, first_retained),
+            _ => false,
+        };
+
+        let (root_hash, leaf_count) = match target_version {
+            None => (Some(self.hasher.empty_tree_hash()), 0),
+            Some(_) if is_target_version_pruned => (None, 0),
+            Some(version) => {
+                let leaf_count = self.root(version).map_or(0, |root| root.leaf_count());
+                (self.root_hash(version), leaf_count)
+            }
+        };
+        let unreachable_node_count = (retained_version_count..version_count)
+            .map(|version| self.count_nodes_created_at(version))
+            .sum();
+
+        TruncationReport {
+            retained_version_count,
+            root_hash,
+            leaf_count,
+            unreachable_node_count,
+            first_retained_version,
+            is_target_version_pruned,
+        }
+    }
+
+    /// Checks whether the specified `version` is (partially) pruned. Versions preceding
+    /// `first_retained_version` are pruned unless they are retained as archival checkpoints.
+    /// Since the pruner processes versions in ascending order, the root of a version is removed
+    /// in the first pruning iteration affecting the version; thus, a version is intact iff
+    /// its root is present.
+    fn is_pruned(&self, version: u64, first_retained_version: u64) -> bool {
+        version < first_retained_version && self.root(version).is_none()
+    }
+
+    /// Counts nodes created at the specified `version`, including the root. Since nodes are
+    /// immutable, a node created at `version` can only be referenced by a node created
+    /// at the same version, so it suffices to traverse the tree along such references.
+    fn count_nodes_created_at(&self, version: u64) -> usize {
+        let Some(root) = self.root(version) else {
+            return 0;
+        };
+        let Root::Filled { node, .. } = root else {
+            return 1;
+        };
+
+        let mut node_count = 1;
+        let mut level = vec![(Nibbles::EMPTY, node)];
+        while !level.is_empty() {
+            let mut internal_node_keys = vec![];
+            for (nibbles, node) in &level {
+                let Node::Internal(node) = node else {
+                    continue;
+                };
+                for (nibble, child_ref) in node.children() {
+                    if child_ref.version != version {
+                        continue;
+                    }
+                    node_count += 1;
+                    if !child_ref.is_leaf {
+                        let child_nibbles = nibbles.push(nibble).unwrap();
+                        // ^ `unwrap()` is safe: internal nodes cannot be at the terminal level
+                        internal_node_keys.push((child_nibbles.with_version(version), false));
+                    }
+                }
+            }
+
+            let nodes = self.db.tree_nodes(&internal_node_keys);
+            level = internal_node_keys
+                .into_iter()
+                .zip(nodes)
+                .filter_map(|((key, _), node)| Some((key.nibbles, node?)))
+                .collect();
+        }
+        node_count
+    }
+
+    /// Same as [`Self::truncate_recent_versions()`], but refuses to truncate the tree if
+    /// the latest retained version was pruned, which would leave the tree in an unusable state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the latest retained version was pruned.
+    pub fn try_truncate_recent_versions(
+        &mut self,
+        retained_version_count: u64,
+    ) -> Result<(), TruncationError> {
+        self.check_truncation(retained_version_count)?;
+        self.truncate_recent_versions(retained_version_count);
+        Ok(())
+    }
+
+    pub(crate) fn check_truncation(
+        &self,
+        retained_version_count: u64,
+    ) -> Result<(), TruncationError> {
+        let Some(target_version) = retained_version_count.checked_sub(1) else {
+            return Ok(()); // Truncating all versions is always possible
+        };
+        match self.first_retained_version() {
+            Some(first_retained_version)
+                if self.is_pruned(target_version, first_retained_version) =>
+            {
+                Err(TruncationError {
+                    retained_version_count,
+                    first_retained_version,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use axon_types::B256;
+
+    use super::*;
+    use crate::{types::TreeEntry, Key, MerkleTreePruner, PatchSet};
+
+    fn create_tree() -> MerkleTree<PatchSet> {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        for version in 0..5_u64 {
+            let entries = (0..20_u64).map(|i| {
+                let key = Key::from(version * 10 + i) << 200;
+                TreeEntry::new(key, version * 10 + i + 1, B256::repeat_byte(version as u8))
+            });
+            tree.extend(entries.collect());
+        }
+        tree
+    }
+
+    #[test]
+    fn truncation_dry_run() {
+        let mut tree = create_tree();
+        let expected_node_count = tree
+            .db
+            .nodes_mut()
+            .filter(|(key, _)| key.version >= 2)
+            .count()
+            + 3; // roots for versions 2..=4
+
+        let report = tree.truncate_recent_versions_dry_run(2);
+        assert_eq!(
+            report,
+            TruncationReport {
+                retained_version_count: 2,
+                root_hash: tree.root_hash(1),
+                leaf_count: 30,
+                unreachable_node_count: expected_node_count,
+                first_retained_version: Some(0),
+                is_target_version_pruned: false,
+            }
+        );
+
+        // The dry run should not change the tree.
+        assert_eq!(tree.latest_version(), Some(4));
+        let report = tree.truncate_recent_versions_dry_run(10);
+        assert_eq!(report.retained_version_count, 5);
+        assert_eq!(report.root_hash, Some(tree.latest_root_hash()));
+        assert_eq!(report.unreachable_node_count, 0);
+
+        let report = tree.truncate_recent_versions_dry_run(0);
+        assert_eq!(report.root_hash, Some(tree.hasher.empty_tree_hash()));
+        assert_eq!(report.leaf_count, 0);
+
+        tree.try_truncate_recent_versions(2).unwrap();
+        assert_eq!(tree.latest_root_hash(), tree.root_hash(1).unwrap());
+        assert_eq!(tree.root(1).unwrap().leaf_count(), 30);
+    }
+
+    #[test]
+    fn truncation_is_refused_for_pruned_versions() {
+        let mut tree = create_tree();
+        let (mut pruner, _handle) = MerkleTreePruner::new(&mut tree.db, 1);
+        let stats = pruner.run_once().unwrap();
+        drop(pruner);
+        assert_eq!(stats.target_retained_version, 3);
+        assert_eq!(tree.first_retained_version(), Some(3));
+
+        let report = tree.truncate_recent_versions_dry_run(4);
+        assert!(!report.is_target_version_pruned);
+        assert_eq!(report.root_hash, tree.root_hash(3));
+        let report = tree.truncate_recent_versions_dry_run(2);
+        assert!(report.is_target_version_pruned);
+        assert_eq!(report.root_hash, None);
+
+        let err = tree.try_truncate_recent_versions(2).unwrap_err();
+        assert_matches!(
+            err,
+            TruncationError {
+                retained_version_count: 2,
+                first_retained_version: 3,
+            }
+        );
+        assert_eq!(tree.latest_version(), Some(4));
+
+        tree.try_truncate_recent_versions(4).unwrap();
+        assert_eq!(tree.latest_version(), Some(3));
+        tree.verify_consistency(3, true).unwrap();
+        // Truncating all versions is always allowed.
+        tree.try_truncate_recent_versions(0).unwrap();
+        assert_eq!(tree.latest_version(), None);
+    }
+
+    #[test]
+    fn truncation_to_checkpoints() {
+        let mut tree = create_tree();
+        let checkpoint_hash = tree.root_hash(2).unwrap();
+        let (mut pruner, _handle) = MerkleTreePruner::new(&mut tree.db, 0);
+        pruner.set_checkpoint_interval(2);
+        let stats = pruner.run_once().unwrap();
+        drop(pruner);
+        assert_eq!(stats.target_retained_version, 4);
+        assert_eq!(tree.first_retained_version(), Some(4));
+
+        // Version 2 is a checkpoint, so it's intact despite preceding the first retained version.
+        let report = tree.truncate_recent_versions_dry_run(3);
+        assert!(!report.is_target_version_pruned);
+        assert_eq!(report.root_hash, Some(checkpoint_hash));
+        let report = tree.truncate_recent_versions_dry_run(2);
+        assert!(report.is_target_version_pruned);
+        let err = tree.try_truncate_recent_versions(2).unwrap_err();
+        assert_matches!(
+            err,
+            TruncationError {
+                retained_version_count: 2,
+                first_retained_version: 4,
+            }
+        );
+
+        tree.try_truncate_recent_versions(3).unwrap();
+        assert_eq!(tree.latest_version(), Some(2));
+        assert_eq!(tree.latest_root_hash(), checkpoint_hash);
+        tree.verify_consistency(2, true).unwrap();
+
+        // The tree should be extendable after truncation.
+        let entries = (0..10_u64)
+            .map(|i| TreeEntry::new(Key::from(i) << 200, i + 1, B256::repeat_byte(0xff)));
+        tree.extend(entries.collect());
+        tree.verify_consistency(3, true).unwrap();
+    }
+}
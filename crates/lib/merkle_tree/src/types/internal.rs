@@ -3,7 +3,7 @@
 //! module. Still, logically these types are private, so adding them to new public APIs etc. is a
 //! logical error.
 
-use std::{fmt, num::NonZeroU64};
+use std::{fmt, mem, num::NonZeroU64};
 
 use crate::{
     hasher::{HashTree, InternalNodeCache},
@@ -13,7 +13,8 @@ use crate::{
 
 /// Size of a (leaf) tree key in bytes.
 pub(crate) const KEY_SIZE: usize = 32;
-/// Depth of the tree (= number of bits in `KEY_SIZE`).
+/// Depth of the tree with full-size keys (= number of bits in `KEY_SIZE`). This is the default
+/// and the maximum supported tree depth.
 pub(crate) const TREE_DEPTH: usize = KEY_SIZE * 8;
 /// Size of a hashed value in bytes.
 pub(crate) const HASH_SIZE: usize = 32;
@@ -35,7 +36,7 @@ impl TreeTags {
         Self {
             architecture: Self::ARCHITECTURE.to_owned(),
             hasher: hasher.name().to_owned(),
-            depth: TREE_DEPTH,
+            depth: hasher.depth(),
             is_recovering: false,
         }
     }
@@ -49,8 +50,10 @@ impl TreeTags {
             Self::ARCHITECTURE
         );
         assert_eq!(
-            self.depth, TREE_DEPTH,
-            "Unexpected tree depth: expected {TREE_DEPTH}, got {}",
+            self.depth,
+            hasher.depth(),
+            "Unexpected tree depth: expected {}, got {}",
+            hasher.depth(),
             self.depth
         );
         assert_eq!(
@@ -106,6 +109,22 @@ impl Manifest {
 
 pub(crate) type NibblesBytes = [u8; KEY_SIZE];
 
+/// Aligns a `key` of the tree with the specified `depth` so that its root-level nibble
+/// is the most significant one, as expected by [`Nibbles`] methods. For full-depth trees,
+/// this is a no-op.
+///
+/// # Panics
+///
+/// Panics if the key doesn't fit into the tree (i.e., is greater or equal to `2^depth`).
+pub(crate) fn align_key(key: &Key, depth: usize) -> Key {
+    debug_assert!(depth <= TREE_DEPTH && depth % 4 == 0);
+    assert!(
+        key.bit_len() <= depth,
+        "Key {key:0>64x} does not fit into the tree with depth {depth}"
+    );
+    *key << (TREE_DEPTH - depth)
+}
+
 /// Unversioned key (a sequence of nibbles) in a radix-16 Merkle tree.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub(crate) struct Nibbles {
@@ -479,6 +498,13 @@ impl InternalNode {
     pub(crate) fn insert_child_ref(&mut self, nibble: u8, child_ref: ChildRef) {
         self.children.insert(nibble, child_ref);
     }
+
+    pub(crate) fn remove_child_ref(&mut self, nibble: u8) -> Option<ChildRef> {
+        let child_ref = self.children.remove(nibble)?;
+        // The hash cache (if any) is invalidated by the removal.
+        self.cache = None;
+        Some(child_ref)
+    }
 }
 
 /// Tree node (either a leaf or an internal node).
@@ -491,6 +517,23 @@ pub enum Node {
     Leaf(LeafNode),
 }
 
+impl Node {
+    /// Returns the approximate size of this node in RAM, in bytes.
+    pub(crate) fn approx_size(&self) -> usize {
+        let heap_size = match self {
+            Self::Internal(node) => {
+                let cache_size = node
+                    .cache
+                    .as_ref()
+                    .map_or(0, |_| mem::size_of::<InternalNodeCache>());
+                node.children.heap_size() + cache_size
+            }
+            Self::Leaf(_) => 0,
+        };
+        mem::size_of::<Self>() + heap_size
+    }
+}
+
 impl From<LeafNode> for Node {
     fn from(leaf: LeafNode) -> Self {
         Self::Leaf(leaf)
@@ -533,6 +576,16 @@ impl Root {
             Self::Filled { leaf_count, .. } => (*leaf_count).into(),
         }
     }
+
+    /// Returns the approximate size of this root in RAM, in bytes.
+    pub(crate) fn approx_size(&self) -> usize {
+        match self {
+            Self::Empty => mem::size_of::<Self>(),
+            Self::Filled { node, .. } => {
+                mem::size_of::<Self>() - mem::size_of::<Node>() + node.approx_size()
+            }
+        }
+    }
 }
 
 /// Stale [`NodeKey`] with information about when it was replaced.
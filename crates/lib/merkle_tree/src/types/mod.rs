@@ -5,7 +5,8 @@ use axon_types::{B256, U256};
 mod internal;
 
 pub(crate) use self::internal::{
-    ChildRef, Nibbles, NibblesBytes, StaleNodeKey, TreeTags, HASH_SIZE, KEY_SIZE, TREE_DEPTH,
+    align_key, ChildRef, Nibbles, NibblesBytes, StaleNodeKey, TreeTags, HASH_SIZE, KEY_SIZE,
+    TREE_DEPTH,
 };
 pub use self::internal::{InternalNode, LeafNode, Manifest, Node, NodeKey, Root};
 
@@ -14,13 +15,17 @@ pub type Key = U256;
 /// Hash type of values and intermediate nodes in the tree.
 pub type ValueHash = B256;
 
-/// Instruction to read or write a tree value at a certain key.
+/// Instruction to read, write or remove a tree value at a certain key.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TreeInstruction<K = Key> {
     /// Read the current tree value at the specified key.
     Read(K),
     /// Write the specified entry.
     Write(TreeEntry<K>),
+    /// Remove the entry with the specified key. Unlike writing a zero value, this frees the leaf
+    /// occupied by the entry, so that the tree hash is the same as if the entry was never inserted.
+    /// Removing a missing key is a no-op resulting in [`TreeLogEntry::ReadMissingKey`].
+    Remove(K),
 }
 
 impl<K: Copy> TreeInstruction<K> {
@@ -32,7 +37,7 @@ impl<K: Copy> TreeInstruction<K> {
     /// Returns the tree key this instruction is related to.
     pub fn key(&self) -> K {
         match self {
-            Self::Read(key) => *key,
+            Self::Read(key) | Self::Remove(key) => *key,
             Self::Write(entry) => entry.key,
         }
     }
@@ -41,6 +46,7 @@ impl<K: Copy> TreeInstruction<K> {
         match self {
             Self::Read(key) => TreeInstruction::Read(map_fn(key)),
             Self::Write(entry) => TreeInstruction::Write(entry.map_key(map_fn)),
+            Self::Remove(key) => TreeInstruction::Remove(map_fn(key)),
         }
     }
 }
@@ -116,15 +122,98 @@ pub struct TreeEntryWithProof {
     pub base: TreeEntry,
     /// Proof of the value authenticity.
     ///
-    /// If specified, a proof is the Merkle path consisting of up to 256 hashes
-    /// ordered starting the bottom-most level of the tree (one with leaves) and ending before
-    /// the root level.
+    /// If specified, a proof is the Merkle path consisting of up to 256 hashes (or up to the tree
+    /// depth for trees with a [custom depth](crate::WithDepth)) ordered starting the bottom-most
+    /// level of the tree (one with leaves) and ending before the root level.
     ///
-    /// If the path is not full (contains <256 hashes), it means that the hashes at the beginning
-    /// corresponding to the empty subtrees are skipped. This allows compacting the proof ~10x.
+    /// If the path is not full (contains fewer hashes than the tree depth), it means that
+    /// the hashes at the beginning corresponding to the empty subtrees are skipped.
+    /// This allows compacting the proof ~10x.
     pub merkle_path: Vec<ValueHash>,
 }
 
+/// Proof that a certain key is missing from a Merkle tree.
+///
+/// The proof shows what is located in the tree at the point where the path to the key ends:
+/// either an empty subtree, or a leaf with a different key (a *neighbor*). In the latter case,
+/// the path to the key diverges from the path to the neighbor below the level at which
+/// the neighbor is placed, meaning that the subtree containing the key is empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExclusionProof {
+    /// Key proven to be missing from the tree.
+    pub key: Key,
+    /// Neighboring leaf occupying the position where the path to `key` ends, or `None`
+    /// if the path ends in an empty subtree.
+    pub neighbor: Option<TreeEntry>,
+    /// Merkle path from the `neighbor` leaf (or from the empty subtree) to the root of the tree.
+    /// The path has the same format as [`TreeEntryWithProof::merkle_path`].
+    pub merkle_path: Vec<ValueHash>,
+}
+
+/// Entries in a Merkle tree together with a compact proof of their authenticity.
+///
+/// Unlike a collection of [`TreeEntryWithProof`]s, a multi-proof contains each hash
+/// required for verification at most once; hashes that can be computed from the proven entries
+/// themselves are not included at all. Hashes of empty subtrees below the level specified
+/// by [`Self::path_lengths`] are omitted as well.
+#[derive(Debug, Clone)]
+pub struct TreeMultiProof {
+    /// Proven entries ordered by key. Keys are unique. Any entry can be
+    /// [empty](TreeEntry::is_empty()), proving that the key is missing from the tree.
+    pub entries: Vec<TreeEntry>,
+    /// Length of the Merkle path for each entry. Has the same meaning as the length
+    /// of [`TreeEntryWithProof::merkle_path`]: hashes for all lower levels of the tree
+    /// correspond to empty subtrees.
+    pub path_lengths: Vec<u16>,
+    /// Hashes that cannot be computed from `entries`. Hashes are ordered starting from
+    /// the bottom-most level of the tree (one with leaves), and by the ascending key
+    /// within each level.
+    pub hashes: Vec<ValueHash>,
+}
+
+/// Page of tree entries in a certain key range returned by [`MerkleTree::entries_page()`]
+/// and [`MerkleTree::entries_page_with_proofs()`].
+///
+/// [`MerkleTree::entries_page()`]: crate::MerkleTree::entries_page()
+/// [`MerkleTree::entries_page_with_proofs()`]: crate::MerkleTree::entries_page_with_proofs()
+#[derive(Debug, Clone)]
+pub struct TreeEntriesPage<E = TreeEntry> {
+    /// Entries on the page in the ascending key order.
+    pub entries: Vec<E>,
+    /// Key of the first entry after this page, or `None` if this is the last page in the range.
+    /// Can be used as the start of the range to load the next page.
+    pub next_key: Option<Key>,
+}
+
+/// Change of a single tree entry between two tree versions, as returned by
+/// [`MerkleTree::diff()`].
+///
+/// [`MerkleTree::diff()`]: crate::MerkleTree::diff()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeEntryChange {
+    /// Entry is present in the newer version only.
+    Inserted(TreeEntry),
+    /// Entry is present in both versions, but its value and / or leaf index has changed.
+    Updated {
+        /// Entry in the older version.
+        previous: TreeEntry,
+        /// Entry in the newer version.
+        current: TreeEntry,
+    },
+    /// Entry is present in the older version only.
+    Removed(TreeEntry),
+}
+
+impl TreeEntryChange {
+    /// Returns the tree key this change is related to.
+    pub fn key(&self) -> Key {
+        match self {
+            Self::Inserted(entry) | Self::Removed(entry) => entry.key,
+            Self::Updated { current, .. } => current.key,
+        }
+    }
+}
+
 /// Output of inserting a block of entries into a Merkle tree.
 #[derive(Debug, PartialEq, Eq)]
 pub struct BlockOutput {
@@ -157,6 +246,13 @@ pub enum TreeLogEntry {
     },
     /// A missing key was read.
     ReadMissingKey,
+    /// A node with the specified index was removed.
+    Removed {
+        /// Index of the removed node.
+        leaf_index: u64,
+        /// Hash of the removed value.
+        previous_value: ValueHash,
+    },
 }
 
 impl TreeLogEntry {
@@ -171,6 +267,13 @@ impl TreeLogEntry {
         Self::Read { leaf_index, value }
     }
 
+    pub(crate) fn removed(leaf_index: u64, previous_value: ValueHash) -> Self {
+        Self::Removed {
+            leaf_index,
+            previous_value,
+        }
+    }
+
     pub(crate) fn is_read(&self) -> bool {
         matches!(self, Self::Read { .. } | Self::ReadMissingKey)
     }
@@ -198,12 +301,13 @@ impl BlockOutputWithProofs {
 pub struct TreeLogEntryWithProof<P = Vec<ValueHash>> {
     /// Log entry about an atomic operation on the tree.
     pub base: TreeLogEntry,
-    /// Merkle path to prove log authenticity. The path consists of up to 256 hashes
-    /// ordered starting the bottom-most level of the tree (one with leaves) and ending before
-    /// the root level.
+    /// Merkle path to prove log authenticity. The path consists of up to 256 hashes (or up to
+    /// the tree depth for trees with a [custom depth](crate::WithDepth)) ordered starting
+    /// the bottom-most level of the tree (one with leaves) and ending before the root level.
     ///
-    /// If the path is not full (contains <256 hashes), it means that the hashes at the beginning
-    /// corresponding to the empty subtrees are skipped. This allows compacting the proof ~10x.
+    /// If the path is not full (contains fewer hashes than the tree depth), it means that
+    /// the hashes at the beginning corresponding to the empty subtrees are skipped.
+    /// This allows compacting the proof ~10x.
     pub merkle_path: P,
     /// Root tree hash after the operation.
     pub root_hash: ValueHash,
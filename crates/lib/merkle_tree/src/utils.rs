@@ -1,6 +1,6 @@
 //! Misc utils used in tree algorithms.
 
-use std::{iter::Peekable, vec};
+use std::{iter::Peekable, mem, vec};
 
 use crate::types::Key;
 
@@ -43,6 +43,11 @@ impl<V> SmallMap<V> {
         self.bitmap.count_ones() as usize
     }
 
+    /// Returns the size of heap allocations made by this map, in bytes.
+    pub fn heap_size(&self) -> usize {
+        self.values.capacity() * mem::size_of::<V>()
+    }
+
     pub fn get(&self, index: u8) -> Option<&V> {
         assert!(index < Self::CAPACITY, "index is too large");
 
@@ -112,6 +117,19 @@ impl<V> SmallMap<V> {
             self.values[index] = value;
         }
     }
+
+    pub fn remove(&mut self, index: u8) -> Option<V> {
+        assert!(index < Self::CAPACITY, "index is too large");
+
+        let mask = 1 << u16::from(index);
+        if self.bitmap & mask == 0 {
+            None
+        } else {
+            let value_index = (self.bitmap & (mask - 1)).count_ones();
+            self.bitmap &= !mask;
+            Some(self.values.remove(value_index as usize))
+        }
+    }
 }
 
 pub(crate) fn find_diverging_bit(lhs: Key, rhs: Key) -> usize {
@@ -209,6 +227,15 @@ mod tests {
             map.iter().collect::<Vec<_>>(),
             [(0, &"0"), (2, &"2!"), (7, &"7")]
         );
+
+        assert_eq!(map.remove(2), Some("2!"));
+        assert_eq!(map.remove(2), None);
+        assert_eq!(map.remove(15), None);
+        assert_eq!(map.bitmap, 0b_1000_0001);
+        assert_eq!(map.values, ["0", "7"]);
+        assert_eq!(map.get(2), None);
+        assert_eq!(map.get(7), Some(&"7"));
+        assert_eq!(map.iter().collect::<Vec<_>>(), [(0, &"0"), (7, &"7")]);
     }
 
     #[test]
@@ -0,0 +1,1059 @@
+//! Stable wire format for tree proofs.
+//!
+//! This module contains no items; it specifies encodings used by the `to_bytes()` / `from_bytes()`
+//! methods and `serde` implementations of proof types, so that they can be implemented
+//! by third parties.
+//!
+//! [`TreeEntryWithProof`], [`TreeLogEntryWithProof`], [`BlockOutputWithProofs`] and
+//! [`ExclusionProof`] can be encoded either as JSON (via `serde`), or in a compact binary form.
+//! Both encodings are versioned; the current version of the format is 1.
+//!
+//! # JSON format
+//!
+//! - Keys and hashes are encoded as `0x`-prefixed hex strings with 64 hex digits. Keys use
+//!   the big-endian byte order.
+//! - Leaf indices and leaf counts are encoded as JSON numbers.
+//! - Each top-level object has a `version` field. Objects nested in a top-level object
+//!   (e.g., log entries in a block output) do not have this field.
+//! - Unknown fields are ignored when reading.
+//!
+//! For example, a [`TreeEntryWithProof`] is encoded as follows:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "key": "0x0000..0001",
+//!   "value": "0x1111..1111",
+//!   "leaf_index": 3,
+//!   "merkle_path": ["0x2222..2222"]
+//! }
+//! ```
+//!
+//! [`TreeLogEntry`] is encoded as an object with the `type` field, which is one of `inserted`,
+//! `updated`, `read`, `read_missing_key` or `removed`. Updated and removed entries additionally
+//! have `leaf_index` and `previous_value` fields; read entries have `leaf_index` and `value`
+//! fields.
+//!
+//! # Binary format
+//!
+//! The binary format uses the same conventions as the database encoding of tree nodes:
+//!
+//! - Integers are LEB128-encoded.
+//! - Keys and hashes are encoded as 32 bytes; keys use the big-endian byte order.
+//! - Merkle paths are encoded as the number of hashes followed by the hashes.
+//!
+//! Each top-level value starts with the format version. Other than that, values are encoded
+//! as follows:
+//!
+//! - [`TreeEntryWithProof`]: key, value hash, leaf index, Merkle path.
+//! - [`TreeLogEntry`]: a tag byte (0 – inserted, 1 – updated, 2 – read, 3 – read missing key,
+//!   4 – removed), followed by the leaf index and the value hash for updated, read and removed
+//!   entries.
+//! - [`TreeLogEntryWithProof`]: log entry, Merkle path, root hash.
+//! - [`BlockOutputWithProofs`]: leaf count, the number of log entries, log entries.
+//! - [`ExclusionProof`]: key, a tag byte (0 – no neighbor, 1 – neighbor present), neighbor key,
+//!   value hash and leaf index if the neighbor is present, Merkle path.
+//! - [`SnapshotChunk`]: tree version, root hash, entry count, first key, last key, Merkle paths
+//!   for the first and last entries, entries (each encoded as key, value hash, leaf index).
+//!   Snapshot chunks are only encoded in the binary format.
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    errors::{DeserializeError, DeserializeErrorKind, ErrorContext},
+    recovery::{SnapshotChunk, SnapshotChunkHeader},
+    types::{
+        BlockOutputWithProofs, ExclusionProof, Key, TreeEntry, TreeEntryWithProof, TreeLogEntry,
+        TreeLogEntryWithProof, ValueHash, HASH_SIZE, KEY_SIZE,
+    },
+};
+
+/// Current version of the wire format.
+const FORMAT_VERSION: u64 = 1;
+
+/// Key or hash encoded as a `0x`-prefixed hex string.
+#[derive(Debug, Clone, Copy)]
+struct HexBytes([u8; HASH_SIZE]);
+
+impl From<ValueHash> for HexBytes {
+    fn from(hash: ValueHash) -> Self {
+        Self(hash.0)
+    }
+}
+
+impl From<HexBytes> for ValueHash {
+    fn from(bytes: HexBytes) -> Self {
+        Self::new(bytes.0)
+    }
+}
+
+impl From<Key> for HexBytes {
+    fn from(key: Key) -> Self {
+        Self(key.to_be_bytes())
+    }
+}
+
+impl From<HexBytes> for Key {
+    fn from(bytes: HexBytes) -> Self {
+        Self::from_be_bytes(bytes.0)
+    }
+}
+
+impl Serialize for HexBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(self.0)))
+    }
+}
+
+impl<'de> Deserialize<'de> for HexBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let hex_str = s
+            .strip_prefix("0x")
+            .ok_or_else(|| de::Error::custom("hex string must be `0x`-prefixed"))?;
+        let mut bytes = [0_u8; HASH_SIZE];
+        hex::decode_to_slice(hex_str, &mut bytes).map_err(de::Error::custom)?;
+        Ok(Self(bytes))
+    }
+}
+
+/// Top-level object with the format version.
+#[derive(Debug, Serialize, Deserialize)]
+struct Versioned<T> {
+    version: u64,
+    #[serde(flatten)]
+    payload: T,
+}
+
+impl<T> Versioned<T> {
+    fn new(payload: T) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            payload,
+        }
+    }
+
+    fn into_payload<E: de::Error>(self) -> Result<T, E> {
+        if self.version == FORMAT_VERSION {
+            Ok(self.payload)
+        } else {
+            Err(E::custom(format_args!(
+                "unsupported wire format version {}, expected {FORMAT_VERSION}",
+                self.version
+            )))
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TreeEntryV1 {
+    key: HexBytes,
+    value: HexBytes,
+    leaf_index: u64,
+}
+
+impl From<TreeEntry> for TreeEntryV1 {
+    fn from(entry: TreeEntry) -> Self {
+        Self {
+            key: entry.key.into(),
+            value: entry.value.into(),
+            leaf_index: entry.leaf_index,
+        }
+    }
+}
+
+impl From<TreeEntryV1> for TreeEntry {
+    fn from(entry: TreeEntryV1) -> Self {
+        Self::new(entry.key.into(), entry.leaf_index, entry.value.into())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TreeEntryWithProofV1 {
+    #[serde(flatten)]
+    base: TreeEntryV1,
+    merkle_path: Vec<HexBytes>,
+}
+
+impl From<&TreeEntryWithProof> for TreeEntryWithProofV1 {
+    fn from(entry: &TreeEntryWithProof) -> Self {
+        Self {
+            base: entry.base.into(),
+            merkle_path: entry
+                .merkle_path
+                .iter()
+                .copied()
+                .map(HexBytes::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<TreeEntryWithProofV1> for TreeEntryWithProof {
+    fn from(entry: TreeEntryWithProofV1) -> Self {
+        Self {
+            base: entry.base.into(),
+            merkle_path: entry.merkle_path.into_iter().map(ValueHash::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TreeLogEntryV1 {
+    Inserted,
+    Updated {
+        leaf_index: u64,
+        previous_value: HexBytes,
+    },
+    Read {
+        leaf_index: u64,
+        value: HexBytes,
+    },
+    ReadMissingKey,
+    Removed {
+        leaf_index: u64,
+        previous_value: HexBytes,
+    },
+}
+
+impl From<TreeLogEntry> for TreeLogEntryV1 {
+    fn from(entry: TreeLogEntry) -> Self {
+        match entry {
+            TreeLogEntry::Inserted => Self::Inserted,
+            TreeLogEntry::Updated {
+                leaf_index,
+                previous_value,
+            } => Self::Updated {
+                leaf_index,
+                previous_value: previous_value.into(),
+            },
+            TreeLogEntry::Read { leaf_index, value } => Self::Read {
+                leaf_index,
+                value: value.into(),
+            },
+            TreeLogEntry::ReadMissingKey => Self::ReadMissingKey,
+            TreeLogEntry::Removed {
+                leaf_index,
+                previous_value,
+            } => Self::Removed {
+                leaf_index,
+                previous_value: previous_value.into(),
+            },
+        }
+    }
+}
+
+impl From<TreeLogEntryV1> for TreeLogEntry {
+    fn from(entry: TreeLogEntryV1) -> Self {
+        match entry {
+            TreeLogEntryV1::Inserted => Self::Inserted,
+            TreeLogEntryV1::Updated {
+                leaf_index,
+                previous_value,
+            } => Self::update(leaf_index, previous_value.into()),
+            TreeLogEntryV1::Read { leaf_index, value } => Self::read(leaf_index, value.into()),
+            TreeLogEntryV1::ReadMissingKey => Self::ReadMissingKey,
+            TreeLogEntryV1::Removed {
+                leaf_index,
+                previous_value,
+            } => Self::removed(leaf_index, previous_value.into()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TreeLogEntryWithProofV1 {
+    base: TreeLogEntryV1,
+    merkle_path: Vec<HexBytes>,
+    root_hash: HexBytes,
+}
+
+impl From<&TreeLogEntryWithProof> for TreeLogEntryWithProofV1 {
+    fn from(entry: &TreeLogEntryWithProof) -> Self {
+        Self {
+            base: entry.base.into(),
+            merkle_path: entry
+                .merkle_path
+                .iter()
+                .copied()
+                .map(HexBytes::from)
+                .collect(),
+            root_hash: entry.root_hash.into(),
+        }
+    }
+}
+
+impl From<TreeLogEntryWithProofV1> for TreeLogEntryWithProof {
+    fn from(entry: TreeLogEntryWithProofV1) -> Self {
+        Self {
+            base: entry.base.into(),
+            merkle_path: entry.merkle_path.into_iter().map(ValueHash::from).collect(),
+            root_hash: entry.root_hash.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BlockOutputWithProofsV1 {
+    leaf_count: u64,
+    logs: Vec<TreeLogEntryWithProofV1>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExclusionProofV1 {
+    key: HexBytes,
+    neighbor: Option<TreeEntryV1>,
+    merkle_path: Vec<HexBytes>,
+}
+
+macro_rules! impl_versioned_serde {
+    ($ty:ty => $repr:ty) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                Versioned::new(<$repr>::from(self)).serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let versioned = Versioned::<$repr>::deserialize(deserializer)?;
+                let payload = versioned.into_payload::<D::Error>()?;
+                Ok(payload.into())
+            }
+        }
+    };
+}
+
+impl_versioned_serde!(TreeEntryWithProof => TreeEntryWithProofV1);
+impl_versioned_serde!(TreeLogEntryWithProof => TreeLogEntryWithProofV1);
+impl_versioned_serde!(BlockOutputWithProofs => BlockOutputWithProofsV1);
+impl_versioned_serde!(ExclusionProof => ExclusionProofV1);
+
+impl From<&BlockOutputWithProofs> for BlockOutputWithProofsV1 {
+    fn from(output: &BlockOutputWithProofs) -> Self {
+        Self {
+            leaf_count: output.leaf_count,
+            logs: output
+                .logs
+                .iter()
+                .map(TreeLogEntryWithProofV1::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<BlockOutputWithProofsV1> for BlockOutputWithProofs {
+    fn from(output: BlockOutputWithProofsV1) -> Self {
+        Self {
+            leaf_count: output.leaf_count,
+            logs: output
+                .logs
+                .into_iter()
+                .map(TreeLogEntryWithProof::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<&ExclusionProof> for ExclusionProofV1 {
+    fn from(proof: &ExclusionProof) -> Self {
+        Self {
+            key: proof.key.into(),
+            neighbor: proof.neighbor.map(TreeEntryV1::from),
+            merkle_path: proof
+                .merkle_path
+                .iter()
+                .copied()
+                .map(HexBytes::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<ExclusionProofV1> for ExclusionProof {
+    fn from(proof: ExclusionProofV1) -> Self {
+        Self {
+            key: proof.key.into(),
+            neighbor: proof.neighbor.map(TreeEntry::from),
+            merkle_path: proof.merkle_path.into_iter().map(ValueHash::from).collect(),
+        }
+    }
+}
+
+// Binary encoding.
+
+fn read_u64(bytes: &mut &[u8], context: ErrorContext) -> Result<u64, DeserializeError> {
+    leb128::read::unsigned(bytes)
+        .map_err(|err| DeserializeErrorKind::Leb128(err).with_context(context))
+}
+
+fn read_tag(bytes: &mut &[u8]) -> Result<u8, DeserializeError> {
+    let (&tag, rest) = bytes
+        .split_first()
+        .ok_or(DeserializeErrorKind::UnexpectedEof)?;
+    *bytes = rest;
+    Ok(tag)
+}
+
+fn read_bytes32(
+    bytes: &mut &[u8],
+    context: ErrorContext,
+) -> Result<[u8; HASH_SIZE], DeserializeError> {
+    if bytes.len() < HASH_SIZE {
+        return Err(DeserializeErrorKind::UnexpectedEof.with_context(context));
+    }
+    let (head, rest) = bytes.split_at(HASH_SIZE);
+    *bytes = rest;
+    Ok(head.try_into().unwrap())
+    // ^ `unwrap()` is safe: `head` has the necessary length by construction
+}
+
+fn read_format_version(bytes: &mut &[u8]) -> Result<(), DeserializeError> {
+    let version = read_u64(bytes, ErrorContext::FormatVersion)?;
+    if version == FORMAT_VERSION {
+        Ok(())
+    } else {
+        let err = DeserializeErrorKind::UnsupportedFormatVersion(version);
+        Err(err.with_context(ErrorContext::FormatVersion))
+    }
+}
+
+fn ensure_consumed(bytes: &[u8]) -> Result<(), DeserializeError> {
+    if bytes.is_empty() {
+        Ok(())
+    } else {
+        Err(DeserializeErrorKind::TrailingBytes(bytes.len()).into())
+    }
+}
+
+fn serialize_entry(entry: &TreeEntry, buffer: &mut Vec<u8>) {
+    let key_bytes: [u8; KEY_SIZE] = entry.key.to_be_bytes();
+    buffer.extend_from_slice(&key_bytes);
+    buffer.extend_from_slice(entry.value.as_slice());
+    leb128::write::unsigned(buffer, entry.leaf_index).unwrap();
+    // ^ `unwrap()` is safe; writing to a `Vec<u8>` always succeeds
+}
+
+fn deserialize_entry(bytes: &mut &[u8]) -> Result<TreeEntry, DeserializeError> {
+    let key = Key::from_be_bytes(read_bytes32(bytes, ErrorContext::EntryKey)?);
+    let value = ValueHash::new(read_bytes32(bytes, ErrorContext::ValueHash)?);
+    let leaf_index = read_u64(bytes, ErrorContext::LeafIndex)?;
+    Ok(TreeEntry::new(key, leaf_index, value))
+}
+
+fn serialize_merkle_path(path: &[ValueHash], buffer: &mut Vec<u8>) {
+    buffer.reserve(1 + path.len() * HASH_SIZE);
+    leb128::write::unsigned(buffer, path.len() as u64).unwrap();
+    for hash in path {
+        buffer.extend_from_slice(hash.as_slice());
+    }
+}
+
+fn deserialize_merkle_path(bytes: &mut &[u8]) -> Result<Vec<ValueHash>, DeserializeError> {
+    let len = read_u64(bytes, ErrorContext::MerklePath)?;
+    // Check the length before allocating in order to not allocate lots of memory
+    // on malformed inputs.
+    let byte_len = usize::try_from(len)
+        .ok()
+        .and_then(|len| len.checked_mul(HASH_SIZE))
+        .filter(|&byte_len| byte_len <= bytes.len());
+    let Some(byte_len) = byte_len else {
+        return Err(DeserializeErrorKind::UnexpectedEof.with_context(ErrorContext::MerklePath));
+    };
+
+    let (path_bytes, rest) = bytes.split_at(byte_len);
+    *bytes = rest;
+    Ok(path_bytes
+        .chunks_exact(HASH_SIZE)
+        .map(ValueHash::from_slice)
+        .collect())
+}
+
+impl TreeLogEntry {
+    const INSERTED_TAG: u8 = 0;
+    const UPDATED_TAG: u8 = 1;
+    const READ_TAG: u8 = 2;
+    const READ_MISSING_KEY_TAG: u8 = 3;
+    const REMOVED_TAG: u8 = 4;
+
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Self::Inserted => buffer.push(Self::INSERTED_TAG),
+            Self::Updated {
+                leaf_index,
+                previous_value,
+            } => {
+                buffer.push(Self::UPDATED_TAG);
+                leb128::write::unsigned(buffer, *leaf_index).unwrap();
+                buffer.extend_from_slice(previous_value.as_slice());
+            }
+            Self::Read { leaf_index, value } => {
+                buffer.push(Self::READ_TAG);
+                leb128::write::unsigned(buffer, *leaf_index).unwrap();
+                buffer.extend_from_slice(value.as_slice());
+            }
+            Self::ReadMissingKey => buffer.push(Self::READ_MISSING_KEY_TAG),
+            Self::Removed {
+                leaf_index,
+                previous_value,
+            } => {
+                buffer.push(Self::REMOVED_TAG);
+                leb128::write::unsigned(buffer, *leaf_index).unwrap();
+                buffer.extend_from_slice(previous_value.as_slice());
+            }
+        }
+    }
+
+    fn deserialize(bytes: &mut &[u8]) -> Result<Self, DeserializeError> {
+        Ok(match read_tag(bytes)? {
+            Self::INSERTED_TAG => Self::Inserted,
+            Self::UPDATED_TAG => {
+                let leaf_index = read_u64(bytes, ErrorContext::LeafIndex)?;
+                let previous_value = ValueHash::new(read_bytes32(bytes, ErrorContext::ValueHash)?);
+                Self::update(leaf_index, previous_value)
+            }
+            Self::READ_TAG => {
+                let leaf_index = read_u64(bytes, ErrorContext::LeafIndex)?;
+                let value = ValueHash::new(read_bytes32(bytes, ErrorContext::ValueHash)?);
+                Self::read(leaf_index, value)
+            }
+            Self::READ_MISSING_KEY_TAG => Self::ReadMissingKey,
+            Self::REMOVED_TAG => {
+                let leaf_index = read_u64(bytes, ErrorContext::LeafIndex)?;
+                let previous_value = ValueHash::new(read_bytes32(bytes, ErrorContext::ValueHash)?);
+                Self::removed(leaf_index, previous_value)
+            }
+            tag => return Err(DeserializeErrorKind::InvalidTag(tag).into()),
+        })
+    }
+}
+
+impl TreeEntryWithProof {
+    /// Serializes this entry in the [binary wire format](crate::wire).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        leb128::write::unsigned(&mut buffer, FORMAT_VERSION).unwrap();
+        serialize_entry(&self.base, &mut buffer);
+        serialize_merkle_path(&self.merkle_path, &mut buffer);
+        buffer
+    }
+
+    /// Deserializes an entry from the [binary wire format](crate::wire).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is malformed or uses an unsupported format version.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, DeserializeError> {
+        read_format_version(&mut bytes)?;
+        let base = deserialize_entry(&mut bytes)?;
+        let merkle_path = deserialize_merkle_path(&mut bytes)?;
+        ensure_consumed(bytes)?;
+        Ok(Self { base, merkle_path })
+    }
+}
+
+impl TreeLogEntryWithProof {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        self.base.serialize(buffer);
+        serialize_merkle_path(&self.merkle_path, buffer);
+        buffer.extend_from_slice(self.root_hash.as_slice());
+    }
+
+    fn deserialize(bytes: &mut &[u8]) -> Result<Self, DeserializeError> {
+        let base = TreeLogEntry::deserialize(bytes)?;
+        let merkle_path = deserialize_merkle_path(bytes)?;
+        let root_hash = ValueHash::new(read_bytes32(bytes, ErrorContext::RootHash)?);
+        Ok(Self {
+            base,
+            merkle_path,
+            root_hash,
+        })
+    }
+
+    /// Serializes this log entry in the [binary wire format](crate::wire).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        leb128::write::unsigned(&mut buffer, FORMAT_VERSION).unwrap();
+        self.serialize(&mut buffer);
+        buffer
+    }
+
+    /// Deserializes a log entry from the [binary wire format](crate::wire).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is malformed or uses an unsupported format version.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, DeserializeError> {
+        read_format_version(&mut bytes)?;
+        let this = Self::deserialize(&mut bytes)?;
+        ensure_consumed(bytes)?;
+        Ok(this)
+    }
+}
+
+impl BlockOutputWithProofs {
+    /// Serializes this output in the [binary wire format](crate::wire).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        leb128::write::unsigned(&mut buffer, FORMAT_VERSION).unwrap();
+        leb128::write::unsigned(&mut buffer, self.leaf_count).unwrap();
+        leb128::write::unsigned(&mut buffer, self.logs.len() as u64).unwrap();
+        for log in &self.logs {
+            log.serialize(&mut buffer);
+        }
+        buffer
+    }
+
+    /// Deserializes an output from the [binary wire format](crate::wire).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is malformed or uses an unsupported format version.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, DeserializeError> {
+        read_format_version(&mut bytes)?;
+        let leaf_count = read_u64(&mut bytes, ErrorContext::LeafCount)?;
+        let log_count = read_u64(&mut bytes, ErrorContext::LogCount)?;
+        // Each log entry occupies at least 34 bytes (a tag byte, an empty Merkle path
+        // and a root hash); we use this to limit pre-allocation.
+        let max_log_count = bytes.len() / (HASH_SIZE + 2);
+        let log_count = usize::try_from(log_count).unwrap_or(usize::MAX);
+        let mut logs = Vec::with_capacity(log_count.min(max_log_count));
+        for i in 0..log_count {
+            let log = TreeLogEntryWithProof::deserialize(&mut bytes)
+                .map_err(|err| err.with_context(ErrorContext::LogEntry(i)))?;
+            logs.push(log);
+        }
+        ensure_consumed(bytes)?;
+        Ok(Self { logs, leaf_count })
+    }
+}
+
+impl ExclusionProof {
+    const NO_NEIGHBOR_TAG: u8 = 0;
+    const NEIGHBOR_TAG: u8 = 1;
+
+    /// Serializes this proof in the [binary wire format](crate::wire).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        leb128::write::unsigned(&mut buffer, FORMAT_VERSION).unwrap();
+        let key_bytes: [u8; KEY_SIZE] = self.key.to_be_bytes();
+        buffer.extend_from_slice(&key_bytes);
+        if let Some(neighbor) = &self.neighbor {
+            buffer.push(Self::NEIGHBOR_TAG);
+            serialize_entry(neighbor, &mut buffer);
+        } else {
+            buffer.push(Self::NO_NEIGHBOR_TAG);
+        }
+        serialize_merkle_path(&self.merkle_path, &mut buffer);
+        buffer
+    }
+
+    /// Deserializes a proof from the [binary wire format](crate::wire).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is malformed or uses an unsupported format version.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, DeserializeError> {
+        read_format_version(&mut bytes)?;
+        let key = Key::from_be_bytes(read_bytes32(&mut bytes, ErrorContext::EntryKey)?);
+        let neighbor = match read_tag(&mut bytes)? {
+            Self::NO_NEIGHBOR_TAG => None,
+            Self::NEIGHBOR_TAG => Some(deserialize_entry(&mut bytes)?),
+            tag => return Err(DeserializeErrorKind::InvalidTag(tag).into()),
+        };
+        let merkle_path = deserialize_merkle_path(&mut bytes)?;
+        ensure_consumed(bytes)?;
+        Ok(Self {
+            key,
+            neighbor,
+            merkle_path,
+        })
+    }
+}
+
+impl SnapshotChunk {
+    /// Serializes this chunk in the [binary wire format](crate::wire).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header = &self.header;
+        let mut buffer = vec![];
+        leb128::write::unsigned(&mut buffer, FORMAT_VERSION).unwrap();
+        leb128::write::unsigned(&mut buffer, header.version).unwrap();
+        buffer.extend_from_slice(header.root_hash.as_slice());
+        leb128::write::unsigned(&mut buffer, header.entry_count).unwrap();
+        let first_key_bytes: [u8; KEY_SIZE] = header.first_key.to_be_bytes();
+        buffer.extend_from_slice(&first_key_bytes);
+        let last_key_bytes: [u8; KEY_SIZE] = header.last_key.to_be_bytes();
+        buffer.extend_from_slice(&last_key_bytes);
+        serialize_merkle_path(&header.first_entry_path, &mut buffer);
+        serialize_merkle_path(&header.last_entry_path, &mut buffer);
+        for entry in &self.entries {
+            serialize_entry(entry, &mut buffer);
+        }
+        buffer
+    }
+
+    /// Deserializes a chunk from the [binary wire format](crate::wire). Checks that the header
+    /// is consistent with the chunk entries, but doesn't check Merkle paths.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input is malformed or uses an unsupported format version.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, DeserializeError> {
+        read_format_version(&mut bytes)?;
+        let version = read_u64(&mut bytes, ErrorContext::Version)?;
+        let root_hash = ValueHash::new(read_bytes32(&mut bytes, ErrorContext::RootHash)?);
+        let entry_count = read_u64(&mut bytes, ErrorContext::EntryCount)?;
+        let first_key = Key::from_be_bytes(read_bytes32(&mut bytes, ErrorContext::EntryKey)?);
+        let last_key = Key::from_be_bytes(read_bytes32(&mut bytes, ErrorContext::EntryKey)?);
+        let first_entry_path = deserialize_merkle_path(&mut bytes)?;
+        let last_entry_path = deserialize_merkle_path(&mut bytes)?;
+
+        // Each entry occupies at least 65 bytes; we use this to limit pre-allocation.
+        let max_entry_count = bytes.len() / (KEY_SIZE + HASH_SIZE + 1);
+        let entry_count_usize = usize::try_from(entry_count).unwrap_or(usize::MAX);
+        let mut entries = Vec::with_capacity(entry_count_usize.min(max_entry_count));
+        for i in 0..entry_count_usize {
+            let entry = deserialize_entry(&mut bytes)
+                .map_err(|err| err.with_context(ErrorContext::SnapshotEntry(i)))?;
+            entries.push(entry);
+        }
+        ensure_consumed(bytes)?;
+
+        if entries.first().map(|entry| entry.key) != Some(first_key) {
+            return Err(DeserializeErrorKind::InconsistentSnapshotChunk("first key").into());
+        }
+        if entries.last().map(|entry| entry.key) != Some(last_key) {
+            return Err(DeserializeErrorKind::InconsistentSnapshotChunk("last key").into());
+        }
+
+        Ok(Self {
+            header: SnapshotChunkHeader {
+                version,
+                root_hash,
+                entry_count,
+                first_key,
+                last_key,
+                first_entry_path,
+                last_entry_path,
+            },
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::{MerkleTree, PatchSet, TreeInstruction};
+
+    fn test_entry() -> TreeEntryWithProof {
+        TreeEntry::new(Key::from(1), 3, ValueHash::repeat_byte(0x11))
+            .with_merkle_path(vec![ValueHash::repeat_byte(0x22)])
+    }
+
+    fn assert_entries_eq(lhs: &TreeEntryWithProof, rhs: &TreeEntryWithProof) {
+        assert_eq!(lhs.base, rhs.base);
+        assert_eq!(lhs.merkle_path, rhs.merkle_path);
+    }
+
+    fn assert_logs_eq(lhs: &TreeLogEntryWithProof, rhs: &TreeLogEntryWithProof) {
+        assert_eq!(lhs.base, rhs.base);
+        assert_eq!(lhs.merkle_path, rhs.merkle_path);
+        assert_eq!(lhs.root_hash, rhs.root_hash);
+    }
+
+    fn assert_outputs_eq(lhs: &BlockOutputWithProofs, rhs: &BlockOutputWithProofs) {
+        assert_eq!(lhs.leaf_count, rhs.leaf_count);
+        assert_eq!(lhs.logs.len(), rhs.logs.len());
+        for (lhs, rhs) in lhs.logs.iter().zip(&rhs.logs) {
+            assert_logs_eq(lhs, rhs);
+        }
+    }
+
+    fn create_block_output() -> BlockOutputWithProofs {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        let instructions: Vec<_> = (1_u64..=20)
+            .map(|i| TreeInstruction::write(Key::from(i) << 200, i, ValueHash::repeat_byte(1)))
+            .collect();
+        tree.extend_with_proofs(instructions);
+
+        let instructions = vec![
+            TreeInstruction::write(Key::from(1) << 200, 1, ValueHash::repeat_byte(2)),
+            TreeInstruction::Read(Key::from(2) << 200),
+            TreeInstruction::Read(Key::from(100)),
+            TreeInstruction::write(Key::from(101), 21, ValueHash::repeat_byte(3)),
+            TreeInstruction::Remove(Key::from(3) << 200),
+        ];
+        let output = tree.extend_with_proofs(instructions);
+        assert_matches!(output.logs[0].base, TreeLogEntry::Updated { .. });
+        assert_matches!(output.logs[1].base, TreeLogEntry::Read { .. });
+        assert_matches!(output.logs[2].base, TreeLogEntry::ReadMissingKey);
+        assert_matches!(output.logs[3].base, TreeLogEntry::Inserted);
+        assert_matches!(
+            output.logs[4].base,
+            TreeLogEntry::Removed { leaf_index: 3, .. }
+        );
+        output
+    }
+
+    #[test]
+    fn entry_json_v1_format() {
+        let entry = test_entry();
+        let json = serde_json::to_value(&entry).unwrap();
+        let expected_json = serde_json::json!({
+            "version": 1,
+            "key": format!("0x{}01", "00".repeat(31)),
+            "value": format!("0x{}", "11".repeat(32)),
+            "leaf_index": 3,
+            "merkle_path": [format!("0x{}", "22".repeat(32))],
+        });
+        assert_eq!(json, expected_json);
+
+        let restored: TreeEntryWithProof = serde_json::from_value(json).unwrap();
+        assert_entries_eq(&restored, &entry);
+    }
+
+    #[test]
+    fn entry_binary_v1_format() {
+        let entry = test_entry();
+        let bytes = entry.to_bytes();
+
+        let mut expected_bytes = vec![1]; // format version
+        expected_bytes.extend_from_slice(&[0; 31]);
+        expected_bytes.push(1); // key
+        expected_bytes.extend_from_slice(&[0x11; 32]); // value
+        expected_bytes.push(3); // leaf index
+        expected_bytes.push(1); // Merkle path length
+        expected_bytes.extend_from_slice(&[0x22; 32]);
+        assert_eq!(bytes, expected_bytes);
+
+        let restored = TreeEntryWithProof::from_bytes(&bytes).unwrap();
+        assert_entries_eq(&restored, &entry);
+    }
+
+    #[test]
+    fn log_entry_json_v1_format() {
+        let log = TreeLogEntryWithProof {
+            base: TreeLogEntry::update(5, ValueHash::repeat_byte(0x33)),
+            merkle_path: vec![],
+            root_hash: ValueHash::repeat_byte(0x44),
+        };
+        let json = serde_json::to_value(&log).unwrap();
+        let expected_json = serde_json::json!({
+            "version": 1,
+            "base": {
+                "type": "updated",
+                "leaf_index": 5,
+                "previous_value": format!("0x{}", "33".repeat(32)),
+            },
+            "merkle_path": [],
+            "root_hash": format!("0x{}", "44".repeat(32)),
+        });
+        assert_eq!(json, expected_json);
+
+        let mut expected_bytes = vec![1, 1, 5];
+        expected_bytes.extend_from_slice(&[0x33; 32]);
+        expected_bytes.push(0);
+        expected_bytes.extend_from_slice(&[0x44; 32]);
+        assert_eq!(log.to_bytes(), expected_bytes);
+    }
+
+    #[test]
+    fn block_output_binary_v1_format() {
+        let output = BlockOutputWithProofs {
+            logs: vec![
+                TreeLogEntryWithProof {
+                    base: TreeLogEntry::Inserted,
+                    merkle_path: vec![ValueHash::repeat_byte(0x11)],
+                    root_hash: ValueHash::repeat_byte(0x22),
+                },
+                TreeLogEntryWithProof {
+                    base: TreeLogEntry::read(2, ValueHash::repeat_byte(0x33)),
+                    merkle_path: vec![],
+                    root_hash: ValueHash::repeat_byte(0x22),
+                },
+                TreeLogEntryWithProof {
+                    base: TreeLogEntry::ReadMissingKey,
+                    merkle_path: vec![],
+                    root_hash: ValueHash::repeat_byte(0x22),
+                },
+                TreeLogEntryWithProof {
+                    base: TreeLogEntry::removed(200, ValueHash::repeat_byte(0x44)),
+                    merkle_path: vec![],
+                    root_hash: ValueHash::repeat_byte(0x55),
+                },
+            ],
+            leaf_count: 300,
+        };
+        let bytes = output.to_bytes();
+
+        let mut expected_bytes = vec![1]; // format version
+        expected_bytes.extend_from_slice(&[0xac, 0x02]); // leaf count
+        expected_bytes.push(4); // log count
+                                // Inserted entry: tag, Merkle path, root hash
+        expected_bytes.extend_from_slice(&[0, 1]);
+        expected_bytes.extend_from_slice(&[0x11; 32]);
+        expected_bytes.extend_from_slice(&[0x22; 32]);
+        // Read entry: tag, leaf index, value hash, Merkle path, root hash
+        expected_bytes.extend_from_slice(&[2, 2]);
+        expected_bytes.extend_from_slice(&[0x33; 32]);
+        expected_bytes.push(0);
+        expected_bytes.extend_from_slice(&[0x22; 32]);
+        // Read entry for a missing key: tag, Merkle path, root hash
+        expected_bytes.extend_from_slice(&[3, 0]);
+        expected_bytes.extend_from_slice(&[0x22; 32]);
+        // Removed entry: tag, leaf index, previous value hash, Merkle path, root hash
+        expected_bytes.extend_from_slice(&[4, 0xc8, 0x01]);
+        expected_bytes.extend_from_slice(&[0x44; 32]);
+        expected_bytes.push(0);
+        expected_bytes.extend_from_slice(&[0x55; 32]);
+        assert_eq!(bytes, expected_bytes);
+
+        let restored = BlockOutputWithProofs::from_bytes(&bytes).unwrap();
+        assert_outputs_eq(&restored, &output);
+    }
+
+    #[test]
+    fn exclusion_proof_binary_v1_format() {
+        let proof = ExclusionProof {
+            key: Key::from(2),
+            neighbor: Some(TreeEntry::new(
+                Key::from(3),
+                1,
+                ValueHash::repeat_byte(0x11),
+            )),
+            merkle_path: vec![ValueHash::repeat_byte(0x22)],
+        };
+        let bytes = proof.to_bytes();
+
+        let mut expected_bytes = vec![1]; // format version
+        expected_bytes.extend_from_slice(&[0; 31]);
+        expected_bytes.push(2); // key
+        expected_bytes.push(1); // neighbor tag
+        expected_bytes.extend_from_slice(&[0; 31]);
+        expected_bytes.push(3); // neighbor key
+        expected_bytes.extend_from_slice(&[0x11; 32]); // neighbor value
+        expected_bytes.push(1); // neighbor leaf index
+        expected_bytes.push(1); // Merkle path length
+        expected_bytes.extend_from_slice(&[0x22; 32]);
+        assert_eq!(bytes, expected_bytes);
+        assert_eq!(ExclusionProof::from_bytes(&bytes).unwrap(), proof);
+
+        let proof = ExclusionProof {
+            neighbor: None,
+            merkle_path: vec![],
+            ..proof
+        };
+        let bytes = proof.to_bytes();
+        let mut expected_bytes = vec![1]; // format version
+        expected_bytes.extend_from_slice(&[0; 31]);
+        expected_bytes.push(2); // key
+        expected_bytes.extend_from_slice(&[0, 0]); // neighbor tag, Merkle path length
+        assert_eq!(bytes, expected_bytes);
+        assert_eq!(ExclusionProof::from_bytes(&bytes).unwrap(), proof);
+    }
+
+    #[test]
+    fn block_output_round_trip() {
+        let output = create_block_output();
+
+        let json = serde_json::to_string(&output).unwrap();
+        let restored: BlockOutputWithProofs = serde_json::from_str(&json).unwrap();
+        assert_outputs_eq(&restored, &output);
+
+        let bytes = output.to_bytes();
+        let restored = BlockOutputWithProofs::from_bytes(&bytes).unwrap();
+        assert_outputs_eq(&restored, &output);
+
+        for log in &output.logs {
+            let json = serde_json::to_string(log).unwrap();
+            let restored: TreeLogEntryWithProof = serde_json::from_str(&json).unwrap();
+            assert_logs_eq(&restored, log);
+
+            let restored = TreeLogEntryWithProof::from_bytes(&log.to_bytes()).unwrap();
+            assert_logs_eq(&restored, log);
+        }
+    }
+
+    #[test]
+    fn exclusion_proof_round_trip() {
+        let mut tree = MerkleTree::new(PatchSet::default());
+        let output = tree.extend(vec![
+            TreeEntry::new(Key::from(1) << 200, 1, ValueHash::repeat_byte(1)),
+            TreeEntry::new(Key::from(2) << 200, 2, ValueHash::repeat_byte(2)),
+        ]);
+        let missing_keys = [(Key::from(1) << 200) + Key::from(1), Key::from(3) << 252];
+
+        for missing_key in missing_keys {
+            let proof = tree.exclusion_proof(0, missing_key).unwrap().unwrap();
+            let json = serde_json::to_string(&proof).unwrap();
+            let restored: ExclusionProof = serde_json::from_str(&json).unwrap();
+            assert_eq!(restored, proof);
+            restored.verify(&tree.hasher, output.root_hash);
+
+            let restored = ExclusionProof::from_bytes(&proof.to_bytes()).unwrap();
+            assert_eq!(restored, proof);
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored() {
+        let json = serde_json::json!({
+            "version": 1,
+            "key": format!("0x{}01", "00".repeat(31)),
+            "value": format!("0x{}", "11".repeat(32)),
+            "leaf_index": 3,
+            "merkle_path": [format!("0x{}", "22".repeat(32))],
+            "comment": "added in a future version",
+        });
+        let restored: TreeEntryWithProof = serde_json::from_value(json).unwrap();
+        assert_entries_eq(&restored, &test_entry());
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let mut json = serde_json::to_value(test_entry()).unwrap();
+        json["version"] = 2.into();
+        let err = serde_json::from_value::<TreeEntryWithProof>(json)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unsupported wire format version 2"), "{err}");
+
+        let mut bytes = test_entry().to_bytes();
+        bytes[0] = 2;
+        let err = TreeEntryWithProof::from_bytes(&bytes)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unsupported wire format version 2"), "{err}");
+    }
+
+    #[test]
+    fn malformed_binary_inputs_are_rejected() {
+        let bytes = test_entry().to_bytes();
+        let err = TreeEntryWithProof::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+        let err = err.to_string();
+        assert!(err.ends_with("unexpected end of input"), "{err}");
+
+        let mut extended_bytes = bytes.clone();
+        extended_bytes.push(0);
+        let err = TreeEntryWithProof::from_bytes(&extended_bytes).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("1 trailing byte(s)"), "{err}");
+
+        let mut bytes = create_block_output().to_bytes();
+        let log_count_pos = 2; // version and leaf count each take 1 byte
+        assert_eq!(bytes[log_count_pos], 5);
+        bytes[log_count_pos + 1] = 0xff; // tag of the first log entry
+        let err = BlockOutputWithProofs::from_bytes(&bytes).unwrap_err();
+        let err = err.to_string();
+        assert!(err.starts_with("[in log entry #0]"), "{err}");
+        assert!(err.ends_with("invalid tag byte 255"), "{err}");
+
+        let err = BlockOutputWithProofs::from_bytes(&[1, 20, 0x80]).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("number of log entries"), "{err}");
+    }
+}
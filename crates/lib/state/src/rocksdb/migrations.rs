@@ -0,0 +1,194 @@
+//! Schema migrations for [`RocksdbStorage`](super::RocksdbStorage).
+
+use std::ops;
+
+use axon_storage::{
+    db::{NamedColumnFamily, WriteBatch},
+    rocksdb, MigrationChunk, MigrationStep, ReadWrite, RocksDB,
+};
+
+use super::{RocksdbStorage, StateKeeperColumnFamily, StateValue};
+
+/// Migration step ensuring that all state entries have enumeration indices.
+///
+/// Enumeration indices cannot be restored from RocksDB alone. Thus, if an entry without an index
+/// is found, the entire state (including the L1 batch number) is removed, so that it is restored
+/// together with enumeration indices on the next
+/// [`RocksdbStorage::update_from_postgres()`](super::RocksdbStorage::update_from_postgres()) call.
+#[derive(Debug)]
+pub(super) struct AddEnumIndices;
+
+impl AddEnumIndices {
+    /// Maximum number of state entries checked in a single chunk.
+    const CHUNK_SIZE: usize = 10_000;
+    /// Key in the state column family used by the legacy ad-hoc enum index migration. An empty
+    /// value means that the legacy migration has finished; otherwise, the value is the hashed key
+    /// to continue the migration from. Entries before this key have enumeration indices.
+    const LEGACY_CURSOR_KEY: &'static [u8] = b"enum_index_migration_cursor";
+
+    fn is_special_key(key: &[u8]) -> bool {
+        key == RocksdbStorage::<ReadWrite>::BLOCK_NUMBER_KEY || key == Self::LEGACY_CURSOR_KEY
+    }
+
+    /// Returns the key to start checking entries from, or `None` if all entries are known to have
+    /// enumeration indices. Removes the legacy migration cursor.
+    fn start_key(
+        db: &RocksDB<StateKeeperColumnFamily>,
+        batch: &mut WriteBatch<'_, StateKeeperColumnFamily>,
+    ) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        let cf = StateKeeperColumnFamily::State;
+        let legacy_cursor = db.get_cf(cf, Self::LEGACY_CURSOR_KEY)?;
+        batch.delete_cf(cf, Self::LEGACY_CURSOR_KEY);
+        Ok(match legacy_cursor {
+            Some(cursor) if cursor.is_empty() => None,
+            Some(cursor) => Some(cursor),
+            None => Some(vec![]),
+        })
+    }
+}
+
+impl MigrationStep<StateKeeperColumnFamily> for AddEnumIndices {
+    fn name(&self) -> &'static str {
+        "enum_indices"
+    }
+
+    fn migrate_chunk(
+        &self,
+        db: &RocksDB<StateKeeperColumnFamily>,
+        cursor: Option<&[u8]>,
+        batch: &mut WriteBatch<'_, StateKeeperColumnFamily>,
+    ) -> Result<MigrationChunk, rocksdb::Error> {
+        let start_key = match cursor {
+            Some(cursor) => cursor.to_vec(),
+            None => match Self::start_key(db, batch)? {
+                Some(key) => key,
+                None => {
+                    tracing::info!("Legacy enum index migration has finished; nothing to check");
+                    return Ok(MigrationChunk {
+                        processed_count: 0,
+                        next_cursor: None,
+                    });
+                }
+            },
+        };
+
+        let cf = StateKeeperColumnFamily::State;
+        let mut processed_count = 0;
+        for (key, value) in db.from_iterator_cf(cf, &start_key) {
+            if Self::is_special_key(&key) {
+                continue;
+            }
+            if processed_count == Self::CHUNK_SIZE {
+                return Ok(MigrationChunk {
+                    processed_count,
+                    next_cursor: Some(key.into_vec()),
+                });
+            }
+            processed_count += 1;
+
+            if StateValue::deserialize(&value).enum_index.is_none() {
+                tracing::warn!(
+                    "State entry with key {key:?} has no enumeration index; removing state \
+                     so that it is restored from Postgres"
+                );
+                // All keys in the column family are shorter than the range end.
+                let all_keys: ops::Range<&[u8]> = &[]..&[u8::MAX; 33];
+                batch.delete_range_cf(cf, all_keys);
+                break;
+            }
+        }
+        Ok(MigrationChunk {
+            processed_count,
+            next_cursor: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axon_types::{L1BatchNumber, B256};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Column families of the state keeper cache without migrations registered.
+    #[derive(Debug, Clone, Copy)]
+    struct LegacyColumnFamily(StateKeeperColumnFamily);
+
+    impl NamedColumnFamily for LegacyColumnFamily {
+        const DB_NAME: &'static str = StateKeeperColumnFamily::DB_NAME;
+        const ALL: &'static [Self] = &[
+            Self(StateKeeperColumnFamily::State),
+            Self(StateKeeperColumnFamily::Contracts),
+            Self(StateKeeperColumnFamily::FactoryDeps),
+        ];
+
+        fn name(&self) -> &'static str {
+            self.0.name()
+        }
+    }
+
+    fn populate_legacy_db(
+        temp_dir: &TempDir,
+        with_enum_indices: bool,
+        legacy_cursor: Option<&[u8]>,
+    ) {
+        let db = RocksDB::<LegacyColumnFamily>::new(temp_dir.path());
+        let cf = LegacyColumnFamily(StateKeeperColumnFamily::State);
+        let mut batch = db.new_write_batch();
+        let block_number_key = RocksdbStorage::<ReadWrite>::BLOCK_NUMBER_KEY;
+        batch.put_cf(cf, block_number_key, &3_u32.to_le_bytes());
+        for i in 0_u8..5 {
+            let enum_index = with_enum_indices.then_some(u64::from(i) + 1);
+            let value = StateValue::new(B256::repeat_byte(i), enum_index);
+            batch.put_cf(cf, &[i; 32], &value.serialize());
+        }
+        if let Some(cursor) = legacy_cursor {
+            batch.put_cf(cf, AddEnumIndices::LEGACY_CURSOR_KEY, cursor);
+        }
+        db.write(batch).unwrap();
+    }
+
+    fn state_keys(storage: &RocksdbStorage) -> Vec<Vec<u8>> {
+        storage
+            .db
+            .prefix_iterator_cf(StateKeeperColumnFamily::State, &[])
+            .map(|(key, _)| key.into_vec())
+            .collect()
+    }
+
+    #[test]
+    fn migrating_state_with_enum_indices() {
+        for legacy_cursor in [None, Some(&[] as &[_]), Some(&[2; 32] as &[_])] {
+            println!("Testing with legacy cursor {legacy_cursor:?}");
+            let temp_dir = TempDir::new().unwrap();
+            populate_legacy_db(&temp_dir, true, legacy_cursor);
+
+            let storage = RocksdbStorage::new(temp_dir.path());
+            assert_eq!(storage.db.schema_version(), 1);
+            assert_eq!(storage.l1_batch_number(), L1BatchNumber(3));
+            let keys = state_keys(&storage);
+            assert_eq!(keys.len(), 6); // 5 entries + L1 batch number
+            assert!(!keys.contains(&AddEnumIndices::LEGACY_CURSOR_KEY.to_vec()));
+            let raw_value = storage
+                .db
+                .get_cf(StateKeeperColumnFamily::State, &[1; 32])
+                .unwrap()
+                .unwrap();
+            let value = StateValue::deserialize(&raw_value);
+            assert_eq!(value.value, B256::repeat_byte(1));
+            assert_eq!(value.enum_index, Some(2));
+        }
+    }
+
+    #[test]
+    fn migrating_state_without_enum_indices() {
+        let temp_dir = TempDir::new().unwrap();
+        populate_legacy_db(&temp_dir, false, Some(&[0; 32]));
+
+        let storage = RocksdbStorage::new(temp_dir.path());
+        assert_eq!(storage.db.schema_version(), 1);
+        assert_eq!(storage.l1_batch_number(), L1BatchNumber(0));
+        assert!(state_keys(&storage).is_empty());
+    }
+}
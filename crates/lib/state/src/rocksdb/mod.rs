@@ -11,9 +11,6 @@
 //! | Column       | Key                             | Value                           | Description                               |
 //! | ------------ | ------------------------------- | ------------------------------- | ----------------------------------------- |
 //! | State        | 'block_number'                  | serialized block number         | Last processed L1 batch number (u32)      |
-//! | State        | 'enum_index_migration_cursor'   | serialized hashed key or empty  | If key is not present it means that the migration hasn't started.                   |
-//! |              |                                 | bytes                           | If value is of length 32 then it represents hashed_key migration should start from. |
-//! |              |                                 |                                 | If value is empty then it means the migration has finished                          |
 //! | State        | hashed `StorageKey`             | 32 bytes value ++ 8 bytes index | State value for the given key             |
 //! |              |                                 |                    (big-endian) |                                           |
 //! | Contracts    | address (20 bytes)              | `Vec<u8>`                       | Contract contents                         |
@@ -22,15 +19,18 @@
 use std::{collections::HashMap, mem, path::Path, time::Instant};
 
 use axon_dal::StorageProcessor;
-use axon_storage::{db::NamedColumnFamily, RocksDB};
+use axon_storage::{
+    db::NamedColumnFamily, rocksdb, AccessMode, MigrationStep, ReadOnly, ReadWrite, RocksDB,
+    RocksDBOptions, Secondary,
+};
 use axon_types::{L1BatchNumber, StorageKey, StorageValue, B256};
-use axon_utils::{b256_to_u256, u256_to_b256, U256ONE};
 use itertools::{Either, Itertools};
 
-use self::metrics::METRICS;
+use self::{metrics::METRICS, migrations::AddEnumIndices};
 use crate::{InMemoryStorage, ReadStorage};
 
 mod metrics;
+mod migrations;
 
 fn serialize_block_number(block_number: u32) -> [u8; 4] {
     block_number.to_le_bytes()
@@ -51,6 +51,7 @@ enum StateKeeperColumnFamily {
 impl NamedColumnFamily for StateKeeperColumnFamily {
     const DB_NAME: &'static str = "state_keeper";
     const ALL: &'static [Self] = &[Self::State, Self::Contracts, Self::FactoryDeps];
+    const MIGRATIONS: &'static [&'static dyn MigrationStep<Self>] = &[&AddEnumIndices];
 
     fn name(&self) -> &'static str {
         match self {
@@ -97,34 +98,67 @@ impl StateValue {
 }
 
 /// [`ReadStorage`] implementation backed by RocksDB.
+///
+/// By default, the storage is opened in the [`ReadWrite`] mode and can be updated from Postgres.
+/// Storages opened in the [`ReadOnly`] or [`Secondary`] modes can be used to read the state while
+/// another process owns the database; they can only be read from.
 #[derive(Debug)]
-pub struct RocksdbStorage {
-    db: RocksDB<StateKeeperColumnFamily>,
+pub struct RocksdbStorage<M = ReadWrite> {
+    db: RocksDB<StateKeeperColumnFamily, M>,
     pending_patch: InMemoryStorage,
-    enum_index_migration_chunk_size: usize,
 }
 
-impl RocksdbStorage {
+impl<M: AccessMode> RocksdbStorage<M> {
     const BLOCK_NUMBER_KEY: &'static [u8] = b"block_number";
-    const ENUM_INDEX_MIGRATION_CURSOR: &'static [u8] = b"enum_index_migration_cursor";
-
-    fn is_special_key(key: &[u8]) -> bool {
-        key == Self::BLOCK_NUMBER_KEY || key == Self::ENUM_INDEX_MIGRATION_CURSOR
-    }
 
-    /// Creates a new storage with the provided RocksDB `path`.
-    pub fn new(path: &Path) -> Self {
-        let db = RocksDB::new(path);
+    fn from_db(db: RocksDB<StateKeeperColumnFamily, M>) -> Self {
         Self {
             db,
             pending_patch: InMemoryStorage::default(),
-            enum_index_migration_chunk_size: 100,
         }
     }
 
-    /// Enables enum indices migration.
-    pub fn enable_enum_index_migration(&mut self, chunk_size: usize) {
-        self.enum_index_migration_chunk_size = chunk_size;
+    /// Returns the last processed l1 batch number + 1
+    /// # Panics
+    /// Panics on RocksDB errors.
+    pub fn l1_batch_number(&self) -> L1BatchNumber {
+        let cf = StateKeeperColumnFamily::State;
+        let block_number = self
+            .db
+            .get_cf(cf, Self::BLOCK_NUMBER_KEY)
+            .expect("failed to fetch block number");
+        let block_number = block_number.map_or(0, |bytes| deserialize_block_number(&bytes));
+        L1BatchNumber(block_number)
+    }
+
+    fn serialize_state_key(key: &StorageKey) -> [u8; 32] {
+        *key.hashed_key()
+    }
+
+    /// Estimates the number of key–value entries in the VM state.
+    fn estimated_map_size(&self) -> u64 {
+        self.db
+            .estimated_number_of_entries(StateKeeperColumnFamily::State)
+    }
+
+    fn read_value_inner(&self, key: &StorageKey) -> Option<StorageValue> {
+        self.read_state_value(key)
+            .map(|state_value| state_value.value)
+    }
+
+    fn read_state_value(&self, key: &StorageKey) -> Option<StateValue> {
+        let cf = StateKeeperColumnFamily::State;
+        self.db
+            .get_cf(cf, &Self::serialize_state_key(key))
+            .expect("failed to read rocksdb state value")
+            .map(|value| StateValue::deserialize(&value))
+    }
+}
+
+impl RocksdbStorage {
+    /// Creates a new storage with the provided RocksDB `path`.
+    pub fn new(path: &Path) -> Self {
+        Self::from_db(RocksDB::new(path))
     }
 
     /// Synchronizes this storage with Postgres using the provided connection.
@@ -184,12 +218,6 @@ impl RocksdbStorage {
         tracing::info!(
             "Secondary storage for L1 batch #{latest_l1_batch_number} initialized, size is {estimated_size}"
         );
-
-        assert!(self.enum_index_migration_chunk_size > 0);
-        // Enum indices must be at the storage. Run migration till the end.
-        while self.enum_migration_start_from().is_some() {
-            self.save_missing_enum_indices(conn).await;
-        }
     }
 
     async fn apply_storage_logs(
@@ -225,92 +253,6 @@ impl RocksdbStorage {
                 .collect();
     }
 
-    async fn save_missing_enum_indices(&self, conn: &mut StorageProcessor<'_>) {
-        let (Some(start_from), true) = (
-            self.enum_migration_start_from(),
-            self.enum_index_migration_chunk_size > 0,
-        ) else {
-            return;
-        };
-
-        let started_at = Instant::now();
-        tracing::info!(
-            "RocksDB enum index migration is not finished, starting from key {start_from:0>64x}"
-        );
-
-        let mut write_batch = self.db.new_write_batch();
-        let (keys, values): (Vec<_>, Vec<_>) = self
-            .db
-            .from_iterator_cf(StateKeeperColumnFamily::State, start_from.as_slice())
-            .filter_map(|(key, value)| {
-                if Self::is_special_key(&key) {
-                    return None;
-                }
-                let state_value = StateValue::deserialize(&value);
-                (state_value.enum_index.is_none())
-                    .then(|| (B256::from_slice(&key), state_value.value))
-            })
-            .take(self.enum_index_migration_chunk_size)
-            .unzip();
-        let enum_indices_and_batches = conn
-            .storage_logs_dal()
-            .get_l1_batches_and_indices_for_initial_writes(&keys)
-            .await;
-        assert_eq!(keys.len(), enum_indices_and_batches.len());
-
-        for (key, value) in keys.iter().zip(values) {
-            let index = enum_indices_and_batches[key].1;
-            write_batch.put_cf(
-                StateKeeperColumnFamily::State,
-                key.as_slice(),
-                &StateValue::new(value, Some(index)).serialize(),
-            );
-        }
-
-        let next_key = keys
-            .last()
-            .and_then(|last_key| b256_to_u256(*last_key).checked_add(U256ONE))
-            .map(u256_to_b256);
-        match (next_key, keys.len()) {
-            (Some(next_key), keys_len) if keys_len == self.enum_index_migration_chunk_size => {
-                write_batch.put_cf(
-                    StateKeeperColumnFamily::State,
-                    Self::ENUM_INDEX_MIGRATION_CURSOR,
-                    next_key.as_slice(),
-                );
-            }
-            _ => {
-                write_batch.put_cf(
-                    StateKeeperColumnFamily::State,
-                    Self::ENUM_INDEX_MIGRATION_CURSOR,
-                    &[],
-                );
-                tracing::info!("RocksDB enum index migration finished");
-            }
-        }
-        self.db
-            .write(write_batch)
-            .expect("failed to save state data into rocksdb");
-        tracing::info!(
-            "RocksDB enum index migration chunk took {:?}, migrated {} keys",
-            started_at.elapsed(),
-            keys.len()
-        );
-    }
-
-    fn read_value_inner(&self, key: &StorageKey) -> Option<StorageValue> {
-        self.read_state_value(key)
-            .map(|state_value| state_value.value)
-    }
-
-    fn read_state_value(&self, key: &StorageKey) -> Option<StateValue> {
-        let cf = StateKeeperColumnFamily::State;
-        self.db
-            .get_cf(cf, &Self::serialize_state_key(key))
-            .expect("failed to read rocksdb state value")
-            .map(|value| StateValue::deserialize(&value))
-    }
-
     /// Returns storage logs to apply.
     fn process_transaction_logs(
         &self,
@@ -439,47 +381,35 @@ impl RocksdbStorage {
         });
         save_task.await.unwrap();
     }
+}
 
-    /// Returns the last processed l1 batch number + 1
-    /// # Panics
-    /// Panics on RocksDB errors.
-    pub fn l1_batch_number(&self) -> L1BatchNumber {
-        let cf = StateKeeperColumnFamily::State;
-        let block_number = self
-            .db
-            .get_cf(cf, Self::BLOCK_NUMBER_KEY)
-            .expect("failed to fetch block number");
-        let block_number = block_number.map_or(0, |bytes| deserialize_block_number(&bytes));
-        L1BatchNumber(block_number)
-    }
-
-    fn serialize_state_key(key: &StorageKey) -> [u8; 32] {
-        *key.hashed_key()
+impl RocksdbStorage<ReadOnly> {
+    /// Opens an existing storage at the specified RocksDB `path` in the read-only mode.
+    /// Changes made to the storage after it is opened are not visible.
+    pub fn open_read_only(path: &Path) -> Self {
+        Self::from_db(RocksDB::open_read_only(path, RocksDBOptions::default()))
     }
+}
 
-    /// Estimates the number of key–value entries in the VM state.
-    fn estimated_map_size(&self) -> u64 {
-        self.db
-            .estimated_number_of_entries(StateKeeperColumnFamily::State)
+impl RocksdbStorage<Secondary> {
+    /// Opens a secondary instance for the storage at the specified RocksDB `path`.
+    /// The secondary instance keeps its info logs at `secondary_path`.
+    pub fn open_secondary(path: &Path, secondary_path: &Path) -> Self {
+        let options = RocksDBOptions::default();
+        Self::from_db(RocksDB::open_secondary(path, secondary_path, options))
     }
 
-    fn enum_migration_start_from(&self) -> Option<B256> {
-        let value = self
-            .db
-            .get_cf(
-                StateKeeperColumnFamily::State,
-                Self::ENUM_INDEX_MIGRATION_CURSOR,
-            )
-            .expect("failed to read `ENUM_INDEX_MIGRATION_CURSOR`");
-        match value {
-            Some(v) if v.is_empty() => None,
-            Some(cursor) => Some(B256::from_slice(&cursor)),
-            None => Some(B256::ZERO),
-        }
+    /// Catches up with the changes made by the primary storage instance.
+    ///
+    /// # Errors
+    ///
+    /// Propagates RocksDB errors.
+    pub fn try_catch_up_with_primary(&self) -> Result<(), rocksdb::Error> {
+        self.db.try_catch_up_with_primary()
     }
 }
 
-impl ReadStorage for RocksdbStorage {
+impl<M: AccessMode> ReadStorage for RocksdbStorage<M> {
     fn read_value(&mut self, key: &StorageKey) -> StorageValue {
         self.read_value_inner(key).unwrap_or(B256::ZERO)
     }
@@ -496,8 +426,8 @@ impl ReadStorage for RocksdbStorage {
     }
 
     fn get_enumeration_index(&mut self, key: &StorageKey) -> Option<u64> {
-        // Can safely unwrap here since it indicates that the migration has not yet ended and boojum
-        // will only be deployed when the migration is finished.
+        // Can safely unwrap here since the `AddEnumIndices` migration step ensures that all entries
+        // have enumeration indices.
         self.read_state_value(key)
             .map(|state_value| state_value.enum_index.unwrap())
     }
@@ -0,0 +1,264 @@
+//! Checkpoints and backups for [`RocksDB`] instances.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+use rocksdb::{
+    backup::{BackupEngine, BackupEngineInfo, BackupEngineOptions, RestoreOptions},
+    checkpoint::Checkpoint,
+    Env,
+};
+
+use crate::{
+    db::{NamedColumnFamily, RocksDB},
+    metrics::METRICS,
+};
+
+/// Information about a backup created using [`RocksDB::create_backup()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupInfo {
+    /// Backup ID. IDs are assigned sequentially starting from 1.
+    pub id: u32,
+    /// Time when the backup was created (with second precision).
+    pub timestamp: SystemTime,
+    /// Total byte size of files in the backup. Files may be shared with other backups.
+    pub size: u64,
+    /// Number of files in the backup.
+    pub file_count: u32,
+}
+
+impl From<BackupEngineInfo> for BackupInfo {
+    fn from(info: BackupEngineInfo) -> Self {
+        let timestamp = Duration::from_secs(info.timestamp.try_into().unwrap_or(0));
+        Self {
+            id: info.backup_id,
+            timestamp: SystemTime::UNIX_EPOCH + timestamp,
+            size: info.size,
+            file_count: info.num_files,
+        }
+    }
+}
+
+fn open_backup_engine(backup_dir: &Path) -> Result<BackupEngine, rocksdb::Error> {
+    let options = BackupEngineOptions::new(backup_dir)?;
+    BackupEngine::open(&options, &Env::new()?)
+}
+
+impl<CF: NamedColumnFamily> RocksDB<CF> {
+    /// Creates a checkpoint of this database at the specified `path`, which must not exist.
+    /// A checkpoint is an openable copy of the database; SST files are hard-linked if `path` is
+    /// on the same filesystem as the database, and copied otherwise.
+    ///
+    /// The database can be used while the checkpoint is created; the checkpoint is consistent
+    /// with the database state at some point during the call.
+    ///
+    /// # Errors
+    ///
+    /// Propagates RocksDB I/O errors.
+    pub fn create_checkpoint(&self, path: &Path) -> Result<(), rocksdb::Error> {
+        let started_at = Instant::now();
+        Checkpoint::new(self.raw_db())?.create_checkpoint(path)?;
+        let elapsed = started_at.elapsed();
+        METRICS.observe_checkpoint_duration(CF::DB_NAME, elapsed);
+        tracing::info!(
+            "Created checkpoint of RocksDB `{}` at `{}` in {elapsed:?}",
+            CF::DB_NAME,
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Creates a backup of this database in the specified directory, which is created if
+    /// necessary. Backups are incremental: SST files shared with the previous backups in the same
+    /// directory are not copied again. Memtables are flushed before creating the backup, so
+    /// the backup includes all writes performed before the call.
+    ///
+    /// If `max_backups` is specified, the oldest backups are purged after creating the new
+    /// backup so that at most `max_backups` backups are retained.
+    ///
+    /// # Errors
+    ///
+    /// Propagates RocksDB I/O errors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_backups` is `Some(0)`.
+    pub fn create_backup(
+        &self,
+        backup_dir: &Path,
+        max_backups: Option<usize>,
+    ) -> Result<BackupInfo, rocksdb::Error> {
+        assert_ne!(max_backups, Some(0), "At least one backup must be retained");
+
+        let started_at = Instant::now();
+        let mut engine = open_backup_engine(backup_dir)?;
+        engine.create_new_backup_flush(self.raw_db(), true)?;
+        if let Some(max_backups) = max_backups {
+            engine.purge_old_backups(max_backups)?;
+        }
+        let backups = engine.get_backup_info();
+        let elapsed = started_at.elapsed();
+
+        let latest_backup = backups.iter().max_by_key(|info| info.backup_id);
+        let latest_backup = BackupInfo::from(*latest_backup.expect("no backup after creation"));
+        METRICS.observe_backup(CF::DB_NAME, elapsed, latest_backup.size, backups.len());
+        tracing::info!(
+            "Created backup #{} of RocksDB `{}` at `{}` in {elapsed:?}: {latest_backup:?}",
+            latest_backup.id,
+            CF::DB_NAME,
+            backup_dir.display()
+        );
+        Ok(latest_backup)
+    }
+
+    /// Lists backups in the specified directory ordered by increasing ID.
+    ///
+    /// # Errors
+    ///
+    /// Propagates RocksDB I/O errors.
+    pub fn list_backups(backup_dir: &Path) -> Result<Vec<BackupInfo>, rocksdb::Error> {
+        let engine = open_backup_engine(backup_dir)?;
+        let mut backups: Vec<_> = engine
+            .get_backup_info()
+            .into_iter()
+            .map(BackupInfo::from)
+            .collect();
+        backups.sort_unstable_by_key(|info| info.id);
+        Ok(backups)
+    }
+
+    /// Verifies the backup with the specified ID by checking that all its files are present and
+    /// have expected sizes. File checksums are not verified.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backup doesn't exist or is corrupted.
+    pub fn verify_backup(backup_dir: &Path, backup_id: u32) -> Result<(), rocksdb::Error> {
+        open_backup_engine(backup_dir)?.verify_backup(backup_id)
+    }
+
+    /// Restores the database at `db_path` from a backup. If `backup_id` is not specified,
+    /// the latest backup is restored. Any existing data at `db_path` is overwritten.
+    ///
+    /// The database at `db_path` must not be open while restoring it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backup doesn't exist, or propagates RocksDB I/O errors.
+    pub fn restore_backup(
+        backup_dir: &Path,
+        backup_id: Option<u32>,
+        db_path: &Path,
+    ) -> Result<(), rocksdb::Error> {
+        let mut engine = open_backup_engine(backup_dir)?;
+        let options = RestoreOptions::default();
+        if let Some(backup_id) = backup_id {
+            engine.restore_from_backup(db_path, db_path, &options, backup_id)?;
+        } else {
+            engine.restore_from_latest_backup(db_path, db_path, &options)?;
+        }
+        tracing::info!(
+            "Restored RocksDB `{}` at `{}` from backup {} in `{}`",
+            CF::DB_NAME,
+            db_path.display(),
+            backup_id.map_or_else(|| "(latest)".to_owned(), |id| format!("#{id}")),
+            backup_dir.display()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy)]
+    struct TestColumnFamily;
+
+    impl NamedColumnFamily for TestColumnFamily {
+        const DB_NAME: &'static str = "backup_test";
+        const ALL: &'static [Self] = &[Self];
+
+        fn name(&self) -> &'static str {
+            "test"
+        }
+    }
+
+    fn put(db: &RocksDB<TestColumnFamily>, key: &[u8], value: &[u8]) {
+        let mut batch = db.new_write_batch();
+        batch.put_cf(TestColumnFamily, key, value);
+        db.write(batch).unwrap();
+    }
+
+    #[test]
+    fn creating_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let db = RocksDB::<TestColumnFamily>::new(&db_path);
+        put(&db, b"test", b"value");
+
+        let checkpoint_path = temp_dir.path().join("checkpoint");
+        db.create_checkpoint(&checkpoint_path).unwrap();
+        put(&db, b"test", b"new_value");
+        drop(db);
+
+        let checkpoint = RocksDB::<TestColumnFamily>::new(&checkpoint_path);
+        let value = checkpoint.get_cf(TestColumnFamily, b"test").unwrap();
+        assert_eq!(value.unwrap(), b"value");
+    }
+
+    #[test]
+    fn creating_and_restoring_backups() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("db");
+        let backup_dir = temp_dir.path().join("backups");
+        let db = RocksDB::<TestColumnFamily>::new(&db_path);
+        put(&db, b"test", b"value");
+        let first_backup = db.create_backup(&backup_dir, None).unwrap();
+        assert_eq!(first_backup.id, 1);
+        assert!(first_backup.file_count > 0);
+
+        put(&db, b"test", b"new_value");
+        let second_backup = db.create_backup(&backup_dir, None).unwrap();
+        assert_eq!(second_backup.id, 2);
+        drop(db);
+
+        let backups = RocksDB::<TestColumnFamily>::list_backups(&backup_dir).unwrap();
+        assert_eq!(backups, [first_backup, second_backup]);
+        for backup in &backups {
+            RocksDB::<TestColumnFamily>::verify_backup(&backup_dir, backup.id).unwrap();
+        }
+        RocksDB::<TestColumnFamily>::verify_backup(&backup_dir, 3).unwrap_err();
+
+        let restored_path = temp_dir.path().join("restored");
+        RocksDB::<TestColumnFamily>::restore_backup(&backup_dir, Some(1), &restored_path).unwrap();
+        let restored_db = RocksDB::<TestColumnFamily>::new(&restored_path);
+        let value = restored_db.get_cf(TestColumnFamily, b"test").unwrap();
+        assert_eq!(value.unwrap(), b"value");
+        drop(restored_db);
+
+        RocksDB::<TestColumnFamily>::restore_backup(&backup_dir, None, &restored_path).unwrap();
+        let restored_db = RocksDB::<TestColumnFamily>::new(&restored_path);
+        let value = restored_db.get_cf(TestColumnFamily, b"test").unwrap();
+        assert_eq!(value.unwrap(), b"new_value");
+    }
+
+    #[test]
+    fn purging_old_backups() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDB::<TestColumnFamily>::new(&temp_dir.path().join("db"));
+        let backup_dir = temp_dir.path().join("backups");
+        for i in 0_u8..5 {
+            put(&db, b"test", &[i]);
+            db.create_backup(&backup_dir, Some(2)).unwrap();
+        }
+
+        let backups = RocksDB::<TestColumnFamily>::list_backups(&backup_dir).unwrap();
+        let backup_ids: Vec<_> = backups.iter().map(|info| info.id).collect();
+        assert_eq!(backup_ids, [4, 5]);
+    }
+}
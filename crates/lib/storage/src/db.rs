@@ -15,7 +15,10 @@ use rocksdb::{
     Direction, IteratorMode, Options, PrefixRange, ReadOptions, WriteOptions, DB,
 };
 
-use crate::metrics::{RocksdbLabels, RocksdbSizeMetrics, METRICS};
+use crate::{
+    metrics::{RocksdbLabels, RocksdbSizeMetrics, METRICS},
+    migrations::{MigrationMode, MigrationStep},
+};
 
 /// Number of active RocksDB instances used to determine if it's safe to exit current process.
 /// Not properly dropped RocksDB instances can lead to DB corruption.
@@ -27,7 +30,13 @@ pub trait NamedColumnFamily: 'static + Copy {
     const DB_NAME: &'static str;
     /// Lists all column families in the database.
     const ALL: &'static [Self];
+    /// Ordered schema migration steps for the database; see [`RocksDB::run_migrations()`].
+    /// Migrations are run automatically when the database is opened in the [`ReadWrite`] mode.
+    /// Databases opened in other modes must be fully migrated.
+    const MIGRATIONS: &'static [&'static dyn MigrationStep<Self>] = &[];
+
     /// Names a column family to access it in `RocksDB`. Also used in metrics reporting.
+    /// A column family named `default` must not use the empty key, which stores the schema state.
     fn name(&self) -> &'static str;
 
     /// Returns whether this CF is so large that it's likely to require special configuration in
@@ -37,6 +46,41 @@ pub trait NamedColumnFamily: 'static + Copy {
     }
 }
 
+/// Mode in which a [`RocksDB`] instance is opened. Write operations are only available for
+/// instances opened in the [`ReadWrite`] mode.
+pub trait AccessMode: 'static + Copy + fmt::Debug + Send + Sync {
+    /// Human-readable name of the mode used in logging.
+    const NAME: &'static str;
+}
+
+/// Default [`AccessMode`]: the database is opened for reading and writing. Only one process can
+/// open a database in this mode at a time.
+#[derive(Debug, Clone, Copy)]
+pub enum ReadWrite {}
+
+impl AccessMode for ReadWrite {
+    const NAME: &'static str = "read-write";
+}
+
+/// [`AccessMode`] in which the database can only be read. The database can be opened in this
+/// mode while another process has it open in the [`ReadWrite`] mode, but changes made
+/// by the other process after the database is opened are not visible.
+#[derive(Debug, Clone, Copy)]
+pub enum ReadOnly {}
+
+impl AccessMode for ReadOnly {
+    const NAME: &'static str = "read-only";
+}
+
+/// [`AccessMode`] for secondary instances. Similar to [`ReadOnly`], but the instance can catch up
+/// with changes made by the primary instance using [`RocksDB::try_catch_up_with_primary()`].
+#[derive(Debug, Clone, Copy)]
+pub enum Secondary {}
+
+impl AccessMode for Secondary {
+    const NAME: &'static str = "secondary";
+}
+
 /// Thin typesafe wrapper around RocksDB `WriteBatch`.
 #[must_use = "Batch should be written to DB"]
 pub struct WriteBatch<'a, CF> {
@@ -68,6 +112,132 @@ impl<CF: NamedColumnFamily> WriteBatch<'_, CF> {
         let cf = self.db.column_family(cf);
         self.inner.delete_range_cf(cf, keys.start, keys.end);
     }
+
+    /// Puts a value into the default column family, which may be not included into `CF`.
+    pub(crate) fn put_default(&mut self, key: &[u8], value: &[u8]) {
+        self.inner.put(key, value);
+    }
+}
+
+/// Point-in-time, read-only view of a [`RocksDB`] instance. Reads from a snapshot are not
+/// influenced by writes to the database performed after the snapshot was created.
+///
+/// A snapshot provides the same read API as the database itself. Use it if several reads
+/// (e.g., from different column families) must be consistent with each other.
+pub struct RocksDBSnapshot<'a, CF> {
+    inner: rocksdb::Snapshot<'a>,
+    db: &'a RocksDB<CF>,
+}
+
+impl<CF: fmt::Debug> fmt::Debug for RocksDBSnapshot<'_, CF> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("RocksDBSnapshot")
+            .field("db", self.db)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<CF: NamedColumnFamily> RocksDBSnapshot<'_, CF> {
+    fn read_options(&self) -> ReadOptions {
+        let mut options = ReadOptions::default();
+        options.set_snapshot(&self.inner);
+        options
+    }
+
+    pub fn get_cf(&self, cf: CF, key: &[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        let cf = self.db.column_family(cf);
+        self.db.inner.db.get_cf_opt(cf, key, &self.read_options())
+    }
+
+    pub fn multi_get_cf(
+        &self,
+        cf: CF,
+        keys: impl Iterator<Item = Vec<u8>>,
+    ) -> Vec<Result<Option<DBPinnableSlice<'_>>, rocksdb::Error>> {
+        let cf = self.db.column_family(cf);
+        self.db
+            .inner
+            .db
+            .batched_multi_get_cf_opt(cf, keys, false, &self.read_options())
+    }
+
+    pub fn multi_get<K, I>(&self, keys: I) -> Vec<Result<Option<Vec<u8>>, rocksdb::Error>>
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = K>,
+    {
+        self.db.inner.db.multi_get_opt(keys, &self.read_options())
+    }
+
+    /// Iterates over key-value pairs in the specified column family `cf` in the lexical
+    /// key order. The keys are filtered so that they start from the specified `prefix`.
+    pub fn prefix_iterator_cf(
+        &self,
+        cf: CF,
+        prefix: &[u8],
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        let mut options = self.read_options();
+        options.set_iterate_range(PrefixRange(prefix));
+        self.db.iterator_cf_opt(cf, options, IteratorMode::Start)
+    }
+
+    /// Iterates over key-value pairs in the specified column family `cf` in the lexical
+    /// key order starting from the given `key_from`.
+    pub fn from_iterator_cf(
+        &self,
+        cf: CF,
+        key_from: &[u8],
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        let mode = IteratorMode::From(key_from, Direction::Forward);
+        self.db.iterator_cf_opt(cf, self.read_options(), mode)
+    }
+
+    /// Iterates over key-value pairs in the specified column family `cf` with keys in
+    /// the specified `range` in the lexical key order.
+    pub fn range_iterator_cf(
+        &self,
+        cf: CF,
+        range: ops::Range<&[u8]>,
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        let mut options = self.read_options();
+        options.set_iterate_range(range);
+        self.db.iterator_cf_opt(cf, options, IteratorMode::Start)
+    }
+
+    /// Iterates over key-value pairs in the specified column family `cf` with keys in
+    /// the specified `range` in the reverse lexical key order.
+    pub fn reverse_range_iterator_cf(
+        &self,
+        cf: CF,
+        range: ops::Range<&[u8]>,
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        let mut options = self.read_options();
+        options.set_iterate_range(range);
+        self.db.iterator_cf_opt(cf, options, IteratorMode::End)
+    }
+
+    /// Fallible version of [`Self::range_iterator_cf()`] and [`Self::reverse_range_iterator_cf()`]
+    /// iterating in the specified `direction`. The iterator is terminated after yielding
+    /// an error.
+    pub fn try_range_iterator_cf(
+        &self,
+        cf: CF,
+        range: ops::Range<&[u8]>,
+        direction: Direction,
+    ) -> impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>> + '_ {
+        let mut options = self.read_options();
+        options.set_iterate_range(range);
+        self.db
+            .try_iterator_cf_opt(cf, options, iterator_mode(direction))
+    }
+}
+
+fn iterator_mode(direction: Direction) -> IteratorMode<'static> {
+    match direction {
+        Direction::Forward => IteratorMode::Start,
+        Direction::Reverse => IteratorMode::End,
+    }
 }
 
 struct RocksDBCaches {
@@ -274,6 +444,10 @@ pub struct RocksDBOptions {
     /// Timeout to wait for the database to run compaction on stalled writes during startup or
     /// when the corresponding RocksDB error is encountered.
     pub stalled_writes_retries: StalledWritesRetries,
+    /// Mode for running schema migrations registered in [`NamedColumnFamily::MIGRATIONS`] when
+    /// the database is opened. In the dry-run mode, the database is left at its current schema
+    /// version.
+    pub migration_mode: MigrationMode,
 }
 
 impl Default for RocksDBOptions {
@@ -282,6 +456,7 @@ impl Default for RocksDBOptions {
             block_cache_capacity: None,
             large_memtable_capacity: None,
             stalled_writes_retries: StalledWritesRetries::new(Duration::from_secs(10)),
+            migration_mode: MigrationMode::Apply,
         }
     }
 }
@@ -289,12 +464,16 @@ impl Default for RocksDBOptions {
 /// Thin wrapper around a RocksDB instance.
 ///
 /// The wrapper is cheaply cloneable (internally, it wraps a DB instance in an [`Arc`]).
+///
+/// The [`AccessMode`] type param specifies whether the instance can be written to; by default,
+/// instances are opened in the [`ReadWrite`] mode.
 #[derive(Debug, Clone)]
-pub struct RocksDB<CF> {
+pub struct RocksDB<CF, M = ReadWrite> {
     inner: Arc<RocksDBInner>,
     sync_writes: bool,
     stalled_writes_retries: StalledWritesRetries,
     _cf: PhantomData<CF>,
+    _mode: PhantomData<M>,
 }
 
 impl<CF: NamedColumnFamily> RocksDB<CF> {
@@ -303,6 +482,79 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
     }
 
     pub fn with_options(path: &Path, options: RocksDBOptions) -> Self {
+        let this = Self::open(path, &options, |db_options, cfs| {
+            DB::open_cf_descriptors(&db_options, path, cfs)
+        });
+        RocksdbSizeMetrics::register(CF::DB_NAME, Arc::downgrade(&this.inner));
+        this.inner
+            .wait_for_writes_to_resume(&options.stalled_writes_retries);
+        this.run_migrations(options.migration_mode)
+            .expect("failed running RocksDB migrations");
+        this
+    }
+
+    /// Switches on sync writes in [`Self::write()`] and [`Self::put()`]. This has a performance
+    /// penalty and is mostly useful for tests.
+    #[must_use]
+    pub fn with_sync_writes(mut self) -> Self {
+        self.sync_writes = true;
+        self
+    }
+}
+
+impl<CF: NamedColumnFamily> RocksDB<CF, ReadOnly> {
+    /// Opens an existing database at the specified `path` in the [`ReadOnly`] mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the database cannot be opened, e.g. if it doesn't exist or doesn't contain
+    /// some of the column families, or if the database is not fully migrated
+    /// (see [`Self::run_migrations()`]).
+    pub fn open_read_only(path: &Path, options: RocksDBOptions) -> Self {
+        let this = Self::open(path, &options, |db_options, cfs| {
+            DB::open_cf_descriptors_read_only(&db_options, path, cfs, false)
+        });
+        this.check_schema_version();
+        this
+    }
+}
+
+impl<CF: NamedColumnFamily> RocksDB<CF, Secondary> {
+    /// Opens a secondary instance for the database at `primary_path`. The secondary instance
+    /// stores its info logs at `secondary_path`; it doesn't need to exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the database cannot be opened, e.g. if it doesn't exist or doesn't contain
+    /// some of the column families, or if the database is not fully migrated
+    /// (see [`RocksDB::run_migrations()`]).
+    pub fn open_secondary(
+        primary_path: &Path,
+        secondary_path: &Path,
+        options: RocksDBOptions,
+    ) -> Self {
+        let this = Self::open(primary_path, &options, |mut db_options, cfs| {
+            // Secondary instances require keeping all SST files open.
+            db_options.set_max_open_files(-1);
+            DB::open_cf_descriptors_as_secondary(&db_options, primary_path, secondary_path, cfs)
+        });
+        this.check_schema_version();
+        this
+    }
+
+    /// Catches up with the changes made by the primary instance since this instance was opened
+    /// or since the previous call.
+    pub fn try_catch_up_with_primary(&self) -> Result<(), rocksdb::Error> {
+        self.inner.db.try_catch_up_with_primary()
+    }
+}
+
+impl<CF: NamedColumnFamily, M: AccessMode> RocksDB<CF, M> {
+    fn open(
+        path: &Path,
+        options: &RocksDBOptions,
+        open_db: impl FnOnce(Options, Vec<ColumnFamilyDescriptor>) -> Result<DB, rocksdb::Error>,
+    ) -> Self {
         let caches = RocksDBCaches::new(options.block_cache_capacity);
         let db_options = Self::rocksdb_options(None, None);
         let existing_cfs = DB::list_cf(&db_options, path).unwrap_or_else(|err| {
@@ -354,7 +606,7 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
             ColumnFamilyDescriptor::new(cf_name, cf_options)
         });
 
-        let db = DB::open_cf_descriptors(&db_options, path, cfs).expect("failed to init rocksdb");
+        let db = open_db(db_options, cfs.collect()).expect("failed to init rocksdb");
         let inner = Arc::new(RocksDBInner {
             db,
             db_name: CF::DB_NAME,
@@ -362,31 +614,22 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
             _registry_entry: RegistryEntry::new(),
             _caches: caches,
         });
-        RocksdbSizeMetrics::register(CF::DB_NAME, Arc::downgrade(&inner));
 
         tracing::info!(
-            "Initialized RocksDB `{}` at `{}` with {options:?}",
+            "Initialized RocksDB `{}` at `{}` in {} mode with {options:?}",
             CF::DB_NAME,
-            path.display()
+            path.display(),
+            M::NAME
         );
-
-        inner.wait_for_writes_to_resume(&options.stalled_writes_retries);
         Self {
             inner,
             sync_writes: false,
             stalled_writes_retries: options.stalled_writes_retries,
             _cf: PhantomData,
+            _mode: PhantomData,
         }
     }
 
-    /// Switches on sync writes in [`Self::write()`] and [`Self::put()`]. This has a performance
-    /// penalty and is mostly useful for tests.
-    #[must_use]
-    pub fn with_sync_writes(mut self) -> Self {
-        self.sync_writes = true;
-        self
-    }
-
     fn rocksdb_options(
         memtable_capacity: Option<usize>,
         block_based_options: Option<BlockBasedOptions>,
@@ -411,6 +654,11 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
         options
     }
 
+    /// Returns the path to the database directory.
+    pub fn path(&self) -> &Path {
+        self.inner.db.path()
+    }
+
     pub fn estimated_number_of_entries(&self, cf: CF) -> u64 {
         const ERROR_MSG: &str = "failed to get estimated number of entries";
 
@@ -422,6 +670,21 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
             .unwrap_or(0)
     }
 
+    /// Checks whether writes are currently stopped or delayed for any of the column families,
+    /// e.g., because compaction cannot keep up with the write rate. Background jobs can use
+    /// this to throttle themselves.
+    pub fn has_stalled_writes(&self) -> bool {
+        self.inner.cf_names.iter().any(|cf_name| {
+            let cf = self.inner.db.cf_handle(cf_name).unwrap();
+            // ^ `unwrap()` is safe (CF existence is checked during DB initialization)
+            let is_stopped = self.inner.int_property(cf, properties::IS_WRITE_STOPPED) == Some(1);
+            let delayed_write_rate = self
+                .inner
+                .int_property(cf, properties::ACTUAL_DELAYED_WRITE_RATE);
+            is_stopped || delayed_write_rate.is_some_and(|rate| rate > 0)
+        })
+    }
+
     pub fn multi_get<K, I>(&self, keys: I) -> Vec<Result<Option<Vec<u8>>, rocksdb::Error>>
     where
         K: AsRef<[u8]>,
@@ -439,6 +702,119 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
         self.inner.db.batched_multi_get_cf(cf, keys, false)
     }
 
+    pub(crate) fn raw_db(&self) -> &DB {
+        &self.inner.db
+    }
+
+    fn column_family(&self, cf: CF) -> &ColumnFamily {
+        self.inner
+            .db
+            .cf_handle(cf.name())
+            .unwrap_or_else(|| panic!("Column family `{}` doesn't exist", cf.name()))
+    }
+
+    pub fn get_cf(&self, cf: CF, key: &[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error> {
+        let cf = self.column_family(cf);
+        self.inner.db.get_cf(cf, key)
+    }
+
+    /// Iterates over key-value pairs in the specified column family `cf` in the lexical
+    /// key order. The keys are filtered so that they start from the specified `prefix`.
+    pub fn prefix_iterator_cf(
+        &self,
+        cf: CF,
+        prefix: &[u8],
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        let mut options = ReadOptions::default();
+        options.set_iterate_range(PrefixRange(prefix));
+        self.iterator_cf_opt(cf, options, IteratorMode::Start)
+    }
+
+    /// Iterates over key-value pairs in the specified column family `cf` in the lexical
+    /// key order starting from the given `key_from`.
+    pub fn from_iterator_cf(
+        &self,
+        cf: CF,
+        key_from: &[u8],
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        let mode = IteratorMode::From(key_from, Direction::Forward);
+        self.iterator_cf_opt(cf, ReadOptions::default(), mode)
+    }
+
+    /// Iterates over key-value pairs in the specified column family `cf` with keys in
+    /// the specified `range` in the lexical key order.
+    pub fn range_iterator_cf(
+        &self,
+        cf: CF,
+        range: ops::Range<&[u8]>,
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        let mut options = ReadOptions::default();
+        options.set_iterate_range(range);
+        self.iterator_cf_opt(cf, options, IteratorMode::Start)
+    }
+
+    /// Iterates over key-value pairs in the specified column family `cf` with keys in
+    /// the specified `range` in the reverse lexical key order. E.g., this can be used to get
+    /// the greatest key in the range.
+    pub fn reverse_range_iterator_cf(
+        &self,
+        cf: CF,
+        range: ops::Range<&[u8]>,
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        let mut options = ReadOptions::default();
+        options.set_iterate_range(range);
+        self.iterator_cf_opt(cf, options, IteratorMode::End)
+    }
+
+    /// Fallible version of [`Self::range_iterator_cf()`] and [`Self::reverse_range_iterator_cf()`]
+    /// iterating in the specified `direction`. Unlike infallible iterators, this iterator
+    /// yields RocksDB errors instead of panicking on them; it is terminated after yielding
+    /// an error.
+    pub fn try_range_iterator_cf(
+        &self,
+        cf: CF,
+        range: ops::Range<&[u8]>,
+        direction: Direction,
+    ) -> impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>> + '_ {
+        let mut options = ReadOptions::default();
+        options.set_iterate_range(range);
+        self.try_iterator_cf_opt(cf, options, iterator_mode(direction))
+    }
+
+    fn iterator_cf_opt(
+        &self,
+        cf: CF,
+        options: ReadOptions,
+        mode: IteratorMode<'_>,
+    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
+        self.try_iterator_cf_opt(cf, options, mode)
+            .map(Result::unwrap)
+        // ^ The rocksdb docs say that a raw iterator (which is used by the returned ordinary
+        // iterator) can become invalid "when it reaches the end of its defined range, or
+        // when it encounters an error." We panic on RocksDB errors elsewhere and fuse it to
+        // prevent polling after the end of the range. Thus, `unwrap()` should be safe.
+    }
+
+    fn try_iterator_cf_opt(
+        &self,
+        cf: CF,
+        options: ReadOptions,
+        mode: IteratorMode<'_>,
+    ) -> impl Iterator<Item = Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>> + '_ {
+        let cf = self.column_family(cf);
+        self.inner.db.iterator_cf_opt(cf, options, mode).fuse()
+    }
+}
+
+impl<CF: NamedColumnFamily> RocksDB<CF> {
+    /// Creates a point-in-time snapshot of this database.
+    pub fn snapshot(&self) -> RocksDBSnapshot<'_, CF> {
+        RocksDBSnapshot {
+            inner: self.inner.db.snapshot(),
+            db: self,
+        }
+    }
+
     pub fn new_write_batch(&self) -> WriteBatch<'_, CF> {
         WriteBatch {
             inner: rocksdb::WriteBatch::default(),
@@ -501,55 +877,6 @@ impl<CF: NamedColumnFamily> RocksDB<CF> {
             self.inner.db.write(raw_batch)
         }
     }
-
-    fn column_family(&self, cf: CF) -> &ColumnFamily {
-        self.inner
-            .db
-            .cf_handle(cf.name())
-            .unwrap_or_else(|| panic!("Column family `{}` doesn't exist", cf.name()))
-    }
-
-    pub fn get_cf(&self, cf: CF, key: &[u8]) -> Result<Option<Vec<u8>>, rocksdb::Error> {
-        let cf = self.column_family(cf);
-        self.inner.db.get_cf(cf, key)
-    }
-
-    /// Iterates over key-value pairs in the specified column family `cf` in the lexical
-    /// key order. The keys are filtered so that they start from the specified `prefix`.
-    pub fn prefix_iterator_cf(
-        &self,
-        cf: CF,
-        prefix: &[u8],
-    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
-        let cf = self.column_family(cf);
-        let mut options = ReadOptions::default();
-        options.set_iterate_range(PrefixRange(prefix));
-        self.inner
-            .db
-            .iterator_cf_opt(cf, options, IteratorMode::Start)
-            .map(Result::unwrap)
-            .fuse()
-        // ^ The rocksdb docs say that a raw iterator (which is used by the returned ordinary
-        // iterator) can become invalid "when it reaches the end of its defined range, or
-        // when it encounters an error." We panic on RocksDB errors elsewhere and fuse it to
-        // prevent polling after the end of the range. Thus, `unwrap()` should be safe.
-    }
-
-    /// Iterates over key-value pairs in the specified column family `cf` in the lexical
-    /// key order starting from the given `key_from`.
-    pub fn from_iterator_cf(
-        &self,
-        cf: CF,
-        key_from: &[u8],
-    ) -> impl Iterator<Item = (Box<[u8]>, Box<[u8]>)> + '_ {
-        let cf = self.column_family(cf);
-        self.inner
-            .db
-            .iterator_cf(cf, IteratorMode::From(key_from, Direction::Forward))
-            .map(Result::unwrap)
-            .fuse()
-        // ^ unwrap() is safe for the same reasons as in `prefix_iterator_cf()`.
-    }
 }
 
 impl RocksDB<()> {
@@ -703,6 +1030,194 @@ mod tests {
         assert_eq!(value.unwrap(), b"value");
     }
 
+    #[test]
+    fn snapshot_is_not_affected_by_later_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDB::<NewColumnFamilies>::new(temp_dir.path());
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamilies::Other, b"test", b"value");
+        db.write(batch).unwrap();
+
+        let snapshot = db.snapshot();
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamilies::Other, b"test", b"new_value");
+        batch.put_cf(NewColumnFamilies::Other, b"test2", b"value2");
+        db.write(batch).unwrap();
+
+        let value = snapshot.get_cf(NewColumnFamilies::Other, b"test").unwrap();
+        assert_eq!(value.unwrap(), b"value");
+        let keys = [b"test".to_vec(), b"test2".to_vec()];
+        let values = snapshot.multi_get_cf(NewColumnFamilies::Other, keys.into_iter());
+        let values: Vec<_> = values
+            .into_iter()
+            .map(|value| value.unwrap().map(|slice| slice.to_vec()))
+            .collect();
+        assert_eq!(values, [Some(b"value".to_vec()), None]);
+
+        let value = db.get_cf(NewColumnFamilies::Other, b"test").unwrap();
+        assert_eq!(value.unwrap(), b"new_value");
+    }
+
+    #[test]
+    fn opening_db_in_read_only_mode() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDB::<NewColumnFamilies>::new(temp_dir.path()).with_sync_writes();
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamilies::Other, b"test", b"value");
+        db.write(batch).unwrap();
+
+        let options = RocksDBOptions::default();
+        let read_only_db =
+            RocksDB::<NewColumnFamilies, _>::open_read_only(temp_dir.path(), options);
+        let value = read_only_db
+            .get_cf(NewColumnFamilies::Other, b"test")
+            .unwrap();
+        assert_eq!(value.unwrap(), b"value");
+
+        // Writes performed after opening the DB are not visible.
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamilies::Other, b"test2", b"value2");
+        db.write(batch).unwrap();
+        let value = read_only_db
+            .get_cf(NewColumnFamilies::Other, b"test2")
+            .unwrap();
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn secondary_instance_catches_up_with_primary() {
+        let temp_dir = TempDir::new().unwrap();
+        let primary_path = temp_dir.path().join("primary");
+        let db = RocksDB::<NewColumnFamilies>::new(&primary_path).with_sync_writes();
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamilies::Other, b"test", b"value");
+        db.write(batch).unwrap();
+
+        let secondary_path = temp_dir.path().join("secondary");
+        let options = RocksDBOptions::default();
+        let secondary_db = RocksDB::<NewColumnFamilies, _>::open_secondary(
+            &primary_path,
+            &secondary_path,
+            options,
+        );
+        let value = secondary_db
+            .get_cf(NewColumnFamilies::Other, b"test")
+            .unwrap();
+        assert_eq!(value.unwrap(), b"value");
+
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamilies::Other, b"test", b"new_value");
+        db.write(batch).unwrap();
+        secondary_db.try_catch_up_with_primary().unwrap();
+        let value = secondary_db
+            .get_cf(NewColumnFamilies::Other, b"test")
+            .unwrap();
+        assert_eq!(value.unwrap(), b"new_value");
+    }
+
+    #[test]
+    fn snapshot_iterators_are_not_affected_by_later_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDB::<NewColumnFamilies>::new(temp_dir.path());
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamilies::Default, b"test", b"value");
+        batch.put_cf(NewColumnFamilies::Other, b"key1", b"value1");
+        batch.put_cf(NewColumnFamilies::Other, b"key3", b"value3");
+        db.write(batch).unwrap();
+
+        let snapshot = db.snapshot();
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamilies::Default, b"test", b"new_value");
+        batch.put_cf(NewColumnFamilies::Other, b"key2", b"value2");
+        batch.delete_cf(NewColumnFamilies::Other, b"key3");
+        db.write(batch).unwrap();
+
+        let values = snapshot.multi_get([b"test".as_slice(), b"missing"]);
+        let values: Vec<_> = values.into_iter().map(Result::unwrap).collect();
+        assert_eq!(values, [Some(b"value".to_vec()), None]);
+
+        let keys: Vec<_> = snapshot
+            .prefix_iterator_cf(NewColumnFamilies::Other, b"key")
+            .map(|(key, _)| key.into_vec())
+            .collect();
+        assert_eq!(keys, [b"key1".to_vec(), b"key3".to_vec()]);
+        let entries: Vec<_> = snapshot
+            .from_iterator_cf(NewColumnFamilies::Other, b"key2")
+            .map(|(key, value)| (key.into_vec(), value.into_vec()))
+            .collect();
+        assert_eq!(entries, [(b"key3".to_vec(), b"value3".to_vec())]);
+
+        let keys: Vec<_> = db
+            .prefix_iterator_cf(NewColumnFamilies::Other, b"key")
+            .map(|(key, _)| key.into_vec())
+            .collect();
+        assert_eq!(keys, [b"key1".to_vec(), b"key2".to_vec()]);
+    }
+
+    fn collect_keys(iter: impl Iterator<Item = (Box<[u8]>, Box<[u8]>)>) -> Vec<Vec<u8>> {
+        iter.map(|(key, _)| key.into_vec()).collect()
+    }
+
+    #[test]
+    fn range_iterators() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDB::<NewColumnFamilies>::new(temp_dir.path());
+        let mut batch = db.new_write_batch();
+        for key in [b"key0", b"key1", b"key2", b"key3"] {
+            batch.put_cf(NewColumnFamilies::Other, key, b"value");
+        }
+        db.write(batch).unwrap();
+
+        let cf = NewColumnFamilies::Other;
+        let keys = collect_keys(db.range_iterator_cf(cf, b"key1".as_slice()..b"key3"));
+        assert_eq!(keys, [b"key1".to_vec(), b"key2".to_vec()]);
+        let keys = collect_keys(db.reverse_range_iterator_cf(cf, b"key1".as_slice()..b"key3"));
+        assert_eq!(keys, [b"key2".to_vec(), b"key1".to_vec()]);
+        let keys = collect_keys(db.reverse_range_iterator_cf(cf, b"key".as_slice()..b"kez"));
+        assert_eq!(keys[0], b"key3");
+        let keys = collect_keys(db.range_iterator_cf(cf, b"key4".as_slice()..b"key5"));
+        assert!(keys.is_empty());
+
+        let snapshot = db.snapshot();
+        let mut batch = db.new_write_batch();
+        batch.delete_cf(cf, b"key1");
+        db.write(batch).unwrap();
+
+        let keys = collect_keys(snapshot.range_iterator_cf(cf, b"key1".as_slice()..b"key3"));
+        assert_eq!(keys, [b"key1".to_vec(), b"key2".to_vec()]);
+        let range = b"key0".as_slice()..b"key2";
+        let keys = collect_keys(snapshot.reverse_range_iterator_cf(cf, range.clone()));
+        assert_eq!(keys, [b"key1".to_vec(), b"key0".to_vec()]);
+        let keys = collect_keys(db.reverse_range_iterator_cf(cf, range));
+        assert_eq!(keys, [b"key0".to_vec()]);
+    }
+
+    #[test]
+    fn fallible_range_iterators() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDB::<NewColumnFamilies>::new(temp_dir.path());
+        let mut batch = db.new_write_batch();
+        for key in [b"key0", b"key1", b"key2"] {
+            batch.put_cf(NewColumnFamilies::Other, key, b"value");
+        }
+        db.write(batch).unwrap();
+
+        let cf = NewColumnFamilies::Other;
+        let range = b"key1".as_slice()..b"key3";
+        let keys: Vec<_> = db
+            .try_range_iterator_cf(cf, range.clone(), Direction::Reverse)
+            .map(|entry| entry.unwrap().0.into_vec())
+            .collect();
+        assert_eq!(keys, [b"key2".to_vec(), b"key1".to_vec()]);
+
+        let snapshot = db.snapshot();
+        let keys: Vec<_> = snapshot
+            .try_range_iterator_cf(cf, range, Direction::Forward)
+            .map(|entry| entry.unwrap().0.into_vec())
+            .collect();
+        assert_eq!(keys, [b"key1".to_vec(), b"key2".to_vec()]);
+    }
+
     #[test]
     fn write_batch_can_be_restored_from_bytes() {
         let temp_dir = TempDir::new().unwrap();
@@ -1,5 +1,14 @@
+mod backup;
 pub mod db;
 mod metrics;
+mod migrations;
 
-pub use db::{RocksDB, RocksDBOptions, StalledWritesRetries};
+pub use backup::BackupInfo;
+pub use db::{
+    AccessMode, ReadOnly, ReadWrite, RocksDB, RocksDBOptions, RocksDBSnapshot, Secondary,
+    StalledWritesRetries,
+};
+pub use migrations::{
+    MigrationChunk, MigrationMode, MigrationReport, MigrationStep, MigrationStepReport,
+};
 pub use rocksdb;
@@ -36,6 +36,12 @@ impl RocksdbLabels {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EncodeLabelSet)]
+struct MigrationLabels {
+    db: &'static str,
+    step: &'static str,
+}
+
 const BYTE_SIZE_BUCKETS: Buckets = Buckets::exponential(65_536.0..=16.0 * 1_024.0 * 1_024.0, 2.0);
 
 #[derive(Debug, Metrics)]
@@ -54,6 +60,22 @@ pub(crate) struct RocksdbMetrics {
     /// propagated, which leads to a panic).
     #[metrics(buckets = Buckets::LATENCIES, unit = Unit::Seconds)]
     stalled_write_duration: Family<DbLabel, Histogram<Duration>>,
+    /// Latency of creating a checkpoint of a RocksDB instance.
+    #[metrics(buckets = Buckets::LATENCIES, unit = Unit::Seconds)]
+    checkpoint_duration: Family<DbLabel, Histogram<Duration>>,
+    /// Latency of creating a backup of a RocksDB instance.
+    #[metrics(buckets = Buckets::LATENCIES, unit = Unit::Seconds)]
+    backup_duration: Family<DbLabel, Histogram<Duration>>,
+    /// Total size of files in the latest backup of a RocksDB instance. Since backups are
+    /// incremental, files may be shared with the previous backups.
+    #[metrics(unit = Unit::Bytes)]
+    backup_size: Family<DbLabel, Gauge<u64>>,
+    /// Number of backups of a RocksDB instance retained in the backup directory.
+    backup_count: Family<DbLabel, Gauge<usize>>,
+    /// Schema version of a RocksDB instance after running migrations.
+    schema_version: Family<DbLabel, Gauge<u64>>,
+    /// Number of items processed by a migration step for a RocksDB instance.
+    migration_processed_items: Family<MigrationLabels, Counter>,
 }
 
 impl RocksdbMetrics {
@@ -72,6 +94,37 @@ impl RocksdbMetrics {
     ) {
         self.stalled_write_duration[&db.into()].observe(stall_duration);
     }
+
+    pub(crate) fn observe_checkpoint_duration(&self, db: &'static str, duration: Duration) {
+        self.checkpoint_duration[&db.into()].observe(duration);
+    }
+
+    pub(crate) fn observe_backup(
+        &self,
+        db: &'static str,
+        duration: Duration,
+        size: u64,
+        backup_count: usize,
+    ) {
+        let label = db.into();
+        self.backup_duration[&label].observe(duration);
+        self.backup_size[&label].set(size);
+        self.backup_count[&label].set(backup_count);
+    }
+
+    pub(crate) fn report_schema_version(&self, db: &'static str, version: u32) {
+        self.schema_version[&db.into()].set(version.into());
+    }
+
+    pub(crate) fn observe_migration_chunk(
+        &self,
+        db: &'static str,
+        step: &'static str,
+        processed_count: usize,
+    ) {
+        let labels = MigrationLabels { db, step };
+        self.migration_processed_items[&labels].inc_by(processed_count as u64);
+    }
 }
 
 #[vetric::register]
@@ -0,0 +1,644 @@
+//! Schema versioning and migrations for [`RocksDB`] instances.
+//!
+//! The schema version of a database is stored in the default column family under a reserved key.
+//! It is equal to the number of [`MigrationStep`]s from [`NamedColumnFamily::MIGRATIONS`]
+//! applied to the database. A newly created database is initialized with the latest schema
+//! version without running any steps. Migration steps are run in chunks; after each chunk,
+//! changes made by the step are persisted atomically together with the step cursor, so that
+//! an interrupted migration can be resumed from the last processed chunk.
+
+use std::time::Instant;
+
+use crate::{
+    db::{AccessMode, NamedColumnFamily, RocksDB, WriteBatch},
+    metrics::METRICS,
+};
+
+/// Key in the default column family storing the [`SchemaState`]. The empty key is ordered before
+/// all other keys, so it cannot fall into a key range (e.g., one removed via `delete_range_cf()`)
+/// bounded by non-empty keys. Databases storing data in the default column family must not use it.
+const SCHEMA_KEY: &[u8] = &[];
+
+/// Persisted schema state: the schema version and, if a migration step is in progress,
+/// its cursor.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SchemaState {
+    version: u32,
+    cursor: Option<Vec<u8>>,
+}
+
+impl SchemaState {
+    /// Tag byte preceding the cursor in the serialized state.
+    const CURSOR_TAG: u8 = 1;
+
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let version = u32::from_be_bytes(bytes.get(..4)?.try_into().unwrap());
+        let cursor = match bytes.get(4) {
+            None => None,
+            Some(&Self::CURSOR_TAG) => Some(bytes[5..].to_vec()),
+            Some(_) => return None,
+        };
+        Some(Self { version, cursor })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.version.to_be_bytes().to_vec();
+        if let Some(cursor) = &self.cursor {
+            buffer.push(Self::CURSOR_TAG);
+            buffer.extend_from_slice(cursor);
+        }
+        buffer
+    }
+}
+
+/// Outcome of processing a single chunk by a [`MigrationStep`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationChunk {
+    /// Number of items (e.g., keys) processed in the chunk. Used for progress reporting.
+    pub processed_count: usize,
+    /// Cursor to pass to the next [`MigrationStep::migrate_chunk()`] call, or `None` if
+    /// the step is completed.
+    pub next_cursor: Option<Vec<u8>>,
+}
+
+/// Single step of a schema migration for a database with column families `CF`. The step
+/// registered at index `i` in [`NamedColumnFamily::MIGRATIONS`] migrates the database from
+/// schema version `i` to `i + 1`.
+pub trait MigrationStep<CF: NamedColumnFamily>: Send + Sync {
+    /// Human-readable name of the step used in logging and metrics.
+    fn name(&self) -> &'static str;
+
+    /// Processes the next chunk of the migration starting from the specified `cursor`
+    /// (`None` for the first chunk). Changes should be added to `batch`, which is written
+    /// to the database together with the returned cursor.
+    ///
+    /// In the [dry-run mode](MigrationMode::DryRun), `batch` is discarded, so the step must
+    /// not rely on changes made when processing previous chunks being visible in `db`.
+    /// Likewise, changes made by previous steps are not visible in this mode.
+    ///
+    /// # Errors
+    ///
+    /// Should propagate RocksDB errors; the migration can be resumed afterwards.
+    fn migrate_chunk(
+        &self,
+        db: &RocksDB<CF>,
+        cursor: Option<&[u8]>,
+        batch: &mut WriteBatch<'_, CF>,
+    ) -> Result<MigrationChunk, rocksdb::Error>;
+}
+
+/// Mode of running migrations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MigrationMode {
+    /// Migrations are applied and persisted.
+    #[default]
+    Apply,
+    /// Migrations are run, but their changes are not persisted. Can be used to estimate
+    /// the migration scope.
+    ///
+    /// Since changes are not persisted, all steps observe the data before migrations. Thus,
+    /// the scope reported for a step may be inaccurate if the step depends on changes
+    /// made by the preceding steps.
+    DryRun,
+}
+
+/// Report for a single [`MigrationStep`] produced by [`RocksDB::run_migrations()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStepReport {
+    /// Step name.
+    pub name: &'static str,
+    /// Schema version after the step is applied.
+    pub target_version: u32,
+    /// Number of processed chunks. If the step was resumed, only includes chunks processed
+    /// after resumption.
+    pub chunk_count: usize,
+    /// Total number of items processed in all chunks.
+    pub processed_count: usize,
+}
+
+/// Report produced by [`RocksDB::run_migrations()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Mode the migrations were run in.
+    pub mode: MigrationMode,
+    /// Schema version before running migrations.
+    pub initial_version: u32,
+    /// Schema version after running migrations. In the dry-run mode, this is the version
+    /// the database would have been migrated to.
+    pub final_version: u32,
+    /// Reports for the run migration steps.
+    pub steps: Vec<MigrationStepReport>,
+}
+
+impl<CF: NamedColumnFamily, M: AccessMode> RocksDB<CF, M> {
+    /// Returns the schema version of this database, i.e., the number of applied migration steps.
+    ///
+    /// # Panics
+    ///
+    /// Panics on RocksDB errors or if the persisted schema state is malformed.
+    pub fn schema_version(&self) -> u32 {
+        self.schema_state().version
+    }
+
+    fn schema_state(&self) -> SchemaState {
+        let raw_state = self
+            .raw_db()
+            .get(SCHEMA_KEY)
+            .expect("failed reading schema state");
+        raw_state.map_or_else(SchemaState::default, |bytes| {
+            SchemaState::deserialize(&bytes).unwrap_or_else(|| {
+                panic!(
+                    "Schema state for RocksDB `{}` is malformed: {bytes:?}",
+                    CF::DB_NAME
+                )
+            })
+        })
+    }
+
+    /// Checks that the database is fully migrated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the database schema version differs from the number of registered migration
+    /// steps, or if a migration step is in progress.
+    pub(crate) fn check_schema_version(&self) {
+        let latest_version = CF::MIGRATIONS.len();
+        let state = self.schema_state();
+        assert!(
+            state.version as usize == latest_version && state.cursor.is_none(),
+            "RocksDB `{}` has schema version {}{}, while the code expects version \
+             {latest_version}; the database must be migrated by opening it in the read-write mode",
+            CF::DB_NAME,
+            state.version,
+            if state.cursor.is_some() {
+                " with an in-progress migration"
+            } else {
+                ""
+            }
+        );
+    }
+
+    /// Checks whether all column families in the database are empty.
+    fn is_empty(&self) -> bool {
+        CF::ALL
+            .iter()
+            .all(|&cf| self.prefix_iterator_cf(cf, &[]).next().is_none())
+    }
+}
+
+impl<CF: NamedColumnFamily> RocksDB<CF> {
+    /// Runs migration steps from [`NamedColumnFamily::MIGRATIONS`] that were not applied
+    /// to this database yet. If a step was interrupted, it is resumed from the last persisted
+    /// chunk. This is called automatically when opening a database in the read-write mode.
+    ///
+    /// If the database is empty and has no persisted schema version (e.g., it was just created),
+    /// it is initialized with the latest schema version without running migration steps.
+    /// This is performed in the dry-run mode as well, since new data written to the database
+    /// will use the latest schema.
+    ///
+    /// # Errors
+    ///
+    /// Propagates errors returned by migration steps and RocksDB errors.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the schema version of the database is greater than the number of registered
+    /// migration steps, e.g. if the database was migrated by a newer version of the code.
+    pub fn run_migrations(&self, mode: MigrationMode) -> Result<MigrationReport, rocksdb::Error> {
+        let steps = CF::MIGRATIONS;
+        let latest_version = u32::try_from(steps.len()).expect("too many migration steps");
+        let mut state = self.schema_state();
+        assert!(
+            state.version <= latest_version,
+            "Schema version {} of RocksDB `{}` is newer than the latest supported version \
+             {latest_version}",
+            state.version,
+            CF::DB_NAME
+        );
+
+        if state == SchemaState::default() && self.is_empty() {
+            tracing::info!(
+                "Initializing empty RocksDB `{}` with schema version {latest_version}",
+                CF::DB_NAME
+            );
+            state.version = latest_version;
+            let mut batch = self.new_write_batch();
+            batch.put_default(SCHEMA_KEY, &state.serialize());
+            self.write(batch)?;
+        }
+
+        let initial_version = state.version;
+        let mut step_reports = vec![];
+        while let Some(&step) = steps.get(state.version as usize) {
+            step_reports.push(self.run_migration_step(step, &mut state, mode)?);
+        }
+        if mode == MigrationMode::Apply {
+            METRICS.report_schema_version(CF::DB_NAME, state.version);
+        }
+        Ok(MigrationReport {
+            mode,
+            initial_version,
+            final_version: state.version,
+            steps: step_reports,
+        })
+    }
+
+    fn run_migration_step(
+        &self,
+        step: &dyn MigrationStep<CF>,
+        state: &mut SchemaState,
+        mode: MigrationMode,
+    ) -> Result<MigrationStepReport, rocksdb::Error> {
+        let started_at = Instant::now();
+        let target_version = state.version + 1;
+        let action = if state.cursor.is_some() {
+            "Resuming"
+        } else {
+            "Starting"
+        };
+        tracing::info!(
+            "{action} migration step `{}` for RocksDB `{}` to schema version {target_version} \
+             ({mode:?} mode)",
+            step.name(),
+            CF::DB_NAME
+        );
+
+        let mut report = MigrationStepReport {
+            name: step.name(),
+            target_version,
+            chunk_count: 0,
+            processed_count: 0,
+        };
+        loop {
+            let mut batch = self.new_write_batch();
+            let chunk = step.migrate_chunk(self, state.cursor.as_deref(), &mut batch)?;
+            report.chunk_count += 1;
+            report.processed_count += chunk.processed_count;
+
+            let is_finished = chunk.next_cursor.is_none();
+            *state = if is_finished {
+                SchemaState {
+                    version: target_version,
+                    cursor: None,
+                }
+            } else {
+                SchemaState {
+                    version: state.version,
+                    cursor: chunk.next_cursor,
+                }
+            };
+            if mode == MigrationMode::Apply {
+                batch.put_default(SCHEMA_KEY, &state.serialize());
+                self.write(batch)?;
+                METRICS.observe_migration_chunk(CF::DB_NAME, step.name(), chunk.processed_count);
+            }
+            tracing::info!(
+                "Migration step `{}` for RocksDB `{}` processed {} items in {} chunks",
+                step.name(),
+                CF::DB_NAME,
+                report.processed_count,
+                report.chunk_count
+            );
+
+            if is_finished {
+                break;
+            }
+        }
+
+        tracing::info!(
+            "Finished migration step `{}` for RocksDB `{}` in {:?} ({mode:?} mode): {report:?}",
+            step.name(),
+            CF::DB_NAME,
+            started_at.elapsed()
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::db::{ReadOnly, RocksDBOptions, Secondary};
+
+    const CHUNK_SIZE: usize = 2;
+
+    #[derive(Debug, Clone, Copy)]
+    struct OldColumnFamily;
+
+    impl NamedColumnFamily for OldColumnFamily {
+        const DB_NAME: &'static str = "test";
+        const ALL: &'static [Self] = &[Self];
+
+        fn name(&self) -> &'static str {
+            "data"
+        }
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct NewColumnFamily;
+
+    impl NamedColumnFamily for NewColumnFamily {
+        const DB_NAME: &'static str = "test";
+        const ALL: &'static [Self] = &[Self];
+        const MIGRATIONS: &'static [&'static dyn MigrationStep<Self>] =
+            &[&UppercaseValues, &RemoveTombstones];
+
+        fn name(&self) -> &'static str {
+            "data"
+        }
+    }
+
+    /// Processes a chunk of entries in the `cf` column family by converting their values
+    /// to the upper case.
+    fn uppercase_values<CF: NamedColumnFamily>(
+        db: &RocksDB<CF>,
+        cf: CF,
+        cursor: Option<&[u8]>,
+        batch: &mut WriteBatch<'_, CF>,
+    ) -> MigrationChunk {
+        let entries: Vec<_> = db
+            .from_iterator_cf(cf, cursor.unwrap_or_default())
+            .take(CHUNK_SIZE + 1)
+            .collect();
+        let next_cursor = entries.get(CHUNK_SIZE).map(|(key, _)| key.to_vec());
+        let processed_entries = &entries[..entries.len().min(CHUNK_SIZE)];
+        for (key, value) in processed_entries {
+            batch.put_cf(cf, key, &value.to_ascii_uppercase());
+        }
+        MigrationChunk {
+            processed_count: processed_entries.len(),
+            next_cursor,
+        }
+    }
+
+    #[derive(Debug)]
+    struct UppercaseValues;
+
+    impl MigrationStep<NewColumnFamily> for UppercaseValues {
+        fn name(&self) -> &'static str {
+            "uppercase_values"
+        }
+
+        fn migrate_chunk(
+            &self,
+            db: &RocksDB<NewColumnFamily>,
+            cursor: Option<&[u8]>,
+            batch: &mut WriteBatch<'_, NewColumnFamily>,
+        ) -> Result<MigrationChunk, rocksdb::Error> {
+            Ok(uppercase_values(db, NewColumnFamily, cursor, batch))
+        }
+    }
+
+    /// Removes tombstones; relies on values being converted to the upper case
+    /// by the previous step.
+    #[derive(Debug)]
+    struct RemoveTombstones;
+
+    impl MigrationStep<NewColumnFamily> for RemoveTombstones {
+        fn name(&self) -> &'static str {
+            "remove_tombstones"
+        }
+
+        fn migrate_chunk(
+            &self,
+            db: &RocksDB<NewColumnFamily>,
+            _cursor: Option<&[u8]>,
+            batch: &mut WriteBatch<'_, NewColumnFamily>,
+        ) -> Result<MigrationChunk, rocksdb::Error> {
+            let mut processed_count = 0;
+            for (key, value) in db.prefix_iterator_cf(NewColumnFamily, &[]) {
+                if *value == *b"TOMBSTONE" {
+                    batch.delete_cf(NewColumnFamily, &key);
+                    processed_count += 1;
+                }
+            }
+            Ok(MigrationChunk {
+                processed_count,
+                next_cursor: None,
+            })
+        }
+    }
+
+    fn populate_db(path: &std::path::Path) {
+        let db = RocksDB::<OldColumnFamily>::new(path);
+        let mut batch = db.new_write_batch();
+        for (key, value) in [
+            (b"key0", b"value0".as_slice()),
+            (b"key1", b"tombstone"),
+            (b"key2", b"value2"),
+            (b"key3", b"value3"),
+            (b"key4", b"value4"),
+        ] {
+            batch.put_cf(OldColumnFamily, key, value);
+        }
+        db.write(batch).unwrap();
+    }
+
+    fn read_values<CF: NamedColumnFamily>(db: &RocksDB<CF>, cf: CF) -> Vec<Vec<u8>> {
+        db.prefix_iterator_cf(cf, &[])
+            .map(|(_, value)| value.into_vec())
+            .collect()
+    }
+
+    #[test]
+    fn schema_state_serialization() {
+        let states = [
+            SchemaState::default(),
+            SchemaState {
+                version: 3,
+                cursor: Some(vec![]),
+            },
+            SchemaState {
+                version: 1,
+                cursor: Some(b"key".to_vec()),
+            },
+        ];
+        for state in states {
+            let bytes = state.serialize();
+            assert_eq!(SchemaState::deserialize(&bytes).unwrap(), state);
+        }
+        assert!(SchemaState::deserialize(&[0, 0, 1]).is_none());
+        assert!(SchemaState::deserialize(&[0, 0, 0, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn migrations_are_run_on_open() {
+        let temp_dir = TempDir::new().unwrap();
+        populate_db(temp_dir.path());
+
+        let db = RocksDB::<NewColumnFamily>::new(temp_dir.path());
+        assert_eq!(db.schema_version(), 2);
+        let values = read_values(&db, NewColumnFamily);
+        assert_eq!(
+            values,
+            [
+                b"VALUE0".to_vec(),
+                b"VALUE2".to_vec(),
+                b"VALUE3".to_vec(),
+                b"VALUE4".to_vec()
+            ]
+        );
+
+        // Migrations should not be rerun.
+        let report = db.run_migrations(MigrationMode::Apply).unwrap();
+        assert_eq!(report.initial_version, 2);
+        assert_eq!(report.final_version, 2);
+        assert!(report.steps.is_empty());
+
+        // The schema state should be the only entry in the default CF.
+        let default_cf_keys: Vec<_> = db
+            .raw_db()
+            .iterator(rocksdb::IteratorMode::Start)
+            .map(|entry| entry.unwrap().0.into_vec())
+            .collect();
+        assert_eq!(default_cf_keys, [SCHEMA_KEY.to_vec()]);
+    }
+
+    #[test]
+    fn new_db_is_initialized_with_latest_schema_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = RocksDB::<NewColumnFamily>::new(temp_dir.path());
+        assert_eq!(db.schema_version(), 2);
+        let mut batch = db.new_write_batch();
+        batch.put_cf(NewColumnFamily, b"key", b"TOMBSTONE");
+        db.write(batch).unwrap();
+        drop(db);
+
+        let db = RocksDB::<NewColumnFamily>::new(temp_dir.path());
+        assert_eq!(db.schema_version(), 2);
+        assert_eq!(read_values(&db, NewColumnFamily), [b"TOMBSTONE"]);
+    }
+
+    #[test]
+    fn read_only_instances_require_migrated_db() {
+        let temp_dir = TempDir::new().unwrap();
+        populate_db(temp_dir.path());
+
+        let options = RocksDBOptions::default();
+        let result = panic::catch_unwind(|| {
+            RocksDB::<NewColumnFamily, ReadOnly>::open_read_only(temp_dir.path(), options)
+        });
+        let err = result.unwrap_err();
+        let err = err.downcast_ref::<String>().unwrap();
+        assert!(err.contains("schema version 0"), "{err}");
+
+        drop(RocksDB::<NewColumnFamily>::new(temp_dir.path()));
+        let db = RocksDB::<NewColumnFamily, ReadOnly>::open_read_only(temp_dir.path(), options);
+        assert_eq!(db.schema_version(), 2);
+        let secondary_dir = TempDir::new().unwrap();
+        let db = RocksDB::<NewColumnFamily, Secondary>::open_secondary(
+            temp_dir.path(),
+            secondary_dir.path(),
+            options,
+        );
+        assert_eq!(db.schema_version(), 2);
+    }
+
+    #[test]
+    fn dry_run_migrations() {
+        let temp_dir = TempDir::new().unwrap();
+        populate_db(temp_dir.path());
+
+        let options = RocksDBOptions {
+            migration_mode: MigrationMode::DryRun,
+            ..RocksDBOptions::default()
+        };
+        let db = RocksDB::<NewColumnFamily>::with_options(temp_dir.path(), options);
+        assert_eq!(db.schema_version(), 0);
+        let report = db.run_migrations(MigrationMode::DryRun).unwrap();
+        assert_eq!(report.initial_version, 0);
+        assert_eq!(report.final_version, 2);
+        let step_reports: Vec<_> = report
+            .steps
+            .iter()
+            .map(|step| (step.name, step.chunk_count, step.processed_count))
+            .collect();
+        // Changes made by `uppercase_values` are not persisted, so `remove_tombstones` doesn't
+        // find any tombstones.
+        assert_eq!(
+            step_reports,
+            [("uppercase_values", 3, 5), ("remove_tombstones", 1, 0)]
+        );
+
+        assert_eq!(db.schema_version(), 0);
+        let values = read_values(&db, NewColumnFamily);
+        assert_eq!(values[1], b"tombstone");
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct FlakyColumnFamily;
+
+    impl NamedColumnFamily for FlakyColumnFamily {
+        const DB_NAME: &'static str = "test";
+        const ALL: &'static [Self] = &[Self];
+        const MIGRATIONS: &'static [&'static dyn MigrationStep<Self>] = &[&FlakyUppercaseValues];
+
+        fn name(&self) -> &'static str {
+            "data"
+        }
+    }
+
+    static SHOULD_FAIL: AtomicBool = AtomicBool::new(false);
+
+    #[derive(Debug)]
+    struct FlakyUppercaseValues;
+
+    impl MigrationStep<FlakyColumnFamily> for FlakyUppercaseValues {
+        fn name(&self) -> &'static str {
+            "flaky_uppercase_values"
+        }
+
+        fn migrate_chunk(
+            &self,
+            db: &RocksDB<FlakyColumnFamily>,
+            cursor: Option<&[u8]>,
+            batch: &mut WriteBatch<'_, FlakyColumnFamily>,
+        ) -> Result<MigrationChunk, rocksdb::Error> {
+            if cursor.is_some() && SHOULD_FAIL.swap(false, Ordering::SeqCst) {
+                panic!("emulated migration failure");
+            }
+            Ok(uppercase_values(db, FlakyColumnFamily, cursor, batch))
+        }
+    }
+
+    #[test]
+    fn interrupted_migration_is_resumed() {
+        let temp_dir = TempDir::new().unwrap();
+        populate_db(temp_dir.path());
+
+        SHOULD_FAIL.store(true, Ordering::SeqCst);
+        let result = panic::catch_unwind(|| RocksDB::<FlakyColumnFamily>::new(temp_dir.path()));
+        assert!(result.is_err());
+
+        let options = RocksDBOptions {
+            migration_mode: MigrationMode::DryRun,
+            ..RocksDBOptions::default()
+        };
+        let db = RocksDB::<FlakyColumnFamily>::with_options(temp_dir.path(), options);
+        let state = db.schema_state();
+        assert_eq!(state.version, 0);
+        assert_eq!(state.cursor.unwrap(), b"key2");
+        let values = read_values(&db, FlakyColumnFamily);
+        assert_eq!(
+            &values[..3],
+            [b"VALUE0".as_slice(), b"TOMBSTONE", b"value2"]
+        );
+
+        let report = db.run_migrations(MigrationMode::Apply).unwrap();
+        assert_eq!(report.final_version, 1);
+        assert_eq!(report.steps[0].chunk_count, 2);
+        assert_eq!(report.steps[0].processed_count, 3);
+        assert_eq!(db.schema_version(), 1);
+        let values = read_values(&db, FlakyColumnFamily);
+        assert!(values
+            .iter()
+            .all(|value| value.to_ascii_uppercase() == *value));
+    }
+}